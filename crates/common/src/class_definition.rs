@@ -85,7 +85,7 @@ impl<T> Dummy<T> for Cairo<'_> {
     }
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize, Dummy)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, Dummy)]
 #[serde(deny_unknown_fields)]
 pub struct SierraEntryPoints {
     #[serde(rename = "EXTERNAL")]
@@ -182,7 +182,7 @@ impl<T> Dummy<T> for SelectorAndOffset {
 }
 
 /// Descriptor of an entry point in a Sierra class.
-#[derive(Clone, Debug, serde::Deserialize, serde::Serialize, Dummy)]
+#[derive(Clone, Debug, PartialEq, serde::Deserialize, serde::Serialize, Dummy)]
 #[serde(deny_unknown_fields)]
 pub struct SelectorAndFunctionIndex {
     pub selector: EntryPoint,
@@ -624,23 +624,6 @@ pub fn truncated_keccak(mut plain: [u8; 32]) -> Felt {
     Felt::from_be_bytes(plain).expect("cannot overflow: smaller than modulus")
 }
 
-/// Calculate class commitment tree leaf hash value.
-///
-/// See: <https://docs.starknet.io/documentation/starknet_versions/upcoming_versions/#state_commitment>
-pub fn calculate_class_commitment_leaf_hash(
-    compiled_class_hash: CasmHash,
-) -> ClassCommitmentLeafHash {
-    const CONTRACT_CLASS_HASH_VERSION: pathfinder_crypto::Felt =
-        felt_bytes!(b"CONTRACT_CLASS_LEAF_V0");
-    ClassCommitmentLeafHash(
-        pathfinder_crypto::hash::poseidon_hash(
-            CONTRACT_CLASS_HASH_VERSION.into(),
-            compiled_class_hash.0.into(),
-        )
-        .into(),
-    )
-}
-
 #[cfg(test)]
 mod tests {
     use crate::{felt, CallParam, ClassHash, ContractAddress, ContractAddressSalt};
@@ -2,6 +2,7 @@ use std::collections::{HashMap, HashSet};
 
 use fake::Dummy;
 
+use crate::transaction::DataAvailabilityMode;
 use crate::{
     BlockHash,
     CasmHash,
@@ -32,6 +33,14 @@ pub struct StateUpdateData {
     pub system_contract_updates: HashMap<ContractAddress, SystemContractUpdate>,
     pub declared_cairo_classes: HashSet<ClassHash>,
     pub declared_sierra_classes: HashMap<SierraHash, CasmHash>,
+    /// The data availability domain of the storage updates of contracts whose
+    /// storage is not in the default L1 domain. See
+    /// [StateUpdateData::storage_domain].
+    ///
+    /// This is transient: the domains are not persisted, so state diffs read
+    /// back from storage are all in the L1 domain.
+    #[dummy(default)]
+    pub storage_domains: HashMap<ContractAddress, DataAvailabilityMode>,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Dummy)]
@@ -296,6 +305,26 @@ impl StateUpdateData {
         )
     }
 
+    /// The data availability domain of the storage updates of `contract`.
+    ///
+    /// Only state diffs received over p2p carry the domain, so this is
+    /// [DataAvailabilityMode::L1] for state diffs from any other source.
+    pub fn storage_domain(&self, contract: &ContractAddress) -> DataAvailabilityMode {
+        self.storage_domains
+            .get(contract)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Only domains other than the default L1 are stored.
+    pub fn set_storage_domain(&mut self, contract: ContractAddress, domain: DataAvailabilityMode) {
+        if domain == DataAvailabilityMode::L1 {
+            self.storage_domains.remove(&contract);
+        } else {
+            self.storage_domains.insert(contract, domain);
+        }
+    }
+
     pub fn is_empty(&self) -> bool {
         self.contract_updates.is_empty()
             && self.system_contract_updates.is_empty()
@@ -332,6 +361,7 @@ impl From<StateUpdate> for StateUpdateData {
             system_contract_updates: state_update.system_contract_updates,
             declared_cairo_classes: state_update.declared_cairo_classes,
             declared_sierra_classes: state_update.declared_sierra_classes,
+            storage_domains: Default::default(),
         }
     }
 }
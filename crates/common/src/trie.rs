@@ -1,12 +1,21 @@
 use bitvec::prelude::Msb0;
 use bitvec::vec::BitVec;
 use pathfinder_crypto::Felt;
+use serde::{Deserialize, Serialize};
 
 use crate::hash::FeltHash;
 
 /// A node in a Starknet patricia-merkle trie.
 ///
 /// See pathfinders merkle-tree crate for more information.
+///
+/// Serializes to the JSON shape used by proofs served over RPC, with an edge's
+/// path encoded as its value and bit length:
+///
+/// ```json
+/// {"binary": {"left": "0x1", "right": "0x2"}}
+/// {"edge": {"path": {"value": "0x5", "len": 3}, "child": "0x3"}}
+/// ```
 #[derive(Debug, Clone, PartialEq)]
 pub enum TrieNode {
     Binary { left: Felt, right: Felt },
@@ -29,3 +38,133 @@ impl TrieNode {
         }
     }
 }
+
+/// The serialized form of a [TrieNode].
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+enum TrieNodeDto {
+    Binary { left: Felt, right: Felt },
+    Edge { path: PathDto, child: Felt },
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PathDto {
+    value: Felt,
+    len: usize,
+}
+
+impl Serialize for TrieNode {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let dto = match self {
+            TrieNode::Binary { left, right } => TrieNodeDto::Binary {
+                left: *left,
+                right: *right,
+            },
+            TrieNode::Edge { child, path } => TrieNodeDto::Edge {
+                path: PathDto {
+                    value: Felt::from_bits(path).map_err(serde::ser::Error::custom)?,
+                    len: path.len(),
+                },
+                child: *child,
+            },
+        };
+
+        dto.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for TrieNode {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        use serde::de::Error;
+
+        let node = match TrieNodeDto::deserialize(deserializer)? {
+            TrieNodeDto::Binary { left, right } => TrieNode::Binary { left, right },
+            TrieNodeDto::Edge { path, child } => {
+                let bits = path.value.view_bits();
+                let Some(start) = bits.len().checked_sub(path.len) else {
+                    return Err(D::Error::custom(format!(
+                        "Edge path length {} exceeds 251 bits",
+                        path.len
+                    )));
+                };
+                if bits[..start].any() {
+                    return Err(D::Error::custom(format!(
+                        "Edge path value {} does not fit in {} bits",
+                        path.value, path.len
+                    )));
+                }
+
+                TrieNode::Edge {
+                    child,
+                    path: bits[start..].to_bitvec(),
+                }
+            }
+        };
+
+        Ok(node)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitvec::bitvec;
+    use serde_json::json;
+
+    use super::*;
+    use crate::felt;
+
+    #[test]
+    fn binary_serde_round_trip() {
+        let node = TrieNode::Binary {
+            left: felt!("0x1"),
+            right: felt!("0x2"),
+        };
+
+        let json = serde_json::to_value(&node).unwrap();
+        assert_eq!(json, json!({"binary": {"left": "0x1", "right": "0x2"}}));
+        assert_eq!(serde_json::from_value::<TrieNode>(json).unwrap(), node);
+    }
+
+    #[test]
+    fn edge_serde_round_trip() {
+        // Leading zeros are part of the path, so only the length tells them apart.
+        let node = TrieNode::Edge {
+            child: felt!("0x3"),
+            path: bitvec![u8, Msb0; 0, 1, 0, 1],
+        };
+
+        let json = serde_json::to_value(&node).unwrap();
+        assert_eq!(
+            json,
+            json!({"edge": {"path": {"value": "0x5", "len": 4}, "child": "0x3"}})
+        );
+        assert_eq!(serde_json::from_value::<TrieNode>(json).unwrap(), node);
+    }
+
+    #[test]
+    fn full_length_edge_serde_round_trip() {
+        let node = TrieNode::Edge {
+            child: felt!("0x3"),
+            path: bitvec![u8, Msb0; 1; 251],
+        };
+
+        let json = serde_json::to_string(&node).unwrap();
+        assert_eq!(serde_json::from_str::<TrieNode>(&json).unwrap(), node);
+    }
+
+    #[test]
+    fn edge_path_value_must_fit_its_length() {
+        let too_long = json!({"edge": {"path": {"value": "0x1", "len": 252}, "child": "0x3"}});
+        serde_json::from_value::<TrieNode>(too_long).unwrap_err();
+
+        let overflowing = json!({"edge": {"path": {"value": "0x5", "len": 2}, "child": "0x3"}});
+        serde_json::from_value::<TrieNode>(overflowing).unwrap_err();
+    }
+}
@@ -1,17 +1,35 @@
+use std::ops::ControlFlow;
+
 use anyhow::Context;
+use bitvec::prelude::Msb0;
+use bitvec::slice::BitSlice;
 use pathfinder_common::hash::PoseidonHash;
 use pathfinder_common::trie::TrieNode;
 use pathfinder_common::{
+    felt_bytes,
     BlockNumber,
+    CasmHash,
     ClassCommitment,
     ClassCommitmentLeafHash,
     ClassHash,
     SierraHash,
 };
+use pathfinder_crypto::hash::poseidon_hash;
 use pathfinder_crypto::Felt;
 use pathfinder_storage::{Transaction, TrieUpdate};
 
-use crate::tree::MerkleTree;
+use crate::merkle_node::InternalNode;
+use crate::storage::CachedStorage;
+use crate::tree::{AuditReport, MerkleTree, Visit};
+
+#[derive(Debug, thiserror::Error)]
+pub enum ProofError {
+    /// The trie history of the block is no longer kept by this node.
+    #[error("Trie of block {block} has been pruned")]
+    Pruned { block: BlockNumber },
+    #[error(transparent)]
+    Internal(#[from] anyhow::Error),
+}
 
 /// A [Patricia Merkle tree](MerkleTree) used to calculate commitments to
 /// Starknet's Sierra classes.
@@ -21,7 +39,7 @@ use crate::tree::MerkleTree;
 /// Tree data is persisted by a sqlite table 'tree_class'.
 pub struct ClassCommitmentTree<'tx> {
     tree: MerkleTree<PoseidonHash, 251>,
-    storage: ClassStorage<'tx>,
+    storage: CachedStorage<ClassStorage<'tx>>,
 }
 
 impl<'tx> ClassCommitmentTree<'tx> {
@@ -29,7 +47,10 @@ impl<'tx> ClassCommitmentTree<'tx> {
         let storage = ClassStorage { tx, block: None };
         let tree = MerkleTree::empty();
 
-        Self { tree, storage }
+        Self {
+            tree,
+            storage: CachedStorage::new(storage),
+        }
     }
 
     pub fn load(tx: &'tx Transaction<'tx>, block: BlockNumber) -> anyhow::Result<Self> {
@@ -46,7 +67,10 @@ impl<'tx> ClassCommitmentTree<'tx> {
         };
         let tree = MerkleTree::new(root);
 
-        Ok(Self { tree, storage })
+        Ok(Self {
+            tree,
+            storage: CachedStorage::new(storage),
+        })
     }
 
     pub fn with_verify_hashes(mut self, verify_hashes: bool) -> Self {
@@ -54,16 +78,38 @@ impl<'tx> ClassCommitmentTree<'tx> {
         self
     }
 
+    /// See [MerkleTree::with_parallel_commit].
+    pub fn with_parallel_commit(mut self, parallel_commit: bool) -> Self {
+        self.tree = self.tree.with_parallel_commit(parallel_commit);
+        self
+    }
+
+    /// See [MerkleTree::set_verify_hashes].
+    pub fn set_verify_hashes(&mut self, verify_hashes: bool) {
+        self.tree.set_verify_hashes(verify_hashes);
+    }
+
     /// Adds a leaf node for a Sierra -> CASM commitment.
     ///
     /// Note that the leaf value is _not_ the Cairo hash, but a hashed value
-    /// based on that. See <https://github.com/starkware-libs/cairo-lang/blob/12ca9e91bbdc8a423c63280949c7e34382792067/src/starkware/starknet/core/os/state.cairo#L302>
-    /// for details.
+    /// based on that, see [class_commitment_leaf_hash].
     pub fn set(&mut self, class: SierraHash, value: ClassCommitmentLeafHash) -> anyhow::Result<()> {
         let key = class.view_bits().to_owned();
         self.tree.set(&self.storage, key, value.0)
     }
 
+    /// Adds many leaf nodes at once, see [set](Self::set) and
+    /// [MerkleTree::set_batch].
+    pub fn set_batch(
+        &mut self,
+        updates: impl IntoIterator<Item = (SierraHash, ClassCommitmentLeafHash)>,
+    ) -> anyhow::Result<()> {
+        let updates = updates
+            .into_iter()
+            .map(|(class, value)| (class.view_bits().to_owned(), value.0));
+        self.tree.set_batch(&self.storage, updates)
+    }
+
     /// Commits the changes and calculates the new node hashes. Returns the new
     /// commitment and any potentially newly created nodes.
     pub fn commit(self) -> anyhow::Result<(ClassCommitment, TrieUpdate)> {
@@ -73,12 +119,65 @@ impl<'tx> ClassCommitmentTree<'tx> {
         Ok((commitment, update))
     }
 
-    /// Generates a proof for a given `key`
+    /// Calculates the commitment [commit](Self::commit) would return, without
+    /// collecting the nodes to persist. Useful to check the changes against an
+    /// expected commitment before committing them.
+    pub fn preview_commitment(&self) -> anyhow::Result<ClassCommitment> {
+        self.tree.root_hash(&self.storage).map(ClassCommitment)
+    }
+
+    /// See [`MerkleTree::dfs`]
+    pub fn dfs<B, F: FnMut(&InternalNode, &BitSlice<u8, Msb0>) -> ControlFlow<B, Visit>>(
+        &mut self,
+        f: &mut F,
+    ) -> anyhow::Result<Option<B>> {
+        self.tree.dfs(&self.storage, f)
+    }
+
+    /// Returns every leaf of the tree, in key order, by visiting the whole
+    /// tree. Changes which have not been committed yet are included.
+    pub fn iter_leaves(
+        &mut self,
+    ) -> anyhow::Result<impl Iterator<Item = (SierraHash, ClassCommitmentLeafHash)>> {
+        let mut paths = Vec::new();
+        self.dfs::<(), _>(&mut |node, path| {
+            if let InternalNode::Leaf = node {
+                paths.push(path.to_bitvec());
+            }
+            ControlFlow::Continue(Visit::ContinueDeeper)
+        })?;
+
+        let leaves = paths
+            .into_iter()
+            .map(|path| {
+                let class =
+                    SierraHash(Felt::from_bits(&path).context("Mapping leaf path to sierra hash")?);
+                let leaf = self
+                    .tree
+                    .get(&self.storage, path)
+                    .context("Querying class leaf")?
+                    .with_context(|| format!("Class leaf for {class} is missing"))?;
+                Ok((class, ClassCommitmentLeafHash(leaf)))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        Ok(leaves.into_iter())
+    }
+
+    /// Generates a proof for a given `key`. Fails with [ProofError::Pruned]
+    /// if the trie nodes of `block` were removed by trie pruning.
     pub fn get_proof(
         tx: &'tx Transaction<'tx>,
         block: BlockNumber,
         class_hash: ClassHash,
-    ) -> anyhow::Result<Option<Vec<TrieNode>>> {
+    ) -> Result<Option<Vec<TrieNode>>, ProofError> {
+        if tx
+            .trie_pruned_at(block)
+            .context("Checking whether the class trie was pruned")?
+        {
+            return Err(ProofError::Pruned { block });
+        }
+
         let root = tx
             .class_root_index(block)
             .context("Querying class root index")?;
@@ -92,10 +191,106 @@ impl<'tx> ClassCommitmentTree<'tx> {
             block: Some(block),
         };
 
-        MerkleTree::<PoseidonHash, 251>::get_proof(root, &storage, class_hash.0.view_bits())
+        Ok(MerkleTree::<PoseidonHash, 251>::get_proof(
+            root,
+            &storage,
+            class_hash.0.view_bits(),
+        )?)
+    }
+
+    /// Generates a proof for each of the `class_hashes`. See
+    /// [`MerkleTree::get_proofs`].
+    pub fn get_proofs(
+        tx: &'tx Transaction<'tx>,
+        block: BlockNumber,
+        class_hashes: &[ClassHash],
+    ) -> anyhow::Result<Vec<Option<Vec<TrieNode>>>> {
+        let root = tx
+            .class_root_index(block)
+            .context("Querying class root index")?;
+
+        let Some(root) = root else {
+            return Ok(vec![None; class_hashes.len()]);
+        };
+
+        let storage = ClassStorage {
+            tx,
+            block: Some(block),
+        };
+
+        let keys = class_hashes
+            .iter()
+            .map(|class_hash| class_hash.0.view_bits())
+            .collect::<Vec<_>>();
+        MerkleTree::<PoseidonHash, 251>::get_proofs(root, &storage, &keys)
+    }
+
+    /// Returns the classes whose leaves differ between the trees at `block_a`
+    /// and `block_b`. See [MerkleTree::diff].
+    pub fn diff(
+        tx: &'tx Transaction<'tx>,
+        block_a: BlockNumber,
+        block_b: BlockNumber,
+    ) -> anyhow::Result<Vec<SierraHash>> {
+        let root_a = tx
+            .class_root_index(block_a)
+            .context("Querying class root index")?;
+        let root_b = tx
+            .class_root_index(block_b)
+            .context("Querying class root index")?;
+
+        let storage_a = ClassStorage {
+            tx,
+            block: Some(block_a),
+        };
+        let storage_b = ClassStorage {
+            tx,
+            block: Some(block_b),
+        };
+
+        MerkleTree::<PoseidonHash, 251>::diff(root_a, &storage_a, root_b, &storage_b)?
+            .into_iter()
+            .map(|path| {
+                Felt::from_bits(&path)
+                    .map(SierraHash)
+                    .context("Mapping leaf path to sierra hash")
+            })
+            .collect()
+    }
+
+    /// Recomputes every node hash of the persisted tree at `block` and reports
+    /// any nodes whose stored hash diverges. See [MerkleTree::audit].
+    ///
+    /// This walks the entire tree and is intended for offline integrity
+    /// checks, e.g. after suspected database corruption.
+    pub fn audit(tx: &'tx Transaction<'tx>, block: BlockNumber) -> anyhow::Result<AuditReport> {
+        let root = tx
+            .class_root_index(block)
+            .context("Querying class root index")?;
+
+        let Some(root) = root else {
+            return Ok(AuditReport::default());
+        };
+
+        let storage = ClassStorage {
+            tx,
+            block: Some(block),
+        };
+
+        MerkleTree::<PoseidonHash, 251>::audit(root, &storage)
     }
 }
 
+/// Calculates the value of a class's leaf in the [ClassCommitmentTree] from its
+/// CASM hash.
+///
+/// See <https://github.com/starkware-libs/cairo-lang/blob/12ca9e91bbdc8a423c63280949c7e34382792067/src/starkware/starknet/core/os/state.cairo#L302>
+/// for details.
+pub fn class_commitment_leaf_hash(casm: CasmHash) -> ClassCommitmentLeafHash {
+    const CONTRACT_CLASS_LEAF_VERSION: Felt = felt_bytes!(b"CONTRACT_CLASS_LEAF_V0");
+    ClassCommitmentLeafHash(poseidon_hash(CONTRACT_CLASS_LEAF_VERSION.into(), casm.0.into()).into())
+}
+
 struct ClassStorage<'tx> {
     tx: &'tx Transaction<'tx>,
     block: Option<BlockNumber>,
@@ -139,3 +334,54 @@ impl crate::storage::Storage for ClassStorage<'_> {
         Ok(leaf)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use pathfinder_common::macro_prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn leaf_hash() {
+        // Class declared in block 35748 of Sepolia integration, the expected value
+        // was computed with an independent implementation of the formula.
+        let casm = casm_hash!("0x6506976af042088c9ea49e6cc9c9a12838ee6920bb989dce02f5c6467667367");
+        let expected = class_commitment_leaf_hash!(
+            "0x33c8a03c8e462ab425215e9ed83a5a6617165e26ba65f81d7e937fbfa5d8b22"
+        );
+
+        assert_eq!(class_commitment_leaf_hash(casm), expected);
+    }
+
+    #[test]
+    fn proof_of_pruned_block() {
+        let mut db = pathfinder_storage::StorageBuilder::in_memory_with_trie_pruning(
+            pathfinder_storage::TriePruneMode::Prune { num_blocks_kept: 1 },
+        )
+        .unwrap()
+        .connection()
+        .unwrap();
+        let tx = db.transaction().unwrap();
+        for number in 0..3 {
+            tx.insert_block_header(&pathfinder_common::BlockHeader {
+                number: BlockNumber::new_or_panic(number),
+                hash: pathfinder_common::BlockHash(Felt::from_u64(number)),
+                ..Default::default()
+            })
+            .unwrap();
+        }
+
+        let error = ClassCommitmentTree::get_proof(&tx, BlockNumber::GENESIS, class_hash!("0x1"))
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            ProofError::Pruned { block } if block == BlockNumber::GENESIS
+        ));
+
+        // Blocks still kept are unaffected, the tree is empty at all of them.
+        let proof =
+            ClassCommitmentTree::get_proof(&tx, BlockNumber::new_or_panic(1), class_hash!("0x1"))
+                .unwrap();
+        assert!(proof.is_none());
+    }
+}
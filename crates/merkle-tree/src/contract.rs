@@ -7,7 +7,7 @@
 use std::ops::ControlFlow;
 
 use anyhow::Context;
-use bitvec::prelude::Msb0;
+use bitvec::prelude::{BitVec, Msb0};
 use bitvec::slice::BitSlice;
 use pathfinder_common::hash::PedersenHash;
 use pathfinder_common::trie::TrieNode;
@@ -21,9 +21,10 @@ use pathfinder_common::{
     StorageValue,
 };
 use pathfinder_crypto::Felt;
-use pathfinder_storage::{Transaction, TrieUpdate};
+use pathfinder_storage::{StoredNode, Transaction, TrieUpdate};
 
-use crate::merkle_node::InternalNode;
+use crate::merkle_node::{Direction, InternalNode};
+use crate::storage::{CachedStorage, Storage};
 use crate::tree::{MerkleTree, Visit};
 
 /// A [Patricia Merkle tree](MerkleTree) used to calculate commitments to a
@@ -35,7 +36,7 @@ use crate::tree::{MerkleTree, Visit};
 /// Tree data is persisted by a sqlite table 'tree_contracts'.
 pub struct ContractsStorageTree<'tx> {
     tree: MerkleTree<PedersenHash, 251>,
-    storage: ContractStorage<'tx>,
+    storage: CachedStorage<ContractStorage<'tx>>,
 }
 
 impl<'tx> ContractsStorageTree<'tx> {
@@ -47,7 +48,10 @@ impl<'tx> ContractsStorageTree<'tx> {
         };
         let tree = MerkleTree::empty();
 
-        Self { tree, storage }
+        Self {
+            tree,
+            storage: CachedStorage::new(storage),
+        }
     }
 
     pub fn load(
@@ -69,7 +73,10 @@ impl<'tx> ContractsStorageTree<'tx> {
         };
         let tree = MerkleTree::new(root);
 
-        Ok(Self { tree, storage })
+        Ok(Self {
+            tree,
+            storage: CachedStorage::new(storage),
+        })
     }
 
     pub fn with_verify_hashes(mut self, verify_hashes: bool) -> Self {
@@ -77,6 +84,17 @@ impl<'tx> ContractsStorageTree<'tx> {
         self
     }
 
+    /// See [MerkleTree::with_parallel_commit].
+    pub fn with_parallel_commit(mut self, parallel_commit: bool) -> Self {
+        self.tree = self.tree.with_parallel_commit(parallel_commit);
+        self
+    }
+
+    /// See [MerkleTree::set_verify_hashes].
+    pub fn set_verify_hashes(&mut self, verify_hashes: bool) {
+        self.tree.set_verify_hashes(verify_hashes);
+    }
+
     /// Generates a proof for `key`. See [`MerkleTree::get_proof`].
     pub fn get_proof(
         tx: &'tx Transaction<'tx>,
@@ -94,11 +112,49 @@ impl<'tx> ContractsStorageTree<'tx> {
         MerkleTree::<PedersenHash, 251>::get_proof(root, &storage, key)
     }
 
+    /// Generates a proof for each of the `keys`. See
+    /// [`MerkleTree::get_proofs`].
+    pub fn get_proofs(
+        tx: &'tx Transaction<'tx>,
+        contract: ContractAddress,
+        block: BlockNumber,
+        keys: &[&BitSlice<u8, Msb0>],
+        root: u64,
+    ) -> anyhow::Result<Vec<Option<Vec<TrieNode>>>> {
+        let storage = ContractStorage {
+            tx,
+            block: Some(block),
+            contract,
+        };
+
+        MerkleTree::<PedersenHash, 251>::get_proofs(root, &storage, keys)
+    }
+
     pub fn set(&mut self, address: StorageAddress, value: StorageValue) -> anyhow::Result<()> {
         let key = address.view_bits().to_owned();
         self.tree.set(&self.storage, key, value.0)
     }
 
+    /// Returns the value stored at `address`, or `None` if it is not set.
+    ///
+    /// This includes changes which have not been committed yet.
+    pub fn get(&self, address: StorageAddress) -> anyhow::Result<Option<StorageValue>> {
+        let key = address.view_bits().to_owned();
+        let value = self.tree.get(&self.storage, key)?;
+        Ok(value.map(StorageValue))
+    }
+
+    /// Sets many storage values at once, see [MerkleTree::set_batch].
+    pub fn set_batch(
+        &mut self,
+        updates: impl IntoIterator<Item = (StorageAddress, StorageValue)>,
+    ) -> anyhow::Result<()> {
+        let updates = updates
+            .into_iter()
+            .map(|(address, value)| (address.view_bits().to_owned(), value.0));
+        self.tree.set_batch(&self.storage, updates)
+    }
+
     /// Commits the changes and calculates the new node hashes. Returns the new
     /// commitment and any potentially newly created nodes.
     pub fn commit(self) -> anyhow::Result<(ContractRoot, TrieUpdate)> {
@@ -114,6 +170,161 @@ impl<'tx> ContractsStorageTree<'tx> {
     ) -> anyhow::Result<Option<B>> {
         self.tree.dfs(&self.storage, f)
     }
+
+    /// Returns the storage addresses of `contract` whose values differ between
+    /// `block_a` and `block_b`. See [MerkleTree::diff].
+    pub fn diff(
+        tx: &'tx Transaction<'tx>,
+        contract: ContractAddress,
+        block_a: BlockNumber,
+        block_b: BlockNumber,
+    ) -> anyhow::Result<Vec<StorageAddress>> {
+        let root_a = tx
+            .contract_root_index(block_a, contract)
+            .context("Querying contract root index")?;
+        let root_b = tx
+            .contract_root_index(block_b, contract)
+            .context("Querying contract root index")?;
+
+        let storage_a = ContractStorage {
+            tx,
+            block: Some(block_a),
+            contract,
+        };
+        let storage_b = ContractStorage {
+            tx,
+            block: Some(block_b),
+            contract,
+        };
+
+        MerkleTree::<PedersenHash, 251>::diff(root_a, &storage_a, root_b, &storage_b)?
+            .into_iter()
+            .map(|path| {
+                Felt::from_bits(&path)
+                    .map(StorageAddress)
+                    .context("Mapping leaf path to storage address")
+            })
+            .collect()
+    }
+
+    /// Returns the complete storage of `contract` at `block` by visiting every
+    /// leaf of the contract's storage trie, in ascending order of storage
+    /// address.
+    ///
+    /// Trie nodes and storage values are only read from the database as the
+    /// iterator is advanced, so arbitrarily large contracts can be exported.
+    /// The iterator is empty if the contract does not exist at `block`.
+    pub fn export_contract_storage(
+        tx: &'tx Transaction<'tx>,
+        block: BlockNumber,
+        contract: ContractAddress,
+    ) -> anyhow::Result<impl Iterator<Item = anyhow::Result<(StorageAddress, StorageValue)>> + 'tx>
+    {
+        let root = tx
+            .contract_root_index(block, contract)
+            .context("Querying contract root index")?;
+
+        let storage = ContractStorage {
+            tx,
+            block: Some(block),
+            contract,
+        };
+
+        Ok(StorageExport {
+            storage,
+            nodes: root.map(|root| (root, BitVec::new())).into_iter().collect(),
+            leaves: Vec::new(),
+        })
+    }
+}
+
+/// Walks a contract's storage trie depth-first, see
+/// [ContractsStorageTree::export_contract_storage].
+struct StorageExport<'tx> {
+    storage: ContractStorage<'tx>,
+    /// Nodes which are yet to be visited, with the next one at the end.
+    nodes: Vec<(u64, BitVec<u8, Msb0>)>,
+    /// Paths of leaves which are yet to be yielded, with the next one at the
+    /// end.
+    leaves: Vec<BitVec<u8, Msb0>>,
+}
+
+impl StorageExport<'_> {
+    fn next_leaf(&mut self) -> anyhow::Result<Option<BitVec<u8, Msb0>>> {
+        loop {
+            if let Some(leaf) = self.leaves.pop() {
+                return Ok(Some(leaf));
+            }
+
+            let Some((index, path)) = self.nodes.pop() else {
+                return Ok(None);
+            };
+
+            let node = self
+                .storage
+                .get(index)?
+                .with_context(|| format!("Node {index} is missing"))?;
+
+            let child = |direction: Direction| {
+                let mut child = path.clone();
+                child.push(direction.into());
+                child
+            };
+            let extended = |edge: &BitSlice<u8, Msb0>| {
+                let mut child = path.clone();
+                child.extend_from_bitslice(edge);
+                child
+            };
+
+            // Children are pushed right first so that the left subtree is
+            // visited first.
+            match node {
+                StoredNode::Binary { left, right } => {
+                    self.nodes.push((right, child(Direction::Right)));
+                    self.nodes.push((left, child(Direction::Left)));
+                }
+                StoredNode::Edge { child, path } => self.nodes.push((child, extended(&path))),
+                StoredNode::LeafBinary => {
+                    self.leaves.push(child(Direction::Right));
+                    self.leaves.push(child(Direction::Left));
+                }
+                StoredNode::LeafEdge { path } => self.leaves.push(extended(&path)),
+            }
+        }
+    }
+
+    fn read_leaf(
+        &self,
+        path: &BitSlice<u8, Msb0>,
+    ) -> anyhow::Result<(StorageAddress, StorageValue)> {
+        let address =
+            StorageAddress(Felt::from_bits(path).context("Mapping leaf path to storage address")?);
+        let value = self
+            .storage
+            .leaf(path)
+            .context("Querying storage value")?
+            .with_context(|| format!("Storage value for {address} is missing"))?;
+
+        Ok((address, StorageValue(value)))
+    }
+}
+
+impl Iterator for StorageExport<'_> {
+    type Item = anyhow::Result<(StorageAddress, StorageValue)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let leaf = match self.next_leaf() {
+            Ok(leaf) => leaf?,
+            Err(e) => {
+                // Stop after the first error rather than yielding a partial trie.
+                self.nodes.clear();
+                self.leaves.clear();
+                return Some(Err(e));
+            }
+        };
+
+        Some(self.read_leaf(&leaf))
+    }
 }
 
 /// A [Patricia Merkle tree](MerkleTree) used to calculate commitments to all of
@@ -125,7 +336,7 @@ impl<'tx> ContractsStorageTree<'tx> {
 /// Tree data is persisted by a sqlite table 'tree_global'.
 pub struct StorageCommitmentTree<'tx> {
     tree: MerkleTree<PedersenHash, 251>,
-    storage: StorageTrieStorage<'tx>,
+    storage: CachedStorage<StorageTrieStorage<'tx>>,
 }
 
 impl<'tx> StorageCommitmentTree<'tx> {
@@ -133,7 +344,10 @@ impl<'tx> StorageCommitmentTree<'tx> {
         let storage = StorageTrieStorage { tx, block: None };
         let tree = MerkleTree::empty();
 
-        Self { tree, storage }
+        Self {
+            tree,
+            storage: CachedStorage::new(storage),
+        }
     }
 
     pub fn load(tx: &'tx Transaction<'tx>, block: BlockNumber) -> anyhow::Result<Self> {
@@ -151,7 +365,10 @@ impl<'tx> StorageCommitmentTree<'tx> {
 
         let tree = MerkleTree::new(root);
 
-        Ok(Self { tree, storage })
+        Ok(Self {
+            tree,
+            storage: CachedStorage::new(storage),
+        })
     }
 
     pub fn with_verify_hashes(mut self, verify_hashes: bool) -> Self {
@@ -159,6 +376,17 @@ impl<'tx> StorageCommitmentTree<'tx> {
         self
     }
 
+    /// See [MerkleTree::with_parallel_commit].
+    pub fn with_parallel_commit(mut self, parallel_commit: bool) -> Self {
+        self.tree = self.tree.with_parallel_commit(parallel_commit);
+        self
+    }
+
+    /// See [MerkleTree::set_verify_hashes].
+    pub fn set_verify_hashes(&mut self, verify_hashes: bool) {
+        self.tree.set_verify_hashes(verify_hashes);
+    }
+
     pub fn set(
         &mut self,
         address: ContractAddress,
@@ -204,6 +432,33 @@ impl<'tx> StorageCommitmentTree<'tx> {
         MerkleTree::<PedersenHash, 251>::get_proof(root, &storage, address.view_bits())
     }
 
+    /// Generates a proof for each of the `addresses`. See
+    /// [`MerkleTree::get_proofs`].
+    pub fn get_proofs(
+        tx: &'tx Transaction<'tx>,
+        block: BlockNumber,
+        addresses: &[ContractAddress],
+    ) -> anyhow::Result<Vec<Option<Vec<TrieNode>>>> {
+        let root = tx
+            .storage_root_index(block)
+            .context("Querying storage root index")?;
+
+        let Some(root) = root else {
+            return Ok(vec![None; addresses.len()]);
+        };
+
+        let storage = StorageTrieStorage {
+            tx,
+            block: Some(block),
+        };
+
+        let keys = addresses
+            .iter()
+            .map(|address| address.view_bits())
+            .collect::<Vec<_>>();
+        MerkleTree::<PedersenHash, 251>::get_proofs(root, &storage, &keys)
+    }
+
     /// See [`MerkleTree::dfs`]
     pub fn dfs<B, F: FnMut(&InternalNode, &BitSlice<u8, Msb0>) -> ControlFlow<B, Visit>>(
         &mut self,
@@ -277,3 +532,110 @@ impl crate::storage::Storage for StorageTrieStorage<'_> {
         Ok(value)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use pathfinder_common::macro_prelude::*;
+    use pathfinder_common::state_update::StateUpdate;
+    use pathfinder_common::{BlockHash, BlockHeader};
+
+    use super::*;
+
+    /// Stores `updates` for `contract` in a new block, both as storage values
+    /// and in the contract's storage trie.
+    fn insert_block(
+        tx: &Transaction<'_>,
+        block: BlockNumber,
+        contract: ContractAddress,
+        updates: &[(StorageAddress, StorageValue)],
+    ) {
+        tx.insert_block_header(&BlockHeader {
+            number: block,
+            hash: BlockHash(Felt::from_u64(block.get() + 1)),
+            ..Default::default()
+        })
+        .unwrap();
+
+        let state_update = updates
+            .iter()
+            .fold(StateUpdate::default(), |state_update, (key, value)| {
+                state_update.with_storage_update(contract, *key, *value)
+            });
+        tx.insert_state_update(block, &state_update).unwrap();
+
+        let mut tree = match block.parent() {
+            Some(parent) => ContractsStorageTree::load(tx, contract, parent).unwrap(),
+            None => ContractsStorageTree::empty(tx, contract),
+        };
+        tree.set_batch(updates.iter().copied()).unwrap();
+        let (_, trie_update) = tree.commit().unwrap();
+
+        let root = tx.insert_contract_trie(&trie_update, block).unwrap();
+        tx.insert_contract_root(block, contract, root).unwrap();
+    }
+
+    #[test]
+    fn export_contract_storage() {
+        let mut db = pathfinder_storage::StorageBuilder::in_memory()
+            .unwrap()
+            .connection()
+            .unwrap();
+        let tx = db.transaction().unwrap();
+
+        let contract = contract_address!("0x100");
+        // 0x2 and 0x3 are siblings, which exercises leaf binary nodes.
+        let genesis = [
+            (storage_address!("0x2"), storage_value!("0x20")),
+            (storage_address!("0x3"), storage_value!("0x30")),
+            (storage_address!("0x1234"), storage_value!("0x40")),
+        ];
+        insert_block(&tx, BlockNumber::GENESIS, contract, &genesis);
+        let block_one = [
+            (storage_address!("0x3"), storage_value!("0x31")),
+            (storage_address!("0x5"), storage_value!("0x50")),
+        ];
+        insert_block(&tx, BlockNumber::GENESIS + 1, contract, &block_one);
+
+        let export = |block| {
+            ContractsStorageTree::export_contract_storage(&tx, block, contract)
+                .unwrap()
+                .collect::<anyhow::Result<Vec<_>>>()
+                .unwrap()
+        };
+
+        assert_eq!(export(BlockNumber::GENESIS), genesis);
+        assert_eq!(
+            export(BlockNumber::GENESIS + 1),
+            [
+                (storage_address!("0x2"), storage_value!("0x20")),
+                (storage_address!("0x3"), storage_value!("0x31")),
+                (storage_address!("0x5"), storage_value!("0x50")),
+                (storage_address!("0x1234"), storage_value!("0x40")),
+            ]
+        );
+    }
+
+    #[test]
+    fn export_contract_storage_of_missing_contract_is_empty() {
+        let mut db = pathfinder_storage::StorageBuilder::in_memory()
+            .unwrap()
+            .connection()
+            .unwrap();
+        let tx = db.transaction().unwrap();
+
+        insert_block(
+            &tx,
+            BlockNumber::GENESIS,
+            contract_address!("0x100"),
+            &[(storage_address!("0x1"), storage_value!("0x10"))],
+        );
+
+        let mut export = ContractsStorageTree::export_contract_storage(
+            &tx,
+            BlockNumber::GENESIS,
+            contract_address!("0x200"),
+        )
+        .unwrap();
+        assert!(export.next().is_none());
+    }
+}
@@ -70,11 +70,9 @@ pub fn update_contract_state(
         }
         .with_verify_hashes(verify_hashes);
 
-        for (key, value) in updates {
-            contract_tree
-                .set(*key, *value)
-                .context("Update contract storage tree")?;
-        }
+        contract_tree
+            .set_batch(updates.iter().map(|(key, value)| (*key, *value)))
+            .context("Update contract storage tree")?;
         let (contract_root, trie_update) = contract_tree
             .commit()
             .context("Apply contract storage tree changes")?;
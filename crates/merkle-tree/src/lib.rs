@@ -5,8 +5,10 @@ pub mod tree;
 
 pub mod class;
 mod contract;
+mod storage_proof;
 mod transaction;
 
-pub use class::ClassCommitmentTree;
+pub use class::{ClassCommitmentTree, ProofError};
 pub use contract::{ContractsStorageTree, StorageCommitmentTree};
+pub use storage_proof::{storage_proof, ContractLeafData, NodeHashToNodeMapping, StorageProof};
 pub use transaction::TransactionOrEventTree;
@@ -1,4 +1,8 @@
+use std::cell::RefCell;
+use std::num::NonZeroUsize;
+
 use bitvec::prelude::*;
+use cached::{Cached, SizedCache};
 use pathfinder_crypto::Felt;
 use pathfinder_storage::StoredNode;
 
@@ -11,3 +15,158 @@ pub trait Storage {
     /// Returns the value of the leaf at the given path.
     fn leaf(&self, path: &BitSlice<u8, Msb0>) -> anyhow::Result<Option<Felt>>;
 }
+
+impl<S: Storage + ?Sized> Storage for &S {
+    fn get(&self, index: u64) -> anyhow::Result<Option<StoredNode>> {
+        (**self).get(index)
+    }
+
+    fn hash(&self, index: u64) -> anyhow::Result<Option<Felt>> {
+        (**self).hash(index)
+    }
+
+    fn leaf(&self, path: &BitSlice<u8, Msb0>) -> anyhow::Result<Option<Felt>> {
+        (**self).leaf(path)
+    }
+}
+
+/// Caches the most recently used node reads from the wrapped [Storage], for
+/// operations which visit the same nodes repeatedly, such as generating
+/// proofs for many keys or calculating a tree's root hash before committing
+/// it.
+///
+/// Nodes and their hashes are cached by storage index, since a stored node is
+/// never modified once written. Leaf values are looked up by path and change
+/// from block to block, so they are always read from the wrapped storage.
+/// Missing nodes are not cached either.
+pub struct CachedStorage<S> {
+    storage: S,
+    nodes: RefCell<SizedCache<u64, StoredNode>>,
+    hashes: RefCell<SizedCache<u64, Felt>>,
+}
+
+impl<S: Storage> CachedStorage<S> {
+    /// The number of nodes cached by [new](Self::new), enough for the paths
+    /// touched by a block's updates to a single tree.
+    const DEFAULT_CAPACITY: usize = 1024;
+
+    pub fn new(storage: S) -> Self {
+        let capacity =
+            NonZeroUsize::new(Self::DEFAULT_CAPACITY).expect("Cache capacity is non-zero");
+        Self::with_capacity(storage, capacity)
+    }
+
+    /// Keeps up to `capacity` nodes and node hashes each.
+    pub fn with_capacity(storage: S, capacity: NonZeroUsize) -> Self {
+        Self {
+            storage,
+            nodes: RefCell::new(SizedCache::with_size(capacity.get())),
+            hashes: RefCell::new(SizedCache::with_size(capacity.get())),
+        }
+    }
+
+    /// The number of reads served from the cache.
+    pub fn hits(&self) -> u64 {
+        self.nodes.borrow().cache_hits().unwrap_or_default()
+            + self.hashes.borrow().cache_hits().unwrap_or_default()
+    }
+
+    /// The number of reads passed on to the wrapped storage.
+    pub fn misses(&self) -> u64 {
+        self.nodes.borrow().cache_misses().unwrap_or_default()
+            + self.hashes.borrow().cache_misses().unwrap_or_default()
+    }
+}
+
+impl<S: Storage> Storage for CachedStorage<S> {
+    fn get(&self, index: u64) -> anyhow::Result<Option<StoredNode>> {
+        if let Some(node) = self.nodes.borrow_mut().cache_get(&index) {
+            return Ok(Some(node.clone()));
+        }
+
+        let node = self.storage.get(index)?;
+        if let Some(node) = &node {
+            self.nodes.borrow_mut().cache_set(index, node.clone());
+        }
+        Ok(node)
+    }
+
+    fn hash(&self, index: u64) -> anyhow::Result<Option<Felt>> {
+        if let Some(hash) = self.hashes.borrow_mut().cache_get(&index) {
+            return Ok(Some(*hash));
+        }
+
+        let hash = self.storage.hash(index)?;
+        if let Some(hash) = hash {
+            self.hashes.borrow_mut().cache_set(index, hash);
+        }
+        Ok(hash)
+    }
+
+    fn leaf(&self, path: &BitSlice<u8, Msb0>) -> anyhow::Result<Option<Felt>> {
+        self.storage.leaf(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+
+    /// Counts the reads which reach it.
+    #[derive(Default)]
+    struct CountingStorage {
+        reads: Cell<usize>,
+    }
+
+    impl Storage for CountingStorage {
+        fn get(&self, _index: u64) -> anyhow::Result<Option<StoredNode>> {
+            self.reads.set(self.reads.get() + 1);
+            Ok(Some(StoredNode::LeafBinary))
+        }
+
+        fn hash(&self, index: u64) -> anyhow::Result<Option<Felt>> {
+            self.reads.set(self.reads.get() + 1);
+            Ok(Some(Felt::from_u64(index)))
+        }
+
+        fn leaf(&self, _path: &BitSlice<u8, Msb0>) -> anyhow::Result<Option<Felt>> {
+            self.reads.set(self.reads.get() + 1);
+            Ok(None)
+        }
+    }
+
+    #[test]
+    fn least_recently_used_reads_are_evicted() {
+        let storage = CountingStorage::default();
+        let cached = CachedStorage::with_capacity(&storage, NonZeroUsize::new(2).unwrap());
+
+        assert_eq!(cached.hash(1).unwrap(), Some(Felt::from_u64(1)));
+        assert_eq!(cached.hash(1).unwrap(), Some(Felt::from_u64(1)));
+        assert_eq!(storage.reads.get(), 1);
+
+        cached.hash(2).unwrap();
+        cached.hash(3).unwrap();
+        // Node 1 was evicted by node 3.
+        cached.hash(1).unwrap();
+        assert_eq!(storage.reads.get(), 4);
+        assert_eq!((cached.hits(), cached.misses()), (1, 4));
+
+        // Nodes and their hashes are cached separately.
+        cached.get(3).unwrap();
+        assert_eq!(storage.reads.get(), 5);
+    }
+
+    #[test]
+    fn leaves_are_not_cached() {
+        let storage = CountingStorage::default();
+        let cached = CachedStorage::new(&storage);
+        let path = bitvec![u8, Msb0; 0, 1];
+
+        cached.leaf(&path).unwrap();
+        cached.leaf(&path).unwrap();
+        assert_eq!(storage.reads.get(), 2);
+        assert_eq!((cached.hits(), cached.misses()), (0, 0));
+    }
+}
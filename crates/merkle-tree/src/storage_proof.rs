@@ -0,0 +1,242 @@
+//! Proofs of the classes, contracts and contract storage of a block, as served
+//! by the `starknet_getStorageProof` RPC method.
+
+use std::collections::HashSet;
+
+use anyhow::Context;
+use pathfinder_common::hash::{FeltHash, PedersenHash, PoseidonHash};
+use pathfinder_common::trie::TrieNode;
+use pathfinder_common::{
+    BlockNumber,
+    ClassCommitment,
+    ClassHash,
+    ContractAddress,
+    ContractNonce,
+    ContractRoot,
+    ContractStateHash,
+    StorageAddress,
+    StorageCommitment,
+};
+use pathfinder_crypto::Felt;
+use pathfinder_storage::Transaction;
+
+use crate::class::ProofError;
+use crate::contract_state::calculate_contract_state_hash;
+use crate::{ClassCommitmentTree, ContractsStorageTree, StorageCommitmentTree};
+
+/// The nodes of one or more proofs in the same tree, keyed by their hash.
+/// Nodes shared by several proofs are only included once.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct NodeHashToNodeMapping(pub Vec<(Felt, TrieNode)>);
+
+impl NodeHashToNodeMapping {
+    fn from_proofs<H: FeltHash>(proofs: impl IntoIterator<Item = Vec<TrieNode>>) -> Self {
+        let mut seen = HashSet::new();
+        let nodes = proofs
+            .into_iter()
+            .flatten()
+            .map(|node| (node.hash::<H>(), node))
+            .filter(|(hash, _)| seen.insert(*hash))
+            .collect();
+
+        Self(nodes)
+    }
+}
+
+/// The preimage of a contract's leaf in the storage commitment tree.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ContractLeafData {
+    pub nonce: ContractNonce,
+    pub class_hash: ClassHash,
+    pub storage_root: ContractRoot,
+}
+
+impl ContractLeafData {
+    /// The value of the contract's leaf.
+    pub fn hash(&self) -> ContractStateHash {
+        calculate_contract_state_hash(self.class_hash, self.storage_root, self.nonce)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct StorageProof {
+    pub classes_proof: NodeHashToNodeMapping,
+    pub contracts_proof: NodeHashToNodeMapping,
+    /// In the same order as the requested contracts.
+    pub contract_leaves_data: Vec<ContractLeafData>,
+    /// One entry per contract of the requested storage keys, in the order the
+    /// contracts first appear.
+    pub contracts_storage_proofs: Vec<(ContractAddress, NodeHashToNodeMapping)>,
+    /// The root of `contracts_proof`.
+    pub contracts_tree_root: StorageCommitment,
+    /// The root of `classes_proof`.
+    pub classes_tree_root: ClassCommitment,
+}
+
+/// Returns merkle proofs for the given classes, contracts and contract storage
+/// keys at `block`, along with the roots they can be verified against.
+///
+/// Proofs of keys which are not in their tree are proofs of non-membership.
+pub fn storage_proof(
+    tx: &Transaction<'_>,
+    block: BlockNumber,
+    contracts: &[ContractAddress],
+    storage_keys: &[(ContractAddress, StorageAddress)],
+    class_hashes: &[ClassHash],
+) -> Result<StorageProof, ProofError> {
+    if tx
+        .trie_pruned_at(block)
+        .context("Checking whether the tries were pruned")?
+    {
+        return Err(ProofError::Pruned { block });
+    }
+
+    let header = tx
+        .block_header(block.into())
+        .context("Fetching block header")?
+        .with_context(|| format!("Block {block} not found"))?;
+
+    // The proof of non-membership in an empty tree is empty. Otherwise a missing
+    // proof means that the tree is no longer stored for this block.
+    let classes_proof = if header.class_commitment == ClassCommitment::ZERO {
+        Default::default()
+    } else {
+        let proofs = ClassCommitmentTree::get_proofs(tx, block, class_hashes)
+            .context("Creating class proofs")?
+            .into_iter()
+            .collect::<Option<Vec<_>>>()
+            .ok_or(ProofError::Pruned { block })?;
+        NodeHashToNodeMapping::from_proofs::<PoseidonHash>(proofs)
+    };
+
+    let contracts_proof = if header.storage_commitment == StorageCommitment::ZERO {
+        Default::default()
+    } else {
+        let proofs = StorageCommitmentTree::get_proofs(tx, block, contracts)
+            .context("Creating contract proofs")?
+            .into_iter()
+            .collect::<Option<Vec<_>>>()
+            .ok_or(ProofError::Pruned { block })?;
+        NodeHashToNodeMapping::from_proofs::<PedersenHash>(proofs)
+    };
+
+    let contract_leaves_data = contracts
+        .iter()
+        .map(|&address| {
+            let nonce = tx
+                .contract_nonce(address, block.into())
+                .context("Querying contract's nonce")?
+                .unwrap_or_default();
+            let class_hash = tx
+                .contract_class_hash(block.into(), address)
+                .context("Querying contract's class hash")?
+                .unwrap_or_default();
+            let storage_root = tx
+                .contract_root(block, address)
+                .context("Querying contract's root")?
+                .unwrap_or_default();
+
+            Ok(ContractLeafData {
+                nonce,
+                class_hash,
+                storage_root,
+            })
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let mut grouped_keys: Vec<(ContractAddress, Vec<&StorageAddress>)> = Vec::new();
+    for (contract, key) in storage_keys {
+        match grouped_keys.iter_mut().find(|(c, _)| c == contract) {
+            Some((_, keys)) => keys.push(key),
+            None => grouped_keys.push((*contract, vec![key])),
+        }
+    }
+
+    let mut contracts_storage_proofs = Vec::with_capacity(grouped_keys.len());
+    for (contract, keys) in grouped_keys {
+        let root = tx
+            .contract_root_index(block, contract)
+            .context("Querying contract root index")?;
+
+        let Some(root) = root else {
+            contracts_storage_proofs.push((contract, Default::default()));
+            continue;
+        };
+
+        let keys = keys.iter().map(|key| key.view_bits()).collect::<Vec<_>>();
+        let proofs = ContractsStorageTree::get_proofs(tx, contract, block, &keys, root)
+            .context("Creating storage proofs")?
+            .into_iter()
+            .collect::<Option<Vec<_>>>()
+            .ok_or(ProofError::Pruned { block })?;
+        contracts_storage_proofs.push((
+            contract,
+            NodeHashToNodeMapping::from_proofs::<PedersenHash>(proofs),
+        ));
+    }
+
+    Ok(StorageProof {
+        classes_proof,
+        contracts_proof,
+        contract_leaves_data,
+        contracts_storage_proofs,
+        contracts_tree_root: header.storage_commitment,
+        classes_tree_root: header.class_commitment,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use pathfinder_common::macro_prelude::*;
+    use pathfinder_common::{BlockHash, BlockHeader};
+
+    use super::*;
+
+    #[test]
+    fn empty_tries_have_empty_proofs() {
+        let mut db = pathfinder_storage::StorageBuilder::in_memory()
+            .unwrap()
+            .connection()
+            .unwrap();
+        let tx = db.transaction().unwrap();
+        tx.insert_block_header(&BlockHeader {
+            number: BlockNumber::GENESIS,
+            hash: BlockHash(Felt::from_u64(1)),
+            ..Default::default()
+        })
+        .unwrap();
+
+        let contract = contract_address!("0x1");
+        let proof = storage_proof(
+            &tx,
+            BlockNumber::GENESIS,
+            &[contract],
+            &[
+                (contract, storage_address!("0x2")),
+                (contract_address!("0x3"), storage_address!("0x4")),
+                (contract, storage_address!("0x5")),
+            ],
+            &[class_hash!("0x6")],
+        )
+        .unwrap();
+
+        assert_eq!(
+            proof,
+            StorageProof {
+                classes_proof: Default::default(),
+                contracts_proof: Default::default(),
+                contract_leaves_data: vec![ContractLeafData {
+                    nonce: ContractNonce::ZERO,
+                    class_hash: ClassHash::ZERO,
+                    storage_root: ContractRoot::ZERO,
+                }],
+                contracts_storage_proofs: vec![
+                    (contract, Default::default()),
+                    (contract_address!("0x3"), Default::default()),
+                ],
+                contracts_tree_root: StorageCommitment::ZERO,
+                classes_tree_root: ClassCommitment::ZERO,
+            }
+        );
+    }
+}
@@ -53,6 +53,7 @@
 
 use std::cell::RefCell;
 use std::collections::HashMap;
+use std::num::NonZeroUsize;
 use std::ops::ControlFlow;
 use std::rc::Rc;
 
@@ -64,7 +65,7 @@ use pathfinder_crypto::Felt;
 use pathfinder_storage::{Node, NodeRef, StoredNode, TrieUpdate};
 
 use crate::merkle_node::{BinaryNode, Direction, EdgeNode, InternalNode};
-use crate::storage::Storage;
+use crate::storage::{CachedStorage, Storage};
 
 /// A Starknet binary Merkle-Patricia tree.
 #[derive(Debug, Clone)]
@@ -76,6 +77,9 @@ pub struct MerkleTree<H: FeltHash, const HEIGHT: usize> {
     /// If enables, node hashes are verified as they are resolved. This allows
     /// testing for database corruption.
     verify_hashes: bool,
+    /// If enabled, independent subtrees are hashed on the rayon thread pool
+    /// during [commit](Self::commit).
+    parallel_commit: bool,
 }
 
 impl<H: FeltHash, const HEIGHT: usize> MerkleTree<H, HEIGHT> {
@@ -85,13 +89,28 @@ impl<H: FeltHash, const HEIGHT: usize> MerkleTree<H, HEIGHT> {
             root,
             _hasher: std::marker::PhantomData,
             verify_hashes: false,
+            parallel_commit: false,
             leaves: Default::default(),
             nodes_removed: Default::default(),
         }
     }
 
     pub fn with_verify_hashes(mut self, verify_hashes: bool) -> Self {
+        self.set_verify_hashes(verify_hashes);
+        self
+    }
+
+    /// Like [with_verify_hashes](Self::with_verify_hashes), for a tree which
+    /// is already in use. Only nodes resolved from now on are affected.
+    pub fn set_verify_hashes(&mut self, verify_hashes: bool) {
         self.verify_hashes = verify_hashes;
+    }
+
+    /// Hashes independent subtrees concurrently when committing, which speeds
+    /// up commits touching many leaves. The resulting [TrieUpdate] is the
+    /// same as with a sequential commit.
+    pub fn with_parallel_commit(mut self, parallel_commit: bool) -> Self {
+        self.parallel_commit = parallel_commit;
         self
     }
 
@@ -100,6 +119,7 @@ impl<H: FeltHash, const HEIGHT: usize> MerkleTree<H, HEIGHT> {
             root: None,
             _hasher: std::marker::PhantomData,
             verify_hashes: false,
+            parallel_commit: false,
             leaves: Default::default(),
             nodes_removed: Default::default(),
         }
@@ -120,6 +140,14 @@ impl<H: FeltHash, const HEIGHT: usize> MerkleTree<H, HEIGHT> {
                     .hash(*idx)
                     .context("Fetching root node's hash")?
                     .context("Root node's hash is missing")?,
+                other if self.parallel_commit => {
+                    let committed = self
+                        .dirty_subtree(other, storage, BitVec::new())?
+                        .commit::<H>(0)?;
+                    added = committed.added;
+                    removed = committed.removed;
+                    committed.hash
+                }
                 other => {
                     let (root_hash, _) = self.commit_subtree(
                         other,
@@ -145,6 +173,111 @@ impl<H: FeltHash, const HEIGHT: usize> MerkleTree<H, HEIGHT> {
         })
     }
 
+    /// Calculates the root hash the tree would have if it was
+    /// [committed](Self::commit) now, without collecting the changed nodes.
+    pub fn root_hash(&self, storage: &impl Storage) -> anyhow::Result<Felt> {
+        match self.root.as_ref() {
+            Some(root) => self.subtree_hash(&root.borrow(), storage, BitVec::new()),
+            // An empty trie has a root of zero
+            None => Ok(Felt::ZERO),
+        }
+    }
+
+    /// Calculates the hash of this subtree, as
+    /// [commit_subtree](Self::commit_subtree) does, without persisting
+    /// anything.
+    fn subtree_hash(
+        &self,
+        node: &InternalNode,
+        storage: &impl Storage,
+        mut path: BitVec<u8, Msb0>,
+    ) -> anyhow::Result<Felt> {
+        match node {
+            InternalNode::Unresolved(idx) => storage
+                .hash(*idx)
+                .context("Fetching stored node's hash")?
+                .context("Stored node's hash is missing"),
+            InternalNode::Leaf => match self.leaves.get(&path) {
+                Some(value) => Ok(*value),
+                None => storage
+                    .leaf(&path)
+                    .context("Fetching leaf value from storage")?
+                    .context("Leaf value missing from storage"),
+            },
+            InternalNode::Binary(binary) => {
+                let mut left_path = path.clone();
+                left_path.push(Direction::Left.into());
+                let left_hash = self.subtree_hash(&binary.left.borrow(), storage, left_path)?;
+                path.push(Direction::Right.into());
+                let right_hash = self.subtree_hash(&binary.right.borrow(), storage, path)?;
+
+                Ok(BinaryNode::calculate_hash::<H>(left_hash, right_hash))
+            }
+            InternalNode::Edge(edge) => {
+                path.extend_from_bitslice(&edge.path);
+                let child_hash = self.subtree_hash(&edge.child.borrow(), storage, path)?;
+
+                Ok(EdgeNode::calculate_hash::<H>(child_hash, &edge.path))
+            }
+        }
+    }
+
+    /// Copies this subtree into a [DirtySubtree], reading the hashes of stored
+    /// nodes and the values of leaves, so that it can be committed without
+    /// access to storage.
+    fn dirty_subtree(
+        &self,
+        node: &InternalNode,
+        storage: &impl Storage,
+        mut path: BitVec<u8, Msb0>,
+    ) -> anyhow::Result<DirtySubtree> {
+        let subtree = match node {
+            InternalNode::Unresolved(idx) => DirtySubtree::Stored {
+                hash: storage
+                    .hash(*idx)
+                    .context("Fetching stored node's hash")?
+                    .context("Stored node's hash is missing")?,
+                index: *idx,
+            },
+            InternalNode::Leaf => DirtySubtree::Leaf {
+                value: match self.leaves.get(&path) {
+                    Some(value) => *value,
+                    None => storage
+                        .leaf(&path)
+                        .context("Fetching leaf value from storage")?
+                        .context("Leaf value missing from storage")?,
+                },
+            },
+            InternalNode::Binary(binary) => {
+                let mut left_path = path.clone();
+                left_path.push(Direction::Left.into());
+                let left = self.dirty_subtree(&binary.left.borrow(), storage, left_path)?;
+                path.push(Direction::Right.into());
+                let right = self.dirty_subtree(&binary.right.borrow(), storage, path)?;
+
+                DirtySubtree::Binary {
+                    storage_index: binary.storage_index,
+                    nodes: left.nodes() + right.nodes() + 1,
+                    left: Box::new(left),
+                    right: Box::new(right),
+                }
+            }
+            InternalNode::Edge(edge) => {
+                path.extend_from_bitslice(&edge.path);
+                let child = self.dirty_subtree(&edge.child.borrow(), storage, path)?;
+
+                DirtySubtree::Edge {
+                    storage_index: edge.storage_index,
+                    path: edge.path.clone(),
+                    nodes: child.nodes() + 1,
+                    child: Box::new(child),
+                }
+            }
+        };
+
+        Ok(subtree)
+    }
+
     /// Persists any changes in this subtree to storage.
     ///
     /// This necessitates recursively calculating the hash of, and
@@ -257,6 +390,28 @@ impl<H: FeltHash, const HEIGHT: usize> MerkleTree<H, HEIGHT> {
         Ok(result)
     }
 
+    /// Sets the values of many keys, as if [set](Self::set) was called for
+    /// each of them in order.
+    ///
+    /// The updates are applied in key order so that consecutive updates share
+    /// most of their path through the tree. If a key is updated more than once
+    /// its last value wins.
+    pub fn set_batch(
+        &mut self,
+        storage: &impl Storage,
+        updates: impl IntoIterator<Item = (BitVec<u8, Msb0>, Felt)>,
+    ) -> anyhow::Result<()> {
+        let mut updates = updates.into_iter().collect::<Vec<_>>();
+        // The sort is stable, which keeps repeated updates of a key in order.
+        updates.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        for (key, value) in updates {
+            self.set(storage, key, value)?;
+        }
+
+        Ok(())
+    }
+
     /// Sets the value of a key. To delete a key, set the value to [Felt::ZERO].
     pub fn set(
         &mut self,
@@ -624,6 +779,280 @@ impl<H: FeltHash, const HEIGHT: usize> MerkleTree<H, HEIGHT> {
         Ok(Some(nodes))
     }
 
+    /// The number of nodes cached by [get_proofs](Self::get_proofs), enough for
+    /// the paths of a few hundred keys.
+    const PROOF_CACHE_CAPACITY: usize = 100_000;
+
+    /// Generates a merkle-proof for each of the `keys`, in the same order. See
+    /// [get_proof](Self::get_proof).
+    ///
+    /// Nodes on the shared parts of the keys' paths are only read from storage
+    /// once.
+    pub fn get_proofs(
+        root: u64,
+        storage: &impl Storage,
+        keys: &[&BitSlice<u8, Msb0>],
+    ) -> anyhow::Result<Vec<Option<Vec<TrieNode>>>> {
+        let capacity =
+            NonZeroUsize::new(Self::PROOF_CACHE_CAPACITY).expect("Cache capacity is non-zero");
+        let storage = CachedStorage::with_capacity(storage, capacity);
+        keys.iter()
+            .map(|key| Self::get_proof(root, &storage, key))
+            .collect()
+    }
+
+    /// Verifies a proof generated by [get_proof](Self::get_proof) against the
+    /// `root` hash of the tree.
+    ///
+    /// Returns `true` if the proof shows that `key` is set to `value`. Keys set
+    /// to [Felt::ZERO] are not part of the tree, so for a `value` of zero this
+    /// returns `true` if the proof shows that `key` does not exist.
+    pub fn verify_proof(
+        root: Felt,
+        key: &BitSlice<u8, Msb0>,
+        value: Felt,
+        proof: &[TrieNode],
+    ) -> bool {
+        if key.len() != HEIGHT {
+            return false;
+        }
+
+        // An empty tree has a root of zero and no nodes.
+        if proof.is_empty() {
+            return root == Felt::ZERO && value == Felt::ZERO;
+        }
+
+        let mut expected_hash = root;
+        let mut remaining_path = key;
+        let mut nodes = proof.iter();
+        while let Some(node) = nodes.next() {
+            if node.hash::<H>() != expected_hash {
+                return false;
+            }
+
+            match node {
+                TrieNode::Binary { left, right } => {
+                    let Some((direction, rest)) = remaining_path.split_first() else {
+                        return false;
+                    };
+                    expected_hash = match Direction::from(*direction) {
+                        Direction::Left => *left,
+                        Direction::Right => *right,
+                    };
+                    remaining_path = rest;
+                }
+                TrieNode::Edge { child, path } => {
+                    let Some(prefix) = remaining_path.get(..path.len()) else {
+                        return false;
+                    };
+                    // The key diverges from the edge, so it cannot be part of the
+                    // tree. This must be the last node of the proof.
+                    if prefix != path {
+                        return value == Felt::ZERO && nodes.next().is_none();
+                    }
+                    expected_hash = *child;
+                    remaining_path = &remaining_path[path.len()..];
+                }
+            }
+        }
+
+        remaining_path.is_empty() && expected_hash == value
+    }
+
+    /// Walks the entire persisted tree under `root` and recomputes every node's
+    /// hash bottom-up from the leaf values, comparing it against the hash
+    /// stored for that node.
+    ///
+    /// Mismatches are collected in the returned [AuditReport] instead of
+    /// failing on the first one. Since parent hashes are computed from the
+    /// recomputed hashes of their children, a corrupted leaf or node will also
+    /// cause all of its ancestors to diverge.
+    ///
+    /// Missing nodes, hashes or leaves are still reported as an error as the
+    /// tree cannot be walked any further.
+    pub fn audit(root: u64, storage: &impl Storage) -> anyhow::Result<AuditReport> {
+        let mut report = AuditReport::default();
+        Self::audit_node(root, storage, &mut BitVec::new(), &mut report)?;
+
+        Ok(report)
+    }
+
+    /// Recursively audits the node at `index`, returning its recomputed hash.
+    fn audit_node(
+        index: u64,
+        storage: &impl Storage,
+        path: &mut BitVec<u8, Msb0>,
+        report: &mut AuditReport,
+    ) -> anyhow::Result<Felt> {
+        anyhow::ensure!(
+            path.len() < HEIGHT,
+            "Node {index} at height {} exceeds the tree height {HEIGHT}",
+            path.len()
+        );
+
+        let node = storage
+            .get(index)
+            .context("Resolving node")?
+            .with_context(|| format!("Node {index} at height {} is missing", path.len()))?;
+
+        let computed = match node {
+            StoredNode::Binary { left, right } => {
+                path.push(Direction::Left.into());
+                let left = Self::audit_node(left, storage, path, report)?;
+                path.pop();
+
+                path.push(Direction::Right.into());
+                let right = Self::audit_node(right, storage, path, report)?;
+                path.pop();
+
+                BinaryNode::calculate_hash::<H>(left, right)
+            }
+            StoredNode::Edge {
+                child,
+                path: edge_path,
+            } => {
+                let height = path.len();
+                path.extend_from_bitslice(&edge_path);
+                let child = Self::audit_node(child, storage, path, report)?;
+                path.truncate(height);
+
+                EdgeNode::calculate_hash::<H>(child, &edge_path)
+            }
+            StoredNode::LeafBinary => {
+                path.push(Direction::Left.into());
+                let left = storage
+                    .leaf(path)
+                    .context("Querying left leaf hash")?
+                    .context("Left leaf is missing")?;
+                path.pop();
+
+                path.push(Direction::Right.into());
+                let right = storage
+                    .leaf(path)
+                    .context("Querying right leaf hash")?
+                    .context("Right leaf is missing")?;
+                path.pop();
+
+                BinaryNode::calculate_hash::<H>(left, right)
+            }
+            StoredNode::LeafEdge { path: edge_path } => {
+                let height = path.len();
+                path.extend_from_bitslice(&edge_path);
+                let child = storage
+                    .leaf(path)
+                    .context("Querying leaf hash")?
+                    .context("Child leaf is missing")?;
+                path.truncate(height);
+
+                EdgeNode::calculate_hash::<H>(child, &edge_path)
+            }
+        };
+
+        let expected = storage
+            .hash(index)
+            .context("Querying node hash")?
+            .with_context(|| format!("Hash of node {index} is missing"))?;
+
+        report.nodes_checked += 1;
+        if expected != computed {
+            report.divergences.push(HashDivergence {
+                index,
+                expected,
+                computed,
+            });
+        }
+
+        Ok(computed)
+    }
+
+    /// Returns the keys whose values differ between the persisted trees under
+    /// `root_a` and `root_b`, in key order. `None` stands for an empty tree.
+    ///
+    /// Both trees are walked side by side and subtrees with equal hashes are
+    /// skipped, so the cost depends on the size of the difference and not on
+    /// the size of the trees.
+    pub fn diff(
+        root_a: Option<u64>,
+        storage_a: &impl Storage,
+        root_b: Option<u64>,
+        storage_b: &impl Storage,
+    ) -> anyhow::Result<Vec<BitVec<u8, Msb0>>> {
+        let mut changed = Vec::new();
+        Self::diff_subtrees(
+            root_a.map(Subtree::Stored),
+            storage_a,
+            root_b.map(Subtree::Stored),
+            storage_b,
+            &mut BitVec::new(),
+            &mut changed,
+        )?;
+
+        Ok(changed)
+    }
+
+    /// Recursively compares two subtrees at `path`, collecting the keys of the
+    /// leaves which differ.
+    fn diff_subtrees(
+        a: Option<Subtree>,
+        storage_a: &impl Storage,
+        b: Option<Subtree>,
+        storage_b: &impl Storage,
+        path: &mut BitVec<u8, Msb0>,
+        changed: &mut Vec<BitVec<u8, Msb0>>,
+    ) -> anyhow::Result<()> {
+        match (&a, &b) {
+            (None, None) => return Ok(()),
+            (Some(Subtree::Stored(a)), Some(Subtree::Stored(b))) => {
+                let a = storage_a
+                    .hash(*a)
+                    .context("Querying node hash")?
+                    .with_context(|| format!("Hash of node {a} is missing"))?;
+                let b = storage_b
+                    .hash(*b)
+                    .context("Querying node hash")?
+                    .with_context(|| format!("Hash of node {b} is missing"))?;
+                if a == b {
+                    return Ok(());
+                }
+            }
+            _ => {}
+        }
+
+        if path.len() == HEIGHT {
+            let a = match a {
+                Some(_) => storage_a.leaf(path).context("Querying leaf")?,
+                None => None,
+            };
+            let b = match b {
+                Some(_) => storage_b.leaf(path).context("Querying leaf")?,
+                None => None,
+            };
+            if a != b {
+                changed.push(path.clone());
+            }
+            return Ok(());
+        }
+
+        let [a_left, a_right] = match a {
+            Some(a) => a.children(storage_a)?,
+            None => [None, None],
+        };
+        let [b_left, b_right] = match b {
+            Some(b) => b.children(storage_b)?,
+            None => [None, None],
+        };
+
+        path.push(Direction::Left.into());
+        Self::diff_subtrees(a_left, storage_a, b_left, storage_b, path, changed)?;
+        path.pop();
+
+        path.push(Direction::Right.into());
+        Self::diff_subtrees(a_right, storage_a, b_right, storage_b, path, changed)?;
+        path.pop();
+
+        Ok(())
+    }
+
     /// Traverses from the current root towards destination node.
     /// Returns the list of nodes along the path.
     ///
@@ -881,6 +1310,225 @@ pub enum Visit {
     StopSubtree,
 }
 
+/// A persisted subtree, as seen by [`MerkleTree::diff`] which walks the tree
+/// one bit at a time.
+enum Subtree {
+    Stored(u64),
+    Leaf,
+    /// The remaining part of an edge, leading to `child`.
+    Edge {
+        path: BitVec<u8, Msb0>,
+        child: Box<Subtree>,
+    },
+}
+
+impl Subtree {
+    /// Returns the left and right children of this subtree, one level down.
+    fn children(self, storage: &impl Storage) -> anyhow::Result<[Option<Subtree>; 2]> {
+        let (path, child) = match self {
+            Subtree::Stored(index) => match storage
+                .get(index)
+                .context("Resolving node")?
+                .with_context(|| format!("Node {index} is missing"))?
+            {
+                StoredNode::Binary { left, right } => {
+                    return Ok([Some(Subtree::Stored(left)), Some(Subtree::Stored(right))])
+                }
+                StoredNode::LeafBinary => return Ok([Some(Subtree::Leaf), Some(Subtree::Leaf)]),
+                StoredNode::Edge { child, path } => (path, Box::new(Subtree::Stored(child))),
+                StoredNode::LeafEdge { path } => (path, Box::new(Subtree::Leaf)),
+            },
+            Subtree::Edge { path, child } => (path, child),
+            Subtree::Leaf => anyhow::bail!("Leaves have no children"),
+        };
+
+        let Some((direction, rest)) = path.split_first() else {
+            anyhow::bail!("Edge path is empty");
+        };
+        let direction = Direction::from(*direction);
+        let next = if rest.is_empty() {
+            *child
+        } else {
+            Subtree::Edge {
+                path: rest.to_bitvec(),
+                child,
+            }
+        };
+
+        Ok(match direction {
+            Direction::Left => [Some(next), None],
+            Direction::Right => [None, Some(next)],
+        })
+    }
+}
+
+/// An owned copy of the part of a tree being committed, see
+/// [`MerkleTree::with_parallel_commit`]. Unlike the tree itself it can be sent
+/// across threads.
+enum DirtySubtree {
+    /// A node which is already in storage.
+    Stored {
+        hash: Felt,
+        index: u64,
+    },
+    Leaf {
+        value: Felt,
+    },
+    Binary {
+        storage_index: Option<u64>,
+        left: Box<DirtySubtree>,
+        right: Box<DirtySubtree>,
+        /// The number of nodes this subtree adds to the [TrieUpdate].
+        nodes: usize,
+    },
+    Edge {
+        storage_index: Option<u64>,
+        path: BitVec<u8, Msb0>,
+        child: Box<DirtySubtree>,
+        /// The number of nodes this subtree adds to the [TrieUpdate].
+        nodes: usize,
+    },
+}
+
+/// A [DirtySubtree] which has been hashed.
+struct CommittedSubtree {
+    hash: Felt,
+    node: Option<NodeRef>,
+    added: Vec<(Felt, Node)>,
+    removed: Vec<u64>,
+}
+
+impl DirtySubtree {
+    /// Subtrees adding fewer nodes than this are not worth splitting across
+    /// threads.
+    const PARALLEL_MIN_NODES: usize = 64;
+
+    fn nodes(&self) -> usize {
+        match self {
+            DirtySubtree::Stored { .. } | DirtySubtree::Leaf { .. } => 0,
+            DirtySubtree::Binary { nodes, .. } | DirtySubtree::Edge { nodes, .. } => *nodes,
+        }
+    }
+
+    /// Hashes the subtree the same way as [`MerkleTree::commit_subtree`], with
+    /// its new nodes numbered from `offset` onwards.
+    fn commit<H: FeltHash>(self, offset: usize) -> anyhow::Result<CommittedSubtree> {
+        let committed = match self {
+            DirtySubtree::Stored { hash, index } => CommittedSubtree {
+                hash,
+                node: Some(NodeRef::StorageIndex(index)),
+                added: Vec::new(),
+                removed: Vec::new(),
+            },
+            DirtySubtree::Leaf { value } => CommittedSubtree {
+                hash: value,
+                node: None,
+                added: Vec::new(),
+                removed: Vec::new(),
+            },
+            DirtySubtree::Binary {
+                storage_index,
+                left,
+                right,
+                nodes,
+            } => {
+                let right_offset = offset + left.nodes();
+                let commit_left = move || left.commit::<H>(offset);
+                let commit_right = move || right.commit::<H>(right_offset);
+                let (left, right) = if nodes >= Self::PARALLEL_MIN_NODES {
+                    rayon::join(commit_left, commit_right)
+                } else {
+                    (commit_left(), commit_right())
+                };
+                let (mut left, right) = (left?, right?);
+
+                let hash = BinaryNode::calculate_hash::<H>(left.hash, right.hash);
+                let persisted_node = match (left.node, right.node) {
+                    (None, None) => Node::LeafBinary,
+                    (Some(_), None) | (None, Some(_)) => {
+                        anyhow::bail!(
+                            "Inconsistent binary children. Both children must be leaves or not \
+                             leaves."
+                        )
+                    }
+                    (Some(left), Some(right)) => Node::Binary { left, right },
+                };
+
+                left.added.extend(right.added);
+                left.removed.extend(right.removed);
+                if let Some(storage_index) = storage_index {
+                    left.removed.push(storage_index);
+                }
+
+                let node_index = offset + left.added.len();
+                left.added.push((hash, persisted_node));
+
+                CommittedSubtree {
+                    hash,
+                    node: Some(NodeRef::Index(node_index)),
+                    added: left.added,
+                    removed: left.removed,
+                }
+            }
+            DirtySubtree::Edge {
+                storage_index,
+                path,
+                child,
+                nodes: _,
+            } => {
+                let mut child = child.commit::<H>(offset)?;
+
+                let hash = EdgeNode::calculate_hash::<H>(child.hash, &path);
+                let persisted_node = match child.node {
+                    None => Node::LeafEdge { path },
+                    Some(child) => Node::Edge { child, path },
+                };
+
+                let node_index = offset + child.added.len();
+                child.added.push((hash, persisted_node));
+                if let Some(storage_index) = storage_index {
+                    child.removed.push(storage_index);
+                }
+
+                CommittedSubtree {
+                    hash,
+                    node: Some(NodeRef::Index(node_index)),
+                    added: child.added,
+                    removed: child.removed,
+                }
+            }
+        };
+
+        Ok(committed)
+    }
+}
+
+/// The outcome of a [`MerkleTree::audit`].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct AuditReport {
+    /// The number of persisted nodes whose hash was recomputed.
+    pub nodes_checked: usize,
+    /// All nodes whose persisted hash differs from the recomputed one.
+    pub divergences: Vec<HashDivergence>,
+}
+
+impl AuditReport {
+    pub fn is_ok(&self) -> bool {
+        self.divergences.is_empty()
+    }
+}
+
+/// A persisted node whose hash does not match its recomputed hash.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HashDivergence {
+    /// Storage index of the node.
+    pub index: u64,
+    /// The hash persisted for the node.
+    pub expected: Felt,
+    /// The hash recomputed from the node's subtree.
+    pub computed: Felt,
+}
+
 #[cfg(test)]
 mod tests {
     use bitvec::prelude::*;
@@ -1038,6 +1686,44 @@ mod tests {
 
             assert_eq!(uut.get(&storage, key).unwrap(), Some(new_value));
         }
+
+        #[test]
+        fn batch_matches_individual_updates() {
+            let mut storage = TestStorage::default();
+
+            let mut uut = TestTree::empty();
+            for key in [0x1, 0x5, 0x99cadc82] {
+                uut.set(
+                    &storage,
+                    Felt::from_u64(key).view_bits().to_bitvec(),
+                    felt!("0x1"),
+                )
+                .unwrap();
+            }
+            let (_, root) = commit_and_persist_with_pruning(uut, &mut storage);
+
+            // Unsorted, with an overwritten key and a deleted one.
+            let updates = [
+                (felt!("0x901823"), felt!("0x2")),
+                (felt!("0x1"), felt!("0x3")),
+                (felt!("0x8975"), felt!("0x4")),
+                (felt!("0x5"), Felt::ZERO),
+                (felt!("0x1"), felt!("0x5")),
+            ]
+            .map(|(key, value)| (key.view_bits().to_bitvec(), value));
+
+            let mut individual = TestTree::new(root);
+            for (key, value) in updates.clone() {
+                individual.set(&storage, key, value).unwrap();
+            }
+            let expected = individual.commit(&storage).unwrap().root_commitment;
+
+            let mut batch = TestTree::new(root);
+            batch.set_batch(&storage, updates).unwrap();
+            let actual = batch.commit(&storage).unwrap().root_commitment;
+
+            assert_eq!(actual, expected);
+        }
     }
 
     mod tree_state {
@@ -1341,6 +2027,229 @@ mod tests {
             assert_eq!(root.0, Felt::ZERO);
             assert!(storage.nodes.is_empty());
         }
+
+        #[test]
+        fn root_hash_matches_the_committed_root() {
+            let mut storage = TestStorage::default();
+            assert_eq!(TestTree::empty().root_hash(&storage).unwrap(), Felt::ZERO);
+
+            let mut tree = TestTree::empty();
+            for (key, value) in [
+                (felt!("0x1"), felt!("0x1")),
+                (felt!("0x2"), felt!("0x2")),
+                (felt!("0x99"), felt!("0x3")),
+            ] {
+                tree.set(&storage, key.view_bits().to_bitvec(), value)
+                    .unwrap();
+            }
+            let expected = tree.root_hash(&storage).unwrap();
+            let root = commit_and_persist_without_pruning(tree, &mut storage);
+            assert_eq!(expected, root.0);
+
+            // Unmodified trees preview the stored root.
+            let tree = TestTree::new(root.1);
+            assert_eq!(tree.root_hash(&storage).unwrap(), root.0);
+
+            // Changes on top of stored nodes, including a deletion.
+            let mut tree = TestTree::new(root.1);
+            tree.set(&storage, felt!("0x2").view_bits().to_bitvec(), Felt::ZERO)
+                .unwrap();
+            tree.set(&storage, felt!("0x3").view_bits().to_bitvec(), felt!("0x4"))
+                .unwrap();
+            let expected = tree.root_hash(&storage).unwrap();
+            let root = commit_and_persist_without_pruning(tree, &mut storage);
+            assert_eq!(expected, root.0);
+        }
+    }
+
+    mod parallel_commit {
+        use proptest::prelude::*;
+
+        use super::*;
+
+        fn set_all(tree: &mut TestTree, storage: &TestStorage, leaves: &[(u64, u64)]) {
+            for (key, value) in leaves {
+                tree.set(
+                    storage,
+                    Felt::from_u64(*key).view_bits().to_bitvec(),
+                    Felt::from_u64(*value),
+                )
+                .unwrap();
+            }
+        }
+
+        proptest! {
+            #![proptest_config(ProptestConfig::with_cases(50))]
+            #[test]
+            fn matches_sequential_commit(
+                initial in prop::collection::vec((0..2048u64, 1..4u64), 0..300),
+                // Zero values delete leaves.
+                updates in prop::collection::vec((0..2048u64, 0..4u64), 0..300),
+            ) {
+                let mut storage = TestStorage::default();
+                let root = (!initial.is_empty()).then(|| {
+                    let mut tree = TestTree::empty();
+                    set_all(&mut tree, &storage, &initial);
+                    commit_and_persist_without_pruning(tree, &mut storage).1
+                });
+
+                let load = |parallel_commit| {
+                    let tree = match root {
+                        Some(root) => TestTree::new(root),
+                        None => TestTree::empty(),
+                    };
+                    tree.with_parallel_commit(parallel_commit)
+                };
+                let mut sequential = load(false);
+                set_all(&mut sequential, &storage, &updates);
+                let mut parallel = load(true);
+                set_all(&mut parallel, &storage, &updates);
+
+                let sequential = sequential.commit(&storage).unwrap();
+                let parallel = parallel.commit(&storage).unwrap();
+                prop_assert_eq!(format!("{parallel:?}"), format!("{sequential:?}"));
+            }
+        }
+    }
+
+    mod diff {
+        use super::*;
+
+        /// Persists a tree with the given leaves in its own storage.
+        fn persist(leaves: &[(u64, u64)]) -> (Option<u64>, TestStorage) {
+            let mut uut = TestTree::empty();
+            let mut storage = TestStorage::default();
+            for (key, value) in leaves {
+                uut.set(
+                    &storage,
+                    Felt::from_u64(*key).view_bits().to_bitvec(),
+                    Felt::from_u64(*value),
+                )
+                .unwrap();
+            }
+
+            if leaves.is_empty() {
+                return (None, storage);
+            }
+            let (_, root) = commit_and_persist_with_pruning(uut, &mut storage);
+            (Some(root), storage)
+        }
+
+        fn diff(a: &[(u64, u64)], b: &[(u64, u64)]) -> Vec<Felt> {
+            let (root_a, storage_a) = persist(a);
+            let (root_b, storage_b) = persist(b);
+
+            TestTree::diff(root_a, &storage_a, root_b, &storage_b)
+                .unwrap()
+                .into_iter()
+                .map(|key| Felt::from_bits(&key).unwrap())
+                .collect()
+        }
+
+        fn keys(keys: &[u64]) -> Vec<Felt> {
+            keys.iter().copied().map(Felt::from_u64).collect()
+        }
+
+        const LEAVES: [(u64, u64); 4] = [(0x1, 1), (0x2, 2), (0x5, 5), (0x99cadc82, 3)];
+
+        #[test]
+        fn identical_trees() {
+            assert_eq!(diff(&LEAVES, &LEAVES), keys(&[]));
+        }
+
+        #[test]
+        fn changed_added_and_removed_leaves() {
+            let b = [(0x1, 1), (0x2, 7), (0x8975, 4), (0x99cadc82, 3)];
+
+            assert_eq!(diff(&LEAVES, &b), keys(&[0x2, 0x5, 0x8975]));
+            assert_eq!(diff(&b, &LEAVES), keys(&[0x2, 0x5, 0x8975]));
+        }
+
+        #[test]
+        fn empty_tree() {
+            assert_eq!(diff(&[], &LEAVES), keys(&[0x1, 0x2, 0x5, 0x99cadc82]));
+            assert_eq!(diff(&LEAVES, &[]), keys(&[0x1, 0x2, 0x5, 0x99cadc82]));
+            assert_eq!(diff(&[], &[]), keys(&[]));
+        }
+    }
+
+    mod audit {
+        use super::*;
+
+        fn populated_storage() -> (TestStorage, u64) {
+            let mut tree = TestTree::empty();
+            let mut storage = TestStorage::default();
+
+            for key in [
+                felt!("0x1"),
+                felt!("0x2"),
+                felt!("0x3"),
+                felt!("0x99cadc82"),
+            ] {
+                tree.set(&storage, key.view_bits().to_bitvec(), felt!("0xabc"))
+                    .unwrap();
+            }
+            let (_, root_idx) = commit_and_persist_with_pruning(tree, &mut storage);
+
+            (storage, root_idx)
+        }
+
+        #[test]
+        fn intact_tree() {
+            let (storage, root_idx) = populated_storage();
+
+            let report = TestTree::audit(root_idx, &storage).unwrap();
+
+            assert!(report.is_ok());
+            assert_eq!(report.nodes_checked, storage.nodes.len());
+        }
+
+        #[test]
+        fn corrupted_node_hash() {
+            let (mut storage, root_idx) = populated_storage();
+
+            let corrupted = *storage.nodes.keys().find(|idx| **idx != root_idx).unwrap();
+            let (hash, _) = storage.nodes.get_mut(&corrupted).unwrap();
+            let computed = *hash;
+            *hash = felt!("0xdead");
+
+            let report = TestTree::audit(root_idx, &storage).unwrap();
+
+            // Parents are hashed using the recomputed hash, so only the corrupted
+            // node itself diverges.
+            assert_eq!(
+                report.divergences,
+                vec![HashDivergence {
+                    index: corrupted,
+                    expected: felt!("0xdead"),
+                    computed,
+                }]
+            );
+            assert_eq!(report.nodes_checked, storage.nodes.len());
+        }
+
+        #[test]
+        fn corrupted_leaf_diverges_up_to_the_root() {
+            let (mut storage, root_idx) = populated_storage();
+
+            storage.leaves.insert(felt!("0x99cadc82"), felt!("0xdead"));
+
+            let report = TestTree::audit(root_idx, &storage).unwrap();
+
+            assert!(!report.is_ok());
+            assert!(report.divergences.iter().any(|d| d.index == root_idx));
+            assert_eq!(report.nodes_checked, storage.nodes.len());
+        }
+
+        #[test]
+        fn missing_node_is_an_error() {
+            let (mut storage, root_idx) = populated_storage();
+
+            let missing = *storage.nodes.keys().find(|idx| **idx != root_idx).unwrap();
+            storage.nodes.remove(&missing);
+
+            TestTree::audit(root_idx, &storage).unwrap_err();
+        }
     }
 
     mod persistence {
@@ -1753,6 +2662,29 @@ mod tests {
             assert!(tx.class_root_exists(block_number).unwrap());
             assert_eq!(tx.class_root_index(block_number).unwrap(), None);
         }
+
+        #[test]
+        fn class_leaves() {
+            let mut db = pathfinder_storage::StorageBuilder::in_memory()
+                .unwrap()
+                .connection()
+                .unwrap();
+            let tx = db.transaction().unwrap();
+
+            let mut uut = crate::class::ClassCommitmentTree::empty(&tx);
+            let leaves = vec![
+                (sierra_hash!("0x1"), class_commitment_leaf_hash!("0x11")),
+                (sierra_hash!("0x2"), class_commitment_leaf_hash!("0x22")),
+                (
+                    sierra_hash!("0xdeadbeef"),
+                    class_commitment_leaf_hash!("0x33"),
+                ),
+            ];
+            uut.set_batch(leaves.iter().rev().copied()).unwrap();
+
+            let actual = uut.iter_leaves().unwrap().collect::<Vec<_>>();
+            assert_eq!(actual, leaves);
+        }
     }
 
     mod dfs {
@@ -2368,6 +3300,134 @@ mod tests {
                 });
         }
 
+        #[test]
+        fn non_membership_ends_at_divergent_edge() {
+            let mut uut = TestTree::empty();
+            let mut storage = TestStorage::default();
+
+            // A single leaf, the tree is an edge from the root to it.
+            let key = felt!("0x1").view_bits().to_owned();
+            let value = felt!("0x2");
+            uut.set(&storage, key.clone(), value).unwrap();
+            let (root, root_idx) = commit_and_persist_with_pruning(uut, &mut storage);
+
+            let absent = felt!("0x3").view_bits().to_owned();
+            let proof = TestTree::get_proof(root_idx, &storage, &absent)
+                .unwrap()
+                .unwrap();
+
+            assert_eq!(
+                proof,
+                vec![TrieNode::Edge {
+                    child: value,
+                    path: key
+                }]
+            );
+            assert_eq!(
+                verify_proof(root, &absent, value, &proof),
+                Some(Membership::NonMember)
+            );
+        }
+
+        #[test]
+        fn batch_proofs_match_individual_proofs() {
+            let random_tree = RandomTree::new(64);
+            let absent = gen_random_hashes(16);
+
+            let keys = random_tree
+                .keys
+                .iter()
+                .chain(&absent)
+                .map(|key| key.view_bits())
+                .collect::<Vec<_>>();
+
+            let expected = keys
+                .iter()
+                .map(|key| {
+                    TestTree::get_proof(random_tree.root_idx, &random_tree.storage, key).unwrap()
+                })
+                .collect::<Vec<_>>();
+            let actual =
+                TestTree::get_proofs(random_tree.root_idx, &random_tree.storage, &keys).unwrap();
+
+            assert_eq!(actual, expected);
+        }
+
+        #[test]
+        fn verify_proof_of_membership() {
+            let random_tree = RandomTree::new(16);
+
+            for (key, value) in random_tree.keys.iter().zip(&random_tree.values) {
+                let key = key.view_bits();
+                let proof = TestTree::get_proof(random_tree.root_idx, &random_tree.storage, key)
+                    .unwrap()
+                    .unwrap();
+
+                assert!(TestTree::verify_proof(
+                    random_tree.root,
+                    key,
+                    *value,
+                    &proof
+                ));
+                assert!(!TestTree::verify_proof(
+                    random_tree.root,
+                    key,
+                    *value + Felt::ONE,
+                    &proof
+                ));
+                assert!(!TestTree::verify_proof(
+                    random_tree.root,
+                    key,
+                    Felt::ZERO,
+                    &proof
+                ));
+                assert!(!TestTree::verify_proof(
+                    random_tree.root + Felt::ONE,
+                    key,
+                    *value,
+                    &proof
+                ));
+            }
+        }
+
+        #[test]
+        fn verify_proof_of_non_membership() {
+            let random_tree = RandomTree::new(16);
+            let keys = random_tree
+                .keys
+                .iter()
+                .collect::<std::collections::HashSet<_>>();
+
+            for absent in gen_random_hashes(16)
+                .iter()
+                .filter(|key| !keys.contains(key))
+            {
+                let key = absent.view_bits();
+                let proof = TestTree::get_proof(random_tree.root_idx, &random_tree.storage, key)
+                    .unwrap()
+                    .unwrap();
+
+                assert!(TestTree::verify_proof(
+                    random_tree.root,
+                    key,
+                    Felt::ZERO,
+                    &proof
+                ));
+                assert!(!TestTree::verify_proof(
+                    random_tree.root,
+                    key,
+                    Felt::ONE,
+                    &proof
+                ));
+            }
+
+            // An empty tree proves that no key exists.
+            let key = felt!("0x1");
+            let key = key.view_bits();
+            assert!(TestTree::verify_proof(Felt::ZERO, key, Felt::ZERO, &[]));
+            assert!(!TestTree::verify_proof(Felt::ONE, key, Felt::ZERO, &[]));
+        }
+
         #[test]
         fn invalid_values() {
             const LEN: usize = 256;
@@ -471,6 +471,11 @@ impl Behaviour {
         Ok(())
     }
 
+    pub fn unsubscribe_topic(&mut self, topic: &IdentTopic) -> anyhow::Result<()> {
+        self.inner.gossipsub.unsubscribe(topic)?;
+        Ok(())
+    }
+
     /// Notify the behaviour of a ping event.
     pub fn pinged(&mut self, event: ping::Event) {
         match event.result {
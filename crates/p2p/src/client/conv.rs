@@ -25,6 +25,7 @@ use pathfinder_common::class_definition::{
     SelectorAndFunctionIndex,
     SelectorAndOffset,
     Sierra,
+    SierraEntryPoints,
 };
 use pathfinder_common::event::Event;
 use pathfinder_common::receipt::{
@@ -78,6 +79,8 @@ use pathfinder_crypto::Felt;
 use serde::{Deserialize, Serialize};
 use serde_json::value::RawValue;
 
+use crate::client::types::SierraInterface;
+
 /// Convert a pathfinder common (ie. core) type to a p2p dto type
 pub trait ToDto<T> {
     fn to_dto(self) -> T;
@@ -89,12 +92,43 @@ pub trait FromDto<T> {
 }
 
 /// Fallible version of [`FromDto`]
+///
+/// Data introduced by a newer version of the protocol fails the conversion
+/// with an [`UnsupportedProtocolVersion`] error, any other error means that
+/// the data is malformed.
 pub trait TryFromDto<T> {
     fn try_from_dto(dto: T) -> anyhow::Result<Self>
     where
         Self: Sized;
 }
 
+/// Data which this node cannot handle because it was introduced by a newer
+/// version of the protocol, such as a new kind of transaction.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct UnsupportedProtocolVersion(pub String);
+
+impl UnsupportedProtocolVersion {
+    /// Returns the unsupported data if it is the cause of `error`.
+    pub fn from_conversion_error(error: &anyhow::Error) -> Option<Self> {
+        error.downcast_ref::<Self>().cloned()
+    }
+
+    /// Returns the unsupported data if it is the cause of the response stream
+    /// error `error`, see [`p2p_proto`] for the variants which can not be
+    /// decoded.
+    pub fn from_response_error(error: &std::io::Error) -> Option<Self> {
+        (error.kind() == std::io::ErrorKind::Unsupported).then(|| Self(error.to_string()))
+    }
+}
+
+impl std::fmt::Display for UnsupportedProtocolVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Unsupported protocol version: {}", self.0)
+    }
+}
+
+impl std::error::Error for UnsupportedProtocolVersion {}
+
 impl ToDto<p2p_proto::header::SignedBlockHeader> for SignedBlockHeader {
     fn to_dto(self) -> p2p_proto::header::SignedBlockHeader {
         use p2p_proto::header as proto;
@@ -787,15 +821,21 @@ impl TryFromDto<p2p_proto::transaction::ResourceBounds> for ResourceBounds {
     }
 }
 
+impl FromDto<p2p_proto::common::VolitionDomain> for DataAvailabilityMode {
+    fn from_dto(dto: p2p_proto::common::VolitionDomain) -> Self {
+        match dto {
+            p2p_proto::common::VolitionDomain::L1 => Self::L1,
+            p2p_proto::common::VolitionDomain::L2 => Self::L2,
+        }
+    }
+}
+
 impl TryFromDto<p2p_proto::common::VolitionDomain> for DataAvailabilityMode {
     fn try_from_dto(dto: p2p_proto::common::VolitionDomain) -> anyhow::Result<Self>
     where
         Self: Sized,
     {
-        Ok(match dto {
-            p2p_proto::common::VolitionDomain::L1 => Self::L1,
-            p2p_proto::common::VolitionDomain::L2 => Self::L2,
-        })
+        Ok(Self::from_dto(dto))
     }
 }
 
@@ -909,41 +949,11 @@ impl TryFromDto<p2p_proto::class::Cairo0Class> for CairoDefinition {
     }
 }
 
-pub struct SierraDefinition(pub Vec<u8>);
+/// A Sierra class definition serialized to JSON, along with its interface.
+pub struct SierraDefinition(pub Vec<u8>, pub SierraInterface);
 
 impl TryFromDto<p2p_proto::class::Cairo1Class> for SierraDefinition {
     fn try_from_dto(dto: p2p_proto::class::Cairo1Class) -> anyhow::Result<Self> {
-        #[derive(Debug, Serialize)]
-        pub struct SelectorAndFunctionIndex {
-            pub selector: EntryPoint,
-            pub function_idx: u64,
-        }
-
-        #[derive(Debug, Serialize)]
-        pub struct SierraEntryPoints {
-            #[serde(rename = "EXTERNAL")]
-            pub external: Vec<SelectorAndFunctionIndex>,
-            #[serde(rename = "L1_HANDLER")]
-            pub l1_handler: Vec<SelectorAndFunctionIndex>,
-            #[serde(rename = "CONSTRUCTOR")]
-            pub constructor: Vec<SelectorAndFunctionIndex>,
-        }
-
-        #[derive(Debug, Serialize)]
-        pub struct Sierra<'a> {
-            /// Contract ABI.
-            pub abi: Cow<'a, str>,
-
-            /// Main program definition.
-            pub sierra_program: Vec<Felt>,
-
-            // Version
-            pub contract_class_version: Cow<'a, str>,
-
-            /// The contract entry points
-            pub entry_points_by_type: SierraEntryPoints,
-        }
-
         let from_dto = |x: Vec<p2p_proto::class::SierraEntryPoint>| {
             x.into_iter()
                 .map(|e| SelectorAndFunctionIndex {
@@ -953,24 +963,25 @@ impl TryFromDto<p2p_proto::class::Cairo1Class> for SierraDefinition {
                 .collect::<Vec<_>>()
         };
 
-        let entry_points = SierraEntryPoints {
-            external: from_dto(dto.entry_points.externals),
-            l1_handler: from_dto(dto.entry_points.l1_handlers),
-            constructor: from_dto(dto.entry_points.constructors),
+        let interface = SierraInterface {
+            entry_points_by_type: SierraEntryPoints {
+                external: from_dto(dto.entry_points.externals),
+                l1_handler: from_dto(dto.entry_points.l1_handlers),
+                constructor: from_dto(dto.entry_points.constructors),
+            },
+            abi: dto.abi,
         };
-        let program = dto.program;
-        let contract_class_version = dto.contract_class_version;
 
         let sierra = Sierra {
-            abi: dto.abi.into(),
-            sierra_program: program,
-            contract_class_version: contract_class_version.into(),
-            entry_points_by_type: entry_points,
+            abi: Cow::Borrowed(&interface.abi),
+            sierra_program: dto.program,
+            contract_class_version: dto.contract_class_version.into(),
+            entry_points_by_type: interface.entry_points_by_type.clone(),
         };
 
         let sierra = serde_json::to_vec(&sierra).context("serialize sierra class definition")?;
 
-        Ok(Self(sierra))
+        Ok(Self(sierra, interface))
     }
 }
 
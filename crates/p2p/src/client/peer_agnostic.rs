@@ -1,11 +1,13 @@
 //! _High level_ client for p2p interaction.
 //! Frees the caller from managing peers manually.
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::future::Future;
+use std::num::{NonZeroU64, NonZeroUsize};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
 use futures::channel::mpsc as fmpsc;
+use futures::stream::{BoxStream, FuturesUnordered, PollNext};
 use futures::{Stream, StreamExt, TryStreamExt};
 use libp2p::PeerId;
 use p2p_proto::class::{ClassesRequest, ClassesResponse};
@@ -20,10 +22,19 @@ use p2p_proto::state::{
     StateDiffsResponse,
 };
 use p2p_proto::transaction::{TransactionWithReceipt, TransactionsRequest, TransactionsResponse};
+use p2p_proto::TryFromProtobuf;
+use p2p_stream::ResponseReceiver;
 use pathfinder_common::event::Event;
-use pathfinder_common::state_update::{ContractClassUpdate, StateUpdateData};
-use pathfinder_common::transaction::TransactionVariant;
+use pathfinder_common::state_update::{
+    ContractClassUpdate,
+    ContractUpdate,
+    StateUpdateData,
+    SystemContractUpdate,
+};
+use pathfinder_common::transaction::{DataAvailabilityMode, TransactionVariant};
 use pathfinder_common::{
+    BlockHash,
+    BlockHeader,
     BlockNumber,
     CasmHash,
     ClassHash,
@@ -36,16 +47,24 @@ use pathfinder_common::{
     TransactionHash,
     TransactionIndex,
 };
-use tokio::sync::{mpsc, RwLock};
+use pathfinder_crypto::Felt;
+use tokio::sync::{mpsc, OwnedSemaphorePermit, RwLock, Semaphore};
 use tokio_stream::wrappers::ReceiverStream;
+use tokio_util::sync::CancellationToken;
 
+mod coalesce;
 #[cfg(test)]
 mod fixtures;
+mod metrics;
+pub mod peer_store;
 #[cfg(test)]
 mod tests;
 pub mod traits;
 
+use coalesce::{coalesce, coalesce_stream, InflightRequests};
+use peer_store::{PeerRecord, PeerStore};
 use traits::{
+    AttributedStreamItem,
     BlockClient,
     ClassStream,
     EventStream,
@@ -55,33 +74,665 @@ use traits::{
     TransactionStream,
 };
 
-use crate::client::conv::{CairoDefinition, FromDto, SierraDefinition, TryFromDto};
+use crate::client::conv::{
+    CairoDefinition,
+    FromDto,
+    SierraDefinition,
+    ToDto,
+    TryFromDto,
+    UnsupportedProtocolVersion,
+};
 use crate::client::peer_aware;
 use crate::client::types::{
+    Agreement,
     ClassDefinition,
     ClassDefinitionsError,
+    ClassFilter,
+    ClassHasher,
+    ConversionError,
+    EventCommitmentVerifier,
+    EventCountLookup,
     EventsForBlockByTransaction,
     EventsResponseStreamFailure,
+    FullBlock,
     Receipt,
+    StateDiffChunk,
+    StateDiffCommitmentLookup,
     StateDiffsError,
+    StreamError,
+    TransactionCommitmentVerifier,
     TransactionData,
 };
 use crate::peer_data::PeerData;
+use crate::GossipMessage;
 
 #[derive(Clone, Debug)]
 pub struct Client {
     inner: peer_aware::Client,
     block_propagation_topic: Arc<String>,
-    peers: Arc<RwLock<Decaying<HashSet<PeerId>>>>,
+    peers: Arc<RwLock<PeerState>>,
+    /// Held while the known peers are refreshed from the DHT, so that
+    /// concurrent callers wait for a single refresh instead of starting their
+    /// own.
+    peer_refresh: Arc<tokio::sync::Mutex<()>>,
+    buffers: StreamBuffers,
+    refresh_after_exhaustions: NonZeroUsize,
+    max_failed_rounds: Option<NonZeroUsize>,
+    min_throughput: Option<f64>,
+    response_timeout: Duration,
+    max_request_limit: NonZeroU64,
+    block_request_concurrency: NonZeroUsize,
+    verify_parent_hashes: bool,
+    min_peers: NonZeroUsize,
+    connected_peers_fallback: bool,
+    cancellation: CancellationToken,
+    inflight: Arc<InflightRequests>,
+    transaction_verifier: Option<TransactionCommitmentVerifier>,
+    state_diff_commitments: Option<StateDiffCommitmentLookup>,
+    event_verifier: Option<EventCommitmentVerifier>,
+    event_counts: Option<EventCountLookup>,
+    slow_responses: Option<(Duration, NonZeroUsize)>,
+    /// Heads waiting to be propagated along with their block numbers, if
+    /// propagation is debounced.
+    pending_heads: Option<mpsc::UnboundedSender<(u64, p2p_proto::header::NewBlock)>>,
+    /// Peer asked first by the [`BlockClient`] methods.
+    peer_hint: Option<PeerId>,
+    /// Bounds the number of concurrently running stream tasks, if set.
+    stream_permits: Option<Arc<Semaphore>>,
+    /// Orders the peers instead of the thread local RNG, if set.
+    rng: Option<Arc<std::sync::Mutex<rand::rngs::StdRng>>>,
+    item_limits: BlockItemLimits,
+    /// Receives the data which failed to parse, see
+    /// [`Client::with_conversion_errors`].
+    conversion_errors: Option<fmpsc::UnboundedSender<PeerData<ConversionError>>>,
+}
+
+/// Peer related state shared by all clones of a [`Client`].
+#[derive(Debug, Default)]
+struct PeerState {
+    known: Decaying<HashSet<PeerId>>,
+    /// Encoded size of all the sync responses received from each peer.
+    bytes_received: HashMap<PeerId, u64>,
+    scores: HashMap<PeerId, PeerScore>,
+    /// Peers which are never used, regardless of the DHT results.
+    blacklist: HashSet<PeerId>,
+    /// Peers which are tried first if connected, in order of preference.
+    preferred: Vec<PeerId>,
+    /// The connected peers as of the last failed DHT query which found any,
+    /// see [`Client::with_connected_peers_fallback`].
+    recently_connected: HashSet<PeerId>,
+    /// Where the scores are saved one last time when the state is dropped, see
+    /// [`Client::with_peer_store`].
+    store: Option<Arc<dyn PeerStore>>,
+}
+
+/// Tracks how often a peer provided a valid block, and how often it failed to
+/// do so, i.e. sent malformed data, too much or too little data, or was too
+/// slow. Both counts decay over time so that peers are judged by their recent
+/// behavior.
+#[derive(Clone, Copy, Debug)]
+struct PeerScore {
+    successes: f64,
+    failures: f64,
+    last_update: Instant,
+}
+
+impl PeerScore {
+    /// Time after which the counts are down to half of their value.
+    const HALF_LIFE: Duration = Duration::from_secs(10 * 60);
+    /// A single failure outweighs this many successes.
+    const FAILURE_WEIGHT: f64 = 10.0;
+    /// Score difference that makes a peer `e` times more likely to be picked
+    /// first.
+    const SELECTION_SCALE: f64 = 10.0;
+
+    fn new() -> Self {
+        Self {
+            successes: 0.0,
+            failures: 0.0,
+            last_update: Instant::now(),
+        }
+    }
+
+    fn decayed(&self) -> Self {
+        let half_lives = self.last_update.elapsed().as_secs_f64() / Self::HALF_LIFE.as_secs_f64();
+        let factor = 0.5_f64.powf(half_lives);
+        Self {
+            successes: self.successes * factor,
+            failures: self.failures * factor,
+            last_update: Instant::now(),
+        }
+    }
+
+    /// Picks up where the score was left off before a restart.
+    fn from_record(record: PeerRecord) -> Self {
+        Self {
+            successes: record.successes,
+            failures: record.failures,
+            last_update: Instant::now(),
+        }
+    }
+
+    fn to_record(self) -> PeerRecord {
+        let decayed = self.decayed();
+        PeerRecord {
+            successes: decayed.successes,
+            failures: decayed.failures,
+        }
+    }
+
+    fn record_success(&mut self) {
+        *self = self.decayed();
+        self.successes += 1.0;
+    }
+
+    fn record_failure(&mut self) {
+        *self = self.decayed();
+        self.failures += 1.0;
+    }
+
+    /// Positive for peers which mostly provide valid blocks, negative for
+    /// peers which mostly fail to.
+    fn value(&self) -> f64 {
+        let decayed = self.decayed();
+        decayed.successes - Self::FAILURE_WEIGHT * decayed.failures
+    }
+
+    /// Relative likelihood of the peer being picked first.
+    fn selection_weight(&self) -> f64 {
+        (self.value() / Self::SELECTION_SCALE)
+            .clamp(-50.0, 50.0)
+            .exp()
+    }
+}
+
+impl PeerState {
+    fn score(&self, peer: &PeerId) -> f64 {
+        self.scores
+            .get(peer)
+            .map(PeerScore::value)
+            .unwrap_or_default()
+    }
+
+    fn selection_weight(&self, peer: &PeerId) -> f64 {
+        self.scores
+            .get(peer)
+            .map(PeerScore::selection_weight)
+            .unwrap_or(1.0)
+    }
+
+    fn score_records(&self) -> HashMap<PeerId, PeerRecord> {
+        self.scores
+            .iter()
+            .map(|(peer, score)| (*peer, score.to_record()))
+            .collect()
+    }
+}
+
+impl Drop for PeerState {
+    /// Saves the scores recorded since the last periodic save. This blocks,
+    /// but only once the last clone of the client is dropped.
+    fn drop(&mut self) {
+        if let Some(store) = self.store.take() {
+            if let Err(error) = store.save(&self.score_records()) {
+                tracing::warn!("Failed to save peer scores: {error:#}");
+            }
+        }
+    }
+}
+
+/// Capacities of the channels between the tasks driving the sync streams and
+/// the consumers of those streams, per stream type.
+///
+/// A capacity is the number of stream items that can be buffered ahead of the
+/// consumer, so the worst case memory use of a stream is roughly its capacity
+/// times the size of its largest item. An item is a single header, the
+/// transactions or the state diff or the events of an entire block, or a
+/// single class definition.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StreamBuffers {
+    pub header_buffer: NonZeroUsize,
+    pub transaction_buffer: NonZeroUsize,
+    pub state_diff_buffer: NonZeroUsize,
+    /// Class definitions can be several megabytes each, so keep this small
+    /// unless memory is not a concern.
+    pub class_buffer: NonZeroUsize,
+    pub event_buffer: NonZeroUsize,
+}
+
+impl StreamBuffers {
+    /// Uses the same capacity for all the streams. Keep in mind that class
+    /// definitions are much larger than the items of the other streams.
+    pub fn uniform(buffer: NonZeroUsize) -> Self {
+        Self {
+            header_buffer: buffer,
+            transaction_buffer: buffer,
+            state_diff_buffer: buffer,
+            class_buffer: buffer,
+            event_buffer: buffer,
+        }
+    }
+}
+
+impl Default for StreamBuffers {
+    fn default() -> Self {
+        Self {
+            header_buffer: NonZeroUsize::new(16).expect("16>0"),
+            transaction_buffer: NonZeroUsize::MIN,
+            state_diff_buffer: NonZeroUsize::MIN,
+            class_buffer: NonZeroUsize::MIN,
+            event_buffer: NonZeroUsize::MIN,
+        }
+    }
+}
+
+/// Upper bounds on the number of items a single block may contain, per stream
+/// type.
+///
+/// The sync streams allocate and collect as many items as the block header
+/// advertises, so a bogus header could otherwise make them exhaust memory.
+/// Headers which exceed these bounds are rejected as invalid, and the item
+/// streams refuse to collect a block whose expected count exceeds them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BlockItemLimits {
+    pub max_transactions: usize,
+    pub max_events: usize,
+    pub max_state_diff_length: usize,
+    pub max_classes: usize,
+}
+
+impl BlockItemLimits {
+    /// Fails `counts` at the first count above `max`, before the items of that
+    /// block are requested.
+    fn cap<S>(counts: S, max: usize) -> impl Stream<Item = anyhow::Result<usize>>
+    where
+        S: Stream<Item = anyhow::Result<usize>>,
+    {
+        counts.map(move |count| {
+            let count = count?;
+            anyhow::ensure!(
+                count <= max,
+                "Block item count {count} exceeds the limit of {max}"
+            );
+            Ok(count)
+        })
+    }
+
+    /// Whether the counts advertised by `header` are within the limits.
+    fn admit(&self, header: &BlockHeader) -> bool {
+        header.transaction_count <= self.max_transactions
+            && header.event_count <= self.max_events
+            && header.state_diff_length <= self.max_state_diff_length as u64
+    }
+}
+
+impl Default for BlockItemLimits {
+    /// Orders of magnitude above anything seen on Starknet so far.
+    fn default() -> Self {
+        Self {
+            max_transactions: 100_000,
+            max_events: 1_000_000,
+            max_state_diff_length: 1_000_000,
+            max_classes: 10_000,
+        }
+    }
 }
 
 impl Client {
+    const DEFAULT_RESPONSE_TIMEOUT: Duration = Duration::from_secs(10);
+    /// Number of DHT queries after which the client settles for fewer peers
+    /// than `min_peers`.
+    const MIN_PEERS_ATTEMPTS: usize = 5;
+
     pub fn new(inner: peer_aware::Client, block_propagation_topic: String) -> Self {
         Self {
             inner,
             block_propagation_topic: Arc::new(block_propagation_topic),
             peers: Default::default(),
+            peer_refresh: Default::default(),
+            buffers: Default::default(),
+            refresh_after_exhaustions: NonZeroUsize::MIN,
+            max_failed_rounds: None,
+            min_throughput: None,
+            response_timeout: Self::DEFAULT_RESPONSE_TIMEOUT,
+            max_request_limit: NonZeroU64::new(DEFAULT_MAX_REQUEST_LIMIT).expect("500>0"),
+            block_request_concurrency: NonZeroUsize::MIN,
+            verify_parent_hashes: false,
+            min_peers: NonZeroUsize::MIN,
+            connected_peers_fallback: false,
+            cancellation: CancellationToken::new(),
+            inflight: Default::default(),
+            transaction_verifier: None,
+            state_diff_commitments: None,
+            event_verifier: None,
+            event_counts: None,
+            slow_responses: None,
+            pending_heads: None,
+            peer_hint: None,
+            stream_permits: None,
+            rng: None,
+            item_limits: Default::default(),
+            conversion_errors: None,
+        }
+    }
+
+    /// Overrides the default [`StreamBuffers`].
+    pub fn with_stream_buffers(mut self, buffers: StreamBuffers) -> Self {
+        self.buffers = buffers;
+        self
+    }
+
+    /// Overrides the default [`BlockItemLimits`].
+    pub fn with_block_item_limits(mut self, limits: BlockItemLimits) -> Self {
+        self.item_limits = limits;
+        self
+    }
+
+    /// By default the sync streams query for a fresh set of peers each time
+    /// they run out of peers to try. Setting this to `n` makes each stream
+    /// snapshot the peer set once and go through it `n` times before querying
+    /// for a new one, which keeps long syncs from oscillating between peer
+    /// sets while the DHT results churn.
+    pub fn with_refresh_after_exhaustions(
+        mut self,
+        refresh_after_exhaustions: NonZeroUsize,
+    ) -> Self {
+        self.refresh_after_exhaustions = refresh_after_exhaustions;
+        self
+    }
+
+    /// Makes the sync streams give up once they went through their peer set
+    /// `rounds` times in a row without receiving a single block, in which case
+    /// the last item of the stream is a [`StreamError`]. By default the
+    /// streams keep retrying until they are dropped.
+    pub fn with_max_failed_rounds(mut self, rounds: NonZeroUsize) -> Self {
+        self.max_failed_rounds = Some(rounds);
+        self
+    }
+
+    /// Makes the transaction, state diff, class and event streams abandon a
+    /// peer once its average rate of responses for the block being received
+    /// drops below `items_per_sec`. This catches peers which respond just fast
+    /// enough not to time out, but would otherwise stall the sync.
+    pub fn with_min_throughput(mut self, items_per_sec: f64) -> Self {
+        self.min_throughput = Some(items_per_sec);
+        self
+    }
+
+    /// Makes the sync streams give up on a peer, and move on to the next one,
+    /// if it does not send the next response within `timeout`. The default
+    /// is 10 seconds.
+    pub fn with_response_timeout(mut self, timeout: Duration) -> Self {
+        self.response_timeout = timeout;
+        self
+    }
+
+    /// Limits the number of blocks the sync streams request from a peer at
+    /// once, the default is 500. Longer ranges are split into windows of this
+    /// size which are requested one after another, each from the next peer in
+    /// the round, so a failing peer only costs the window it was asked for.
+    pub fn with_max_request_limit(mut self, limit: NonZeroU64) -> Self {
+        self.max_request_limit = limit;
+        self
+    }
+
+    /// Makes the [`BlockClient`] methods of this client ask `peer` on its own
+    /// first, for example because it advertised the block, and only fall back
+    /// to randomly selected peers if it fails. A request for a block which is
+    /// already in flight still shares the result of that request.
+    pub fn with_peer_hint(mut self, peer: PeerId) -> Self {
+        self.peer_hint = Some(peer);
+        self
+    }
+
+    /// Makes the sync streams move on to the next peer once `consecutive`
+    /// responses in a row each took longer than `threshold` to arrive, even
+    /// though none of them timed out. Progress on the current block is rolled
+    /// back, as when a peer fails. Disabled by default.
+    ///
+    /// Only the time spent waiting for the peer counts, time spent waiting for
+    /// the consumer of the stream does not, as switching peers would not make
+    /// up for a slow consumer.
+    pub fn with_slow_response_rotation(
+        mut self,
+        threshold: Duration,
+        consecutive: NonZeroUsize,
+    ) -> Self {
+        self.slow_responses = Some((threshold, consecutive));
+        self
+    }
+
+    /// How long the set of peers obtained from the DHT is reused before it is
+    /// queried again. The default is 60 seconds.
+    ///
+    /// This starts over with an empty peer cache, but keeps the peer scores.
+    pub fn with_peer_cache_timeout(self, timeout: Duration) -> Self {
+        self.peers
+            .try_write()
+            .expect("Peer state is not in use while building the client")
+            .known = Decaying::new(timeout);
+        self
+    }
+
+    /// Seeds the peer scores from `store`, so that the sync streams prefer
+    /// peers which were reliable before a restart, and saves the scores back
+    /// to it every `interval` for as long as a clone of the client is alive,
+    /// and once more when the last clone is dropped. Scores don't decay while
+    /// the node is offline.
+    ///
+    /// Has to be called within a tokio runtime, as it spawns the task saving
+    /// the scores.
+    pub fn with_peer_store(self, store: Arc<dyn PeerStore>, interval: Duration) -> Self {
+        {
+            let mut peers = self
+                .peers
+                .try_write()
+                .expect("Peer state is not in use while building the client");
+            match store.load() {
+                Ok(records) => peers.scores.extend(
+                    records
+                        .into_iter()
+                        .map(|(peer, record)| (peer, PeerScore::from_record(record))),
+                ),
+                Err(error) => tracing::warn!("Failed to load peer scores: {error:#}"),
+            }
+            peers.store = Some(store.clone());
         }
+
+        tokio::spawn(save_peer_scores(
+            Arc::downgrade(&self.peers),
+            store,
+            interval,
+        ));
+        self
+    }
+
+    /// Makes the client query the DHT a few more times if it finds fewer than
+    /// `min_peers` peers, and warn if it still can't find enough. By default
+    /// a single peer is enough.
+    pub fn with_min_peers(mut self, min_peers: NonZeroUsize) -> Self {
+        self.min_peers = min_peers;
+        self
+    }
+
+    /// Makes the client use the recently connected peers as the known peers
+    /// while querying the DHT fails, instead of waiting for the DHT to
+    /// recover.
+    pub fn with_connected_peers_fallback(mut self) -> Self {
+        self.connected_peers_fallback = true;
+        self
+    }
+
+    /// Cancelling `token` stops the tasks driving all the sync streams
+    /// created by this client, which otherwise keep making requests until
+    /// they are done even if the streams are dropped. Use a
+    /// [child token](CancellationToken::child_token) per clone of the client
+    /// to cancel a subset of the streams.
+    pub fn with_cancellation_token(mut self, token: CancellationToken) -> Self {
+        self.cancellation = token;
+        self
+    }
+
+    /// Limits the number of streams of this client and its clones which
+    /// request data from peers at the same time. Streams started beyond the
+    /// limit wait for one of the running streams to end or be dropped before
+    /// making any requests. A [full block stream](Self::full_block_stream)
+    /// counts as a single stream. Unlimited by default.
+    pub fn with_max_concurrent_streams(mut self, streams: NonZeroUsize) -> Self {
+        self.stream_permits = Some(Arc::new(Semaphore::new(streams.get())));
+        self
+    }
+
+    /// Orders the peers using an RNG seeded with `seed`, shared by the clones
+    /// of this client, so that the order in which peers are tried is
+    /// reproducible. Intended for tests, by default the order is random.
+    pub fn with_rng_seed(mut self, seed: u64) -> Self {
+        use rand::SeedableRng;
+
+        self.rng = Some(Arc::new(std::sync::Mutex::new(
+            rand::rngs::StdRng::seed_from_u64(seed),
+        )));
+        self
+    }
+
+    /// Number of peers the [`BlockClient`] methods query concurrently for a
+    /// single block. The first valid response wins and the other requests
+    /// are dropped. The default is 1, i.e. the peers are queried one after
+    /// another.
+    ///
+    /// Racing `n` peers cuts the latency of a block down to that of the
+    /// fastest of them, and a failing peer no longer delays the next one, but
+    /// up to `n` times the data may be downloaded for a single block.
+    pub fn with_block_request_concurrency(mut self, concurrency: NonZeroUsize) -> Self {
+        self.block_request_concurrency = concurrency;
+        self
+    }
+
+    /// Makes the header streams check that each header links to the previous
+    /// one via its parent hash, and move on to the next peer otherwise. Only
+    /// applies to streams which do not skip blocks. Disabled by default.
+    pub fn with_parent_hash_verification(mut self, enabled: bool) -> Self {
+        self.verify_parent_hashes = enabled;
+        self
+    }
+
+    /// Enables verifying the transaction commitment of each block inside the
+    /// transaction stream, if requested via `verify_commitments`.
+    pub fn with_transaction_commitment_verifier(
+        mut self,
+        verifier: TransactionCommitmentVerifier,
+    ) -> Self {
+        self.transaction_verifier = Some(verifier);
+        self
+    }
+
+    /// Enables verifying the state diff commitment of each block inside the
+    /// state diff stream, if requested via `verify_commitments`.
+    pub fn with_state_diff_commitment_lookup(
+        mut self,
+        commitments: StateDiffCommitmentLookup,
+    ) -> Self {
+        self.state_diff_commitments = Some(commitments);
+        self
+    }
+
+    /// Enables verifying the event commitment of each block inside the event
+    /// stream, if requested via `verify_commitments`.
+    pub fn with_event_commitment_verifier(mut self, verifier: EventCommitmentVerifier) -> Self {
+        self.event_verifier = Some(verifier);
+        self
+    }
+
+    /// Makes the event stream check the total number of events received for
+    /// each block against the `event_count` from its header, which is
+    /// authenticated for all Starknet versions. Useful when the counts passed
+    /// to the event stream come from a less trusted source than `counts`.
+    pub fn with_event_count_lookup(mut self, counts: EventCountLookup) -> Self {
+        self.event_counts = Some(counts);
+        self
+    }
+
+    /// Makes [`Client::propagate_new_head`] and
+    /// [`Client::propagate_new_header`] hold on to a head for `window` and
+    /// only publish the highest of the heads passed to them in the meantime,
+    /// which keeps the block propagation topic from being flooded
+    /// while catching up. By default each head is published right away.
+    ///
+    /// Spawns the task holding the pending head, so it has to be called
+    /// within a tokio runtime.
+    pub fn with_head_propagation_debounce(mut self, window: Duration) -> Self {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let inner = self.inner.clone();
+        let topic = self.block_propagation_topic.clone();
+        tokio::spawn(debounce_heads(window, rx, move |new_block| {
+            let inner = inner.clone();
+            let topic = topic.clone();
+            async move { inner.publish(&topic, new_block).await }
+        }));
+        self.pending_heads = Some(tx);
+        self
+    }
+
+    fn stream_config(&self, name: &'static str, buffer: NonZeroUsize) -> StreamConfig {
+        StreamConfig {
+            name,
+            buffer,
+            refresh_after_exhaustions: self.refresh_after_exhaustions,
+            max_failed_rounds: self.max_failed_rounds,
+            min_throughput: self.min_throughput,
+            response_timeout: self.response_timeout,
+            max_request_limit: self.max_request_limit,
+            peers: self.peers.clone(),
+            cancellation: self.cancellation.clone(),
+            permits: self.stream_permits.clone(),
+            item_limits: self.item_limits,
+            conversion_errors: self.conversion_errors.clone(),
+        }
+    }
+
+    /// Runs the sync stream created by `make`, e.g.
+    /// `client.with_conversion_errors(|c| c.header_stream(start, stop, false,
+    /// None))`, such that data which fails to parse is not only skipped but
+    /// also yielded, along with the peer which sent it. The stream still moves
+    /// on to the next peer as usual.
+    ///
+    /// Such data is yielded as soon as it is received, so it may overtake
+    /// items which were received before it but not yet consumed.
+    pub fn with_conversion_errors<T, S>(
+        &self,
+        make: impl FnOnce(Client) -> S,
+    ) -> impl Stream<Item = AttributedStreamItem<T>>
+    where
+        S: Stream<Item = StreamItem<T>>,
+    {
+        let (sender, errors) = fmpsc::unbounded();
+        let client = Client {
+            conversion_errors: Some(sender),
+            ..self.clone()
+        };
+
+        let items = make(client).map_ok(|PeerData { peer, data }| PeerData::new(peer, Ok(data)));
+        let errors = errors.map(|PeerData { peer, data }| Ok(PeerData::new(peer, Err(data))));
+        // The errors end once the stream and thus all the senders are gone.
+        futures::stream::select_with_strategy(errors, items, |_: &mut ()| PollNext::Left)
+    }
+
+    /// Subscribes to the gossip `topic`, in addition to the block propagation
+    /// topic, and yields the messages received on it along with the peer
+    /// which propagated them. Messages are dropped while the consumer of the
+    /// stream falls behind.
+    pub async fn subscribe_topic(
+        &self,
+        topic: &str,
+    ) -> anyhow::Result<impl Stream<Item = (PeerId, GossipMessage)>> {
+        let messages = self.inner.subscribe_topic_messages(topic).await?;
+        Ok(ReceiverStream::new(messages))
+    }
+
+    /// Stops receiving the messages on `topic`, which ends all the streams
+    /// returned by [`Client::subscribe_topic`] for it.
+    pub async fn unsubscribe_topic(&self, topic: &str) -> anyhow::Result<()> {
+        self.inner.unsubscribe_topic(topic).await
     }
 
     // Propagate new L2 head head
@@ -93,65 +744,347 @@ impl Client {
             "Propagating head"
         );
 
+        self.publish_head(block_id.number, p2p_proto::header::NewBlock::Id(block_id))
+            .await
+    }
+
+    /// Yields the heads announced on the block propagation topic, once the
+    /// announcing peer serves a header with the announced hash for the
+    /// announced number. Announcements of blocks no higher than `tip` or the
+    /// last head yielded are ignored, so each block number is yielded at most
+    /// once, as are announcements with a zero hash or a number above
+    /// `i64::MAX`.
+    ///
+    /// The announcements are still emitted as
+    /// [`Event::BlockPropagation`](crate::Event::BlockPropagation).
+    pub async fn new_head_stream(
+        &self,
+        tip: Option<BlockNumber>,
+    ) -> anyhow::Result<impl Stream<Item = (PeerId, p2p_proto::common::BlockId)>> {
+        let messages = Box::pin(self.subscribe_topic(&self.block_propagation_topic).await?);
+        let client = self.clone();
+
+        let heads = futures::stream::unfold(
+            (messages, client, tip),
+            |(mut messages, client, mut tip)| async move {
+                while let Some((peer, message)) = messages.next().await {
+                    let Some(id) = announced_block(&message.data) else {
+                        tracing::debug!(%peer, "Malformed block propagation message");
+                        continue;
+                    };
+                    let Some(number) = BlockNumber::new(id.number) else {
+                        tracing::debug!(%peer, number=%id.number, "Announced block number out of range");
+                        continue;
+                    };
+                    if id.hash.0 == Felt::ZERO {
+                        tracing::debug!(%peer, %number, "Announced block with zero hash");
+                        continue;
+                    }
+                    if tip.is_some_and(|tip| number <= tip) {
+                        continue;
+                    }
+
+                    match client.header_for_block_from_peer(peer, number).await {
+                        Ok(header) if header.header.hash.0 == id.hash.0 => {
+                            tip = Some(number);
+                            return Some(((peer, id), (messages, client, tip)));
+                        }
+                        Ok(_) => {
+                            tracing::debug!(%peer, %number, "Announced block hash does not match the header");
+                        }
+                        Err(error) => {
+                            tracing::debug!(%peer, %number, %error, "Failed to fetch the announced header");
+                        }
+                    }
+                }
+                None
+            },
+        );
+
+        Ok(heads)
+    }
+
+    /// Propagates the full header of a new L2 head, so that peers can start
+    /// validating it without requesting it first.
+    pub async fn propagate_new_header(
+        &self,
+        signed_header: SignedBlockHeader,
+    ) -> anyhow::Result<()> {
+        tracing::debug!(number=%signed_header.header.number, hash=%signed_header.header.hash.0, topic=%self.block_propagation_topic,
+            "Propagating header"
+        );
+
+        let number = signed_header.header.number.get();
+        let header = BlockHeadersResponse::Header(Box::new(signed_header.to_dto()));
+        self.publish_head(number, p2p_proto::header::NewBlock::Header(header))
+            .await
+    }
+
+    /// Publishes `new_block` right away, or hands it over to the task
+    /// debouncing the propagation of heads.
+    async fn publish_head(
+        &self,
+        number: u64,
+        new_block: p2p_proto::header::NewBlock,
+    ) -> anyhow::Result<()> {
+        if let Some(pending_heads) = &self.pending_heads {
+            return pending_heads
+                .send((number, new_block))
+                .map_err(|_| anyhow::anyhow!("Head propagation task terminated"));
+        }
+
         self.inner
-            .publish(
-                &self.block_propagation_topic,
-                p2p_proto::header::NewBlock::Id(block_id),
-            )
+            .publish(&self.block_propagation_topic, new_block)
             .await
     }
 
     async fn get_random_peers(&self) -> Vec<PeerId> {
         use rand::seq::SliceRandom;
 
-        let r = self.peers.read().await;
-        let mut peers = if let Some(peers) = r.get() {
-            peers.iter().copied().collect::<Vec<_>>()
-        } else {
-            // Avoid deadlock
-            drop(r);
-            let mut w = self.peers.write().await;
-            // Check again because the previous lock in the queue might have been a write
-            // lock that has already updated the peers.
-            if let Some(peers) = w.get() {
-                return peers.iter().copied().collect::<Vec<_>>();
+        let preferred = self.connected_preferred_peers().await;
+
+        let known = self.peers.read().await.known.get().cloned();
+        let mut peers = match known {
+            Some(peers) => peers.into_iter().collect::<Vec<_>>(),
+            None => self.refresh_known_peers().await,
+        };
+
+        let state = self.peers.read().await;
+        peers.retain(|peer| !state.blacklist.contains(peer) && !preferred.contains(peer));
+
+        // Preferred peers which recently failed to provide valid blocks lose
+        // their spot at the front.
+        let (preferred, demoted): (Vec<_>, Vec<_>) = preferred
+            .into_iter()
+            .partition(|peer| state.score(peer) >= 0.0);
+        peers.extend(demoted);
+        // The known peers are unordered, a seeded RNG needs a stable order to
+        // start from.
+        peers.sort();
+
+        let mut seeded;
+        let mut thread_rng;
+        let rng: &mut dyn rand::RngCore = match &self.rng {
+            Some(rng) => {
+                seeded = rng.lock().expect("Peer RNG lock is not poisoned");
+                &mut *seeded
+            }
+            None => {
+                thread_rng = rand::thread_rng();
+                &mut thread_rng
             }
+        };
 
-            // TODO known peers abstraction should not poll
-            //
-            // Loop until we find at least a single peer.
-            // 1. After the process is spawned the first outgoing query may start earlier
-            //    than the `kad` protocol is pushed in from `identify/push` resulting in a
-            //    `kind: ConnectionRefused, error: "protocol not supported"` error.
-            // 2. Initially there may be no other peers but maybe we're running a local test
-            //    and the other peer pops up in a few seconds.
-            // Either way we don't want to wait for the bootstrap timeout or the
-            // `Decaying::DEFAULT_TIMEOUT`, whichever kicks in first.
-            let peers = loop {
-                let mut peers = self
-                    .inner
-                    .get_closest_peers(PeerId::random())
-                    .await
-                    .unwrap_or_default();
-                // We could be on the list
-                peers.remove(self.inner.peer_id());
+        // Peers with a higher score are more likely to be tried first, but
+        // every peer still gets a chance.
+        let shuffled = peers
+            .choose_multiple_weighted(rng, peers.len(), |peer| state.selection_weight(peer))
+            .expect("weights are positive and finite")
+            .copied();
 
-                if peers.is_empty() {
-                    tracing::info!("No peers found in DHT, retrying");
-                    tokio::time::sleep(Duration::from_secs(3)).await;
-                } else {
-                    break peers;
+        preferred.into_iter().chain(shuffled).collect()
+    }
+
+    /// Queries the DHT for peers and stores them as the known peers. Only one
+    /// query runs at a time, callers arriving during a query get its result.
+    async fn refresh_known_peers(&self) -> Vec<PeerId> {
+        let _refresh = self.peer_refresh.lock().await;
+        // The peers may have been refreshed while waiting for the lock.
+        if let Some(peers) = self.peers.read().await.known.get() {
+            return peers.iter().copied().collect();
+        }
+
+        // TODO known peers abstraction should not poll
+        //
+        // Loop until we find at least a single peer.
+        // 1. After the process is spawned the first outgoing query may start earlier
+        //    than the `kad` protocol is pushed in from `identify/push` resulting in a
+        //    `kind: ConnectionRefused, error: "protocol not supported"` error.
+        // 2. Initially there may be no other peers but maybe we're running a local test
+        //    and the other peer pops up in a few seconds.
+        // Either way we don't want to wait for the bootstrap timeout or the
+        // `Decaying::DEFAULT_TIMEOUT`, whichever kicks in first.
+        //
+        // Below `min_peers` the DHT is queried a few more times, but then we make do
+        // with the peers we have.
+        let mut peers = HashSet::new();
+        let mut attempts = 0;
+        let peers = loop {
+            let mut found = match self.inner.get_closest_peers(PeerId::random()).await {
+                Ok(found) => found,
+                Err(error) => {
+                    tracing::warn!(%error, "Querying DHT for peers failed");
+                    if self.connected_peers_fallback {
+                        self.recently_connected_peers().await
+                    } else {
+                        HashSet::new()
+                    }
                 }
             };
+            // We could be on the list
+            found.remove(self.inner.peer_id());
+            peers.extend(found);
+
+            if peers.is_empty() {
+                tracing::info!("No peers found in DHT, retrying");
+                tokio::time::sleep(Duration::from_secs(3)).await;
+                continue;
+            }
+
+            if peers.len() >= self.min_peers.get() {
+                break peers;
+            }
 
-            let peers_vec = peers.iter().copied().collect::<Vec<_>>();
+            attempts += 1;
+            if attempts == Self::MIN_PEERS_ATTEMPTS {
+                tracing::warn!(found=%peers.len(), minimum=%self.min_peers, "Fewer peers than the configured minimum found in DHT");
+                break peers;
+            }
+
+            tracing::debug!(found=%peers.len(), minimum=%self.min_peers, "Too few peers found in DHT, retrying");
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        };
+
+        let peers_vec = peers.iter().copied().collect::<Vec<_>>();
+        self.peers.write().await.known.update(peers);
+        peers_vec
+    }
+
+    /// Caches the currently connected peers, unless there are none, and
+    /// returns the cached peers. Only used once querying the DHT failed. The
+    /// cache outlives the connections so that repeated DHT failures during a
+    /// short loss of connectivity still leave peers to try.
+    async fn recently_connected_peers(&self) -> HashSet<PeerId> {
+        let connected = self.inner.connected_peers().await;
+        let mut state = self.peers.write().await;
+        if !connected.is_empty() {
+            state.recently_connected = connected;
+        }
+        state.recently_connected.clone()
+    }
 
-            w.update(peers);
-            peers_vec
+    /// The preferred peers which are currently connected and not
+    /// blacklisted, in order of preference.
+    async fn connected_preferred_peers(&self) -> Vec<PeerId> {
+        let preferred = {
+            let state = self.peers.read().await;
+            state
+                .preferred
+                .iter()
+                .filter(|peer| !state.blacklist.contains(peer))
+                .copied()
+                .collect::<Vec<_>>()
         };
-        peers.shuffle(&mut rand::thread_rng());
+        if preferred.is_empty() {
+            return preferred;
+        }
+
+        let connected = self.inner.connected_peers().await;
+        preferred
+            .into_iter()
+            .filter(|peer| connected.contains(peer))
+            .collect()
+    }
+
+    /// Makes the sync streams of this client and its clones try `peers`
+    /// before the peers found in the DHT, in the given order, from their next
+    /// peer set on. Only connected peers are tried, and peers which recently
+    /// failed to provide valid blocks are not tried first until their score
+    /// recovers. Replaces the previously set peers.
+    pub async fn set_preferred_peers(&self, peers: Vec<PeerId>) {
+        self.peers.write().await.preferred = peers;
+    }
+
+    /// Stops the sync streams of this client and its clones from using `peer`
+    /// from their next peer set on, even if the DHT keeps returning it.
+    pub async fn blacklist_peer(&self, peer: PeerId) {
+        self.peers.write().await.blacklist.insert(peer);
+    }
+
+    /// Undoes [`Client::blacklist_peer`].
+    pub async fn unblacklist_peer(&self, peer: PeerId) {
+        self.peers.write().await.blacklist.remove(&peer);
+    }
+
+    /// The current score of `peer`, based on the blocks it recently provided
+    /// or failed to provide to the sync streams, and on the invalid class
+    /// definitions it sent to [`BlockClient::class_definitions_for_block`].
+    /// Unknown peers score zero.
+    pub async fn peer_score(&self, peer: PeerId) -> f64 {
+        self.peers.read().await.score(&peer)
+    }
+
+    /// Records that `peer` sent invalid data in response to a single block
+    /// request.
+    async fn penalize(&self, peer: PeerId) {
+        self.peers
+            .write()
+            .await
+            .scores
+            .entry(peer)
+            .or_insert_with(PeerScore::new)
+            .record_failure();
+    }
 
-        peers
+    /// Total number of bytes of sync responses received from `peer` by any of
+    /// the streams of this client or its clones.
+    pub async fn peer_bytes_received(&self, peer: PeerId) -> u64 {
+        self.peers
+            .read()
+            .await
+            .bytes_received
+            .get(&peer)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Adds the bytes read from the wire for each response to the bytes
+    /// received from `peer` as the responses are consumed, and records the
+    /// [metrics](metrics) of the responses of `stream`. `requested_at` is
+    /// when the request was sent.
+    ///
+    /// The responses end early if `peer` is consistently slow, see
+    /// [`Client::with_slow_response_rotation`].
+    fn count_bytes_received<R>(
+        &self,
+        peer: PeerId,
+        stream: &'static str,
+        requested_at: Instant,
+        responses: ResponseReceiver<R>,
+    ) -> BoxStream<'static, std::io::Result<R>>
+    where
+        R: Send + 'static,
+    {
+        // The codec counts the bytes as it reads them, so each response is
+        // attributed the bytes read since the previous one.
+        let responses =
+            futures::stream::unfold((responses, 0), |(mut responses, counted)| async move {
+                let response = responses.next().await?;
+                let received = responses.bytes_received();
+                Some(((response, received - counted), (responses, received)))
+            })
+            .boxed();
+        let responses = match self.slow_responses {
+            Some((threshold, consecutive)) => {
+                end_when_slow(peer, responses, threshold, consecutive).boxed()
+            }
+            None => responses.boxed(),
+        };
+        let peers = self.peers.clone();
+        let mut first = true;
+        responses
+            .then(move |(response, len)| {
+                let peers = peers.clone();
+                if std::mem::take(&mut first) {
+                    metrics::record_first_response(peer, stream, requested_at.elapsed());
+                }
+                metrics::record_response(peer, stream, len);
+                async move {
+                    *peers.write().await.bytes_received.entry(peer).or_default() += len;
+                    response
+                }
+            })
+            .boxed()
     }
 }
 
@@ -161,20 +1094,32 @@ impl HeaderStream for Client {
         start: BlockNumber,
         stop: BlockNumber,
         reverse: bool,
-    ) -> impl Stream<Item = PeerData<SignedBlockHeader>> {
-        let inner = self.inner.clone();
+        step: Option<NonZeroU64>,
+    ) -> impl Stream<Item = StreamItem<SignedBlockHeader>> {
+        let requester = self.clone();
+        let config = self.stream_config("headers", self.buffers.header_buffer);
         let outer = self;
         header_stream::make(
             start,
             stop,
             reverse,
+            step.unwrap_or(NonZeroU64::MIN),
+            outer.verify_parent_hashes,
+            config,
             move || {
                 let outer = outer.clone();
                 async move { outer.get_random_peers().await }
             },
             move |peer, request| {
-                let inner = inner.clone();
-                async move { inner.send_headers_sync_request(peer, request).await }
+                let requester = requester.clone();
+                async move {
+                    let requested_at = Instant::now();
+                    let responses = requester
+                        .inner
+                        .send_headers_sync_request(peer, request)
+                        .await?;
+                    Ok(requester.count_bytes_received(peer, "headers", requested_at, responses))
+                }
             },
         )
     }
@@ -185,21 +1130,51 @@ impl TransactionStream for Client {
         self,
         start: BlockNumber,
         stop: BlockNumber,
+        reverse: bool,
+        step: Option<NonZeroU64>,
+        verify_commitments: bool,
         transaction_count_stream: impl Stream<Item = anyhow::Result<usize>> + Send + 'static,
-    ) -> impl Stream<Item = StreamItem<(TransactionData, BlockNumber)>> {
-        let inner = self.inner.clone();
+    ) -> impl Stream<Item = StreamItem<(TransactionData, BlockNumber)>> + Send {
+        let requester = self.clone();
+        let config = self.stream_config("transactions", self.buffers.transaction_buffer);
+        let verifier = match (verify_commitments, &self.transaction_verifier) {
+            (true, Some(verifier)) => Some(verifier.clone()),
+            (true, None) => {
+                tracing::warn!(
+                    "Transaction commitment verification requested but no verifier configured"
+                );
+                None
+            }
+            (false, _) => None,
+        };
         let outer = self;
         transaction_stream::make(
             start,
             stop,
+            reverse,
+            step.unwrap_or(NonZeroU64::MIN),
             transaction_count_stream,
+            verifier,
+            config,
             move || {
                 let outer = outer.clone();
                 async move { outer.get_random_peers().await }
             },
             move |peer, request| {
-                let inner = inner.clone();
-                async move { inner.send_transactions_sync_request(peer, request).await }
+                let requester = requester.clone();
+                async move {
+                    let requested_at = Instant::now();
+                    let responses = requester
+                        .inner
+                        .send_transactions_sync_request(peer, request)
+                        .await?;
+                    Ok(requester.count_bytes_received(
+                        peer,
+                        "transactions",
+                        requested_at,
+                        responses,
+                    ))
+                }
             },
         )
     }
@@ -215,21 +1190,54 @@ impl StateDiffStream for Client {
         self,
         start: BlockNumber,
         stop: BlockNumber,
+        reverse: bool,
+        step: Option<NonZeroU64>,
+        verify_commitments: bool,
         state_diff_length_stream: impl Stream<Item = anyhow::Result<usize>> + Send + 'static,
-    ) -> impl Stream<Item = StreamItem<(StateUpdateData, BlockNumber)>> {
-        let inner = self.inner.clone();
+    ) -> impl Stream<Item = StreamItem<(StateUpdateData, BlockNumber)>> + Send {
+        let requester = self.clone();
+        let config = self.stream_config("state_diffs", self.buffers.state_diff_buffer);
+        let commitments = match (verify_commitments, &self.state_diff_commitments) {
+            (true, Some(commitments)) => Some(commitments.clone()),
+            (true, None) => {
+                tracing::warn!(
+                    "State diff commitment verification requested but no commitment lookup \
+                     configured"
+                );
+                None
+            }
+            (false, _) => None,
+        };
         let outer = self;
         state_diff_stream::make(
             start,
             stop,
+            reverse,
+            step.unwrap_or(NonZeroU64::MIN),
             state_diff_length_stream,
+            commitments,
+            config,
             move || {
                 let outer = outer.clone();
                 async move { outer.get_random_peers().await }
             },
             move |peer, request| {
-                let inner = inner.clone();
-                async move { inner.send_state_diffs_sync_request(peer, request).await }
+                let requester = requester.clone();
+                async move {
+                    let requested_at = Instant::now();
+                    let responses = requester
+                        .inner
+                        .send_state_diffs_sync_request(peer, request)
+                        .await?;
+                    Ok(
+                        requester.count_bytes_received(
+                            peer,
+                            "state_diffs",
+                            requested_at,
+                            responses,
+                        ),
+                    )
+                }
             },
         )
     }
@@ -240,21 +1248,36 @@ impl ClassStream for Client {
         self,
         start: BlockNumber,
         stop: BlockNumber,
+        reverse: bool,
+        step: Option<NonZeroU64>,
         declared_class_counts_stream: impl Stream<Item = anyhow::Result<usize>> + Send + 'static,
-    ) -> impl Stream<Item = StreamItem<ClassDefinition>> {
-        let inner = self.inner.clone();
+        filter: ClassFilter,
+    ) -> impl Stream<Item = StreamItem<ClassDefinition>> + Send {
+        let requester = self.clone();
+        let config = self.stream_config("classes", self.buffers.class_buffer);
         let outer = self;
         class_definition_stream::make(
             start,
             stop,
+            reverse,
+            step.unwrap_or(NonZeroU64::MIN),
             declared_class_counts_stream,
+            filter,
+            config,
             move || {
                 let outer = outer.clone();
                 async move { outer.get_random_peers().await }
             },
             move |peer, request| {
-                let inner = inner.clone();
-                async move { inner.send_classes_sync_request(peer, request).await }
+                let requester = requester.clone();
+                async move {
+                    let requested_at = Instant::now();
+                    let responses = requester
+                        .inner
+                        .send_classes_sync_request(peer, request)
+                        .await?;
+                    Ok(requester.count_bytes_received(peer, "classes", requested_at, responses))
+                }
             },
         )
     }
@@ -268,31 +1291,922 @@ impl EventStream for Client {
     /// commitment is part of block hash. However the number of events per
     /// transaction for __pre 0.13.2__ Starknet blocks is __TRUSTED__
     /// because neither signature nor block hash contain this information.
+    ///
+    /// `event_counts_stream` is expected to yield the `event_count` from the
+    /// header of each block. The total number of events received for a block
+    /// is checked against it, which holds for all Starknet versions.
     fn event_stream(
         self,
         start: BlockNumber,
         stop: BlockNumber,
+        reverse: bool,
+        step: Option<NonZeroU64>,
+        verify_commitments: bool,
         event_counts_stream: impl Stream<Item = anyhow::Result<usize>> + Send + 'static,
-    ) -> impl Stream<Item = StreamItem<EventsForBlockByTransaction>> {
-        let inner = self.inner.clone();
+    ) -> impl Stream<Item = StreamItem<EventsForBlockByTransaction>> + Send {
+        let requester = self.clone();
+        let config = self.stream_config("events", self.buffers.event_buffer);
+        let verifier = match (verify_commitments, &self.event_verifier) {
+            (true, Some(verifier)) => Some(verifier.clone()),
+            (true, None) => {
+                tracing::warn!(
+                    "Event commitment verification requested but no verifier configured"
+                );
+                None
+            }
+            (false, _) => None,
+        };
         let outer = self;
         event_stream::make(
             start,
             stop,
+            reverse,
+            step.unwrap_or(NonZeroU64::MIN),
             event_counts_stream,
+            verifier,
+            self.event_counts.clone(),
+            config,
             move || {
                 let outer = outer.clone();
                 async move { outer.get_random_peers().await }
             },
             move |peer, request| {
-                let inner = inner.clone();
-                async move { inner.send_events_sync_request(peer, request).await }
+                let requester = requester.clone();
+                async move {
+                    let requested_at = Instant::now();
+                    let responses = requester
+                        .inner
+                        .send_events_sync_request(peer, request)
+                        .await?;
+                    Ok(requester.count_bytes_received(peer, "events", requested_at, responses))
+                }
             },
         )
     }
 }
 
+impl Client {
+    /// Measures the round-trip latency to up to `n` peers picked the same way
+    /// the sync streams pick them, by requesting the genesis header which
+    /// every peer can serve cheaply. The latency is the time until the first
+    /// response arrives.
+    ///
+    /// Peers which fail or don't respond within the response timeout are left
+    /// out. The result is sorted by latency, fastest first.
+    pub async fn probe_peers(&self, n: usize) -> Vec<(PeerId, Duration)> {
+        let mut peers = self.get_random_peers().await;
+        peers.truncate(n);
+
+        let mut probes = peers
+            .into_iter()
+            .map(|peer| async move {
+                let request = BlockHeadersRequest {
+                    iteration: Iteration {
+                        start: BlockNumber::GENESIS.get().into(),
+                        direction: Direction::Forward,
+                        limit: 1,
+                        step: 1.into(),
+                    },
+                };
+                let requested_at = Instant::now();
+                let response = tokio::time::timeout(self.response_timeout, async {
+                    let mut responses = self.inner.send_headers_sync_request(peer, request).await?;
+                    match responses.next().await {
+                        Some(Ok(_)) => Ok(requested_at.elapsed()),
+                        Some(Err(error)) => Err(anyhow::Error::from(error)),
+                        None => anyhow::bail!("Stream ended without a response"),
+                    }
+                })
+                .await;
+
+                match response {
+                    Ok(Ok(latency)) => Some((peer, latency)),
+                    Ok(Err(error)) => {
+                        tracing::debug!(%peer, %error, "Probe failed");
+                        None
+                    }
+                    Err(_) => {
+                        tracing::debug!(%peer, timeout=?self.response_timeout, "Probe timed out");
+                        None
+                    }
+                }
+            })
+            .collect::<FuturesUnordered<_>>();
+
+        let mut latencies = Vec::new();
+        while let Some(probe) = probes.next().await {
+            latencies.extend(probe);
+        }
+        latencies.sort_by_key(|(_, latency)| *latency);
+        latencies
+    }
+
+    /// Requests the header of `block` from `peer` only, bypassing peer
+    /// selection. Useful for diagnosing peer specific data problems.
+    pub async fn header_for_block_from_peer(
+        &self,
+        peer: PeerId,
+        block: BlockNumber,
+    ) -> anyhow::Result<SignedBlockHeader> {
+        let request = BlockHeadersRequest {
+            iteration: Iteration {
+                start: block.get().into(),
+                direction: Direction::Forward,
+                limit: 1,
+                step: 1.into(),
+            },
+        };
+
+        let mut stream = self
+            .inner
+            .send_headers_sync_request(peer, request)
+            .await
+            .inspect_err(|error| tracing::debug!(%peer, %error, "Headers request failed"))?;
+
+        match stream.next().await {
+            Some(Ok(BlockHeadersResponse::Header(hdr))) => SignedBlockHeader::try_from_dto(*hdr),
+            Some(Ok(BlockHeadersResponse::Fin)) | None => {
+                anyhow::bail!("Peer {peer} has no header for block {block}")
+            }
+            Some(Err(error)) => {
+                tracing::debug!(%peer, %error, "Header response stream failed");
+                Err(error.into())
+            }
+        }
+    }
+
+    /// Requests the transactions of `block` from `peer` only, bypassing peer
+    /// selection. Useful for diagnosing peer specific data problems.
+    ///
+    /// The stream fails once the peer sends more than
+    /// [`BlockItemLimits::max_transactions`] transactions.
+    pub async fn transactions_for_block_from_peer(
+        &self,
+        peer: PeerId,
+        block: BlockNumber,
+    ) -> anyhow::Result<impl Stream<Item = anyhow::Result<(TransactionVariant, Receipt)>>> {
+        let request = TransactionsRequest {
+            iteration: Iteration {
+                start: block.get().into(),
+                direction: Direction::Forward,
+                limit: 1,
+                step: 1.into(),
+            },
+        };
+
+        let stream = self
+            .inner
+            .send_transactions_sync_request(peer, request)
+            .await
+            .inspect_err(|error| tracing::debug!(%peer, %error, "Transactions request failed"))?;
+
+        // A peer which never sends `Fin` must not be able to stream an unbounded
+        // number of transactions for a single block.
+        let max = self.item_limits.max_transactions;
+        let stream = stream
+            .try_take_while(|x| std::future::ready(Ok(!matches!(x, &TransactionsResponse::Fin))))
+            .enumerate()
+            .take(max.saturating_add(1))
+            .map(move |(i, x)| -> anyhow::Result<_> {
+                if i == max {
+                    tracing::debug!(%peer, %block, "Too many transactions for block");
+                    anyhow::bail!("Block transaction count exceeds the limit of {max}");
+                }
+
+                match x {
+                    Ok(TransactionsResponse::Fin) => unreachable!("Already handled Fin above"),
+                    Ok(TransactionsResponse::TransactionWithReceipt(tx_with_receipt)) => Ok((
+                        TransactionVariant::try_from_dto(tx_with_receipt.transaction)?,
+                        Receipt::try_from((
+                            tx_with_receipt.receipt,
+                            TransactionIndex::new(i.try_into().unwrap())
+                                .ok_or_else(|| anyhow::anyhow!("Invalid transaction index"))?,
+                        ))?,
+                    )),
+                    Err(error) => {
+                        tracing::debug!(%peer, %error, "Transaction response stream failed");
+                        Err(error.into())
+                    }
+                }
+            });
+
+        Ok(stream)
+    }
+
+    /// Requests the state diff of `block` from `peer` only, bypassing peer
+    /// selection. Useful for diagnosing peer specific data problems.
+    pub async fn state_diff_for_block_from_peer(
+        &self,
+        peer: PeerId,
+        block: BlockNumber,
+        state_diff_length: u64,
+    ) -> Result<StateUpdateData, StateDiffsError> {
+        let request = StateDiffsRequest {
+            iteration: Iteration {
+                start: block.get().into(),
+                direction: Direction::Forward,
+                limit: 1,
+                step: 1.into(),
+            },
+        };
+
+        let stream = self
+            .inner
+            .send_state_diffs_sync_request(peer, request)
+            .await
+            .map_err(|error| {
+                tracing::debug!(%peer, %error, "State diffs request failed");
+                StateDiffsError::RequestFailed(peer, error)
+            })?;
+
+        state_diff_from_responses(peer, state_diff_length, stream)
+            .try_fold(
+                StateUpdateData::default(),
+                |mut state_diff, chunk| async move {
+                    chunk.merge_into(&mut state_diff);
+                    Ok(state_diff)
+                },
+            )
+            .await
+    }
+
+    /// Same as [`Client::state_diff_for_block_from_peer`], except that the
+    /// state diff is yielded in chunks as they arrive instead of being
+    /// collected first.
+    pub async fn state_diff_stream_for_block_from_peer(
+        &self,
+        peer: PeerId,
+        block: BlockNumber,
+        state_diff_length: u64,
+    ) -> Result<impl Stream<Item = Result<StateDiffChunk, StateDiffsError>>, StateDiffsError> {
+        let request = StateDiffsRequest {
+            iteration: Iteration {
+                start: block.get().into(),
+                direction: Direction::Forward,
+                limit: 1,
+                step: 1.into(),
+            },
+        };
+
+        let stream = self
+            .inner
+            .send_state_diffs_sync_request(peer, request)
+            .await
+            .map_err(|error| {
+                tracing::debug!(%peer, %error, "State diffs request failed");
+                StateDiffsError::RequestFailed(peer, error)
+            })?;
+
+        Ok(state_diff_from_responses(peer, state_diff_length, stream))
+    }
+
+    /// Requests the class definitions declared in `block` from `peer` only,
+    /// bypassing peer selection. Useful for diagnosing peer specific data
+    /// problems.
+    pub async fn class_definitions_for_block_from_peer(
+        &self,
+        peer: PeerId,
+        block: BlockNumber,
+        declared_classes_count: u64,
+    ) -> Result<Vec<ClassDefinition>, ClassDefinitionsError> {
+        let request = ClassesRequest {
+            iteration: Iteration {
+                start: block.get().into(),
+                direction: Direction::Forward,
+                limit: 1,
+                step: 1.into(),
+            },
+        };
+
+        let mut stream = self
+            .inner
+            .send_classes_sync_request(peer, request)
+            .await
+            .map_err(|error| {
+                tracing::debug!(%peer, %error, "Classes request failed");
+                ClassDefinitionsError::RequestFailed(peer, error)
+            })?;
+
+        let mut current_count = declared_classes_count;
+        let mut class_definitions = Vec::new();
+
+        while let Some(resp) = stream.next().await {
+            match resp {
+                Ok(ClassesResponse::Class(class)) => {
+                    class_definitions.push(class_definition_from_dto(peer, block, class)?);
+                }
+                Ok(ClassesResponse::Fin) => {
+                    tracing::debug!(%peer, "Received FIN in class definitions source");
+                    break;
+                }
+                Err(error) => {
+                    tracing::debug!(%peer, %error, "Class definition
+                    response stream failed");
+                    return Err(ClassDefinitionsError::ResponseStreamFailure(peer, error));
+                }
+            }
+
+            current_count = match current_count.checked_sub(1) {
+                Some(x) => x,
+                None => {
+                    tracing::debug!(%peer, "Too many class definitions");
+                    return Err(ClassDefinitionsError::IncorrectClassDefinitionCount(peer));
+                }
+            };
+        }
+
+        if current_count != 0 {
+            tracing::debug!(%peer, "Too few class definitions");
+            return Err(ClassDefinitionsError::IncorrectClassDefinitionCount(peer));
+        }
+
+        Ok(class_definitions)
+    }
+
+    /// Fetches the definition of the class with `class_hash`, declared in
+    /// `block` along with `declared_classes_count` classes in total.
+    ///
+    /// Classes can only be requested by block, so all the classes declared in
+    /// the block are fetched as by [`BlockClient::class_definitions_for_block`]
+    /// and the class is picked by its hash, computed with `hasher`. Returns
+    /// `None` if no peer has the block's classes or the class is not among
+    /// them.
+    pub async fn class_definition_by_hash(
+        self,
+        block: BlockNumber,
+        declared_classes_count: u64,
+        class_hash: ClassHash,
+        hasher: &ClassHasher,
+    ) -> Result<Option<(PeerId, ClassDefinition)>, ClassDefinitionsError> {
+        let Some((peer, classes)) = self
+            .class_definitions_for_block(block, declared_classes_count)
+            .await?
+        else {
+            return Ok(None);
+        };
+
+        for class in classes {
+            match hasher.hash(&class) {
+                Ok(hash) if hash == class_hash => return Ok(Some((peer, class))),
+                Ok(_) => {}
+                Err(error) => {
+                    tracing::debug!(%peer, %error, "Failed to compute class hash");
+                    return Err(match class {
+                        ClassDefinition::Cairo { .. } => {
+                            ClassDefinitionsError::CairoDefinitionError(peer)
+                        }
+                        ClassDefinition::Sierra { .. } => {
+                            ClassDefinitionsError::SierraDefinitionError(peer)
+                        }
+                    });
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Same as [`Client::class_definitions_for_block_from_peer`], except that
+    /// the class definitions are yielded as they arrive instead of being
+    /// collected first.
+    pub async fn class_definitions_stream_for_block_from_peer(
+        &self,
+        peer: PeerId,
+        block: BlockNumber,
+        declared_classes_count: u64,
+    ) -> anyhow::Result<impl Stream<Item = Result<ClassDefinition, ClassDefinitionsError>>> {
+        let request = ClassesRequest {
+            iteration: Iteration {
+                start: block.get().into(),
+                direction: Direction::Forward,
+                limit: 1,
+                step: 1.into(),
+            },
+        };
+
+        let stream = self
+            .inner
+            .send_classes_sync_request(peer, request)
+            .await
+            .inspect_err(|error| tracing::debug!(%peer, %error, "Classes request failed"))?;
+
+        Ok(class_definitions_from_responses(
+            peer,
+            block,
+            declared_classes_count,
+            stream,
+        ))
+    }
+
+    /// Requests the events of `block` from `peer` only, bypassing peer
+    /// selection. Useful for diagnosing peer specific data problems.
+    pub async fn events_for_block_from_peer(
+        &self,
+        peer: PeerId,
+        block: BlockNumber,
+    ) -> anyhow::Result<
+        impl Stream<Item = Result<(TransactionHash, Event), EventsResponseStreamFailure>>,
+    > {
+        let request = EventsRequest {
+            iteration: Iteration {
+                start: block.get().into(),
+                direction: Direction::Forward,
+                limit: 1,
+                step: 1.into(),
+            },
+        };
+
+        let stream = self
+            .inner
+            .send_events_sync_request(peer, request)
+            .await
+            .inspect_err(|error| tracing::debug!(%peer, %error, "Events request failed"))?;
+
+        let stream = stream
+            .try_take_while(|x| std::future::ready(Ok(!matches!(x, &EventsResponse::Fin))))
+            .map(move |x| match x {
+                Ok(EventsResponse::Fin) => unreachable!("Already handled Fin above"),
+                Ok(EventsResponse::Event(event)) => Ok((
+                    TransactionHash(event.transaction_hash.0),
+                    Event::from_dto(event),
+                )),
+                Err(error) => {
+                    tracing::debug!(%peer, %error, "Events response stream failed");
+                    Err(EventsResponseStreamFailure(peer, error))
+                }
+            });
+
+        Ok(stream)
+    }
+
+    /// Streams headers from `redundancy` independent header streams, each of
+    /// which only uses its own, disjoint share of the peer set, and
+    /// cross-checks the header each of them yields for every block. This is
+    /// meant for detecting equivocating peers.
+    ///
+    /// Every header is downloaded `redundancy` times so bandwidth use is
+    /// multiplied by `redundancy`. Each stream also has only about
+    /// `1/redundancy` of the peers to fall back on.
+    ///
+    /// The stream ends as soon as any of the underlying streams ends, with the
+    /// error of that stream if it ended early.
+    pub fn redundant_header_stream(
+        self,
+        start: BlockNumber,
+        stop: BlockNumber,
+        redundancy: NonZeroUsize,
+    ) -> impl Stream<Item = StreamItem<Agreement<SignedBlockHeader>>> + Send {
+        let config = self.stream_config("headers", self.buffers.header_buffer);
+        let streams = (0..redundancy.get())
+            .map(|partition| {
+                let requester = self.clone();
+                let outer = self.clone();
+                header_stream::make(
+                    start,
+                    stop,
+                    false,
+                    NonZeroU64::MIN,
+                    self.verify_parent_hashes,
+                    config.clone(),
+                    move || {
+                        let outer = outer.clone();
+                        async move {
+                            loop {
+                                let peers = outer
+                                    .get_random_peers()
+                                    .await
+                                    .into_iter()
+                                    .filter(|peer| peer_partition(peer, redundancy) == partition)
+                                    .collect::<Vec<_>>();
+
+                                if !peers.is_empty() {
+                                    break peers;
+                                }
+
+                                tracing::debug!(%partition, "No peers in partition, retrying");
+                                tokio::time::sleep(Duration::from_secs(3)).await;
+                            }
+                        }
+                    },
+                    move |peer, request| {
+                        let requester = requester.clone();
+                        async move {
+                            let requested_at = Instant::now();
+                            let responses = requester
+                                .inner
+                                .send_headers_sync_request(peer, request)
+                                .await?;
+                            Ok(requester.count_bytes_received(
+                                peer,
+                                "headers",
+                                requested_at,
+                                responses,
+                            ))
+                        }
+                    },
+                )
+                .boxed()
+            })
+            .collect();
+
+        redundant_stream::merge(streams)
+    }
+
+    /// Downloads the headers of `start..=stop` and checks that they form a
+    /// contiguous chain by block number and, if `verify_parent_hashes` is
+    /// set, by parent hash. Header signatures are not checked.
+    ///
+    /// Meant for one-off requests of a range, syncing should use
+    /// [`HeaderStream`] instead.
+    pub async fn headers_for_range(
+        self,
+        start: BlockNumber,
+        stop: BlockNumber,
+        verify_parent_hashes: bool,
+    ) -> anyhow::Result<Vec<SignedBlockHeader>> {
+        anyhow::ensure!(start <= stop, "Empty header range {start}..={stop}");
+
+        let headers = HeaderStream::header_stream(self, start, stop, false, None)
+            .try_collect::<Vec<_>>()
+            .await
+            .map_err(|error| anyhow::Error::from(error.data))?;
+
+        contiguous_headers(start, stop, headers, verify_parent_hashes)
+    }
+
+    /// Streams the blocks of `start..=stop` with all of their data. The counts
+    /// which the transaction, state diff and event streams expect are taken
+    /// from the headers, and the number of declared classes from the state
+    /// diffs, so the caller doesn't have to provide them.
+    ///
+    /// Headers are downloaded ahead of the rest of the data, so the other
+    /// streams can request upcoming blocks while the current one is being
+    /// assembled. Commitments are verified if the client is configured to do
+    /// so, see [`Client::with_transaction_commitment_verifier`] and
+    /// [`Client::with_state_diff_commitment_lookup`].
+    ///
+    /// The peer of a block is the one which supplied its header, the rest of
+    /// its data may come from other peers. The stream ends with the error of
+    /// the first underlying stream that fails.
+    pub fn full_block_stream(
+        self,
+        start: BlockNumber,
+        stop: BlockNumber,
+    ) -> impl Stream<Item = StreamItem<FullBlock>> + Send {
+        // The underlying streams share a single permit, as they could otherwise
+        // starve each other of permits while waiting for each other's items.
+        let permits = self.stream_permits.clone();
+        let client = Self {
+            stream_permits: None,
+            ..self
+        };
+        futures::stream::once(acquire_stream_permit(permits)).flat_map(move |permit| {
+            client
+                .clone()
+                .unbounded_full_block_stream(start, stop)
+                .map(move |block| {
+                    let _permit = &permit;
+                    block
+                })
+        })
+    }
+
+    fn unbounded_full_block_stream(
+        self,
+        start: BlockNumber,
+        stop: BlockNumber,
+    ) -> impl Stream<Item = StreamItem<FullBlock>> + Send {
+        let config = self.stream_config("full_blocks", self.buffers.header_buffer);
+        let (transactions, transaction_counts) = fmpsc::unbounded();
+        let (state_diffs, state_diff_lengths) = fmpsc::unbounded();
+        let (classes, class_counts) = fmpsc::unbounded();
+        let (events, event_counts) = fmpsc::unbounded();
+        let verify_transactions = self.transaction_verifier.is_some();
+        let verify_state_diffs = self.state_diff_commitments.is_some();
+        let verify_events = self.event_verifier.is_some();
+
+        full_block_stream::make(
+            config,
+            HeaderStream::header_stream(self.clone(), start, stop, false, None),
+            full_block_stream::CountSenders {
+                transactions,
+                state_diffs,
+                classes,
+                events,
+            },
+            TransactionStream::transaction_stream(
+                self.clone(),
+                start,
+                stop,
+                false,
+                None,
+                verify_transactions,
+                transaction_counts,
+            ),
+            StateDiffStream::state_diff_stream(
+                self.clone(),
+                start,
+                stop,
+                false,
+                None,
+                verify_state_diffs,
+                state_diff_lengths,
+            ),
+            ClassStream::class_stream(
+                self.clone(),
+                start,
+                stop,
+                false,
+                None,
+                class_counts,
+                ClassFilter::default(),
+            ),
+            EventStream::event_stream(self, start, stop, false, None, verify_events, event_counts),
+        )
+    }
+}
+
+/// Checks that `headers` are the headers of `start..=stop` in order, and
+/// returns an error identifying the first gap otherwise.
+fn contiguous_headers(
+    start: BlockNumber,
+    stop: BlockNumber,
+    headers: Vec<PeerData<SignedBlockHeader>>,
+    verify_parent_hashes: bool,
+) -> anyhow::Result<Vec<SignedBlockHeader>> {
+    let mut expected = start;
+    let mut parent_hash = None;
+    let mut range = Vec::with_capacity(headers.len());
+
+    for PeerData { peer, data } in headers {
+        let number = data.header.number;
+        anyhow::ensure!(
+            number == expected,
+            "Gap in headers: expected block {expected}, got {number} from {peer}"
+        );
+        if verify_parent_hashes {
+            if let Some(parent_hash) = parent_hash {
+                anyhow::ensure!(
+                    data.header.parent_hash == parent_hash,
+                    "Gap in headers: parent hash of block {number} from {peer} does not match the \
+                     previous header"
+                );
+            }
+        }
+
+        parent_hash = Some(data.header.hash);
+        expected += 1;
+        range.push(data);
+    }
+
+    anyhow::ensure!(
+        expected > stop,
+        "Gap in headers: missing blocks {expected}..={stop}"
+    );
+
+    Ok(range)
+}
+
+/// Parses the state diff chunks sent by `peer` from `responses` as they
+/// arrive. The first error ends the stream, including `peer` sending more or
+/// fewer than `state_diff_length` updates in total, or more than one contract
+/// diff for the same contract.
+fn state_diff_from_responses(
+    peer: PeerId,
+    state_diff_length: u64,
+    responses: impl Stream<Item = std::io::Result<StateDiffsResponse>> + Unpin,
+) -> impl Stream<Item = Result<StateDiffChunk, StateDiffsError>> {
+    futures::stream::unfold(
+        Some((responses, state_diff_length, HashSet::new())),
+        move |state| async move {
+            let (mut responses, remaining, mut seen) = state?;
+            let chunk = match responses.next().await {
+                Some(Ok(StateDiffsResponse::ContractDiff(diff))) => {
+                    state_diff_chunk_from_dto(peer, diff, remaining, &mut seen)
+                }
+                Some(Ok(StateDiffsResponse::DeclaredClass(DeclaredClass {
+                    class_hash,
+                    compiled_class_hash,
+                }))) => match remaining.checked_sub(1) {
+                    Some(remaining) => {
+                        let chunk = match compiled_class_hash {
+                            Some(compiled_class_hash) => StateDiffChunk::DeclaredSierraClass(
+                                SierraHash(class_hash.0),
+                                CasmHash(compiled_class_hash.0),
+                            ),
+                            None => StateDiffChunk::DeclaredCairoClass(ClassHash(class_hash.0)),
+                        };
+                        Ok((chunk, remaining))
+                    }
+                    None => {
+                        tracing::debug!(%peer, "Too many declared classes");
+                        Err(StateDiffsError::IncorrectStateDiffCount(peer))
+                    }
+                },
+                Some(Ok(StateDiffsResponse::Fin)) if remaining == 0 => return None,
+                Some(Ok(StateDiffsResponse::Fin)) => {
+                    tracing::debug!(%peer, "Too few storage diffs");
+                    Err(StateDiffsError::IncorrectStateDiffCount(peer))
+                }
+                Some(Err(error)) => {
+                    tracing::debug!(%peer, %error, "State diff response stream failed");
+                    Err(StateDiffsError::ResponseStreamFailure(peer, error))
+                }
+                None => {
+                    tracing::debug!(%peer, "State diff response stream terminated without Fin");
+                    Err(StateDiffsError::PrematureStreamTermination(peer))
+                }
+            };
+
+            match chunk {
+                Ok((chunk, remaining)) => Some((Ok(chunk), Some((responses, remaining, seen)))),
+                Err(error) => Some((Err(error), None)),
+            }
+        },
+    )
+}
+
+/// Parses a contract diff received from `peer`, given that `remaining`
+/// updates of the state diff are still expected and that diffs were already
+/// received for the contracts in `seen`. Returns the updates still expected
+/// after this diff.
+fn state_diff_chunk_from_dto(
+    peer: PeerId,
+    diff: ContractDiff,
+    remaining: u64,
+    seen: &mut HashSet<ContractAddress>,
+) -> Result<(StateDiffChunk, u64), StateDiffsError> {
+    let ContractDiff {
+        address,
+        nonce,
+        class_hash,
+        values,
+        domain,
+    } = diff;
+
+    let address = ContractAddress(address.0);
+    let domain = DataAvailabilityMode::from_dto(domain);
+    // Splitting the diff of a contract across responses would let a peer pass
+    // the count check with a state diff that differs from the one committed to.
+    if !seen.insert(address) {
+        tracing::debug!(%peer, %address, "Repeated contract diff");
+        return Err(StateDiffsError::IncorrectStateDiffCount(peer));
+    }
+
+    let mut remaining = match remaining.checked_sub(values.len().try_into().unwrap()) {
+        Some(x) => x,
+        None => {
+            tracing::debug!(%peer, "Too many storage diffs: {} > {}", values.len(), remaining);
+            return Err(StateDiffsError::IncorrectStateDiffCount(peer));
+        }
+    };
+    let storage = values
+        .into_iter()
+        .map(|ContractStoredValue { key, value }| (StorageAddress(key), StorageValue(value)))
+        .collect();
+
+    if address == ContractAddress::ONE {
+        // The system contract has neither a nonce nor a class.
+        if nonce.is_some() || class_hash.is_some() {
+            tracing::debug!(%peer, "System contract diff with a nonce or class");
+            return Err(StateDiffsError::IncorrectStateDiffCount(peer));
+        }
+        return Ok((
+            StateDiffChunk::SystemContractUpdate(address, SystemContractUpdate { storage }, domain),
+            remaining,
+        ));
+    }
+
+    let mut update = ContractUpdate {
+        storage,
+        ..Default::default()
+    };
+
+    if let Some(nonce) = nonce {
+        remaining = match remaining.checked_sub(1) {
+            Some(x) => x,
+            None => {
+                tracing::debug!(%peer, "Too many nonce updates");
+                return Err(StateDiffsError::IncorrectStateDiffCount(peer));
+            }
+        };
+        update.nonce = Some(ContractNonce(nonce));
+    }
+
+    if let Some(class_hash) = class_hash.map(|x| ClassHash(x.0)) {
+        remaining = match remaining.checked_sub(1) {
+            Some(x) => x,
+            None => {
+                tracing::debug!(%peer, "Too many deployed contracts");
+                return Err(StateDiffsError::IncorrectStateDiffCount(peer));
+            }
+        };
+        update.class = Some(ContractClassUpdate::Deploy(class_hash));
+    }
+
+    Ok((
+        StateDiffChunk::ContractUpdate(address, update, domain),
+        remaining,
+    ))
+}
+
+/// Parses a class definition received from `peer` for `block`.
+fn class_definition_from_dto(
+    peer: PeerId,
+    block: BlockNumber,
+    class: p2p_proto::class::Class,
+) -> Result<ClassDefinition, ClassDefinitionsError> {
+    match class {
+        p2p_proto::class::Class::Cairo0 { class, domain } => {
+            let definition = CairoDefinition::try_from_dto(class)
+                .map_err(|_| ClassDefinitionsError::CairoDefinitionError(peer))?;
+            Ok(ClassDefinition::Cairo {
+                block_number: block,
+                definition: definition.0,
+                domain: DataAvailabilityMode::from_dto(domain),
+            })
+        }
+        p2p_proto::class::Class::Cairo1 { class, domain } => {
+            let SierraDefinition(definition, interface) = SierraDefinition::try_from_dto(class)
+                .map_err(|_| ClassDefinitionsError::SierraDefinitionError(peer))?;
+            Ok(ClassDefinition::Sierra {
+                block_number: block,
+                sierra_definition: definition,
+                interface: Some(interface),
+                domain: DataAvailabilityMode::from_dto(domain),
+            })
+        }
+    }
+}
+
+/// Parses the class definitions of `block` from `responses` as they arrive.
+/// The first error ends the stream, including `peer` sending more or fewer
+/// than `declared_classes_count` classes.
+fn class_definitions_from_responses(
+    peer: PeerId,
+    block: BlockNumber,
+    declared_classes_count: u64,
+    responses: impl Stream<Item = std::io::Result<ClassesResponse>> + Unpin,
+) -> impl Stream<Item = Result<ClassDefinition, ClassDefinitionsError>> {
+    futures::stream::unfold(
+        Some((responses, declared_classes_count)),
+        move |state| async move {
+            let (mut responses, remaining) = state?;
+            let error = match responses.next().await {
+                Some(Ok(ClassesResponse::Class(class))) => match remaining.checked_sub(1) {
+                    Some(remaining) => match class_definition_from_dto(peer, block, class) {
+                        Ok(definition) => {
+                            return Some((Ok(definition), Some((responses, remaining))))
+                        }
+                        Err(error) => error,
+                    },
+                    None => {
+                        tracing::debug!(%peer, "Too many class definitions");
+                        ClassDefinitionsError::IncorrectClassDefinitionCount(peer)
+                    }
+                },
+                Some(Ok(ClassesResponse::Fin)) | None if remaining == 0 => return None,
+                Some(Ok(ClassesResponse::Fin)) | None => {
+                    tracing::debug!(%peer, "Too few class definitions");
+                    ClassDefinitionsError::IncorrectClassDefinitionCount(peer)
+                }
+                Some(Err(error)) => {
+                    tracing::debug!(%peer, %error, "Class definition response stream failed");
+                    ClassDefinitionsError::ResponseStreamFailure(peer, error)
+                }
+            };
+
+            Some((Err(error), None))
+        },
+    )
+}
+
 impl BlockClient for Client {
+    async fn header_for_block(self, block: BlockNumber) -> Option<(PeerId, SignedBlockHeader)> {
+        coalesce(
+            &self.inflight.headers,
+            block,
+            || async {
+                let client = &self;
+                let (found, _) = self
+                    .first_ok_for_block(|peer| async move {
+                        let header = client.header_for_block_from_peer(peer, block).await?;
+                        anyhow::ensure!(
+                            header.header.number == block,
+                            "Peer {peer} sent the header of block {} instead of {block}",
+                            header.header.number
+                        );
+                        Ok(header)
+                    })
+                    .await;
+                found
+            },
+            |found| found.clone(),
+            Some,
+        )
+        .await
+    }
+
     async fn transactions_for_block(
         self,
         block: BlockNumber,
@@ -300,54 +2214,20 @@ impl BlockClient for Client {
         PeerId,
         impl Stream<Item = anyhow::Result<(TransactionVariant, Receipt)>>,
     )> {
-        let request = TransactionsRequest {
-            iteration: Iteration {
-                start: block.get().into(),
-                direction: Direction::Forward,
-                limit: 1,
-                step: 1.into(),
+        coalesce_stream(
+            &self.inflight.transactions,
+            block,
+            || async {
+                let (found, _) = self
+                    .first_ok_for_block(|peer| self.transactions_for_block_from_peer(peer, block))
+                    .await;
+                found
             },
-        };
-
-        let peers = self.get_random_peers().await;
-
-        for peer in peers {
-            let Ok(stream) = self
-                .inner
-                .send_transactions_sync_request(peer, request)
-                .await
-                .inspect_err(|error| tracing::debug!(%peer, %error, "Transactions request failed"))
-            else {
-                continue;
-            };
-
-            let stream = stream
-                .try_take_while(|x| {
-                    std::future::ready(Ok(!matches!(x, &TransactionsResponse::Fin)))
-                })
-                .enumerate()
-                .map(move |(i, x)| -> anyhow::Result<_> {
-                    match x {
-                        Ok(TransactionsResponse::Fin) => unreachable!("Already handled Fin above"),
-                        Ok(TransactionsResponse::TransactionWithReceipt(tx_with_receipt)) => Ok((
-                            TransactionVariant::try_from_dto(tx_with_receipt.transaction)?,
-                            Receipt::try_from((
-                                tx_with_receipt.receipt,
-                                TransactionIndex::new(i.try_into().unwrap())
-                                    .ok_or_else(|| anyhow::anyhow!("Invalid transaction index"))?,
-                            ))?,
-                        )),
-                        Err(error) => {
-                            tracing::debug!(%peer, %error, "Transaction response stream failed");
-                            Err(error.into())
-                        }
-                    }
-                });
-
-            return Some((peer, stream));
-        }
-
-        None
+            |peer| {
+                anyhow::anyhow!("Transaction stream from {peer} shared by another request failed")
+            },
+        )
+        .await
     }
 
     async fn state_diff_for_block(
@@ -355,129 +2235,54 @@ impl BlockClient for Client {
         block: BlockNumber,
         state_diff_length: u64,
     ) -> Result<Option<(PeerId, StateUpdateData)>, StateDiffsError> {
-        let request = StateDiffsRequest {
-            iteration: Iteration {
-                start: block.get().into(),
-                direction: Direction::Forward,
-                limit: 1,
-                step: 1.into(),
-            },
-        };
-
-        let peers = self.get_random_peers().await;
-
-        for peer in peers {
-            let Ok(mut stream) = self
-                .inner
-                .send_state_diffs_sync_request(peer, request)
-                .await
-                .inspect_err(|error| tracing::debug!(%peer, %error, "State diffs request failed"))
-            else {
-                continue;
-            };
-
-            let mut current_count = state_diff_length;
-            let mut state_diff = StateUpdateData::default();
-
-            while let Some(resp) = stream.next().await {
-                match resp {
-                    Ok(StateDiffsResponse::ContractDiff(ContractDiff {
-                        address,
-                        nonce,
-                        class_hash,
-                        values,
-                        domain: _,
-                    })) => {
-                        match current_count.checked_sub(values.len().try_into().unwrap()) {
-                            Some(x) => current_count = x,
-                            None => {
-                                tracing::debug!(%peer, "Too many storage diffs: {} > {}", values.len(), current_count);
-                                return Err(StateDiffsError::IncorrectStateDiffCount(peer));
-                            }
-                        }
-                        let address = ContractAddress(address.0);
-                        if address == ContractAddress::ONE {
-                            let storage = &mut state_diff
-                                .system_contract_updates
-                                .entry(address)
-                                .or_default()
-                                .storage;
-                            values
-                                .into_iter()
-                                .for_each(|ContractStoredValue { key, value }| {
-                                    storage.insert(StorageAddress(key), StorageValue(value));
-                                });
-                        } else {
-                            let update =
-                                &mut state_diff.contract_updates.entry(address).or_default();
-                            values
-                                .into_iter()
-                                .for_each(|ContractStoredValue { key, value }| {
-                                    update
-                                        .storage
-                                        .insert(StorageAddress(key), StorageValue(value));
-                                });
-
-                            if let Some(nonce) = nonce {
-                                match current_count.checked_sub(1) {
-                                    Some(x) => current_count = x,
-                                    None => {
-                                        tracing::debug!(%peer, "Too many nonce updates");
-                                        return Err(StateDiffsError::IncorrectStateDiffCount(peer));
-                                    }
-                                }
-                                update.nonce = Some(ContractNonce(nonce));
-                            }
+        coalesce(
+            &self.inflight.state_diffs,
+            (block, state_diff_length),
+            || async {
+                let (found, errors) = self
+                    .first_ok_for_block(|peer| {
+                        self.state_diff_for_block_from_peer(peer, block, state_diff_length)
+                    })
+                    .await;
+
+                if found.is_some() {
+                    return Ok(found);
+                }
 
-                            if let Some(class_hash) = class_hash.map(|x| ClassHash(x.0)) {
-                                match current_count.checked_sub(1) {
-                                    Some(x) => current_count = x,
-                                    None => {
-                                        tracing::debug!(%peer, "Too many deployed contracts");
-                                        return Err(StateDiffsError::IncorrectStateDiffCount(peer));
-                                    }
-                                }
-                                update.class = Some(ContractClassUpdate::Deploy(class_hash));
-                            }
-                        }
-                    }
-                    Ok(StateDiffsResponse::DeclaredClass(DeclaredClass {
-                        class_hash,
-                        compiled_class_hash,
-                    })) => {
-                        match current_count.checked_sub(1) {
-                            Some(x) => current_count = x,
-                            None => {
-                                tracing::debug!(%peer, "Too many declared classes");
-                                return Err(StateDiffsError::IncorrectStateDiffCount(peer));
-                            }
-                        }
-                        if let Some(compiled_class_hash) = compiled_class_hash {
-                            state_diff
-                                .declared_sierra_classes
-                                .insert(SierraHash(class_hash.0), CasmHash(compiled_class_hash.0));
-                        } else {
-                            state_diff
-                                .declared_cairo_classes
-                                .insert(ClassHash(class_hash.0));
-                        }
-                    }
-                    Ok(StateDiffsResponse::Fin) => {
-                        if current_count != 0 {
-                            tracing::debug!(%peer, "Too few storage diffs");
-                            return Err(StateDiffsError::IncorrectStateDiffCount(peer));
-                        }
-                        return Ok(Some((peer, state_diff)));
-                    }
-                    Err(error) => {
-                        tracing::debug!(%peer, %error, "State diff response stream failed");
-                        return Err(StateDiffsError::ResponseStreamFailure(peer, error));
-                    }
+                // Report invalid data only once no peer could provide a valid state diff.
+                match errors.into_iter().find(|error| {
+                    !matches!(
+                        error,
+                        StateDiffsError::RequestFailed(..)
+                            | StateDiffsError::PrematureStreamTermination(_)
+                    )
+                }) {
+                    Some(error) => Err(error),
+                    None => Ok(None),
                 }
-            }
-        }
+            },
+            |result| result.as_ref().ok().cloned().flatten(),
+            |found| Ok(Some(found)),
+        )
+        .await
+    }
 
-        Ok(None)
+    async fn state_diff_stream_for_block(
+        self,
+        block: BlockNumber,
+        state_diff_length: u64,
+    ) -> Option<(
+        PeerId,
+        impl Stream<Item = Result<StateDiffChunk, StateDiffsError>>,
+    )> {
+        // Not coalesced with concurrent requests for the same block, as sharing the
+        // result would require buffering it.
+        let (found, _) = self
+            .first_ok_for_block(|peer| {
+                self.state_diff_stream_for_block_from_peer(peer, block, state_diff_length)
+            })
+            .await;
+        found
     }
 
     async fn class_definitions_for_block(
@@ -485,83 +2290,71 @@ impl BlockClient for Client {
         block: BlockNumber,
         declared_classes_count: u64,
     ) -> Result<Option<(PeerId, Vec<ClassDefinition>)>, ClassDefinitionsError> {
-        let request = ClassesRequest {
-            iteration: Iteration {
-                start: block.get().into(),
-                direction: Direction::Forward,
-                limit: 1,
-                step: 1.into(),
-            },
-        };
-
-        let peers = self.get_random_peers().await;
-
-        for peer in peers {
-            let Ok(mut stream) = self
-                .inner
-                .send_classes_sync_request(peer, request)
-                .await
-                .inspect_err(|error| tracing::debug!(%peer, %error, "State diffs request failed"))
-            else {
-                continue;
-            };
-
-            let mut current_count = declared_classes_count;
-            let mut class_definitions = Vec::new();
-
-            while let Some(resp) = stream.next().await {
-                match resp {
-                    Ok(ClassesResponse::Class(p2p_proto::class::Class::Cairo0 {
-                        class,
-                        domain: _,
-                    })) => {
-                        let definition = CairoDefinition::try_from_dto(class)
-                            .map_err(|_| ClassDefinitionsError::CairoDefinitionError(peer))?;
-                        class_definitions.push(ClassDefinition::Cairo {
-                            block_number: block,
-                            definition: definition.0,
-                        });
-                    }
-                    Ok(ClassesResponse::Class(p2p_proto::class::Class::Cairo1 {
-                        class,
-                        domain: _,
-                    })) => {
-                        let definition = SierraDefinition::try_from_dto(class)
-                            .map_err(|_| ClassDefinitionsError::SierraDefinitionError(peer))?;
-                        class_definitions.push(ClassDefinition::Sierra {
-                            block_number: block,
-                            sierra_definition: definition.0,
-                        });
-                    }
-                    Ok(ClassesResponse::Fin) => {
-                        tracing::debug!(%peer, "Received FIN in class definitions source");
-                        break;
-                    }
-                    Err(error) => {
-                        tracing::debug!(%peer, %error, "Class definition
-                        response stream failed");
-                        return Err(ClassDefinitionsError::ResponseStreamFailure(peer, error));
+        coalesce(
+            &self.inflight.class_definitions,
+            (block, declared_classes_count),
+            || async {
+                let (found, errors) = self
+                    .first_ok_for_block(|peer| {
+                        self.class_definitions_for_block_from_peer(
+                            peer,
+                            block,
+                            declared_classes_count,
+                        )
+                    })
+                    .await;
+
+                for error in &errors {
+                    match error {
+                        ClassDefinitionsError::IncorrectClassDefinitionCount(peer)
+                        | ClassDefinitionsError::CairoDefinitionError(peer)
+                        | ClassDefinitionsError::SierraDefinitionError(peer) => {
+                            self.penalize(*peer).await
+                        }
+                        ClassDefinitionsError::RequestFailed(..)
+                        | ClassDefinitionsError::ResponseStreamFailure(..) => {}
                     }
                 }
 
-                current_count = match current_count.checked_sub(1) {
-                    Some(x) => x,
-                    None => {
-                        tracing::debug!(%peer, "Too many class definitions");
-                        return Err(ClassDefinitionsError::IncorrectClassDefinitionCount(peer));
-                    }
-                };
-            }
-
-            if current_count != 0 {
-                tracing::debug!(%peer, "Too few class definitions");
-                return Err(ClassDefinitionsError::IncorrectClassDefinitionCount(peer));
-            }
+                if found.is_some() {
+                    return Ok(found);
+                }
 
-            return Ok(Some((peer, class_definitions)));
-        }
+                // Report invalid data only once no peer could provide valid classes.
+                match errors
+                    .into_iter()
+                    .find(|error| !matches!(error, ClassDefinitionsError::RequestFailed(..)))
+                {
+                    Some(error) => Err(error),
+                    None => Ok(None),
+                }
+            },
+            |result| result.as_ref().ok().cloned().flatten(),
+            |found| Ok(Some(found)),
+        )
+        .await
+    }
 
-        Ok(None)
+    async fn class_definitions_stream_for_block(
+        self,
+        block: BlockNumber,
+        declared_classes_count: u64,
+    ) -> Option<(
+        PeerId,
+        impl Stream<Item = Result<ClassDefinition, ClassDefinitionsError>>,
+    )> {
+        // Not coalesced with concurrent requests for the same block, as sharing the
+        // result would require buffering it.
+        let (found, _) = self
+            .first_ok_for_block(|peer| {
+                self.class_definitions_stream_for_block_from_peer(
+                    peer,
+                    block,
+                    declared_classes_count,
+                )
+            })
+            .await;
+        found
     }
 
     async fn events_for_block(
@@ -571,65 +2364,174 @@ impl BlockClient for Client {
         PeerId,
         impl Stream<Item = Result<(TransactionHash, Event), EventsResponseStreamFailure>>,
     )> {
-        let request = EventsRequest {
-            iteration: Iteration {
-                start: block.get().into(),
-                direction: Direction::Forward,
-                limit: 1,
-                step: 1.into(),
+        coalesce_stream(
+            &self.inflight.events,
+            block,
+            || async {
+                let (found, _) = self
+                    .first_ok_for_block(|peer| self.events_for_block_from_peer(peer, block))
+                    .await;
+                found
             },
-        };
+            |peer| {
+                EventsResponseStreamFailure(
+                    peer,
+                    std::io::Error::other("Event stream shared by another request failed"),
+                )
+            },
+        )
+        .await
+    }
+}
 
-        let peers = self.get_random_peers().await;
+/// Publishes the highest of the heads received within `window` of the first
+/// one, see [`Client::with_head_propagation_debounce`]. Ends once all the
+/// senders are dropped.
+async fn debounce_heads<T, F>(
+    window: Duration,
+    mut heads: mpsc::UnboundedReceiver<(u64, T)>,
+    publish: impl Fn(T) -> F,
+) where
+    F: Future<Output = anyhow::Result<()>>,
+{
+    while let Some(mut head) = heads.recv().await {
+        let deadline = tokio::time::Instant::now() + window;
+        while let Ok(Some(next)) = tokio::time::timeout_at(deadline, heads.recv()).await {
+            if next.0 >= head.0 {
+                head = next;
+            }
+        }
 
-        for peer in peers {
-            let Ok(stream) = self
-                .inner
-                .send_events_sync_request(peer, request)
-                .await
-                .inspect_err(|error| tracing::debug!(%peer, %error, "Events request failed"))
-            else {
-                continue;
-            };
+        let (number, head) = head;
+        if let Err(error) = publish(head).await {
+            tracing::debug!(%number, %error, "Failed to propagate head");
+        }
+    }
+}
 
-            let stream = stream
-                .try_take_while(|x| std::future::ready(Ok(!matches!(x, &EventsResponse::Fin))))
-                .map(move |x| match x {
-                    Ok(EventsResponse::Fin) => unreachable!("Already handled Fin above"),
-                    Ok(EventsResponse::Event(event)) => Ok((
-                        TransactionHash(event.transaction_hash.0),
-                        Event::from_dto(event),
-                    )),
-                    Err(error) => {
-                        tracing::debug!(%peer, %error, "Events response stream failed");
-                        Err(EventsResponseStreamFailure(peer, error))
-                    }
-                });
+/// Saves the peer scores to `store` every `interval`, see
+/// [`Client::with_peer_store`]. Ends once the peer state is dropped, which
+/// saves the scores itself.
+async fn save_peer_scores(
+    peers: std::sync::Weak<RwLock<PeerState>>,
+    store: Arc<dyn PeerStore>,
+    interval: Duration,
+) {
+    loop {
+        tokio::time::sleep(interval).await;
+
+        let Some(peers) = peers.upgrade() else {
+            return;
+        };
+        let records = peers.read().await.score_records();
+        drop(peers);
 
-            return Some((peer, stream));
+        let store = store.clone();
+        let result = tokio::task::spawn_blocking(move || store.save(&records))
+            .await
+            .unwrap_or_else(|e| Err(e.into()));
+        if let Err(error) = result {
+            tracing::warn!("Failed to save peer scores: {error:#}");
+        }
+    }
+}
+
+impl Client {
+    /// Sends `request` to the peer hint, if any, and then to randomly selected
+    /// peers as in [`first_ok_from_peers`].
+    async fn first_ok_for_block<T, E, F>(
+        &self,
+        request: impl Fn(PeerId) -> F,
+    ) -> (Option<(PeerId, T)>, Vec<E>)
+    where
+        F: Future<Output = Result<T, E>>,
+    {
+        let mut errors = Vec::new();
+        if let Some(peer) = self.peer_hint {
+            match request(peer).await {
+                Ok(x) => return (Some((peer, x)), errors),
+                Err(error) => {
+                    tracing::debug!(%peer, "Hinted peer failed, falling back to random peers");
+                    errors.push(error);
+                }
+            }
         }
 
-        None
+        let peers = self
+            .get_random_peers()
+            .await
+            .into_iter()
+            .filter(|peer| Some(*peer) != self.peer_hint)
+            .collect();
+        let (found, more_errors) =
+            first_ok_from_peers(peers, self.block_request_concurrency, request).await;
+        errors.extend(more_errors);
+        (found, errors)
+    }
+}
+
+/// Sends `request` to up to `concurrency` of `peers` at a time, in order, and
+/// returns the first successful result along with the errors of the peers
+/// which failed before that. Requests still in flight at that point are
+/// dropped.
+async fn first_ok_from_peers<T, E, F>(
+    peers: Vec<PeerId>,
+    concurrency: NonZeroUsize,
+    request: impl Fn(PeerId) -> F,
+) -> (Option<(PeerId, T)>, Vec<E>)
+where
+    F: Future<Output = Result<T, E>>,
+{
+    let send = |peer| {
+        let response = request(peer);
+        async move { (peer, response.await) }
+    };
+
+    let mut peers = peers.into_iter();
+    let mut in_flight = peers
+        .by_ref()
+        .take(concurrency.get())
+        .map(send)
+        .collect::<FuturesUnordered<_>>();
+    let mut errors = Vec::new();
+
+    while let Some((peer, result)) = in_flight.next().await {
+        match result {
+            Ok(x) => return (Some((peer, x)), errors),
+            Err(error) => {
+                errors.push(error);
+                if let Some(peer) = peers.next() {
+                    in_flight.push(send(peer));
+                }
+            }
+        }
     }
+
+    (None, errors)
 }
 
-/// Maximum number of blocks to request in a single request
-const MAX_BLOCKS_COUNT: u64 = 500;
+/// Default maximum number of blocks to request in a single request, see
+/// [`Client::with_max_request_limit`].
+const DEFAULT_MAX_REQUEST_LIMIT: u64 = 500;
 
 mod header_stream {
     use super::*;
 
-    pub fn make<PF, RF>(
+    #[allow(clippy::too_many_arguments)]
+    pub fn make<PF, RF, RS>(
         start: BlockNumber,
         stop: BlockNumber,
         reverse: bool,
+        step: NonZeroU64,
+        verify_parent_hashes: bool,
+        config: StreamConfig,
         get_peers: impl Fn() -> PF + Send + 'static,
         send_request: impl Fn(PeerId, BlockHeadersRequest) -> RF + Send + 'static,
-    ) -> impl Stream<Item = PeerData<SignedBlockHeader>>
+    ) -> impl Stream<Item = StreamItem<SignedBlockHeader>>
     where
         PF: Future<Output = Vec<PeerId>> + Send,
-        RF: Future<Output = anyhow::Result<fmpsc::Receiver<std::io::Result<BlockHeadersResponse>>>>
-            + Send,
+        RF: Future<Output = anyhow::Result<RS>> + Send,
+        RS: Stream<Item = std::io::Result<BlockHeadersResponse>> + Unpin + Send + 'static,
     {
         let start: i64 = start.get().try_into().expect("block number <= i64::MAX");
         let stop: i64 = stop.get().try_into().expect("block number <= i64::MAX");
@@ -641,24 +2543,75 @@ mod header_stream {
 
         tracing::trace!(?start, ?stop, ?dir, "Streaming headers");
 
-        let (tx, rx) = mpsc::channel(1);
-        tokio::spawn(async move {
+        // Headers which are not adjacent cannot be linked.
+        let verify_parent_hashes = verify_parent_hashes && step == NonZeroU64::MIN;
+
+        let (tx, rx) = mpsc::channel(config.buffer.get());
+        spawn_stream_task(&config, async move {
+            let mut peers =
+                PeerSnapshot::new(config.refresh_after_exhaustions, config.max_failed_rounds);
+            // Hash and parent hash of the last header yielded.
+            let mut last_header = None;
+
             // Loop which refreshes peer set once we exhaust it.
             loop {
-                'next_peer: for peer in get_peers().await {
-                    let mut responses =
-                        match send_request(peer, make_request(start, stop, dir)).await {
-                            Ok(x) => x,
-                            Err(error) => {
-                                tracing::debug!(%peer, reason=%error, "Headers request failed");
+                let position = BlockNumber::new_or_panic(u64::try_from(start).expect("start >= 0"));
+                if let Some(error) = peers.give_up(position) {
+                    tracing::debug!(%error, "Giving up");
+                    _ = tx.send(Err(PeerData::new(PeerId::random(), error))).await;
+                    return;
+                }
+                if peers.needs_refresh() {
+                    peers.refresh(get_peers().await);
+                }
+
+                'next_peer: for peer in peers.next_round() {
+                    let mut responses = match send_request(
+                        peer,
+                        make_request(start, stop, dir, step, config.max_request_limit),
+                    )
+                    .await
+                    {
+                        Ok(x) => x,
+                        Err(error) => {
+                            tracing::debug!(%peer, reason=%error, "Headers request failed");
+                            metrics::record_next_peer(config.name);
+                            continue 'next_peer;
+                        }
+                    };
+
+                    loop {
+                        let r = match config.next_response(peer, &mut responses).await {
+                            Ok(Some(r)) => r,
+                            Ok(None) => break,
+                            Err(_) => {
+                                if done(dir, start, stop) {
+                                    tracing::debug!(%peer, "Header stream Fin missing");
+                                    return;
+                                }
+                                metrics::record_next_peer(config.name);
                                 continue 'next_peer;
                             }
                         };
-
-                    while let Some(r) = responses.next().await {
-                        match handle_response(peer, r, dir, &mut start, stop, tx.clone()).await {
+                        let last = verify_parent_hashes.then_some(&mut last_header);
+                        match handle_response(
+                            peer,
+                            r,
+                            dir,
+                            step,
+                            last,
+                            &config,
+                            &mut start,
+                            stop,
+                            tx.clone(),
+                        )
+                        .await
+                        {
                             Action::NextResponse => {}
-                            Action::NextPeer => continue 'next_peer,
+                            Action::NextPeer => {
+                                metrics::record_next_peer(config.name);
+                                continue 'next_peer;
+                            }
                             Action::TerminateStream => return,
                         }
                     }
@@ -667,9 +2620,6 @@ mod header_stream {
                         tracing::debug!(%peer, "Header stream Fin missing");
                         return;
                     }
-
-                    // TODO: track how much and how fast this peer responded
-                    // with i.e. don't let them drip feed us etc.
                 }
             }
         });
@@ -677,13 +2627,17 @@ mod header_stream {
         ReceiverStream::new(rx)
     }
 
+    #[allow(clippy::too_many_arguments)]
     async fn handle_response(
         peer: PeerId,
         signed_header: std::io::Result<BlockHeadersResponse>,
         direction: Direction,
+        step: NonZeroU64,
+        last: Option<&mut Option<(BlockHash, BlockHash)>>,
+        config: &StreamConfig,
         start: &mut i64,
         stop: i64,
-        tx: mpsc::Sender<PeerData<SignedBlockHeader>>,
+        tx: mpsc::Sender<StreamItem<SignedBlockHeader>>,
     ) -> Action {
         match signed_header {
             Ok(BlockHeadersResponse::Header(hdr)) => match SignedBlockHeader::try_from_dto(*hdr) {
@@ -693,11 +2647,25 @@ mod header_stream {
                         return Action::TerminateStream;
                     }
 
-                    _ = tx.send(PeerData::new(peer, hdr)).await;
+                    if !config.item_limits.admit(&hdr.header) {
+                        tracing::debug!(%peer, block_number=%hdr.header.number, "Header advertises too many items");
+                        return Action::NextPeer;
+                    }
+
+                    if let Some(last) = last {
+                        if !links_to(&hdr, *last, direction) {
+                            tracing::debug!(%peer, block_number=%hdr.header.number, "Header does not link to the previous one");
+                            return Action::NextPeer;
+                        }
+                        *last = Some((hdr.header.hash, hdr.header.parent_hash));
+                    }
+
+                    _ = tx.send(Ok(PeerData::new(peer, hdr))).await;
 
+                    let step = i64::try_from(step.get()).expect("step <= i64::MAX");
                     *start = match direction {
-                        Direction::Forward => *start + 1,
-                        Direction::Backward => *start - 1,
+                        Direction::Forward => *start + step,
+                        Direction::Backward => *start - step,
                     };
 
                     Action::NextResponse
@@ -708,6 +2676,8 @@ mod header_stream {
                         return Action::TerminateStream;
                     }
 
+                    let block = u64::try_from(*start).expect("start >= 0");
+                    config.report_conversion_error(peer, BlockNumber::new_or_panic(block), error);
                     Action::NextPeer
                 }
             },
@@ -730,20 +2700,37 @@ mod header_stream {
         }
     }
 
-    fn make_request(start: i64, stop: i64, dir: Direction) -> BlockHeadersRequest {
-        let limit = start.abs_diff(stop) + 1;
-        let limit = limit.min(MAX_BLOCKS_COUNT);
-
+    fn make_request(
+        start: i64,
+        stop: i64,
+        dir: Direction,
+        step: NonZeroU64,
+        max_limit: NonZeroU64,
+    ) -> BlockHeadersRequest {
         BlockHeadersRequest {
             iteration: Iteration {
                 start: u64::try_from(start).expect("start >= 0").into(),
                 direction: dir,
-                limit,
-                step: 1.into(),
+                limit: request_limit(start.abs_diff(stop), step, max_limit),
+                step: step.get().into(),
             },
         }
     }
 
+    /// Checks that `header` follows the header with the given hash and parent
+    /// hash when walking in `direction`.
+    fn links_to(
+        header: &SignedBlockHeader,
+        last: Option<(BlockHash, BlockHash)>,
+        direction: Direction,
+    ) -> bool {
+        match (last, direction) {
+            (None, _) => true,
+            (Some((hash, _)), Direction::Forward) => header.header.parent_hash == hash,
+            (Some((_, parent_hash)), Direction::Backward) => header.header.hash == parent_hash,
+        }
+    }
+
     enum Action {
         NextResponse,
         NextPeer,
@@ -761,23 +2748,33 @@ mod header_stream {
 mod transaction_stream {
     use super::*;
 
-    pub fn make<PF, RF>(
-        mut start: BlockNumber,
+    #[allow(clippy::too_many_arguments)]
+    pub fn make<PF, RF, RS>(
+        start: BlockNumber,
         stop: BlockNumber,
+        reverse: bool,
+        step: NonZeroU64,
         counts_stream: impl Stream<Item = anyhow::Result<usize>> + Send + 'static,
+        verifier: Option<TransactionCommitmentVerifier>,
+        config: StreamConfig,
         get_peers: impl Fn() -> PF + Send + 'static,
         send_request: impl Fn(PeerId, TransactionsRequest) -> RF + Send + 'static,
     ) -> impl Stream<Item = StreamItem<(TransactionData, BlockNumber)>>
     where
         PF: Future<Output = Vec<PeerId>> + Send,
-        RF: Future<Output = anyhow::Result<fmpsc::Receiver<std::io::Result<TransactionsResponse>>>>
-            + Send,
+        RF: Future<Output = anyhow::Result<RS>> + Send,
+        RS: Stream<Item = std::io::Result<TransactionsResponse>> + Unpin + Send + 'static,
     {
-        tracing::trace!(?start, ?stop, "Streaming Transactions");
+        let (mut start, stop, dir) = walk_order(start, stop, reverse);
 
-        let (tx, rx) = mpsc::channel(1);
-        tokio::spawn(async move {
-            let mut counts_and_commitments_stream = Box::pin(counts_stream);
+        tracing::trace!(?start, ?stop, ?dir, "Streaming Transactions");
+
+        let (tx, rx) = mpsc::channel(config.buffer.get());
+        spawn_stream_task(&config, async move {
+            let mut counts_and_commitments_stream = Box::pin(BlockItemLimits::cap(
+                counts_stream,
+                config.item_limits.max_transactions,
+            ));
 
             let cnt = match try_next(&mut counts_and_commitments_stream).await {
                 Ok(x) => x,
@@ -790,37 +2787,111 @@ mod transaction_stream {
             // Transaction counter for the currently received block
             let mut progress = BlockProgress::new(cnt);
 
+            let mut peers =
+                PeerSnapshot::new(config.refresh_after_exhaustions, config.max_failed_rounds);
+            let mut unsupported_reports = UnsupportedReports::default();
+
             // Loop which refreshes peer set once we exhaust it.
             loop {
-                'next_peer: for peer in get_peers().await {
-                    let mut responses = match send_request(peer, make_request(start, stop)).await {
+                if let Some(error) = peers.give_up(start) {
+                    tracing::debug!(%error, "Giving up");
+                    _ = tx.send(Err(PeerData::new(PeerId::random(), error))).await;
+                    return;
+                }
+                if peers.needs_refresh() {
+                    peers.refresh(get_peers().await);
+                }
+
+                'next_peer: for peer in peers.next_round() {
+                    let request = make_request(start, stop, dir, step, config.max_request_limit);
+                    // The last block covered by this request, after which the peer must send Fin.
+                    let request_stop =
+                        advance(start, dir, (request.iteration.limit - 1) * step.get());
+                    let mut responses = match send_request(peer, request).await {
                         Ok(x) => x,
                         Err(error) => {
                             tracing::debug!(%peer, reason=%error, "Transactions request failed");
+                            config.penalize(peer).await;
                             continue 'next_peer;
                         }
                     };
                     // If the previous peer failed to provide the entire block we need to start over
                     progress.rollback();
 
-                    while start <= stop {
+                    while !past_stop(start, stop, dir) {
                         tracing::trace!(block_number=%start, num_responses=%progress.get(), "Expecting");
                         let mut transactions = Vec::new();
 
                         while progress.get() > 0 {
-                            match responses.next().await {
-                                Some(r) => {
+                            match config.next_response(peer, &mut responses).await {
+                                Ok(Some(r)) => {
                                     let i = into_idx(transactions.len());
-                                    match handle_response(peer, r, i) {
-                                        Some(x) => transactions.push(x),
-                                        None => continue 'next_peer,
+                                    match handle_response(peer, r, i, start, &config) {
+                                        Ok(x) => transactions.push(x),
+                                        Err(Some(unsupported)) => {
+                                            if unsupported_reports.confirms(peer, &unsupported) {
+                                                _ = tx
+                                                    .send(Err(PeerData::new(
+                                                        peer,
+                                                        StreamError::UnsupportedProtocolVersion(
+                                                            unsupported,
+                                                        ),
+                                                    )))
+                                                    .await;
+                                                return;
+                                            }
+                                            metrics::record_next_peer(config.name);
+                                            continue 'next_peer;
+                                        }
+                                        Err(None) => {
+                                            config.penalize(peer).await;
+                                            continue 'next_peer;
+                                        }
                                     }
                                 }
-                                None => continue 'next_peer,
+                                Ok(None) | Err(_) => {
+                                    config.penalize(peer).await;
+                                    continue 'next_peer;
+                                }
                             }
                             *progress.as_mut() -= 1;
+                            if !progress.meets_throughput(config.min_throughput) {
+                                tracing::debug!(%peer, block_number=%start, "Transaction throughput below minimum");
+                                config.penalize(peer).await;
+                                continue 'next_peer;
+                            }
+                        }
+
+                        if start == request_stop
+                            && !fin_follows(peer, &mut responses, &config).await
+                        {
+                            config.penalize(peer).await;
+                            continue 'next_peer;
+                        }
+
+                        if let Some(verifier) = &verifier {
+                            match verifier.verify(start, &transactions) {
+                                Ok(true) => {}
+                                Ok(false) => {
+                                    tracing::debug!(%peer, block_number=%start, "Transaction commitment mismatch");
+                                    config.penalize(peer).await;
+                                    continue 'next_peer;
+                                }
+                                Err(error) => {
+                                    // Not the peer's fault, so it is not reported as the source.
+                                    _ = tx
+                                        .send(Err(PeerData::new(
+                                            PeerId::random(),
+                                            StreamError::Other(error),
+                                        )))
+                                        .await;
+                                    return;
+                                }
+                            }
                         }
 
+                        config.reward(peer).await;
+
                         if yield_block(
                             peer,
                             &mut progress,
@@ -828,12 +2899,19 @@ mod transaction_stream {
                             transactions,
                             &mut start,
                             stop,
+                            dir,
+                            step,
                             tx.clone(),
                         )
                         .await
                         {
                             return;
                         }
+
+                        // The rest of the range is requested in a new window from the next peer.
+                        if past_stop(start, request_stop, dir) {
+                            continue 'next_peer;
+                        }
                     }
 
                     return;
@@ -846,51 +2924,82 @@ mod transaction_stream {
 
     /// ### Important
     ///
-    /// Return None if the caller should move to the next peer
+    /// Returns `Err(None)` if the caller should move to the next peer, and the
+    /// unsupported data if the peer sent data of a newer protocol version
     fn handle_response(
         peer: PeerId,
         response: std::io::Result<TransactionsResponse>,
         txn_idx: TransactionIndex,
-    ) -> Option<(TransactionVariant, Receipt)> {
+        block: BlockNumber,
+        config: &StreamConfig,
+    ) -> Result<(TransactionVariant, Receipt), Option<UnsupportedProtocolVersion>> {
         match response {
             Ok(TransactionsResponse::TransactionWithReceipt(TransactionWithReceipt {
                 transaction,
                 receipt,
             })) => {
-                if let (Ok(t), Ok(r)) = (
+                match (
                     TransactionVariant::try_from_dto(transaction),
                     Receipt::try_from((receipt, txn_idx)),
                 ) {
-                    Some((t, r))
-                } else {
-                    // TODO punish the peer
-                    tracing::debug!(%peer, "Transaction or receipt failed to parse");
-                    None
+                    (Ok(t), Ok(r)) => Ok((t, r)),
+                    (Err(error), _) | (_, Err(error)) => {
+                        tracing::debug!(%peer, %error, "Transaction or receipt failed to parse");
+                        Err(config.unsupported_or_report(peer, block, error))
+                    }
                 }
             }
             Ok(TransactionsResponse::Fin) => {
                 // This peer will not give us more blocks, move to the next peer
-                None
+                Err(None)
             }
             Err(error) => {
                 tracing::debug!(%peer, %error, "Transaction response stream failed");
-                None
+                Err(UnsupportedProtocolVersion::from_response_error(&error))
+            }
+        }
+    }
+
+    /// Checks that the peer does not send any more transactions once all the
+    /// requested blocks are complete. Within the requested range surplus
+    /// transactions are indistinguishable from the next block's, so this is
+    /// only checked after the last block of a request.
+    ///
+    /// ### Important
+    ///
+    /// Returns false if the caller should move to the next peer
+    async fn fin_follows(
+        peer: PeerId,
+        responses: &mut (impl Stream<Item = std::io::Result<TransactionsResponse>> + Unpin),
+        config: &StreamConfig,
+    ) -> bool {
+        match config.next_response(peer, responses).await {
+            Ok(Some(Ok(TransactionsResponse::TransactionWithReceipt(_)))) => {
+                tracing::debug!(%peer, "More transactions than expected");
+                false
             }
+            // All the requested data has been received at this point, so a peer that does
+            // not bother to send Fin is not penalized.
+            Ok(Some(Ok(TransactionsResponse::Fin))) | Ok(Some(Err(_))) | Ok(None) | Err(_) => true,
         }
     }
 
-    fn make_request(start: BlockNumber, stop: BlockNumber) -> TransactionsRequest {
+    fn make_request(
+        start: BlockNumber,
+        stop: BlockNumber,
+        dir: Direction,
+        step: NonZeroU64,
+        max_limit: NonZeroU64,
+    ) -> TransactionsRequest {
         let start = start.get();
         let stop = stop.get();
-        let limit = start.abs_diff(stop) + 1;
-        let limit = limit.min(MAX_BLOCKS_COUNT);
 
         TransactionsRequest {
             iteration: Iteration {
                 start: start.into(),
-                direction: Direction::Forward,
-                limit,
-                step: 1.into(),
+                direction: dir,
+                limit: request_limit(start.abs_diff(stop), step, max_limit),
+                step: step.get().into(),
             },
         }
     }
@@ -902,6 +3011,7 @@ mod transaction_stream {
     /// ### Important
     ///
     /// Returns true if the stream should be terminated
+    #[allow(clippy::too_many_arguments)]
     async fn yield_block(
         peer: PeerId,
         progress: &mut BlockProgress,
@@ -909,6 +3019,8 @@ mod transaction_stream {
         transactions: Vec<(TransactionVariant, Receipt)>,
         start: &mut BlockNumber,
         stop: BlockNumber,
+        dir: Direction,
+        step: NonZeroU64,
         tx: mpsc::Sender<StreamItem<(TransactionData, BlockNumber)>>,
     ) -> bool {
         tracing::trace!(block_number=%start, "All transactions received for block");
@@ -917,11 +3029,11 @@ mod transaction_stream {
             .send(Ok(PeerData::new(peer, (transactions, *start))))
             .await;
 
-        if *start == stop {
+        if is_last(*start, stop, step) {
             return true;
         }
 
-        *start += 1;
+        *start = advance(*start, dir, step.get());
 
         let x = match try_next(count_stream).await {
             Ok(x) => x,
@@ -942,23 +3054,33 @@ mod transaction_stream {
 mod state_diff_stream {
     use super::*;
 
-    pub fn make<PF, RF>(
-        mut start: BlockNumber,
+    #[allow(clippy::too_many_arguments)]
+    pub fn make<PF, RF, RS>(
+        start: BlockNumber,
         stop: BlockNumber,
+        reverse: bool,
+        step: NonZeroU64,
         length_stream: impl Stream<Item = anyhow::Result<usize>> + Send + 'static,
+        commitments: Option<StateDiffCommitmentLookup>,
+        config: StreamConfig,
         get_peers: impl Fn() -> PF + Send + 'static,
         send_request: impl Fn(PeerId, StateDiffsRequest) -> RF + Send + 'static,
     ) -> impl Stream<Item = StreamItem<(StateUpdateData, BlockNumber)>>
     where
         PF: Future<Output = Vec<PeerId>> + Send,
-        RF: Future<Output = anyhow::Result<fmpsc::Receiver<std::io::Result<StateDiffsResponse>>>>
-            + Send,
+        RF: Future<Output = anyhow::Result<RS>> + Send,
+        RS: Stream<Item = std::io::Result<StateDiffsResponse>> + Unpin + Send + 'static,
     {
-        tracing::trace!(?start, ?stop, "Streaming state diffs");
+        let (mut start, stop, dir) = walk_order(start, stop, reverse);
 
-        let (tx, rx) = mpsc::channel(1);
-        tokio::spawn(async move {
-            let mut length_stream = Box::pin(length_stream);
+        tracing::trace!(?start, ?stop, ?dir, "Streaming state diffs");
+
+        let (tx, rx) = mpsc::channel(config.buffer.get());
+        spawn_stream_task(&config, async move {
+            let mut length_stream = Box::pin(BlockItemLimits::cap(
+                length_stream,
+                config.item_limits.max_state_diff_length,
+            ));
 
             let cnt = match try_next(&mut length_stream).await {
                 Ok(x) => x,
@@ -970,36 +3092,86 @@ mod state_diff_stream {
 
             let mut progress = BlockProgress::new(cnt);
 
+            let mut peers =
+                PeerSnapshot::new(config.refresh_after_exhaustions, config.max_failed_rounds);
+
             // Loop which refreshes peer set once we exhaust it.
             loop {
-                'next_peer: for peer in get_peers().await {
-                    let mut responses = match send_request(peer, make_request(start, stop)).await {
+                if let Some(error) = peers.give_up(start) {
+                    tracing::debug!(%error, "Giving up");
+                    _ = tx.send(Err(PeerData::new(PeerId::random(), error))).await;
+                    return;
+                }
+                if peers.needs_refresh() {
+                    peers.refresh(get_peers().await);
+                }
+
+                'next_peer: for peer in peers.next_round() {
+                    let request = make_request(start, stop, dir, step, config.max_request_limit);
+                    // The last block covered by this request.
+                    let request_stop =
+                        advance(start, dir, (request.iteration.limit - 1) * step.get());
+                    let mut responses = match send_request(peer, request).await {
                         Ok(x) => x,
                         Err(error) => {
                             tracing::debug!(%peer, reason=%error, "State diff request failed");
+                            config.penalize(peer).await;
                             continue 'next_peer;
                         }
                     };
                     // If the previous peer failed to provide the entire block we need to start over
                     progress.rollback();
 
-                    while start <= stop {
+                    while !past_stop(start, stop, dir) {
                         tracing::trace!(block_number=%start, num_responses=%progress.get(), "Expecting");
                         let mut state_diff = StateUpdateData::default();
 
                         while progress.get() > 0 {
-                            match responses.next().await {
-                                Some(r) => {
+                            match config.next_response(peer, &mut responses).await {
+                                Ok(Some(r)) => {
                                     if handle_response(peer, r, &mut state_diff, &mut progress)
                                         .is_none()
                                     {
+                                        config.penalize(peer).await;
                                         continue 'next_peer;
                                     }
                                 }
-                                None => continue 'next_peer,
+                                Ok(None) | Err(_) => {
+                                    config.penalize(peer).await;
+                                    continue 'next_peer;
+                                }
+                            }
+                            if !progress.meets_throughput(config.min_throughput) {
+                                tracing::debug!(%peer, block_number=%start, "State diff throughput below minimum");
+                                config.penalize(peer).await;
+                                continue 'next_peer;
+                            }
+                        }
+
+                        if let Some(commitments) = &commitments {
+                            let expected = match commitments.get(start) {
+                                Ok(x) => x,
+                                Err(error) => {
+                                    // Not the peer's fault, so it is not reported as the source.
+                                    _ = tx
+                                        .send(Err(PeerData::new(
+                                            PeerId::random(),
+                                            StreamError::Other(error),
+                                        )))
+                                        .await;
+                                    return;
+                                }
+                            };
+                            let actual = state_diff.compute_state_diff_commitment();
+                            if actual != expected {
+                                tracing::debug!(%peer, block_number=%start, %expected, %actual, "State diff commitment mismatch");
+                                config.penalize(peer).await;
+                                continue 'next_peer;
                             }
                         }
 
+                        config.reward(peer).await;
+
                         if yield_block(
                             peer,
                             &mut progress,
@@ -1007,12 +3179,19 @@ mod state_diff_stream {
                             state_diff,
                             &mut start,
                             stop,
+                            dir,
+                            step,
                             tx.clone(),
                         )
                         .await
                         {
                             return;
                         }
+
+                        // The rest of the range is requested in a new window from the next peer.
+                        if past_stop(start, request_stop, dir) {
+                            continue 'next_peer;
+                        }
                     }
 
                     return;
@@ -1038,13 +3217,27 @@ mod state_diff_stream {
                 nonce,
                 class_hash,
                 values,
-                domain: _,
+                domain,
             })) => {
                 let address = ContractAddress(address.0);
 
+                // Splitting the diff of a contract across responses is not allowed.
+                if state_diff.contract_updates.contains_key(&address)
+                    || state_diff.system_contract_updates.contains_key(&address)
+                {
+                    tracing::debug!(%peer, %address, "Repeated contract diff");
+                    return None;
+                }
+
                 progress.checked_sub_assign(values.len())?;
+                state_diff.set_storage_domain(address, DataAvailabilityMode::from_dto(domain));
 
                 if address == ContractAddress::ONE {
+                    // The system contract has neither a nonce nor a class.
+                    if nonce.is_some() || class_hash.is_some() {
+                        tracing::debug!(%peer, "System contract diff with a nonce or class");
+                        return None;
+                    }
                     let storage = &mut state_diff
                         .system_contract_updates
                         .entry(address)
@@ -1105,18 +3298,22 @@ mod state_diff_stream {
         Some(())
     }
 
-    fn make_request(start: BlockNumber, stop: BlockNumber) -> StateDiffsRequest {
+    fn make_request(
+        start: BlockNumber,
+        stop: BlockNumber,
+        dir: Direction,
+        step: NonZeroU64,
+        max_limit: NonZeroU64,
+    ) -> StateDiffsRequest {
         let start = start.get();
         let stop = stop.get();
-        let limit = start.abs_diff(stop) + 1;
-        let limit = limit.min(MAX_BLOCKS_COUNT);
 
         StateDiffsRequest {
             iteration: Iteration {
                 start: start.into(),
-                direction: Direction::Forward,
-                limit,
-                step: 1.into(),
+                direction: dir,
+                limit: request_limit(start.abs_diff(stop), step, max_limit),
+                step: step.get().into(),
             },
         }
     }
@@ -1124,6 +3321,7 @@ mod state_diff_stream {
     /// ### Important
     ///
     /// Returns true if the stream should be terminated
+    #[allow(clippy::too_many_arguments)]
     async fn yield_block(
         peer: PeerId,
         progress: &mut BlockProgress,
@@ -1131,17 +3329,19 @@ mod state_diff_stream {
         state_diff: StateUpdateData,
         start: &mut BlockNumber,
         stop: BlockNumber,
+        dir: Direction,
+        step: NonZeroU64,
         tx: mpsc::Sender<StreamItem<(StateUpdateData, BlockNumber)>>,
     ) -> bool {
         tracing::trace!(block_number=%start, "State diff received for block");
 
         _ = tx.send(Ok(PeerData::new(peer, (state_diff, *start)))).await;
 
-        if *start == stop {
+        if is_last(*start, stop, step) {
             return true;
         }
 
-        *start += 1;
+        *start = advance(*start, dir, step.get());
 
         let cnt = match try_next(len_stream).await {
             Ok(x) => x,
@@ -1162,23 +3362,33 @@ mod state_diff_stream {
 mod class_definition_stream {
     use super::*;
 
-    pub fn make<PF, RF>(
-        mut start: BlockNumber,
+    #[allow(clippy::too_many_arguments)]
+    pub fn make<PF, RF, RS>(
+        start: BlockNumber,
         stop: BlockNumber,
+        reverse: bool,
+        step: NonZeroU64,
         counts_stream: impl Stream<Item = anyhow::Result<usize>> + Send + 'static,
+        filter: ClassFilter,
+        config: StreamConfig,
         get_peers: impl Fn() -> PF + Send + 'static,
         send_request: impl Fn(PeerId, ClassesRequest) -> RF + Send + 'static,
     ) -> impl Stream<Item = StreamItem<ClassDefinition>>
     where
         PF: Future<Output = Vec<PeerId>> + Send,
-        RF: Future<Output = anyhow::Result<fmpsc::Receiver<std::io::Result<ClassesResponse>>>>
-            + Send,
+        RF: Future<Output = anyhow::Result<RS>> + Send,
+        RS: Stream<Item = std::io::Result<ClassesResponse>> + Unpin + Send + 'static,
     {
-        tracing::trace!(?start, ?stop, "Streaming classes");
+        let (mut start, stop, dir) = walk_order(start, stop, reverse);
 
-        let (tx, rx) = mpsc::channel(1);
-        tokio::spawn(async move {
-            let mut declared_class_counts_stream = Box::pin(counts_stream);
+        tracing::trace!(?start, ?stop, ?dir, "Streaming classes");
+
+        let (tx, rx) = mpsc::channel(config.buffer.get());
+        spawn_stream_task(&config, async move {
+            let mut declared_class_counts_stream = Box::pin(BlockItemLimits::cap(
+                counts_stream,
+                config.item_limits.max_classes,
+            ));
 
             let cnt = match try_next(&mut declared_class_counts_stream).await {
                 Ok(x) => x,
@@ -1190,38 +3400,90 @@ mod class_definition_stream {
 
             let mut progress = BlockProgress::new(cnt);
 
+            let mut peers =
+                PeerSnapshot::new(config.refresh_after_exhaustions, config.max_failed_rounds);
+            let mut unsupported_reports = UnsupportedReports::default();
+
             // Loop which refreshes peer set once we exhaust it.
             loop {
-                'next_peer: for peer in get_peers().await {
-                    let mut responses = match send_request(peer, make_request(start, stop)).await {
+                if let Some(error) = peers.give_up(start) {
+                    tracing::debug!(%error, "Giving up");
+                    _ = tx.send(Err(PeerData::new(PeerId::random(), error))).await;
+                    return;
+                }
+                if peers.needs_refresh() {
+                    peers.refresh(get_peers().await);
+                }
+
+                'next_peer: for peer in peers.next_round() {
+                    let request = make_request(start, stop, dir, step, config.max_request_limit);
+                    // The last block covered by this request.
+                    let request_stop =
+                        advance(start, dir, (request.iteration.limit - 1) * step.get());
+                    let mut responses = match send_request(peer, request).await {
                         Ok(x) => x,
                         Err(error) => {
                             // Failed to establish connection, try next peer.
                             tracing::debug!(%peer, reason=%error, "Classes request failed");
+                            config.penalize(peer).await;
                             continue 'next_peer;
                         }
                     };
                     // If the previous peer failed to provide the entire block we need to start over
                     progress.rollback();
 
-                    while start <= stop {
+                    while !past_stop(start, stop, dir) {
                         tracing::trace!(block_number=%start, expected_classes=%progress.get(), "Expecting class definition responses");
                         let mut class_definitions = Vec::new();
 
                         while progress.get() > 0 {
-                            if let Some(response) = responses.next().await {
-                                match handle_response(peer, response, start) {
-                                    Some(x) => class_definitions.push(x),
-                                    None => continue 'next_peer,
+                            if let Ok(Some(response)) =
+                                config.next_response(peer, &mut responses).await
+                            {
+                                let accepted = match &response {
+                                    Ok(ClassesResponse::Class(class)) => filter.accepts(class),
+                                    _ => true,
+                                };
+                                match handle_response(peer, response, start, &config) {
+                                    // Filtered out classes are parsed anyway to make sure the
+                                    // peer is not sending garbage.
+                                    Ok(x) if accepted => class_definitions.push(x),
+                                    Ok(_) => {}
+                                    Err(Some(unsupported)) => {
+                                        if unsupported_reports.confirms(peer, &unsupported) {
+                                            _ = tx
+                                                .send(Err(PeerData::new(
+                                                    peer,
+                                                    StreamError::UnsupportedProtocolVersion(
+                                                        unsupported,
+                                                    ),
+                                                )))
+                                                .await;
+                                            return;
+                                        }
+                                        metrics::record_next_peer(config.name);
+                                        continue 'next_peer;
+                                    }
+                                    Err(None) => {
+                                        config.penalize(peer).await;
+                                        continue 'next_peer;
+                                    }
                                 }
                                 *progress.as_mut() -= 1;
+                                if !progress.meets_throughput(config.min_throughput) {
+                                    tracing::debug!(%peer, block_number=%start, "Class definition throughput below minimum");
+                                    config.penalize(peer).await;
+                                    continue 'next_peer;
+                                }
                             } else {
                                 tracing::debug!(%peer, "Premature class definition stream termination");
-                                // TODO punish the peer
+                                config.penalize(peer).await;
                                 continue 'next_peer;
                             }
                         }
 
+                        config.reward(peer).await;
+
                         if yield_block(
                             peer,
                             &mut progress,
@@ -1229,12 +3491,19 @@ mod class_definition_stream {
                             class_definitions,
                             &mut start,
                             stop,
+                            dir,
+                            step,
                             tx.clone(),
                         )
                         .await
                         {
                             return;
                         }
+
+                        // The rest of the range is requested in a new window from the next peer.
+                        if past_stop(start, request_stop, dir) {
+                            continue 'next_peer;
+                        }
                     }
 
                     return;
@@ -1245,62 +3514,71 @@ mod class_definition_stream {
         ReceiverStream::new(rx)
     }
 
-    fn make_request(start: BlockNumber, stop: BlockNumber) -> ClassesRequest {
+    fn make_request(
+        start: BlockNumber,
+        stop: BlockNumber,
+        dir: Direction,
+        step: NonZeroU64,
+        max_limit: NonZeroU64,
+    ) -> ClassesRequest {
         let start = start.get();
         let stop = stop.get();
-        let limit = start.abs_diff(stop) + 1;
-        let limit = limit.min(MAX_BLOCKS_COUNT);
 
         ClassesRequest {
             iteration: Iteration {
                 start: start.into(),
-                direction: Direction::Forward,
-                limit,
-                step: 1.into(),
+                direction: dir,
+                limit: request_limit(start.abs_diff(stop), step, max_limit),
+                step: step.get().into(),
             },
         }
     }
 
     /// ### Important
     ///
-    /// Returns `None` if the caller should move to the next peer
+    /// Returns `Err(None)` if the caller should move to the next peer, and the
+    /// unsupported data if the peer sent data of a newer protocol version
     fn handle_response(
         peer: PeerId,
         response: std::io::Result<ClassesResponse>,
         block_number: BlockNumber,
-    ) -> Option<ClassDefinition> {
+        config: &StreamConfig,
+    ) -> Result<ClassDefinition, Option<UnsupportedProtocolVersion>> {
         match response {
-            Ok(ClassesResponse::Class(p2p_proto::class::Class::Cairo0 { class, domain: _ })) => {
-                let Ok(CairoDefinition(definition)) = CairoDefinition::try_from_dto(class) else {
-                    // TODO punish the peer
-                    tracing::debug!(%peer, "Cairo definition failed to parse");
-                    return None;
-                };
-
-                Some(ClassDefinition::Cairo {
+            Ok(ClassesResponse::Class(p2p_proto::class::Class::Cairo0 { class, domain })) => {
+                let CairoDefinition(definition) =
+                    CairoDefinition::try_from_dto(class).map_err(|error| {
+                        tracing::debug!(%peer, %error, "Cairo definition failed to parse");
+                        config.unsupported_or_report(peer, block_number, error)
+                    })?;
+
+                Ok(ClassDefinition::Cairo {
                     block_number,
                     definition,
+                    domain: DataAvailabilityMode::from_dto(domain),
                 })
             }
-            Ok(ClassesResponse::Class(p2p_proto::class::Class::Cairo1 { class, domain: _ })) => {
-                let Ok(SierraDefinition(definition)) = SierraDefinition::try_from_dto(class) else {
-                    // TODO punish the peer
-                    tracing::debug!(%peer, "Sierra definition failed to parse");
-                    return None;
-                };
-
-                Some(ClassDefinition::Sierra {
+            Ok(ClassesResponse::Class(p2p_proto::class::Class::Cairo1 { class, domain })) => {
+                let SierraDefinition(definition, interface) = SierraDefinition::try_from_dto(class)
+                    .map_err(|error| {
+                        tracing::debug!(%peer, %error, "Sierra definition failed to parse");
+                        config.unsupported_or_report(peer, block_number, error)
+                    })?;
+
+                Ok(ClassDefinition::Sierra {
                     block_number,
                     sierra_definition: definition,
+                    interface: Some(interface),
+                    domain: DataAvailabilityMode::from_dto(domain),
                 })
             }
             Ok(ClassesResponse::Fin) => {
                 tracing::debug!(%peer, "Received FIN, continuing with next peer");
-                None
+                Err(None)
             }
             Err(error) => {
                 tracing::debug!(%peer, %error, "Class definition response stream failed");
-                None
+                Err(UnsupportedProtocolVersion::from_response_error(&error))
             }
         }
     }
@@ -1308,6 +3586,7 @@ mod class_definition_stream {
     /// ### Important
     ///
     /// Returns true if the stream should be terminated
+    #[allow(clippy::too_many_arguments)]
     async fn yield_block(
         peer: PeerId,
         progress: &mut BlockProgress,
@@ -1315,6 +3594,8 @@ mod class_definition_stream {
         class_definitions: Vec<ClassDefinition>,
         start: &mut BlockNumber,
         stop: BlockNumber,
+        dir: Direction,
+        step: NonZeroU64,
         tx: mpsc::Sender<StreamItem<ClassDefinition>>,
     ) -> bool {
         tracing::trace!(block_number=%start, "All classes received for block");
@@ -1323,11 +3604,11 @@ mod class_definition_stream {
             _ = tx.send(Ok(PeerData::new(peer, class_definition))).await;
         }
 
-        if *start == stop {
+        if is_last(*start, stop, step) {
             return true;
         }
 
-        *start += 1;
+        *start = advance(*start, dir, step.get());
 
         let cnt = match try_next(counts_stream).await {
             Ok(x) => x,
@@ -1347,39 +3628,69 @@ mod class_definition_stream {
 mod event_stream {
     use super::*;
 
-    pub fn make<PF, RF>(
-        mut start: BlockNumber,
+    #[allow(clippy::too_many_arguments)]
+    pub fn make<PF, RF, RS>(
+        start: BlockNumber,
         stop: BlockNumber,
+        reverse: bool,
+        step: NonZeroU64,
         counts_stream: impl Stream<Item = anyhow::Result<usize>> + Send + 'static,
+        verifier: Option<EventCommitmentVerifier>,
+        header_counts: Option<EventCountLookup>,
+        config: StreamConfig,
         get_peers: impl Fn() -> PF + Send + 'static,
         send_request: impl Fn(PeerId, EventsRequest) -> RF + Send + 'static,
     ) -> impl Stream<Item = StreamItem<EventsForBlockByTransaction>>
     where
         PF: Future<Output = Vec<PeerId>> + Send,
-        RF: Future<Output = anyhow::Result<fmpsc::Receiver<std::io::Result<EventsResponse>>>>
-            + Send,
+        RF: Future<Output = anyhow::Result<RS>> + Send,
+        RS: Stream<Item = std::io::Result<EventsResponse>> + Unpin + Send + 'static,
     {
-        tracing::trace!(?start, ?stop, "Streaming events");
+        let (mut start, stop, dir) = walk_order(start, stop, reverse);
 
-        let (tx, rx) = mpsc::channel(1);
-        tokio::spawn(async move {
-            let mut counts_stream = Box::pin(counts_stream);
+        tracing::trace!(?start, ?stop, ?dir, "Streaming events");
+
+        let (tx, rx) = mpsc::channel(config.buffer.get());
+        spawn_stream_task(&config, async move {
+            let mut counts_stream = Box::pin(BlockItemLimits::cap(
+                counts_stream,
+                config.item_limits.max_events,
+            ));
 
-            let Some(Ok(cnt)) = counts_stream.next().await else {
-                tracing::debug!("Event counts stream terminated prematurely");
-                return;
+            let cnt = match try_next(&mut counts_stream).await {
+                Ok(x) => x,
+                Err(e) => {
+                    _ = tx.send(Err(e)).await;
+                    return;
+                }
             };
 
             let mut progress = BlockProgress::new(cnt);
 
+            let mut peers =
+                PeerSnapshot::new(config.refresh_after_exhaustions, config.max_failed_rounds);
+
             // Loop which refreshes peer set once we exhaust it.
             loop {
-                'next_peer: for peer in get_peers().await {
-                    let mut responses = match send_request(peer, make_request(start, stop)).await {
+                if let Some(error) = peers.give_up(start) {
+                    tracing::debug!(%error, "Giving up");
+                    _ = tx.send(Err(PeerData::new(PeerId::random(), error))).await;
+                    return;
+                }
+                if peers.needs_refresh() {
+                    peers.refresh(get_peers().await);
+                }
+
+                'next_peer: for peer in peers.next_round() {
+                    let request = make_request(start, stop, dir, step, config.max_request_limit);
+                    // The last block covered by this request.
+                    let request_stop =
+                        advance(start, dir, (request.iteration.limit - 1) * step.get());
+                    let mut responses = match send_request(peer, request).await {
                         Ok(x) => x,
                         Err(error) => {
-                            // TODO punish the peer
                             tracing::debug!(%peer, reason=%error, "Events request failed");
+                            config.penalize(peer).await;
                             continue 'next_peer;
                         }
                     };
@@ -1391,24 +3702,85 @@ mod event_stream {
                     // over
                     progress.rollback();
 
-                    while start <= stop {
+                    while !past_stop(start, stop, dir) {
                         tracing::trace!(block_number=%start, expected_responses=%progress.get(), "Expecting event responses");
                         let mut events: Vec<(TransactionHash, Vec<Event>)> = Vec::new();
 
                         while progress.get() > 0 {
-                            if let Some(response) = responses.next().await {
-                                if handle_response(peer, response, &mut txn, &mut events) {
+                            if let Ok(Some(response)) =
+                                config.next_response(peer, &mut responses).await
+                            {
+                                if handle_response(
+                                    peer,
+                                    response,
+                                    &mut txn,
+                                    &mut events,
+                                    start,
+                                    &config,
+                                ) {
+                                    config.penalize(peer).await;
                                     continue 'next_peer;
                                 }
 
                                 *progress.as_mut() -= 1;
+                                if !progress.meets_throughput(config.min_throughput) {
+                                    tracing::debug!(%peer, block_number=%start, "Event throughput below minimum");
+                                    config.penalize(peer).await;
+                                    continue 'next_peer;
+                                }
                             } else {
-                                // TODO punish the peer
-                                tracing::debug!(%peer, block_number=%start, "Premature event stream termination");
+                                tracing::debug!(%peer, block_number=%start, expected=%progress.expected(), received=%progress.received(), "Premature event stream termination");
+                                config.penalize(peer).await;
                                 continue 'next_peer;
                             }
                         }
 
+                        // The total number of events in a block is authenticated by the header,
+                        // even for pre 0.13.2 blocks where the grouping by transaction is not.
+                        if let Some(header_counts) = &header_counts {
+                            match header_counts.get(start) {
+                                Ok(count) if total_events(&events) == count => {}
+                                Ok(count) => {
+                                    tracing::debug!(%peer, block_number=%start, expected=%count, received=%total_events(&events), "Event count does not match header");
+                                    config.penalize(peer).await;
+                                    continue 'next_peer;
+                                }
+                                Err(error) => {
+                                    // Not the peer's fault, so it is not reported as the source.
+                                    _ = tx
+                                        .send(Err(PeerData::new(
+                                            PeerId::random(),
+                                            StreamError::Other(error),
+                                        )))
+                                        .await;
+                                    return;
+                                }
+                            }
+                        }
+
+                        if let Some(verifier) = &verifier {
+                            match verifier.verify(start, &events) {
+                                Ok(true) => {}
+                                Ok(false) => {
+                                    tracing::debug!(%peer, block_number=%start, "Event commitment mismatch");
+                                    config.penalize(peer).await;
+                                    continue 'next_peer;
+                                }
+                                Err(error) => {
+                                    // Not the peer's fault, so it is not reported as the source.
+                                    _ = tx
+                                        .send(Err(PeerData::new(
+                                            PeerId::random(),
+                                            StreamError::Other(error),
+                                        )))
+                                        .await;
+                                    return;
+                                }
+                            }
+                        }
+
+                        config.reward(peer).await;
+
                         if yield_block(
                             peer,
                             &mut progress,
@@ -1416,12 +3788,19 @@ mod event_stream {
                             events,
                             &mut start,
                             stop,
+                            dir,
+                            step,
                             tx.clone(),
                         )
                         .await
                         {
                             return;
                         }
+
+                        // The rest of the range is requested in a new window from the next peer.
+                        if past_stop(start, request_stop, dir) {
+                            continue 'next_peer;
+                        }
                     }
 
                     return;
@@ -1432,22 +3811,32 @@ mod event_stream {
         ReceiverStream::new(rx)
     }
 
-    fn make_request(start: BlockNumber, stop: BlockNumber) -> EventsRequest {
+    fn make_request(
+        start: BlockNumber,
+        stop: BlockNumber,
+        dir: Direction,
+        step: NonZeroU64,
+        max_limit: NonZeroU64,
+    ) -> EventsRequest {
         let start = start.get();
         let stop = stop.get();
-        let limit = start.abs_diff(stop) + 1;
-        let limit = limit.min(MAX_BLOCKS_COUNT);
 
         EventsRequest {
             iteration: Iteration {
                 start: start.into(),
-                direction: Direction::Forward,
-                limit,
-                step: 1.into(),
+                direction: dir,
+                limit: request_limit(start.abs_diff(stop), step, max_limit),
+                step: step.get().into(),
             },
         }
     }
 
+    /// The number of events received for a block, to be checked against the
+    /// `event_count` from the block header.
+    fn total_events(events: &[(TransactionHash, Vec<Event>)]) -> usize {
+        events.iter().map(|(_, events)| events.len()).sum()
+    }
+
     /// ### Important
     ///
     /// Returns true if the caller should move to the next peer
@@ -1456,14 +3845,19 @@ mod event_stream {
         response: std::io::Result<EventsResponse>,
         current_txn: &mut Option<TransactionHash>,
         events: &mut Vec<(TransactionHash, Vec<Event>)>,
+        block: BlockNumber,
+        config: &StreamConfig,
     ) -> bool {
         match response {
             Ok(EventsResponse::Event(event)) => {
                 let txn_hash = TransactionHash(event.transaction_hash.0);
-                let Ok(event) = Event::try_from_dto(event) else {
-                    // TODO punish the peer
-                    tracing::debug!(%peer, "Event failed to parse");
-                    return true;
+                let event = match Event::try_from_dto(event) {
+                    Ok(event) => event,
+                    Err(error) => {
+                        tracing::debug!(%peer, %error, "Event failed to parse");
+                        config.report_conversion_error(peer, block, error);
+                        return true;
+                    }
                 };
 
                 match current_txn {
@@ -1494,6 +3888,7 @@ mod event_stream {
     /// ### Important
     ///
     /// Returns true if the stream should be terminated
+    #[allow(clippy::too_many_arguments)]
     async fn yield_block(
         peer: PeerId,
         progress: &mut BlockProgress,
@@ -1501,17 +3896,19 @@ mod event_stream {
         events: Vec<(TransactionHash, Vec<Event>)>,
         start: &mut BlockNumber,
         stop: BlockNumber,
+        dir: Direction,
+        step: NonZeroU64,
         tx: mpsc::Sender<StreamItem<EventsForBlockByTransaction>>,
     ) -> bool {
         tracing::trace!(block_number=%start, "All events received for block");
 
         _ = tx.send(Ok(PeerData::new(peer, (*start, events)))).await;
 
-        if *start == stop {
+        if is_last(*start, stop, step) {
             return true;
         }
 
-        *start += 1;
+        *start = advance(*start, dir, step.get());
 
         let cnt = match try_next(counts_stream).await {
             Ok(x) => x,
@@ -1528,30 +3925,541 @@ mod event_stream {
     }
 }
 
+mod full_block_stream {
+    use super::*;
+
+    type CountSender = fmpsc::UnboundedSender<anyhow::Result<usize>>;
+
+    /// Feed the counts streams of the underlying sync streams.
+    pub struct CountSenders {
+        pub transactions: CountSender,
+        pub state_diffs: CountSender,
+        pub classes: CountSender,
+        pub events: CountSender,
+    }
+
+    /// Assembles the items of the underlying streams into full blocks. The
+    /// counts of each header are sent as soon as the header arrives, while
+    /// the number of declared classes is only known once the state diff of the
+    /// block arrives.
+    pub fn make(
+        config: StreamConfig,
+        headers: impl Stream<Item = StreamItem<SignedBlockHeader>> + Send + 'static,
+        counts: CountSenders,
+        transactions: impl Stream<Item = StreamItem<(TransactionData, BlockNumber)>> + Send + 'static,
+        state_diffs: impl Stream<Item = StreamItem<(StateUpdateData, BlockNumber)>> + Send + 'static,
+        classes: impl Stream<Item = StreamItem<ClassDefinition>> + Send + 'static,
+        events: impl Stream<Item = StreamItem<EventsForBlockByTransaction>> + Send + 'static,
+    ) -> impl Stream<Item = StreamItem<FullBlock>> {
+        let CountSenders {
+            transactions: transaction_counts,
+            state_diffs: state_diff_lengths,
+            classes: class_counts,
+            events: event_counts,
+        } = counts;
+
+        // Headers are read ahead of the assembled blocks, up to the buffer size.
+        let (header_tx, mut header_rx) = mpsc::channel(config.buffer.get());
+        tokio::spawn(async move {
+            let mut headers = Box::pin(headers);
+            while let Some(header) = headers.next().await {
+                if let Ok(PeerData { data, .. }) = &header {
+                    let header = &data.header;
+                    _ = transaction_counts.unbounded_send(Ok(header.transaction_count));
+                    _ = state_diff_lengths.unbounded_send(Ok(header.state_diff_length as usize));
+                    _ = event_counts.unbounded_send(Ok(header.event_count));
+                }
+                if header_tx.send(header).await.is_err() {
+                    return;
+                }
+            }
+        });
+
+        let (tx, rx) = mpsc::channel(config.buffer.get());
+        spawn_stream_task(&config, async move {
+            let mut transactions = Box::pin(transactions);
+            let mut state_diffs = Box::pin(state_diffs);
+            let mut classes = Box::pin(classes);
+            let mut events = Box::pin(events);
+
+            while let Some(header) = header_rx.recv().await {
+                let block = match header {
+                    Ok(header) => {
+                        assemble(
+                            header,
+                            &mut transactions,
+                            &mut state_diffs,
+                            &class_counts,
+                            &mut classes,
+                            &mut events,
+                        )
+                        .await
+                    }
+                    Err(error) => Err(error),
+                };
+                let failed = block.is_err();
+                if tx.send(block).await.is_err() || failed {
+                    return;
+                }
+            }
+        });
+
+        ReceiverStream::new(rx)
+    }
+
+    async fn assemble(
+        header: PeerData<SignedBlockHeader>,
+        transactions: &mut (impl Stream<Item = StreamItem<(TransactionData, BlockNumber)>> + Unpin),
+        state_diffs: &mut (impl Stream<Item = StreamItem<(StateUpdateData, BlockNumber)>> + Unpin),
+        class_counts: &CountSender,
+        classes: &mut (impl Stream<Item = StreamItem<ClassDefinition>> + Unpin),
+        events: &mut (impl Stream<Item = StreamItem<EventsForBlockByTransaction>> + Unpin),
+    ) -> StreamItem<FullBlock> {
+        let PeerData { peer, data: header } = header;
+        let block = header.header.number;
+
+        let (transactions, _) = next_for_block(transactions, block, |(_, n)| *n).await?;
+        let (state_diff, _) = next_for_block(state_diffs, block, |(_, n)| *n).await?;
+
+        let class_count =
+            state_diff.declared_cairo_classes.len() + state_diff.declared_sierra_classes.len();
+        _ = class_counts.unbounded_send(Ok(class_count));
+        let mut class_definitions = Vec::with_capacity(class_count);
+        for _ in 0..class_count {
+            class_definitions
+                .push(next_for_block(classes, block, ClassDefinition::block_number).await?);
+        }
+
+        let (_, events) = next_for_block(events, block, |(n, _)| *n).await?;
+
+        Ok(PeerData::new(
+            peer,
+            FullBlock {
+                header,
+                transactions,
+                state_diff,
+                classes: class_definitions,
+                events,
+            },
+        ))
+    }
+
+    /// The underlying streams cover the same range in the same order, so
+    /// running out of items or receiving data of another block is not the
+    /// fault of any peer.
+    async fn next_for_block<T>(
+        stream: &mut (impl Stream<Item = StreamItem<T>> + Unpin),
+        block: BlockNumber,
+        block_of: impl Fn(&T) -> BlockNumber,
+    ) -> Result<T, PeerData<StreamError>> {
+        match stream.next().await {
+            Some(Ok(PeerData { data, .. })) if block_of(&data) == block => Ok(data),
+            Some(Ok(PeerData { data, .. })) => Err(PeerData::new(
+                PeerId::random(),
+                StreamError::Other(anyhow::anyhow!(
+                    "Expected data of block {block}, got block {}",
+                    block_of(&data)
+                )),
+            )),
+            Some(Err(error)) => Err(error),
+            None => Err(PeerData::new(
+                PeerId::random(),
+                StreamError::Other(anyhow::anyhow!("Stream ended before block {block}")),
+            )),
+        }
+    }
+}
+
+mod redundant_stream {
+    use super::*;
+
+    /// Zips streams which yield the same sequence of items, cross-checking the
+    /// items at each position. Ends as soon as any of the streams ends, after
+    /// yielding the first error found at that position, if any.
+    pub fn merge<T, S>(streams: Vec<S>) -> impl Stream<Item = StreamItem<Agreement<T>>>
+    where
+        T: PartialEq,
+        S: Stream<Item = StreamItem<T>> + Unpin,
+    {
+        futures::stream::unfold(Some(streams), |streams| async move {
+            let mut streams = streams?;
+            let items = futures::future::join_all(streams.iter_mut().map(|s| s.next()))
+                .await
+                .into_iter()
+                .collect::<Option<Vec<_>>>()?;
+            let items = match items.into_iter().collect::<Result<Vec<_>, _>>() {
+                Ok(items) => items,
+                Err(error) => return Some((Err(error), None)),
+            };
+
+            let peer = items.first()?.peer;
+            let agreement = if items.iter().all(|x| x.data == items[0].data) {
+                Agreement::Agreed(items.into_iter().next().expect("not empty").data)
+            } else {
+                tracing::debug!(peers=?items.iter().map(|x| x.peer).collect::<Vec<_>>(), "Peers disagree");
+                Agreement::Disagreed(items)
+            };
+
+            Some((Ok(PeerData::new(peer, agreement)), Some(streams)))
+        })
+    }
+}
+
+/// Settings of a single sync stream.
+#[derive(Clone, Debug)]
+struct StreamConfig {
+    /// Identifies the stream in the metrics.
+    name: &'static str,
+    buffer: NonZeroUsize,
+    refresh_after_exhaustions: NonZeroUsize,
+    max_failed_rounds: Option<NonZeroUsize>,
+    /// Items per second.
+    min_throughput: Option<f64>,
+    response_timeout: Duration,
+    /// Maximum number of blocks requested from a peer at once.
+    max_request_limit: NonZeroU64,
+    /// Where the outcomes of the requests to each peer are recorded.
+    peers: Arc<RwLock<PeerState>>,
+    cancellation: CancellationToken,
+    /// Shared by the streams of a client to bound how many run at once.
+    permits: Option<Arc<Semaphore>>,
+    item_limits: BlockItemLimits,
+    conversion_errors: Option<fmpsc::UnboundedSender<PeerData<ConversionError>>>,
+}
+
+impl StreamConfig {
+    /// Waits for the next response from `peer` for at most
+    /// `response_timeout`. On timeout the caller is expected to move on to
+    /// the next peer.
+    async fn next_response<S: Stream + Unpin>(
+        &self,
+        peer: PeerId,
+        responses: &mut S,
+    ) -> Result<Option<S::Item>, tokio::time::error::Elapsed> {
+        let response = tokio::time::timeout(self.response_timeout, responses.next()).await;
+        if response.is_err() {
+            tracing::debug!(%peer, timeout=?self.response_timeout, "Timed out waiting for response");
+        }
+        response
+    }
+
+    /// Records that `peer` provided a valid block.
+    async fn reward(&self, peer: PeerId) {
+        self.peers
+            .write()
+            .await
+            .scores
+            .entry(peer)
+            .or_insert_with(PeerScore::new)
+            .record_success();
+    }
+
+    /// Passes the data for `block` which `peer` sent but which failed to parse
+    /// on to the consumer of the stream, if it asked for it.
+    fn report_conversion_error(&self, peer: PeerId, block: BlockNumber, error: anyhow::Error) {
+        if let Some(errors) = &self.conversion_errors {
+            _ = errors.unbounded_send(PeerData::new(peer, ConversionError { block, error }));
+        }
+    }
+
+    /// Tells data introduced by a newer version of the protocol apart from
+    /// malformed data, which is reported as a conversion error.
+    fn unsupported_or_report(
+        &self,
+        peer: PeerId,
+        block: BlockNumber,
+        error: anyhow::Error,
+    ) -> Option<UnsupportedProtocolVersion> {
+        let unsupported = UnsupportedProtocolVersion::from_conversion_error(&error);
+        if unsupported.is_none() {
+            self.report_conversion_error(peer, block, error);
+        }
+        unsupported
+    }
+
+    /// Records that `peer` failed to provide a valid block.
+    async fn penalize(&self, peer: PeerId) {
+        metrics::record_next_peer(self.name);
+        self.peers
+            .write()
+            .await
+            .scores
+            .entry(peer)
+            .or_insert_with(PeerScore::new)
+            .record_failure();
+    }
+}
+
+impl Default for StreamConfig {
+    fn default() -> Self {
+        Self {
+            name: "test",
+            buffer: NonZeroUsize::MIN,
+            refresh_after_exhaustions: NonZeroUsize::MIN,
+            max_failed_rounds: None,
+            min_throughput: None,
+            response_timeout: Client::DEFAULT_RESPONSE_TIMEOUT,
+            max_request_limit: NonZeroU64::new(DEFAULT_MAX_REQUEST_LIMIT).expect("500>0"),
+            peers: Default::default(),
+            cancellation: CancellationToken::new(),
+            permits: None,
+            item_limits: Default::default(),
+            conversion_errors: None,
+        }
+    }
+}
+
+/// Number of distinct peers which have to send the same unsupported data
+/// before a stream ends with [`StreamError::UnsupportedProtocolVersion`].
+const UNSUPPORTED_PROTOCOL_VERSION_QUORUM: usize = 2;
+
+/// Peers which sent data introduced by a newer version of the protocol. A
+/// single peer claiming so could just as well be lying, so the stream only
+/// gives up once enough peers agree.
+#[derive(Default)]
+struct UnsupportedReports(HashMap<UnsupportedProtocolVersion, HashSet<PeerId>>);
+
+impl UnsupportedReports {
+    /// Records that `peer` sent `unsupported` and returns true if enough
+    /// distinct peers sent the same data.
+    fn confirms(&mut self, peer: PeerId, unsupported: &UnsupportedProtocolVersion) -> bool {
+        let peers = self.0.entry(unsupported.clone()).or_default();
+        peers.insert(peer);
+        peers.len() >= UNSUPPORTED_PROTOCOL_VERSION_QUORUM
+    }
+}
+
+/// A set of peers which is reused for a number of rounds before it is
+/// refreshed.
+struct PeerSnapshot {
+    peers: Option<Vec<PeerId>>,
+    rounds: usize,
+    refresh_after_exhaustions: NonZeroUsize,
+    /// Where the stream was at the start of the last round.
+    position: Option<BlockNumber>,
+    failed_rounds: usize,
+    max_failed_rounds: Option<NonZeroUsize>,
+}
+
+impl PeerSnapshot {
+    fn new(
+        refresh_after_exhaustions: NonZeroUsize,
+        max_failed_rounds: Option<NonZeroUsize>,
+    ) -> Self {
+        Self {
+            peers: None,
+            rounds: 0,
+            refresh_after_exhaustions,
+            position: None,
+            failed_rounds: 0,
+            max_failed_rounds,
+        }
+    }
+
+    /// Called before each round with the block the stream is waiting for.
+    /// Returns the error to end the stream with if too many rounds in a row
+    /// went by without the stream moving on from `position`.
+    fn give_up(&mut self, position: BlockNumber) -> Option<StreamError> {
+        self.failed_rounds = match self.position.replace(position) {
+            Some(previous) if previous == position => self.failed_rounds + 1,
+            _ => 0,
+        };
+
+        if self.failed_rounds < self.max_failed_rounds?.get() {
+            return None;
+        }
+
+        match self.peers.as_deref() {
+            Some([]) | None => Some(StreamError::NoPeers),
+            Some(_) => Some(StreamError::AllPeersFailed { block: position }),
+        }
+    }
+
+    fn needs_refresh(&self) -> bool {
+        self.peers.is_none() || self.rounds >= self.refresh_after_exhaustions.get()
+    }
+
+    fn refresh(&mut self, peers: Vec<PeerId>) {
+        self.peers = Some(peers);
+        self.rounds = 0;
+    }
+
+    /// Called each time the caller starts going through the peer set, which
+    /// means the previous round has been exhausted.
+    fn next_round(&mut self) -> Vec<PeerId> {
+        self.rounds += 1;
+        self.peers.clone().unwrap_or_default()
+    }
+}
+
+/// Orders `start..=stop` in the direction in which a stream walks through it,
+/// i.e. swaps `start` and `stop` for reverse streams.
+fn walk_order(
+    start: BlockNumber,
+    stop: BlockNumber,
+    reverse: bool,
+) -> (BlockNumber, BlockNumber, Direction) {
+    match reverse {
+        true => (stop, start, Direction::Backward),
+        false => (start, stop, Direction::Forward),
+    }
+}
+
+/// Moves `n` blocks away from `block` in `dir`.
+fn advance(block: BlockNumber, dir: Direction, n: u64) -> BlockNumber {
+    match dir {
+        Direction::Forward => block + n,
+        Direction::Backward => block - n,
+    }
+}
+
+/// Number of blocks covered by a request walking from `start` towards `stop`,
+/// `distance` blocks away, with `step`, capped at `max_limit`.
+fn request_limit(distance: u64, step: NonZeroU64, max_limit: NonZeroU64) -> u64 {
+    (distance / step.get() + 1).min(max_limit.get())
+}
+
+/// Returns true if `block` is the last one on the walk towards `stop` with
+/// `step`.
+fn is_last(block: BlockNumber, stop: BlockNumber, step: NonZeroU64) -> bool {
+    block.get().abs_diff(stop.get()) < step.get()
+}
+
+/// Returns true if walking from `block` in `dir` already went past `stop`.
+fn past_stop(block: BlockNumber, stop: BlockNumber, dir: Direction) -> bool {
+    match dir {
+        Direction::Forward => block > stop,
+        Direction::Backward => block < stop,
+    }
+}
+
+/// Spawns the task driving a sync stream. Once `cancellation` is cancelled the
+/// task stops at its next await point, without making further requests or
+/// yielding further items.
+///
+/// The task only starts once it holds one of the `permits` of the stream, if
+/// any.
+fn spawn_stream_task(config: &StreamConfig, task: impl Future<Output = ()> + Send + 'static) {
+    let cancellation = config.cancellation.clone();
+    let name = config.name;
+    let permits = config.permits.clone();
+    tokio::spawn(async move {
+        let task = async move {
+            let _permit = acquire_stream_permit(permits).await;
+            task.await
+        };
+        tokio::select! {
+            biased;
+            _ = cancellation.cancelled() => tracing::debug!("Sync stream cancelled"),
+            _ = task => metrics::record_terminated(name),
+        }
+    });
+}
+
+async fn acquire_stream_permit(permits: Option<Arc<Semaphore>>) -> Option<OwnedSemaphorePermit> {
+    match permits {
+        Some(permits) => Some(
+            permits
+                .acquire_owned()
+                .await
+                .expect("Stream permits are never closed"),
+        ),
+        None => None,
+    }
+}
+
+/// The block announced by a message on the block propagation topic.
+fn announced_block(data: &[u8]) -> Option<p2p_proto::common::BlockId> {
+    use p2p_proto::header::NewBlock;
+    use prost::Message;
+
+    let new_block = p2p_proto::proto::header::NewBlock::decode(data).ok()?;
+    match NewBlock::try_from_protobuf(new_block, "message").ok()? {
+        NewBlock::Id(id) => Some(id),
+        NewBlock::Header(BlockHeadersResponse::Header(hdr)) => Some(p2p_proto::common::BlockId {
+            number: hdr.number,
+            hash: hdr.block_hash,
+        }),
+        NewBlock::Header(BlockHeadersResponse::Fin) => None,
+    }
+}
+
+/// Takes the next count from a counts stream of one of the sync streams.
+///
+/// An error from the counts stream, e.g. a failed database read, is passed on
+/// as is, while a counts stream which ends before the sync stream does is
+/// reported as [`StreamError::PrematureTermination`]. Neither is the fault of
+/// a peer.
 async fn try_next<T>(
     count_stream: &mut (impl Stream<Item = anyhow::Result<T>> + Unpin + Send + 'static),
-) -> Result<T, PeerData<anyhow::Error>> {
+) -> Result<T, PeerData<StreamError>> {
     match count_stream.next().await {
         Some(Ok(cnt)) => Ok(cnt),
-        Some(Err(e)) => Err(PeerData::new(PeerId::random(), e)),
+        Some(Err(e)) => Err(PeerData::new(PeerId::random(), StreamError::Other(e))),
         None => Err(PeerData::new(
             PeerId::random(),
-            anyhow::anyhow!("Count stream terminated prematurely"),
+            StreamError::PrematureTermination,
         )),
     }
 }
 
+/// Ends `responses` once `consecutive` of them in a row each took longer than
+/// `threshold` to arrive, so that the sync stream moves on to another peer.
+fn end_when_slow<S>(
+    peer: PeerId,
+    responses: S,
+    threshold: Duration,
+    consecutive: NonZeroUsize,
+) -> impl Stream<Item = S::Item> + Send + 'static
+where
+    S: Stream + Unpin + Send + 'static,
+    S::Item: Send,
+{
+    futures::stream::unfold((responses, 0), move |(mut responses, slow)| async move {
+        let waiting_since = tokio::time::Instant::now();
+        let response = responses.next().await?;
+        let slow = if waiting_since.elapsed() > threshold {
+            slow + 1
+        } else {
+            0
+        };
+        if slow >= consecutive.get() {
+            tracing::debug!(%peer, %slow, "Peer responding too slowly, moving on");
+            return None;
+        }
+        Some((response, (responses, slow)))
+    })
+}
+
+/// Assigns `peer` to one of `partitions` disjoint peer sets. The assignment is
+/// stable for the lifetime of the process.
+fn peer_partition(peer: &PeerId, partitions: NonZeroUsize) -> usize {
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
+
+    let mut hasher = DefaultHasher::new();
+    peer.hash(&mut hasher);
+    (hasher.finish() % partitions.get() as u64) as usize
+}
+
 #[derive(Clone, Copy, Debug)]
 struct BlockProgress {
     count: usize,
     count_backup: usize,
+    /// When the current attempt at receiving this block started.
+    started: Instant,
 }
 
 impl BlockProgress {
+    /// Peers are not judged on their throughput before this much time has
+    /// passed, as the first responses of a request can take a while.
+    const THROUGHPUT_GRACE_PERIOD: Duration = Duration::from_secs(1);
+
     fn new(count: usize) -> Self {
         Self {
             count,
             count_backup: count,
+            started: Instant::now(),
         }
     }
 
@@ -1559,6 +4467,16 @@ impl BlockProgress {
         self.count
     }
 
+    /// The total count this block started with.
+    fn expected(&self) -> usize {
+        self.count_backup
+    }
+
+    /// The count received so far in the current attempt at this block.
+    fn received(&self) -> usize {
+        self.count_backup - self.count
+    }
+
     fn checked_sub_assign(&mut self, x: usize) -> Option<()> {
         self.count = self.count.checked_sub(x)?;
         Some(())
@@ -1566,8 +4484,24 @@ impl BlockProgress {
 
     fn rollback(&mut self) -> Self {
         self.count = self.count_backup;
+        self.started = Instant::now();
         *self
     }
+
+    /// Returns false if the items of this block received so far arrived at a
+    /// lower average rate than `min_throughput` items per second.
+    fn meets_throughput(&self, min_throughput: Option<f64>) -> bool {
+        let Some(min_throughput) = min_throughput else {
+            return true;
+        };
+
+        let elapsed = self.started.elapsed();
+        if elapsed < Self::THROUGHPUT_GRACE_PERIOD {
+            return true;
+        }
+
+        self.received() as f64 / elapsed.as_secs_f64() >= min_throughput
+    }
 }
 
 impl AsMut<usize> for BlockProgress {
@@ -1579,7 +4513,8 @@ impl AsMut<usize> for BlockProgress {
 #[derive(Clone, Debug)]
 struct Decaying<T> {
     data: T,
-    last_update: Instant,
+    /// `None` until the first update.
+    last_update: Option<Instant>,
     timeout: Duration,
 }
 
@@ -1589,9 +4524,7 @@ impl<T: Default> Decaying<T> {
     pub fn new(timeout: Duration) -> Self {
         Self {
             data: Default::default(),
-            last_update: Instant::now()
-                .checked_sub(Self::DEFAULT_TIMEOUT * 2)
-                .expect("Still valid Instant"),
+            last_update: None,
             timeout,
         }
     }
@@ -1599,15 +4532,14 @@ impl<T: Default> Decaying<T> {
     /// Does not clear if elapsed, instead the caller is expected to call
     /// [`Self::update`]
     pub fn get(&self) -> Option<&T> {
-        if self.last_update.elapsed() > self.timeout {
-            None
-        } else {
-            Some(&self.data)
+        match self.last_update {
+            Some(last_update) if last_update.elapsed() <= self.timeout => Some(&self.data),
+            _ => None,
         }
     }
 
     pub fn update(&mut self, data: T) {
-        self.last_update = Instant::now();
+        self.last_update = Some(Instant::now());
         self.data = data;
     }
 }
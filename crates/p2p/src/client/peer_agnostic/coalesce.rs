@@ -0,0 +1,282 @@
+//! Coalescing of concurrent single block requests
+//!
+//! If a block is requested while a request for the same block and data is
+//! already in flight, the second requester waits for the result of the first
+//! one instead of downloading the block again. Only valid results are shared,
+//! if the first request fails the others fall back to making their own.
+//!
+//! Requests are keyed by the block and by whatever else the result is
+//! validated against, such as the expected number of items, so that a
+//! requester is never handed a result validated against someone else's
+//! expectations.
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::future::Future;
+use std::hash::Hash;
+use std::sync::{Arc, Mutex};
+
+use futures::channel::oneshot;
+use futures::future::{BoxFuture, Shared};
+use futures::stream::BoxStream;
+use futures::{FutureExt, Stream, StreamExt};
+use libp2p::PeerId;
+use pathfinder_common::event::Event;
+use pathfinder_common::state_update::StateUpdateData;
+use pathfinder_common::transaction::TransactionVariant;
+use pathfinder_common::{BlockNumber, SignedBlockHeader, TransactionHash};
+use tokio::sync::watch;
+
+use crate::client::types::{ClassDefinition, Receipt};
+
+type SharedResult<T> = Shared<BoxFuture<'static, Option<(PeerId, T)>>>;
+
+pub(super) struct Inflight<K, T>(Mutex<HashMap<K, SharedResult<T>>>);
+
+impl<K, T> Default for Inflight<K, T> {
+    fn default() -> Self {
+        Self(Default::default())
+    }
+}
+
+/// Single block requests in flight, per kind of data requested.
+///
+/// State diffs and class definitions are also keyed by the number of items the
+/// requester expects.
+#[derive(Default)]
+pub(super) struct InflightRequests {
+    pub headers: Inflight<BlockNumber, SignedBlockHeader>,
+    pub transactions: Inflight<BlockNumber, Arc<Broadcast<(TransactionVariant, Receipt)>>>,
+    pub state_diffs: Inflight<(BlockNumber, u64), StateUpdateData>,
+    pub class_definitions: Inflight<(BlockNumber, u64), Vec<ClassDefinition>>,
+    pub events: Inflight<BlockNumber, Arc<Broadcast<(TransactionHash, Event)>>>,
+}
+
+impl std::fmt::Debug for InflightRequests {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("InflightRequests").finish_non_exhaustive()
+    }
+}
+
+/// Removes the entry of a request from [`Inflight`] once the request is done,
+/// or is dropped before that.
+struct Done<'a, K: Eq + Hash, T> {
+    inflight: &'a Inflight<K, T>,
+    key: K,
+}
+
+impl<K: Eq + Hash, T> Drop for Done<'_, K, T> {
+    fn drop(&mut self) {
+        self.inflight
+            .0
+            .lock()
+            .expect("Inflight requests lock is not poisoned")
+            .remove(&self.key);
+    }
+}
+
+/// Runs `fetch` for `key`, unless a request for `key` is already in flight,
+/// in which case its result is reused.
+///
+/// - `share` extracts the data from a result, if the result is valid and can be
+///   shared with other requesters,
+/// - `reuse` turns data shared by another requester into a result.
+pub(super) async fn coalesce<K, T, R, F>(
+    inflight: &Inflight<K, T>,
+    key: K,
+    fetch: impl FnOnce() -> F,
+    share: impl FnOnce(&R) -> Option<(PeerId, T)>,
+    reuse: impl FnOnce((PeerId, T)) -> R,
+) -> R
+where
+    K: Eq + Hash + Clone,
+    T: Clone + Send + Sync + 'static,
+    F: Future<Output = R>,
+{
+    let in_flight = match inflight
+        .0
+        .lock()
+        .expect("Inflight requests lock is not poisoned")
+        .entry(key.clone())
+    {
+        Entry::Occupied(entry) => Ok(entry.get().clone()),
+        Entry::Vacant(entry) => {
+            let (sender, receiver) = oneshot::channel();
+            entry.insert(
+                receiver
+                    .map(|result| result.ok().flatten())
+                    .boxed()
+                    .shared(),
+            );
+            Err(sender)
+        }
+    };
+
+    match in_flight {
+        Ok(shared) => match shared.await {
+            Some(data) => reuse(data),
+            None => fetch().await,
+        },
+        Err(sender) => {
+            let done = Done { inflight, key };
+            let result = fetch().await;
+            drop(done);
+            // The other requesters may have given up already.
+            _ = sender.send(share(&result));
+            result
+        }
+    }
+}
+
+/// Like [`coalesce`], for requests whose data is streamed.
+///
+/// The data is streamed to the first requester as it arrives and replayed to
+/// the requesters which joined while the peer to stream from was being looked
+/// for, at the pace at which the first requester consumes it. If the stream of
+/// the first requester fails, or it is dropped before the end, the streams of
+/// the others end with `failed`.
+pub(super) async fn coalesce_stream<K, T, E, S, F>(
+    inflight: &Inflight<K, Arc<Broadcast<T>>>,
+    key: K,
+    fetch: impl FnOnce() -> F,
+    failed: impl FnOnce(PeerId) -> E + Send + 'static,
+) -> Option<(PeerId, BoxStream<'static, Result<T, E>>)>
+where
+    K: Eq + Hash + Clone,
+    T: Clone + Send + Sync + 'static,
+    E: Send + 'static,
+    S: Stream<Item = Result<T, E>> + Send + 'static,
+    F: Future<Output = Option<(PeerId, S)>>,
+{
+    let found = coalesce(
+        inflight,
+        key,
+        || async {
+            let (peer, stream) = fetch().await?;
+            let broadcast = Arc::new(Broadcast::default());
+            Some((peer, broadcast.clone().publish(stream), broadcast))
+        },
+        |found| {
+            let (peer, _, broadcast) = found.as_ref()?;
+            Some((*peer, broadcast.clone()))
+        },
+        |(peer, broadcast)| Some((peer, broadcast.subscribe(peer, failed), broadcast)),
+    )
+    .await;
+
+    found.map(|(peer, stream, _)| (peer, stream))
+}
+
+/// The items of a stream, kept to be replayed to other requesters, see
+/// [`coalesce_stream`].
+pub(super) struct Broadcast<T> {
+    replay: watch::Sender<Replay<T>>,
+}
+
+struct Replay<T> {
+    items: Vec<T>,
+    end: Option<End>,
+}
+
+#[derive(Clone, Copy)]
+enum End {
+    Complete,
+    Failed,
+}
+
+impl<T> Default for Broadcast<T> {
+    fn default() -> Self {
+        let (replay, _) = watch::channel(Replay {
+            items: Vec::new(),
+            end: None,
+        });
+        Self { replay }
+    }
+}
+
+impl<T: Clone + Send + Sync + 'static> Broadcast<T> {
+    /// Passes `stream` through, keeping each of its items for the subscribers.
+    fn publish<E, S>(self: Arc<Self>, stream: S) -> BoxStream<'static, Result<T, E>>
+    where
+        E: Send + 'static,
+        S: Stream<Item = Result<T, E>> + Send + 'static,
+    {
+        futures::stream::unfold(
+            (stream.boxed(), Publisher(self)),
+            |(mut stream, publisher)| async move {
+                match stream.next().await {
+                    Some(Ok(item)) => {
+                        publisher.push(item.clone());
+                        Some((Ok(item), (stream, publisher)))
+                    }
+                    Some(Err(error)) => {
+                        publisher.end(End::Failed);
+                        Some((Err(error), (stream, publisher)))
+                    }
+                    None => {
+                        publisher.end(End::Complete);
+                        None
+                    }
+                }
+            },
+        )
+        .boxed()
+    }
+
+    /// Replays the items published so far and then those published later.
+    fn subscribe<E>(
+        &self,
+        peer: PeerId,
+        failed: impl FnOnce(PeerId) -> E + Send + 'static,
+    ) -> BoxStream<'static, Result<T, E>>
+    where
+        E: Send + 'static,
+    {
+        let replay = self.replay.subscribe();
+        futures::stream::unfold(Some((replay, 0, failed)), move |state| async move {
+            let (mut replay, next, failed) = state?;
+            loop {
+                let (item, end) = {
+                    let replay = replay.borrow_and_update();
+                    (replay.items.get(next).cloned(), replay.end)
+                };
+                if let Some(item) = item {
+                    return Some((Ok(item), Some((replay, next + 1, failed))));
+                }
+                match end {
+                    Some(End::Complete) => return None,
+                    Some(End::Failed) => return Some((Err(failed(peer)), None)),
+                    None => {}
+                }
+                if replay.changed().await.is_err() {
+                    return Some((Err(failed(peer)), None));
+                }
+            }
+        })
+        .boxed()
+    }
+}
+
+/// Ends the [`Broadcast`] as failed if the stream is dropped before its end.
+struct Publisher<T>(Arc<Broadcast<T>>);
+
+impl<T> Publisher<T> {
+    fn push(&self, item: T) {
+        self.0.replay.send_modify(|replay| replay.items.push(item));
+    }
+
+    fn end(&self, end: End) {
+        self.0.replay.send_if_modified(|replay| {
+            let first = replay.end.is_none();
+            if first {
+                replay.end = Some(end);
+            }
+            first
+        });
+    }
+}
+
+impl<T> Drop for Publisher<T> {
+    fn drop(&mut self) {
+        self.end(End::Failed);
+    }
+}
@@ -13,7 +13,7 @@ use p2p_proto::state::{ContractDiff, ContractStoredValue, DeclaredClass, StateDi
 use p2p_proto::transaction::{TransactionWithReceipt, TransactionsResponse};
 use pathfinder_common::event::Event;
 use pathfinder_common::state_update::{ContractClassUpdate, ContractUpdate, StateUpdateData};
-use pathfinder_common::transaction::TransactionVariant;
+use pathfinder_common::transaction::{DataAvailabilityMode, TransactionVariant};
 use pathfinder_common::{
     BlockHeader,
     BlockNumber,
@@ -29,9 +29,10 @@ use tagged::Tagged;
 use tagged_debug_derive::TaggedDebug;
 use tokio::sync::Mutex;
 
-use super::ClassDefinition;
-use crate::client::conv::{CairoDefinition, SierraDefinition, ToDto, TryFromDto};
+use super::{ClassDefinition, Client};
+use crate::client::conv::{CairoDefinition, FromDto, SierraDefinition, ToDto, TryFromDto};
 use crate::client::peer_agnostic::Receipt;
+use crate::client::peer_aware;
 
 #[derive(Clone, PartialEq, TaggedDebug)]
 pub struct TestPeer(pub PeerId);
@@ -101,6 +102,86 @@ pub async fn send_request<T>(
     }
 }
 
+/// A step of the response of a [`ScriptedPeer`] to a single request.
+pub enum Step<T> {
+    Respond(T),
+    /// The response stream fails, e.g. because the peer disconnected.
+    Fail,
+    /// The response stream stays open without yielding anything else, so the
+    /// request times out.
+    Stall,
+}
+
+/// A peer which answers each request it receives with the next of its
+/// scripted responses, regardless of what is requested. Requests for which
+/// no response is scripted fail.
+#[derive(Clone)]
+pub struct ScriptedPeer<T> {
+    pub peer: TestPeer,
+    scripts: Arc<std::sync::Mutex<VecDeque<Vec<Step<T>>>>>,
+    /// Keeps the stalled response streams open.
+    stalled: Arc<std::sync::Mutex<Vec<mpsc::Sender<std::io::Result<T>>>>>,
+}
+
+impl<T> ScriptedPeer<T> {
+    pub fn new(peer: TestPeer) -> Self {
+        Self {
+            peer,
+            scripts: Default::default(),
+            stalled: Default::default(),
+        }
+    }
+
+    /// Scripts the response to the request after the ones scripted so far.
+    pub fn then(self, steps: Vec<Step<T>>) -> Self {
+        self.scripts.lock().unwrap().push_back(steps);
+        self
+    }
+
+    pub fn respond(&self) -> anyhow::Result<mpsc::Receiver<std::io::Result<T>>> {
+        let steps = self
+            .scripts
+            .lock()
+            .unwrap()
+            .pop_front()
+            .ok_or_else(|| anyhow::anyhow!("peer failed"))?;
+        let (mut tx, rx) = mpsc::channel(steps.len() + 1);
+        for step in steps {
+            match step {
+                Step::Respond(response) => tx.try_send(Ok(response)).unwrap(),
+                Step::Fail => {
+                    tx.try_send(Err(std::io::Error::other("connection lost")))
+                        .unwrap();
+                    break;
+                }
+                Step::Stall => {
+                    self.stalled.lock().unwrap().push(tx);
+                    break;
+                }
+            }
+        }
+        Ok(rx)
+    }
+}
+
+/// Sends each request to the scripted peer it is addressed to.
+#[allow(clippy::type_complexity)]
+pub fn scripted_requests<T, R>(
+    peers: &[ScriptedPeer<T>],
+) -> impl Fn(PeerId, R) -> std::future::Ready<anyhow::Result<mpsc::Receiver<std::io::Result<T>>>>
+where
+    T: Clone,
+{
+    let peers = peers.to_vec();
+    move |peer, _| {
+        let scripted = peers
+            .iter()
+            .find(|scripted| scripted.peer.0 == peer)
+            .expect("request to a scripted peer");
+        std::future::ready(scripted.respond())
+    }
+}
+
 pub fn hdr_resp(tag: i32) -> BlockHeadersResponse {
     let h = hdr(tag);
     BlockHeadersResponse::Header(Box::new(h.to_dto()))
@@ -113,6 +194,10 @@ pub fn hdr(tag: i32) -> SignedBlockHeader {
             // TODO Set storage and class commitment
             storage_commitment: Default::default(),
             class_commitment: Default::default(),
+            // Within the default item limits
+            transaction_count: (0..1000).fake(),
+            event_count: (0..1000).fake(),
+            state_diff_length: (0..1000).fake(),
             ..Faker.fake()
         },
         ..Faker.fake()
@@ -178,6 +263,25 @@ pub fn contract_diff(tag: i32) -> StateDiffsResponse {
     )
 }
 
+/// Same as [`contract_diff`], except that the class hash is sent in a second
+/// diff for the same contract.
+pub fn split_contract_diff(tag: i32) -> Vec<StateDiffsResponse> {
+    let StateDiffsResponse::ContractDiff(mut first) = contract_diff(tag) else {
+        unreachable!()
+    };
+    let second = ContractDiff {
+        address: first.address,
+        nonce: None,
+        class_hash: first.class_hash.take(),
+        values: vec![],
+        domain: first.domain,
+    };
+    vec![
+        StateDiffsResponse::ContractDiff(first),
+        StateDiffsResponse::ContractDiff(second),
+    ]
+}
+
 pub fn declared_class(tag: i32) -> StateDiffsResponse {
     let sd = state_diff(tag);
     let (class_hash, compiled_class_hash) = sd
@@ -228,6 +332,7 @@ pub fn state_diff(tag: i32) -> StateUpdateData {
         system_contract_updates,
         declared_cairo_classes,
         declared_sierra_classes,
+        storage_domains: Default::default(),
     })
     .unwrap()
     .data
@@ -267,6 +372,26 @@ pub fn surplus_class() -> StateDiffsResponse {
     })
 }
 
+pub fn system_contract_nonce() -> StateDiffsResponse {
+    StateDiffsResponse::ContractDiff(ContractDiff {
+        address: Address(ContractAddress::ONE.0),
+        nonce: Some(Faker.fake()),
+        class_hash: None,
+        values: vec![],
+        domain: Faker.fake(),
+    })
+}
+
+pub fn system_contract_class() -> StateDiffsResponse {
+    StateDiffsResponse::ContractDiff(ContractDiff {
+        address: Address(ContractAddress::ONE.0),
+        nonce: None,
+        class_hash: Some(Faker.fake()),
+        values: vec![],
+        domain: Faker.fake(),
+    })
+}
+
 pub fn class_resp(tag: i32) -> ClassesResponse {
     use pathfinder_common::class_definition::ClassDefinition;
     let c = Tagged::<Class>::get(format!("class response {tag}"), || {
@@ -274,11 +399,11 @@ pub fn class_resp(tag: i32) -> ClassesResponse {
         match c {
             ClassDefinition::Sierra(s) => Class::Cairo1 {
                 class: s.to_dto(),
-                domain: 0,
+                domain: VolitionDomain::L1,
             },
             ClassDefinition::Cairo(c) => Class::Cairo0 {
                 class: c.to_dto(),
-                domain: 0,
+                domain: VolitionDomain::L1,
             },
         }
     })
@@ -290,18 +415,25 @@ pub fn class_resp(tag: i32) -> ClassesResponse {
 pub fn class(tag: i32, block_number: u64) -> ClassDefinition {
     let block_number = BlockNumber::new_or_panic(block_number);
     match class_resp(tag) {
-        ClassesResponse::Class(Class::Cairo0 { class, .. }) => {
+        ClassesResponse::Class(Class::Cairo0 { class, domain }) => {
             Tagged::get(format!("class {tag}"), || ClassDefinition::Cairo {
                 block_number,
                 definition: CairoDefinition::try_from_dto(class).unwrap().0,
+                domain: DataAvailabilityMode::from_dto(domain),
             })
             .unwrap()
             .data
         }
-        ClassesResponse::Class(Class::Cairo1 { class, .. }) => {
-            Tagged::get(format!("class {tag}"), || ClassDefinition::Sierra {
-                block_number,
-                sierra_definition: SierraDefinition::try_from_dto(class).unwrap().0,
+        ClassesResponse::Class(Class::Cairo1 { class, domain }) => {
+            Tagged::get(format!("class {tag}"), || {
+                let SierraDefinition(sierra_definition, interface) =
+                    SierraDefinition::try_from_dto(class).unwrap();
+                ClassDefinition::Sierra {
+                    block_number,
+                    sierra_definition,
+                    interface: Some(interface),
+                    domain: DataAvailabilityMode::from_dto(domain),
+                }
             })
             .unwrap()
             .data
@@ -348,3 +480,36 @@ pub fn events(
         .collect();
     (BlockNumber::new_or_panic(block), events_by_txn)
 }
+
+pub fn cairo0_class_resp() -> ClassesResponse {
+    use pathfinder_common::class_definition::Cairo;
+    ClassesResponse::Class(Class::Cairo0 {
+        class: Faker.fake::<Cairo<'_>>().to_dto(),
+        domain: VolitionDomain::L1,
+    })
+}
+
+pub fn sierra_class_resp(contract_class_version: &str) -> ClassesResponse {
+    use pathfinder_common::class_definition::Sierra;
+    let mut sierra = Faker.fake::<Sierra<'_>>();
+    sierra.contract_class_version = contract_class_version.to_owned().into();
+    ClassesResponse::Class(Class::Cairo1 {
+        class: sierra.to_dto(),
+        domain: VolitionDomain::L1,
+    })
+}
+
+/// Creates a client whose commands are passed to `handle` on a spawned task,
+/// which responds to them in place of the network.
+pub fn mock_client(mut handle: impl FnMut(crate::Command) + Send + 'static) -> Client {
+    let (sender, mut receiver) = tokio::sync::mpsc::channel(1);
+    tokio::spawn(async move {
+        while let Some(command) = receiver.recv().await {
+            handle(command);
+        }
+    });
+    Client::new(
+        peer_aware::Client::new(sender, PeerId::random()),
+        "blocks".to_owned(),
+    )
+}
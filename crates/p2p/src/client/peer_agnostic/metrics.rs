@@ -0,0 +1,38 @@
+//! Metrics of the sync streams
+//!
+//! Responses are labelled by the peer they were received from, so that slow
+//! or misbehaving peers can be told apart.
+use std::time::Duration;
+
+use libp2p::PeerId;
+
+const METRIC_RESPONSES: &str = "p2p_sync_responses_total";
+const METRIC_RESPONSE_BYTES: &str = "p2p_sync_response_bytes_total";
+const METRIC_TIME_TO_FIRST_RESPONSE: &str = "p2p_sync_time_to_first_response_seconds";
+const METRIC_STREAM_OUTCOMES: &str = "p2p_sync_stream_outcomes_total";
+const OUTCOME_NEXT_PEER: &str = "next_peer";
+const OUTCOME_TERMINATED: &str = "terminated";
+
+/// Records a single response of `size` encoded bytes.
+pub(super) fn record_response(peer: PeerId, stream: &'static str, size: u64) {
+    let peer = peer.to_string();
+    metrics::increment_counter!(METRIC_RESPONSES, "stream" => stream, "peer" => peer.clone());
+    metrics::counter!(METRIC_RESPONSE_BYTES, size, "stream" => stream, "peer" => peer);
+}
+
+/// Records the time between sending a request and receiving the first
+/// response to it.
+pub(super) fn record_first_response(peer: PeerId, stream: &'static str, elapsed: Duration) {
+    metrics::histogram!(METRIC_TIME_TO_FIRST_RESPONSE, elapsed, "stream" => stream, "peer" => peer.to_string());
+}
+
+/// Records that a stream gave up on a peer and moved on to the next one.
+pub(super) fn record_next_peer(stream: &'static str) {
+    metrics::increment_counter!(METRIC_STREAM_OUTCOMES, "stream" => stream, "outcome" => OUTCOME_NEXT_PEER);
+}
+
+/// Records that a stream terminated, either because it is complete or
+/// because it cannot continue.
+pub(super) fn record_terminated(stream: &'static str) {
+    metrics::increment_counter!(METRIC_STREAM_OUTCOMES, "stream" => stream, "outcome" => OUTCOME_TERMINATED);
+}
@@ -0,0 +1,59 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use anyhow::Context;
+use libp2p::PeerId;
+
+/// The part of a peer's score which is kept across restarts.
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct PeerRecord {
+    pub successes: f64,
+    pub failures: f64,
+}
+
+/// Persists peer scores, so that the [`Client`](super::Client) can prefer
+/// historically reliable peers right after a restart.
+pub trait PeerStore: std::fmt::Debug + Send + Sync {
+    /// Returns the stored records, which is empty if none were saved yet.
+    fn load(&self) -> anyhow::Result<HashMap<PeerId, PeerRecord>>;
+
+    /// Replaces the stored records.
+    fn save(&self, records: &HashMap<PeerId, PeerRecord>) -> anyhow::Result<()>;
+}
+
+/// Stores peer records as a JSON object keyed by peer ID.
+#[derive(Clone, Debug)]
+pub struct FilePeerStore {
+    path: PathBuf,
+}
+
+impl FilePeerStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+impl PeerStore for FilePeerStore {
+    fn load(&self) -> anyhow::Result<HashMap<PeerId, PeerRecord>> {
+        let json = match std::fs::read(&self.path) {
+            Ok(json) => json,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(HashMap::new()),
+            Err(e) => {
+                return Err(e).with_context(|| format!("Reading {}", self.path.display()));
+            }
+        };
+
+        serde_json::from_slice(&json).with_context(|| format!("Parsing {}", self.path.display()))
+    }
+
+    fn save(&self, records: &HashMap<PeerId, PeerRecord>) -> anyhow::Result<()> {
+        let json = serde_json::to_vec(records).context("Serializing peer records")?;
+
+        // Replace the file in one go, so that a crash while writing does not
+        // leave a truncated file behind.
+        let tmp = self.path.with_extension("tmp");
+        std::fs::write(&tmp, json).with_context(|| format!("Writing {}", tmp.display()))?;
+        std::fs::rename(&tmp, &self.path)
+            .with_context(|| format!("Renaming {} to {}", tmp.display(), self.path.display()))
+    }
+}
@@ -1,4 +1,10 @@
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
 use futures::{stream, TryStreamExt};
+use p2p_proto::ToProtobuf;
+use pathfinder_common::state_update::ContractUpdate;
+use pathfinder_crypto::Felt;
+use prost::Message;
 use rstest::rstest;
 use BlockHeadersResponse::Fin as HdrFin;
 use ClassesResponse::Fin as ClassFin;
@@ -8,6 +14,8 @@ use TransactionsResponse::Fin as TxnFin;
 
 use super::*;
 use crate::client::peer_agnostic::fixtures::*;
+use crate::client::peer_agnostic::peer_store::FilePeerStore;
+use crate::client::types::{CountSource, Resumed};
 
 #[rstest]
 #[case::one_peer_1_block(
@@ -127,15 +135,270 @@ async fn make_header_stream(
         let start = BlockNumber::GENESIS;
         let stop = start + (num_blocks - 1) as u64;
 
-        let actual = super::header_stream::make(start, stop, reverse, get_peers, send_request)
-            .map(|x| (TestPeer(x.peer), x.data))
-            .collect::<Vec<_>>()
-            .await;
+        let actual = super::header_stream::make(
+            start,
+            stop,
+            reverse,
+            NonZeroU64::MIN,
+            false,
+            Default::default(),
+            get_peers,
+            send_request,
+        )
+        .map_ok(|x| (TestPeer(x.peer), x.data))
+        .try_collect::<Vec<_>>()
+        .await
+        .unwrap();
 
         pretty_assertions_sorted::assert_eq!(actual, expected_stream, "Direction: {}", direction);
     }
 }
 
+#[tokio::test]
+async fn header_not_linking_to_previous_one_moves_to_next_peer() {
+    use pathfinder_common::BlockHeader;
+
+    use crate::client::conv::ToDto;
+
+    let first = hdr(0);
+    let unlinked = hdr(1);
+    let linked = SignedBlockHeader {
+        header: BlockHeader {
+            parent_hash: first.header.hash,
+            ..unlinked.header.clone()
+        },
+        ..unlinked.clone()
+    };
+    let resp = |header: &SignedBlockHeader| BlockHeadersResponse::Header(Box::new(header.to_dto()));
+
+    let (peers, responses) = unzip_fixtures(vec![
+        Ok((peer(0), vec![resp(&first), resp(&unlinked)])),
+        Ok((peer(1), vec![resp(&linked), HdrFin])),
+    ]);
+    let get_peers = move || {
+        let peers = peers.clone();
+        async move { peers }
+    };
+    let send_request = move |_: PeerId, _: BlockHeadersRequest| {
+        let responses = responses.clone();
+        async move { send_request(responses).await }
+    };
+
+    let actual = super::header_stream::make(
+        BlockNumber::GENESIS,
+        BlockNumber::new_or_panic(1),
+        false,
+        NonZeroU64::MIN,
+        true,
+        Default::default(),
+        get_peers,
+        send_request,
+    )
+    .map_ok(|x| (TestPeer(x.peer), x.data))
+    .try_collect::<Vec<_>>()
+    .await
+    .unwrap();
+
+    pretty_assertions_sorted::assert_eq!(actual, vec![(peer(0), first), (peer(1), linked)]);
+}
+
+#[tokio::test]
+async fn header_over_item_limits_moves_to_next_peer() {
+    use pathfinder_common::BlockHeader;
+
+    use crate::client::conv::ToDto;
+
+    let limits = BlockItemLimits::default();
+    let valid = hdr(0);
+    let bloated = SignedBlockHeader {
+        header: BlockHeader {
+            transaction_count: limits.max_transactions + 1,
+            ..valid.header.clone()
+        },
+        ..valid.clone()
+    };
+    let resp = |header: &SignedBlockHeader| BlockHeadersResponse::Header(Box::new(header.to_dto()));
+
+    let (peers, responses) = unzip_fixtures(vec![
+        Ok((peer(0), vec![resp(&bloated), HdrFin])),
+        Ok((peer(1), vec![resp(&valid), HdrFin])),
+    ]);
+    let get_peers = move || {
+        let peers = peers.clone();
+        async move { peers }
+    };
+    let send_request = move |_: PeerId, _: BlockHeadersRequest| {
+        let responses = responses.clone();
+        async move { send_request(responses).await }
+    };
+
+    let actual = super::header_stream::make(
+        BlockNumber::GENESIS,
+        BlockNumber::GENESIS,
+        false,
+        NonZeroU64::MIN,
+        false,
+        Default::default(),
+        get_peers,
+        send_request,
+    )
+    .map_ok(|x| (TestPeer(x.peer), x.data))
+    .try_collect::<Vec<_>>()
+    .await
+    .unwrap();
+
+    pretty_assertions_sorted::assert_eq!(actual, vec![(peer(1), valid)]);
+}
+
+#[tokio::test]
+async fn malformed_header_is_attributed_to_its_peer() {
+    use crate::client::conv::ToDto;
+
+    let mut malformed = hdr(0).to_dto();
+    malformed.signatures.clear();
+
+    let (peers, responses) = unzip_fixtures(vec![
+        Ok((
+            peer(0),
+            vec![BlockHeadersResponse::Header(Box::new(malformed)), HdrFin],
+        )),
+        Ok((peer(1), vec![hdr_resp(0), HdrFin])),
+    ]);
+    let get_peers = move || {
+        let peers = peers.clone();
+        async move { peers }
+    };
+    let send_request = move |_: PeerId, _: BlockHeadersRequest| {
+        let responses = responses.clone();
+        async move { send_request(responses).await }
+    };
+    let (sender, errors) = fmpsc::unbounded();
+    let config = StreamConfig {
+        conversion_errors: Some(sender),
+        ..Default::default()
+    };
+
+    let actual = super::header_stream::make(
+        BlockNumber::GENESIS,
+        BlockNumber::GENESIS,
+        false,
+        NonZeroU64::MIN,
+        false,
+        config,
+        get_peers,
+        send_request,
+    )
+    .map_ok(|x| (TestPeer(x.peer), x.data))
+    .try_collect::<Vec<_>>()
+    .await
+    .unwrap();
+
+    pretty_assertions_sorted::assert_eq!(actual, vec![(peer(1), hdr(0))]);
+    let errors = errors.collect::<Vec<_>>().await;
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].peer, peer(0).0);
+    assert_eq!(errors[0].data.block, BlockNumber::GENESIS);
+}
+
+#[tokio::test]
+async fn count_over_item_limits_ends_the_stream() {
+    let config = StreamConfig {
+        item_limits: BlockItemLimits {
+            max_transactions: 1,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
+    let mut stream = Box::pin(super::transaction_stream::make(
+        BlockNumber::GENESIS,
+        BlockNumber::GENESIS,
+        false,
+        NonZeroU64::MIN,
+        stream::iter([Ok(2)]),
+        None,
+        config,
+        || async { vec![peer(0).0] },
+        |_: PeerId, _: TransactionsRequest| async {
+            Ok(stream::pending::<std::io::Result<TransactionsResponse>>())
+        },
+    ));
+
+    let error = stream.next().await.unwrap().unwrap_err();
+    assert!(matches!(error.data, StreamError::Other(_)));
+    assert!(stream.next().await.is_none());
+}
+
+#[tokio::test]
+async fn transaction_count_error_is_forwarded() {
+    let (peers, responses) = unzip_fixtures(vec![Ok((
+        peer(0),
+        vec![txn_resp(50, 0), txn_resp(51, 0), TxnFin],
+    ))]);
+    let get_peers = move || {
+        let peers = peers.clone();
+        async move { peers }
+    };
+    let send_request = move |_: PeerId, _: TransactionsRequest| {
+        let responses = responses.clone();
+        async move { send_request(responses).await }
+    };
+
+    let actual = super::transaction_stream::make(
+        BlockNumber::GENESIS,
+        BlockNumber::new_or_panic(1),
+        false,
+        NonZeroU64::MIN,
+        stream::iter([Ok(1), Err(anyhow::anyhow!("Database error"))]),
+        None,
+        Default::default(),
+        get_peers,
+        send_request,
+    )
+    .collect::<Vec<_>>()
+    .await;
+
+    assert_eq!(actual.len(), 2);
+    assert!(actual[0].is_ok());
+    let error = actual[1].as_ref().unwrap_err();
+    assert!(matches!(&error.data, StreamError::Other(e) if e.to_string() == "Database error"));
+}
+
+#[tokio::test]
+async fn state_diff_length_error_is_forwarded() {
+    let (peers, responses) = unzip_fixtures(vec![Ok((
+        peer(0),
+        vec![contract_diff(52), declared_class(52), SDFin],
+    ))]);
+    let get_peers = move || {
+        let peers = peers.clone();
+        async move { peers }
+    };
+    let send_request = move |_: PeerId, _: StateDiffsRequest| {
+        let responses = responses.clone();
+        async move { send_request(responses).await }
+    };
+
+    let actual = super::state_diff_stream::make(
+        BlockNumber::GENESIS,
+        BlockNumber::new_or_panic(1),
+        false,
+        NonZeroU64::MIN,
+        stream::iter([Ok(len(52)), Err(anyhow::anyhow!("Database error"))]),
+        None,
+        Default::default(),
+        get_peers,
+        send_request,
+    )
+    .collect::<Vec<_>>()
+    .await;
+
+    assert_eq!(actual.len(), 2);
+    assert!(actual[0].is_ok());
+    let error = actual[1].as_ref().unwrap_err();
+    assert!(matches!(&error.data, StreamError::Other(e) if e.to_string() == "Database error"));
+}
+
 #[rstest]
 #[case::one_peer_1_block(
     1,
@@ -258,15 +521,34 @@ async fn make_header_stream(
 )]
 #[case::too_many_responses_with_fin(
     1,
-    vec![Ok((peer(0), vec![txn_resp(18, 0), txn_resp(19, 0), TxnFin]))],
+    vec![
+        // Surplus transactions after the last requested block are rejected
+        Ok((peer(0), vec![txn_resp(18, 0), txn_resp(19, 0), TxnFin])),
+        Ok((peer(1), vec![txn_resp(18, 0), TxnFin]))
+    ],
     vec![1],
-    vec![Ok((peer(0), vec![txn(18, 0)]))]
+    vec![Ok((peer(1), vec![txn(18, 0)]))]
 )]
 #[case::too_many_responses_no_fin(
     1,
-    vec![Ok((peer(0), vec![txn_resp(18, 0), txn_resp(19, 0)]))],
+    vec![
+        Ok((peer(0), vec![txn_resp(18, 0), txn_resp(19, 0)])),
+        Ok((peer(1), vec![txn_resp(18, 0), TxnFin]))
+    ],
     vec![1],
-    vec![Ok((peer(0), vec![txn(18, 0)]))]
+    vec![Ok((peer(1), vec![txn(18, 0)]))]
+)]
+#[case::too_many_responses_in_last_block(
+    2,
+    vec![
+        Ok((peer(0), vec![txn_resp(18, 0), txn_resp(19, 0), txn_resp(20, 1), TxnFin])),
+        Ok((peer(1), vec![txn_resp(19, 0), TxnFin]))
+    ],
+    vec![1, 1],
+    vec![
+        Ok((peer(0), vec![txn(18, 0)])),
+        Ok((peer(1), vec![txn(19, 0)]))
+    ]
 )]
 #[case::empty_response_streams_are_ignored(
     1,
@@ -312,7 +594,11 @@ async fn make_transaction_stream(
     let actual = super::transaction_stream::make(
         start,
         stop,
+        false,
+        NonZeroU64::MIN,
         stream::iter(num_txns_per_block.into_iter().map(Ok)),
+        None,
+        Default::default(),
         get_peers,
         send_request,
     )
@@ -487,6 +773,33 @@ async fn make_transaction_stream(
     vec![len(18)],
     vec![Ok((peer(0), state_diff(18)))]
 )]
+#[case::system_contract_nonce_is_rejected(
+    1,
+    vec![
+        Ok((peer(0), vec![system_contract_nonce(), contract_diff(22), declared_class(22), SDFin])),
+        Ok((peer(1), vec![contract_diff(22), declared_class(22), SDFin]))
+    ],
+    vec![len(22)],
+    vec![Ok((peer(1), state_diff(22)))]
+)]
+#[case::system_contract_class_is_rejected(
+    1,
+    vec![
+        Ok((peer(0), vec![system_contract_class(), contract_diff(23), declared_class(23), SDFin])),
+        Ok((peer(1), vec![contract_diff(23), declared_class(23), SDFin]))
+    ],
+    vec![len(23)],
+    vec![Ok((peer(1), state_diff(23)))]
+)]
+#[case::split_contract_diff_is_rejected(
+    1,
+    vec![
+        Ok((peer(0), [split_contract_diff(21), vec![declared_class(21), SDFin]].concat())),
+        Ok((peer(1), vec![contract_diff(21), declared_class(21), SDFin]))
+    ],
+    vec![len(21)],
+    vec![Ok((peer(1), state_diff(21)))]
+)]
 #[case::empty_response_streams_are_ignored(
     1,
     vec![
@@ -531,7 +844,11 @@ async fn make_state_diff_stream(
     let actual = super::state_diff_stream::make(
         start,
         stop,
+        false,
+        NonZeroU64::MIN,
         stream::iter(state_diff_len_per_block.into_iter().map(Ok)),
+        None,
+        Default::default(),
         get_peers,
         send_request,
     )
@@ -549,6 +866,126 @@ async fn make_state_diff_stream(
     pretty_assertions_sorted::assert_eq!(actual, expected);
 }
 
+#[tokio::test]
+async fn state_diff_storage_domain_is_preserved() {
+    let StateDiffsResponse::ContractDiff(mut diff) = contract_diff(23) else {
+        unreachable!()
+    };
+    diff.domain = p2p_proto::common::VolitionDomain::L2;
+    let mut expected = state_diff(23);
+    expected.set_storage_domain(ContractAddress(diff.address.0), DataAvailabilityMode::L2);
+
+    let (peers, responses) = unzip_fixtures(vec![Ok((
+        peer(0),
+        vec![
+            StateDiffsResponse::ContractDiff(diff),
+            declared_class(23),
+            SDFin,
+        ],
+    ))]);
+    let get_peers = move || {
+        let peers = peers.clone();
+        async move { peers }
+    };
+    let send_request = move |_: PeerId, _: StateDiffsRequest| {
+        let responses = responses.clone();
+        async move { send_request(responses).await }
+    };
+
+    let actual = super::state_diff_stream::make(
+        BlockNumber::GENESIS,
+        BlockNumber::GENESIS,
+        false,
+        NonZeroU64::MIN,
+        stream::iter([Ok(len(23))]),
+        None,
+        Default::default(),
+        get_peers,
+        send_request,
+    )
+    .map_ok(|x| x.data.0)
+    .try_collect::<Vec<_>>()
+    .await
+    .unwrap();
+
+    pretty_assertions_sorted::assert_eq!(actual, vec![expected]);
+}
+
+#[tokio::test]
+async fn state_diff_commitment_mismatch_moves_to_next_peer() {
+    let (peers, responses) = unzip_fixtures(vec![
+        // The declared class belongs to a different state diff of the same length.
+        Ok((peer(0), vec![contract_diff(21), declared_class(22), SDFin])),
+        Ok((peer(1), vec![contract_diff(21), declared_class(21), SDFin])),
+    ]);
+    let get_peers = move || {
+        let peers = peers.clone();
+        async move { peers }
+    };
+    let send_request = move |_: PeerId, _: StateDiffsRequest| {
+        let responses = responses.clone();
+        async move { send_request(responses).await }
+    };
+    let commitments =
+        StateDiffCommitmentLookup::new(|_| Ok(state_diff(21).compute_state_diff_commitment()));
+    let config = StreamConfig::default();
+    let scores = config.peers.clone();
+
+    let actual = super::state_diff_stream::make(
+        BlockNumber::GENESIS,
+        BlockNumber::GENESIS,
+        false,
+        NonZeroU64::MIN,
+        stream::iter([Ok(len(21))]),
+        Some(commitments),
+        config,
+        get_peers,
+        send_request,
+    )
+    .map_ok(|x| (TestPeer(x.peer), x.data.0))
+    .try_collect::<Vec<_>>()
+    .await
+    .unwrap();
+
+    pretty_assertions_sorted::assert_eq!(actual, vec![(peer(1), state_diff(21))]);
+    assert!(scores.read().await.score(&peer(0).0) < 0.0);
+}
+
+#[test]
+fn class_updates_of_existing_contracts_are_replacements() {
+    let deployed = ContractAddress(Felt::from_u64(1000));
+    let replaced = ContractAddress(Felt::from_u64(1001));
+    let class_update = |class| ContractUpdate {
+        class: Some(class),
+        ..Default::default()
+    };
+    let mut state_diff = StateUpdateData {
+        contract_updates: [
+            (
+                deployed,
+                class_update(ContractClassUpdate::Deploy(ClassHash::ZERO)),
+            ),
+            (
+                replaced,
+                class_update(ContractClassUpdate::Deploy(ClassHash::ZERO)),
+            ),
+        ]
+        .into(),
+        ..Default::default()
+    };
+
+    traits::resolve_class_updates(&mut state_diff, |address| address == replaced);
+
+    assert_eq!(
+        state_diff.contract_updates[&deployed].class,
+        Some(ContractClassUpdate::Deploy(ClassHash::ZERO))
+    );
+    assert_eq!(
+        state_diff.contract_updates[&replaced].class,
+        Some(ContractClassUpdate::Replace(ClassHash::ZERO))
+    );
+}
+
 #[rstest]
 #[case::one_peer_1_block(
     1,
@@ -726,7 +1163,11 @@ async fn make_class_definition_stream(
     let actual = super::class_definition_stream::make(
         start,
         stop,
+        false,
+        NonZeroU64::MIN,
         stream::iter(declared_classes_per_block.into_iter().map(Ok)),
+        ClassFilter::default(),
+        Default::default(),
         get_peers,
         send_request,
     )
@@ -917,7 +1358,12 @@ async fn make_event_stream(
     let actual = super::event_stream::make(
         start,
         stop,
+        false,
+        NonZeroU64::MIN,
         stream::iter(events_per_block.into_iter().map(Ok)),
+        None,
+        None,
+        Default::default(),
         get_peers,
         send_request,
     )
@@ -940,3 +1386,2218 @@ async fn make_event_stream(
 
     pretty_assertions_sorted::assert_eq!(actual, expected_stream);
 }
+
+#[test]
+fn peer_snapshot_is_refreshed_after_exhaustions() {
+    let mut peers = PeerSnapshot::new(NonZeroUsize::new(2).unwrap(), None);
+    assert!(peers.needs_refresh());
+
+    let first = vec![PeerId::random()];
+    peers.refresh(first.clone());
+    assert!(!peers.needs_refresh());
+    assert_eq!(peers.next_round(), first);
+    assert!(!peers.needs_refresh());
+    assert_eq!(peers.next_round(), first);
+    assert!(peers.needs_refresh());
+
+    let second = vec![PeerId::random()];
+    peers.refresh(second.clone());
+    assert_eq!(peers.next_round(), second);
+}
+
+#[rstest]
+#[case::default_accepts_all(ClassFilter::default(), vec![0, 1, 2, 3])]
+#[case::no_cairo0(
+    ClassFilter { min_sierra_version: None, include_cairo0: false },
+    vec![1, 2, 3]
+)]
+#[case::min_sierra_version(
+    ClassFilter { min_sierra_version: Some(semver::Version::new(0, 1, 0)), include_cairo0: true },
+    vec![0, 2, 3]
+)]
+#[case::min_sierra_version_no_cairo0(
+    ClassFilter { min_sierra_version: Some(semver::Version::new(1, 0, 0)), include_cairo0: false },
+    vec![3]
+)]
+#[test_log::test(tokio::test)]
+async fn make_class_definition_stream_with_filter(
+    #[case] filter: ClassFilter,
+    #[case] expected_indices: Vec<usize>,
+) {
+    let classes = vec![
+        cairo0_class_resp(),
+        sierra_class_resp("0.0.1"),
+        sierra_class_resp("0.1.0"),
+        sierra_class_resp("1.2.0"),
+    ];
+    let expected = classes
+        .iter()
+        .cloned()
+        .enumerate()
+        .filter(|(i, _)| expected_indices.contains(i))
+        .map(|(_, c)| match c {
+            ClassesResponse::Class(p2p_proto::class::Class::Cairo0 { class, domain }) => {
+                ClassDefinition::Cairo {
+                    block_number: BlockNumber::GENESIS,
+                    definition: CairoDefinition::try_from_dto(class).unwrap().0,
+                    domain: DataAvailabilityMode::from_dto(domain),
+                }
+            }
+            ClassesResponse::Class(p2p_proto::class::Class::Cairo1 { class, domain }) => {
+                let SierraDefinition(sierra_definition, interface) =
+                    SierraDefinition::try_from_dto(class).unwrap();
+                ClassDefinition::Sierra {
+                    block_number: BlockNumber::GENESIS,
+                    sierra_definition,
+                    interface: Some(interface),
+                    domain: DataAvailabilityMode::from_dto(domain),
+                }
+            }
+            ClassesResponse::Fin => unreachable!(),
+        })
+        .collect::<Vec<_>>();
+
+    let num_classes = classes.len();
+    let mut responses = classes;
+    responses.push(ClassFin);
+    let (peers, responses) = unzip_fixtures(vec![Ok((peer(0), responses))]);
+    let get_peers = move || {
+        let peers = peers.clone();
+        async move { peers }
+    };
+    let send_request = move |_: PeerId, _: ClassesRequest| {
+        let responses = responses.clone();
+        async move { send_request(responses).await }
+    };
+
+    let actual = super::class_definition_stream::make(
+        BlockNumber::GENESIS,
+        BlockNumber::GENESIS,
+        false,
+        NonZeroU64::MIN,
+        stream::iter(vec![Ok(num_classes)]),
+        filter,
+        Default::default(),
+        get_peers,
+        send_request,
+    )
+    .map_ok(|x| x.data)
+    .try_collect::<Vec<_>>()
+    .await
+    .unwrap();
+
+    pretty_assertions_sorted::assert_eq!(actual, expected);
+}
+
+#[tokio::test]
+async fn redundant_stream_cross_checks_items() {
+    let a = stream::iter(vec![
+        Ok(PeerData::new(peer(0).0, hdr(0))),
+        Ok(PeerData::new(peer(0).0, hdr(1))),
+        Ok(PeerData::new(peer(0).0, hdr(2))),
+    ]);
+    // The second peer equivocates on the second block and has one block less.
+    let b = stream::iter(vec![
+        Ok(PeerData::new(peer(1).0, hdr(0))),
+        Ok(PeerData::new(peer(1).0, hdr(3))),
+    ]);
+
+    let actual = redundant_stream::merge(vec![a, b])
+        .try_collect::<Vec<_>>()
+        .await
+        .unwrap();
+
+    let expected = vec![
+        PeerData::new(peer(0).0, Agreement::Agreed(hdr(0))),
+        PeerData::new(
+            peer(0).0,
+            Agreement::Disagreed(vec![
+                PeerData::new(peer(0).0, hdr(1)),
+                PeerData::new(peer(1).0, hdr(3)),
+            ]),
+        ),
+    ];
+    pretty_assertions_sorted::assert_eq!(actual, expected);
+}
+
+#[test]
+fn peer_partitions_are_stable() {
+    let partitions = NonZeroUsize::new(3).unwrap();
+    let peer = PeerId::random();
+
+    let partition = peer_partition(&peer, partitions);
+    assert!(partition < partitions.get());
+    assert_eq!(peer_partition(&peer, partitions), partition);
+}
+
+#[test]
+fn block_progress_throughput() {
+    let mut progress = BlockProgress::new(100);
+    progress.started = Instant::now() - Duration::from_secs(10);
+    // Nothing received yet, but no floor configured.
+    assert!(progress.meets_throughput(None));
+    assert!(!progress.meets_throughput(Some(1.0)));
+
+    progress.checked_sub_assign(20).unwrap();
+    assert_eq!(progress.received(), 20);
+    // 20 items in 10 seconds.
+    assert!(progress.meets_throughput(Some(1.0)));
+    assert!(!progress.meets_throughput(Some(3.0)));
+
+    // Peers are not judged right after starting.
+    progress.rollback();
+    assert!(progress.meets_throughput(Some(3.0)));
+}
+
+#[rstest]
+#[case::trust_counts_stream(CountSource::TrustCountsStream, Some(vec![1, 2, 3]))]
+#[case::trust_header(CountSource::TrustHeader, Some(vec![1, 5, 3]))]
+#[case::require_agreement(CountSource::RequireAgreement, None)]
+#[tokio::test]
+async fn count_source(#[case] policy: CountSource, #[case] expected: Option<Vec<usize>>) {
+    let counts_stream = stream::iter([1, 2, 3].map(anyhow::Ok));
+    let header_counts = stream::iter([1, 5, 3].map(anyhow::Ok));
+
+    let actual = policy
+        .select(counts_stream, header_counts)
+        .try_collect::<Vec<_>>()
+        .await
+        .ok();
+
+    assert_eq!(actual, expected);
+}
+
+#[rstest]
+#[case::from_the_beginning(false, None, None, Some((0, 9, 0)))]
+#[case::forward(false, None, Some(4), Some((5, 9, 5)))]
+#[case::forward_with_step(false, Some(3), Some(3), Some((6, 9, 2)))]
+#[case::forward_done(false, None, Some(9), None)]
+#[case::reverse(true, None, Some(5), Some((0, 4, 5)))]
+#[case::reverse_with_step(true, Some(3), Some(6), Some((0, 3, 2)))]
+#[case::reverse_done(true, None, Some(0), None)]
+fn resumed(
+    #[case] reverse: bool,
+    #[case] step: Option<u64>,
+    #[case] resume_from: Option<u64>,
+    #[case] expected: Option<(u64, u64, usize)>,
+) {
+    let actual = Resumed::new(
+        BlockNumber::GENESIS,
+        BlockNumber::new_or_panic(9),
+        reverse,
+        step.and_then(NonZeroU64::new),
+        resume_from.map(BlockNumber::new_or_panic),
+    );
+    let expected = expected.map(|(start, stop, delivered)| Resumed {
+        start: BlockNumber::new_or_panic(start),
+        stop: BlockNumber::new_or_panic(stop),
+        delivered,
+    });
+
+    assert_eq!(actual, expected);
+}
+
+#[tokio::test]
+async fn resumed_stream_skips_delivered_blocks() {
+    let (peers, responses) = unzip_fixtures(vec![Ok((peer(0), vec![txn_resp(60, 0), TxnFin]))]);
+    let get_peers = move || {
+        let peers = peers.clone();
+        async move { peers }
+    };
+    let send_request = move |_: PeerId, request: TransactionsRequest| {
+        assert_eq!(
+            request.iteration.start,
+            p2p_proto::common::BlockNumberOrHash::Number(1)
+        );
+        let responses = responses.clone();
+        async move { send_request(responses).await }
+    };
+    let resumed = Resumed::new(
+        BlockNumber::GENESIS,
+        BlockNumber::new_or_panic(1),
+        false,
+        None,
+        Some(BlockNumber::GENESIS),
+    )
+    .unwrap();
+
+    let actual = super::transaction_stream::make(
+        resumed.start,
+        resumed.stop,
+        false,
+        NonZeroU64::MIN,
+        resumed.fast_forward(stream::iter([Ok(2), Ok(1)])),
+        None,
+        Default::default(),
+        get_peers,
+        send_request,
+    )
+    .map_ok(|x| (TestPeer(x.peer), x.data.1))
+    .map_err(|_| ())
+    .collect::<Vec<_>>()
+    .await;
+
+    assert_eq!(actual, vec![Ok((peer(0), BlockNumber::new_or_panic(1)))]);
+}
+
+#[tokio::test]
+async fn peer_bytes_received() {
+    let (sender, _receiver) = mpsc::channel(1);
+    let client = Client::new(
+        peer_aware::Client::new(sender, PeerId::random()),
+        "blocks".to_owned(),
+    );
+    let (mut responder, responses) = fmpsc::channel(2);
+    responder.try_send(Ok(hdr_resp(0))).unwrap();
+    responder.try_send(Ok(HdrFin)).unwrap();
+    drop(responder);
+    // As counted by the codec while reading both responses from the wire.
+    let bytes_received = Arc::new(AtomicU64::new(123));
+    let responses = ResponseReceiver::new(responses, bytes_received);
+
+    client
+        .count_bytes_received(peer(0).0, "headers", Instant::now(), responses)
+        .collect::<Vec<_>>()
+        .await;
+
+    assert_eq!(client.peer_bytes_received(peer(0).0).await, 123);
+    assert_eq!(client.peer_bytes_received(peer(1).0).await, 0);
+}
+
+#[tokio::test]
+async fn peer_scores() {
+    let config = StreamConfig::default();
+    config.reward(peer(0).0).await;
+    config.reward(peer(0).0).await;
+    config.penalize(peer(1).0).await;
+
+    let state = config.peers.read().await;
+    assert!((state.score(&peer(0).0) - 2.0).abs() < 0.01);
+    assert!((state.score(&peer(1).0) + PeerScore::FAILURE_WEIGHT).abs() < 0.01);
+    assert_eq!(state.score(&peer(2).0), 0.0);
+
+    assert!(state.selection_weight(&peer(0).0) > state.selection_weight(&peer(2).0));
+    assert!(state.selection_weight(&peer(1).0) < state.selection_weight(&peer(2).0));
+}
+
+#[test]
+fn peer_score_decays() {
+    let mut score = PeerScore::new();
+    score.record_success();
+    score.last_update = Instant::now() - PeerScore::HALF_LIFE;
+
+    assert!((score.value() - 0.5).abs() < 0.01);
+}
+
+#[tokio::test]
+async fn transaction_stream_in_reverse() {
+    let (peers, responses) = unzip_fixtures(vec![Ok((
+        peer(0),
+        vec![txn_resp(30, 0), txn_resp(31, 1), txn_resp(32, 0), TxnFin],
+    ))]);
+    let requests = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let get_peers = move || {
+        let peers = peers.clone();
+        async move { peers }
+    };
+    let send_request = {
+        let requests = requests.clone();
+        move |_: PeerId, request: TransactionsRequest| {
+            requests.lock().unwrap().push(request.iteration);
+            let responses = responses.clone();
+            async move { send_request(responses).await }
+        }
+    };
+
+    let actual = super::transaction_stream::make(
+        BlockNumber::GENESIS,
+        BlockNumber::new_or_panic(1),
+        true,
+        NonZeroU64::MIN,
+        // Counts of block 1 and then block 0.
+        stream::iter([Ok(2), Ok(1)]),
+        None,
+        Default::default(),
+        get_peers,
+        send_request,
+    )
+    .map_ok(|x| {
+        (
+            x.data.1,
+            x.data.0.into_iter().map(TestTxn::new).collect::<Vec<_>>(),
+        )
+    })
+    .try_collect::<Vec<_>>()
+    .await
+    .unwrap();
+
+    let expected = vec![
+        (BlockNumber::new_or_panic(1), vec![txn(30, 0), txn(31, 1)]),
+        (BlockNumber::GENESIS, vec![txn(32, 0)]),
+    ];
+    pretty_assertions_sorted::assert_eq!(actual, expected);
+
+    let expected_request = Iteration {
+        start: 1u64.into(),
+        direction: Direction::Backward,
+        limit: 2,
+        step: 1.into(),
+    };
+    assert_eq!(*requests.lock().unwrap(), vec![expected_request]);
+}
+
+#[tokio::test]
+async fn transaction_stream_with_step() {
+    let (peers, responses) = unzip_fixtures(vec![Ok((
+        peer(0),
+        vec![txn_resp(33, 0), txn_resp(34, 0), txn_resp(35, 0), TxnFin],
+    ))]);
+    let requests = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let get_peers = move || {
+        let peers = peers.clone();
+        async move { peers }
+    };
+    let send_request = {
+        let requests = requests.clone();
+        move |_: PeerId, request: TransactionsRequest| {
+            requests.lock().unwrap().push(request.iteration);
+            let responses = responses.clone();
+            async move { send_request(responses).await }
+        }
+    };
+
+    // Walking down from block 7 every third block ends at block 1.
+    let actual = super::transaction_stream::make(
+        BlockNumber::GENESIS,
+        BlockNumber::new_or_panic(7),
+        true,
+        NonZeroU64::new(3).unwrap(),
+        stream::iter([Ok(1), Ok(1), Ok(1)]),
+        None,
+        Default::default(),
+        get_peers,
+        send_request,
+    )
+    .map_ok(|x| {
+        (
+            x.data.1,
+            x.data.0.into_iter().map(TestTxn::new).collect::<Vec<_>>(),
+        )
+    })
+    .try_collect::<Vec<_>>()
+    .await
+    .unwrap();
+
+    let expected = vec![
+        (BlockNumber::new_or_panic(7), vec![txn(33, 0)]),
+        (BlockNumber::new_or_panic(4), vec![txn(34, 0)]),
+        (BlockNumber::new_or_panic(1), vec![txn(35, 0)]),
+    ];
+    pretty_assertions_sorted::assert_eq!(actual, expected);
+
+    let expected_request = Iteration {
+        start: 7u64.into(),
+        direction: Direction::Backward,
+        limit: 3,
+        step: 3.into(),
+    };
+    assert_eq!(*requests.lock().unwrap(), vec![expected_request]);
+}
+
+#[tokio::test]
+async fn stalling_peer_times_out() {
+    // The first peer accepts the request but never responds.
+    let (_stalled_sender, stalled) = fmpsc::channel(1);
+    let (mut sender, responsive) = fmpsc::channel(2);
+    sender.try_send(Ok(txn_resp(40, 0))).unwrap();
+    sender.try_send(Ok(TxnFin)).unwrap();
+    let responses = Arc::new(std::sync::Mutex::new(vec![stalled, responsive]));
+
+    let get_peers = || async { vec![peer(0).0, peer(1).0] };
+    let send_request = move |_: PeerId, _: TransactionsRequest| {
+        let responses = responses.lock().unwrap().remove(0);
+        async move { Ok(responses) }
+    };
+    let config = StreamConfig {
+        response_timeout: Duration::from_millis(50),
+        ..Default::default()
+    };
+    let scores = config.peers.clone();
+
+    let actual = super::transaction_stream::make(
+        BlockNumber::GENESIS,
+        BlockNumber::GENESIS,
+        false,
+        NonZeroU64::MIN,
+        stream::iter([Ok(1)]),
+        None,
+        config,
+        get_peers,
+        send_request,
+    )
+    .map_ok(|x| {
+        (
+            TestPeer(x.peer),
+            x.data.0.into_iter().map(TestTxn::new).collect::<Vec<_>>(),
+        )
+    })
+    .try_collect::<Vec<_>>()
+    .await
+    .unwrap();
+
+    pretty_assertions_sorted::assert_eq!(actual, vec![(peer(1), vec![txn(40, 0)])]);
+    assert!(scores.read().await.score(&peer(0).0) < 0.0);
+}
+
+#[rstest]
+#[case::forward(false)]
+#[case::reverse(true)]
+#[tokio::test]
+async fn header_stream_ends_when_peer_stalls_after_the_last_header(#[case] reverse: bool) {
+    // The peer sends the last header but then stalls instead of sending Fin.
+    let stalled = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let requests = Arc::new(std::sync::Mutex::new(0));
+    let get_peers = || async { vec![peer(0).0] };
+    let send_request = {
+        let stalled = stalled.clone();
+        let requests = requests.clone();
+        move |_: PeerId, _: BlockHeadersRequest| {
+            *requests.lock().unwrap() += 1;
+            let (mut sender, responses) = fmpsc::channel(1);
+            sender.try_send(Ok(hdr_resp(0))).unwrap();
+            stalled.lock().unwrap().push(sender);
+            async move { Ok(responses) }
+        }
+    };
+    let config = StreamConfig {
+        response_timeout: Duration::from_millis(50),
+        ..Default::default()
+    };
+
+    let actual = super::header_stream::make(
+        BlockNumber::GENESIS,
+        BlockNumber::GENESIS,
+        reverse,
+        NonZeroU64::MIN,
+        false,
+        config,
+        get_peers,
+        send_request,
+    )
+    .map_ok(|x| (TestPeer(x.peer), x.data))
+    .try_collect::<Vec<_>>()
+    .await
+    .unwrap();
+
+    pretty_assertions_sorted::assert_eq!(actual, vec![(peer(0), hdr(0))]);
+    assert_eq!(*requests.lock().unwrap(), 1);
+}
+
+#[tokio::test]
+async fn transaction_commitment_mismatch_moves_to_next_peer() {
+    let get_peers = || async { vec![peer(0).0, peer(1).0] };
+    let send_request = |requested: PeerId, _: TransactionsRequest| {
+        // The first peer sends a transaction which does not match the commitment.
+        let seed = if requested == peer(0).0 { 40 } else { 41 };
+        let (mut sender, responses) = fmpsc::channel(2);
+        sender.try_send(Ok(txn_resp(seed, 0))).unwrap();
+        sender.try_send(Ok(TxnFin)).unwrap();
+        async move { Ok(responses) }
+    };
+    let verifier = TransactionCommitmentVerifier::new(|_, transactions| {
+        Ok(transactions
+            .iter()
+            .cloned()
+            .map(TestTxn::new)
+            .eq([txn(41, 0)]))
+    });
+    let config = StreamConfig::default();
+    let scores = config.peers.clone();
+
+    let actual = super::transaction_stream::make(
+        BlockNumber::GENESIS,
+        BlockNumber::GENESIS,
+        false,
+        NonZeroU64::MIN,
+        stream::iter([Ok(1)]),
+        Some(verifier),
+        config,
+        get_peers,
+        send_request,
+    )
+    .map_ok(|x| {
+        (
+            TestPeer(x.peer),
+            x.data.0.into_iter().map(TestTxn::new).collect::<Vec<_>>(),
+        )
+    })
+    .try_collect::<Vec<_>>()
+    .await
+    .unwrap();
+
+    pretty_assertions_sorted::assert_eq!(actual, vec![(peer(1), vec![txn(41, 0)])]);
+    assert!(scores.read().await.score(&peer(0).0) < 0.0);
+}
+
+#[rstest]
+#[case::peer_fails(Step::Fail)]
+#[case::peer_stalls(Step::Stall)]
+#[tokio::test]
+async fn transactions_switch_peers_mid_block(#[case] failure: Step<TransactionsResponse>) {
+    // The first peer delivers block 0 and half of block 1 before failing.
+    let first = ScriptedPeer::new(peer(0)).then(vec![
+        Step::Respond(txn_resp(90, 0)),
+        Step::Respond(txn_resp(91, 0)),
+        Step::Respond(txn_resp(92, 1)),
+        failure,
+    ]);
+    // The second peer is only asked for block 1.
+    let second = ScriptedPeer::new(peer(1)).then(vec![
+        Step::Respond(txn_resp(91, 0)),
+        Step::Respond(txn_resp(92, 1)),
+        Step::Respond(txn_resp(93, 2)),
+        Step::Respond(txn_resp(94, 3)),
+        Step::Respond(TxnFin),
+    ]);
+    let expected = vec![
+        (peer(0), vec![txn(90, 0)]),
+        (
+            peer(1),
+            vec![txn(91, 0), txn(92, 1), txn(93, 2), txn(94, 3)],
+        ),
+    ];
+
+    let commitments = expected
+        .iter()
+        .map(|(_, transactions)| transactions.clone())
+        .collect::<Vec<_>>();
+    let verifier = TransactionCommitmentVerifier::new(move |block, transactions| {
+        Ok(transactions
+            .iter()
+            .cloned()
+            .map(TestTxn::new)
+            .eq(commitments[block.get() as usize].iter().cloned()))
+    });
+    let get_peers = || async { vec![peer(0).0, peer(1).0] };
+    let send_request = scripted_requests::<_, TransactionsRequest>(&[first, second]);
+    let config = StreamConfig {
+        response_timeout: Duration::from_millis(50),
+        ..Default::default()
+    };
+
+    let actual = super::transaction_stream::make(
+        BlockNumber::GENESIS,
+        BlockNumber::new_or_panic(1),
+        false,
+        NonZeroU64::MIN,
+        stream::iter([Ok(1), Ok(4)]),
+        Some(verifier),
+        config,
+        get_peers,
+        send_request,
+    )
+    .map_ok(|x| {
+        (
+            TestPeer(x.peer),
+            x.data.0.into_iter().map(TestTxn::new).collect::<Vec<_>>(),
+        )
+    })
+    .try_collect::<Vec<_>>()
+    .await
+    .unwrap();
+
+    pretty_assertions_sorted::assert_eq!(actual, expected);
+}
+
+#[tokio::test]
+async fn transaction_from_newer_protocol_version_ends_the_stream() {
+    let get_peers = || async { vec![peer(0).0, peer(1).0] };
+    let send_request = |_: PeerId, _: TransactionsRequest| {
+        // The transaction kind is unknown to this version of the protocol.
+        let (mut sender, responses) = fmpsc::channel(1);
+        sender
+            .try_send(Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "Unknown variant of field txn",
+            )))
+            .unwrap();
+        async move { Ok(responses) }
+    };
+    let config = StreamConfig::default();
+    let scores = config.peers.clone();
+
+    let actual = super::transaction_stream::make(
+        BlockNumber::GENESIS,
+        BlockNumber::GENESIS,
+        false,
+        NonZeroU64::MIN,
+        stream::iter([Ok(1)]),
+        None,
+        config,
+        get_peers,
+        send_request,
+    )
+    .collect::<Vec<_>>()
+    .await;
+
+    // Only ends once the second peer sends the same data.
+    assert_eq!(actual.len(), 1);
+    let error = actual.into_iter().next().unwrap().unwrap_err();
+    assert_eq!(TestPeer(error.peer), peer(1));
+    assert!(matches!(
+        error.data,
+        StreamError::UnsupportedProtocolVersion(_)
+    ));
+    assert_eq!(scores.read().await.score(&peer(0).0), 0.0);
+    assert_eq!(scores.read().await.score(&peer(1).0), 0.0);
+}
+
+#[tokio::test]
+async fn transaction_from_newer_protocol_version_of_single_peer_moves_to_next_peer() {
+    let get_peers = || async { vec![peer(0).0, peer(1).0] };
+    let send_request = |requested: PeerId, _: TransactionsRequest| {
+        // Only the first peer claims to send data of a newer protocol version.
+        let (mut sender, responses) = fmpsc::channel(2);
+        if requested == peer(0).0 {
+            sender
+                .try_send(Err(std::io::Error::new(
+                    std::io::ErrorKind::Unsupported,
+                    "Unknown variant of field txn",
+                )))
+                .unwrap();
+        } else {
+            sender.try_send(Ok(txn_resp(42, 0))).unwrap();
+            sender.try_send(Ok(TxnFin)).unwrap();
+        }
+        async move { Ok(responses) }
+    };
+    let config = StreamConfig::default();
+    let scores = config.peers.clone();
+
+    let actual = super::transaction_stream::make(
+        BlockNumber::GENESIS,
+        BlockNumber::GENESIS,
+        false,
+        NonZeroU64::MIN,
+        stream::iter([Ok(1)]),
+        None,
+        config,
+        get_peers,
+        send_request,
+    )
+    .map_ok(|x| {
+        (
+            TestPeer(x.peer),
+            x.data.0.into_iter().map(TestTxn::new).collect::<Vec<_>>(),
+        )
+    })
+    .try_collect::<Vec<_>>()
+    .await
+    .unwrap();
+
+    pretty_assertions_sorted::assert_eq!(actual, vec![(peer(1), vec![txn(42, 0)])]);
+    assert_eq!(scores.read().await.score(&peer(0).0), 0.0);
+}
+
+#[test]
+fn deploy_of_unknown_version_is_malformed() {
+    let deploy = p2p_proto::transaction::Transaction::Deploy(p2p_proto::transaction::Deploy {
+        class_hash: p2p_proto::common::Hash(Felt::ZERO),
+        address_salt: Felt::ZERO,
+        calldata: vec![],
+        version: 2,
+    });
+
+    let error = TransactionVariant::try_from_dto(deploy).unwrap_err();
+    assert!(UnsupportedProtocolVersion::from_conversion_error(&error).is_none());
+}
+
+#[tokio::test]
+async fn event_commitment_mismatch_moves_to_next_peer() {
+    let get_peers = || async { vec![peer(0).0, peer(1).0] };
+    let send_request = |requested: PeerId, _: EventsRequest| {
+        // The first peer sends an event which does not match the commitment.
+        let ev = if requested == peer(0).0 { 50 } else { 51 };
+        let (mut sender, responses) = fmpsc::channel(2);
+        sender.try_send(Ok(event_resp(ev, 5))).unwrap();
+        sender.try_send(Ok(EventFin)).unwrap();
+        async move { Ok(responses) }
+    };
+    let expected = events(vec![(vec![51], 5)], 0);
+    let verifier = {
+        let expected = expected.clone();
+        EventCommitmentVerifier::new(move |_, events| {
+            Ok(events
+                .iter()
+                .map(|(t, e)| (TaggedTransactionHash(*t), e.clone()))
+                .eq(expected.1.iter().cloned()))
+        })
+    };
+    let config = StreamConfig::default();
+    let scores = config.peers.clone();
+
+    let actual = super::event_stream::make(
+        BlockNumber::GENESIS,
+        BlockNumber::GENESIS,
+        false,
+        NonZeroU64::MIN,
+        stream::iter([Ok(1)]),
+        Some(verifier),
+        None,
+        config,
+        get_peers,
+        send_request,
+    )
+    .map_ok(|x| {
+        (
+            TestPeer(x.peer),
+            (
+                x.data.0,
+                x.data
+                    .1
+                    .into_iter()
+                    .map(|(t, e)| (TaggedTransactionHash(t), e))
+                    .collect::<Vec<_>>(),
+            ),
+        )
+    })
+    .try_collect::<Vec<_>>()
+    .await
+    .unwrap();
+
+    pretty_assertions_sorted::assert_eq!(actual, vec![(peer(1), expected)]);
+    assert!(scores.read().await.score(&peer(0).0) < 0.0);
+}
+
+#[tokio::test]
+async fn event_count_not_matching_header_is_rejected() {
+    let get_peers = || async { vec![peer(0).0, peer(1).0] };
+    let config = StreamConfig::default();
+    let cancellation = config.cancellation.clone();
+    let requests = Arc::new(AtomicUsize::new(0));
+    let send_request = move |_: PeerId, _: EventsRequest| {
+        let (mut sender, responses) = fmpsc::channel(2);
+        if requests.fetch_add(1, Ordering::SeqCst) < 2 {
+            sender.try_send(Ok(event_resp(52, 6))).unwrap();
+            sender.try_send(Ok(EventFin)).unwrap();
+        } else {
+            // Both peers were rejected, the stream would keep retrying.
+            cancellation.cancel();
+            std::mem::forget(sender);
+        }
+        async move { Ok(responses) }
+    };
+    // The block has a single event according to the counts stream, but two
+    // according to its header.
+    let header_counts = EventCountLookup::new(|_| Ok(2));
+    let scores = config.peers.clone();
+
+    let items = super::event_stream::make(
+        BlockNumber::GENESIS,
+        BlockNumber::GENESIS,
+        false,
+        NonZeroU64::MIN,
+        stream::iter([Ok(1)]),
+        None,
+        Some(header_counts),
+        config,
+        get_peers,
+        send_request,
+    )
+    .collect::<Vec<_>>()
+    .await;
+
+    assert!(items.is_empty());
+    assert!(scores.read().await.score(&peer(0).0) < 0.0);
+    assert!(scores.read().await.score(&peer(1).0) < 0.0);
+}
+
+#[test]
+fn headers_for_range_must_be_contiguous() {
+    let stop = BlockNumber::new_or_panic(2);
+    let mut chain = vec![hdr(0), hdr(1), hdr(2)];
+    chain[1].header.parent_hash = chain[0].header.hash;
+    chain[2].header.parent_hash = chain[1].header.hash;
+    let with_peer = |headers: &[SignedBlockHeader]| {
+        headers
+            .iter()
+            .cloned()
+            .map(|header| PeerData::new(peer(0).0, header))
+            .collect::<Vec<_>>()
+    };
+
+    let range = contiguous_headers(BlockNumber::GENESIS, stop, with_peer(&chain), true).unwrap();
+    assert_eq!(range, chain);
+
+    // Numbers must not skip blocks.
+    let gap = [chain[0].clone(), chain[2].clone()];
+    contiguous_headers(BlockNumber::GENESIS, stop, with_peer(&gap), false).unwrap_err();
+    // The stream must cover the whole range.
+    contiguous_headers(BlockNumber::GENESIS, stop, with_peer(&chain[..2]), false).unwrap_err();
+
+    // Parent hashes are only checked on request.
+    chain[2].header.parent_hash = chain[0].header.hash;
+    contiguous_headers(BlockNumber::GENESIS, stop, with_peer(&chain), false).unwrap();
+    contiguous_headers(BlockNumber::GENESIS, stop, with_peer(&chain), true).unwrap_err();
+}
+
+#[tokio::test]
+async fn first_ok_from_peers_skips_failed_peers() {
+    let peers = (0..4).map(|i| peer(i).0).collect::<Vec<_>>();
+    let slow = peers[0];
+    let invalid = peers[1];
+    let fast = peers[2];
+
+    let (found, errors) =
+        first_ok_from_peers(peers, NonZeroUsize::new(2).unwrap(), |peer| async move {
+            if peer == slow {
+                tokio::time::sleep(Duration::from_secs(60)).await;
+                Ok(peer)
+            } else if peer == invalid {
+                // Fast but wrong peers must not win.
+                Err("invalid")
+            } else {
+                Ok(peer)
+            }
+        })
+        .await;
+
+    // The last peer is not queried as the third one responds first.
+    assert_eq!(found, Some((fast, fast)));
+    assert_eq!(errors, vec!["invalid"]);
+}
+
+#[tokio::test]
+async fn first_ok_from_peers_reports_all_errors() {
+    let peers = (0..3).map(|i| peer(i).0).collect::<Vec<_>>();
+
+    let (found, errors) = first_ok_from_peers(peers, NonZeroUsize::new(2).unwrap(), |_| async {
+        Err::<(), _>("invalid")
+    })
+    .await;
+
+    assert_eq!(found, None);
+    assert_eq!(errors.len(), 3);
+}
+
+#[tokio::test]
+async fn concurrent_requests_for_a_block_are_coalesced() {
+    let inflight = coalesce::Inflight::<BlockNumber, u64>::default();
+    let fetches = std::sync::Mutex::new(0);
+    let request = || {
+        coalesce::coalesce(
+            &inflight,
+            BlockNumber::GENESIS,
+            || async {
+                *fetches.lock().unwrap() += 1;
+                // Give the other requester a chance to join.
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                Some((peer(0).0, 1))
+            },
+            |found| *found,
+            Some,
+        )
+    };
+
+    let (first, second) = tokio::join!(request(), request());
+    assert_eq!(first, Some((peer(0).0, 1)));
+    assert_eq!(second, first);
+    assert_eq!(*fetches.lock().unwrap(), 1);
+
+    // Resolved requests are not cached.
+    request().await;
+    assert_eq!(*fetches.lock().unwrap(), 2);
+}
+
+#[tokio::test]
+async fn concurrent_requests_expecting_different_counts_are_not_coalesced() {
+    let client = mock_client(|command| {
+        if let crate::Command::SendClassesSyncRequest { sender, .. } = command {
+            let (mut tx, rx) = fmpsc::channel(2);
+            tx.try_send(Ok(cairo0_class_resp())).unwrap();
+            tx.try_send(Ok(ClassFin)).unwrap();
+            let _ = sender.send(Ok(rx.into()));
+        }
+    });
+    client
+        .peers
+        .write()
+        .await
+        .known
+        .update(HashSet::from([peer(0).0]));
+
+    // The block only declares a single class, so only the first requester's
+    // expectation is met.
+    let (one, two) = tokio::join!(
+        client
+            .clone()
+            .class_definitions_for_block(BlockNumber::GENESIS, 1),
+        client
+            .clone()
+            .class_definitions_for_block(BlockNumber::GENESIS, 2),
+    );
+
+    let (peer_id, classes) = one.unwrap().unwrap();
+    assert_eq!(TestPeer(peer_id), peer(0));
+    assert_eq!(classes.len(), 1);
+    assert!(matches!(
+        two,
+        Err(ClassDefinitionsError::IncorrectClassDefinitionCount(_))
+    ));
+}
+
+#[tokio::test]
+async fn concurrent_streams_for_a_block_are_coalesced() {
+    let inflight = coalesce::Inflight::<BlockNumber, _>::default();
+    let fetches = std::sync::Mutex::new(0);
+    let (items, stream) = fmpsc::unbounded::<Result<u64, &'static str>>();
+    let stream = std::sync::Mutex::new(Some(stream));
+    let request = || {
+        coalesce::coalesce_stream(
+            &inflight,
+            BlockNumber::GENESIS,
+            || async {
+                *fetches.lock().unwrap() += 1;
+                // Give the other requester a chance to join.
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                Some((peer(0).0, stream.lock().unwrap().take().unwrap()))
+            },
+            |_| "shared stream failed",
+        )
+    };
+
+    let (first, second) = tokio::join!(request(), request());
+    assert_eq!(*fetches.lock().unwrap(), 1);
+    let (first_peer, mut first) = first.unwrap();
+    let (second_peer, mut second) = second.unwrap();
+    assert_eq!(first_peer, peer(0).0);
+    assert_eq!(second_peer, peer(0).0);
+
+    // Items are passed on as they arrive rather than once the block is complete.
+    items.unbounded_send(Ok(1)).unwrap();
+    assert_eq!(first.next().await, Some(Ok(1)));
+    assert_eq!(second.next().await, Some(Ok(1)));
+
+    items.unbounded_send(Ok(2)).unwrap();
+    drop(items);
+    assert_eq!(first.collect::<Vec<_>>().await, [Ok(2)]);
+    assert_eq!(second.collect::<Vec<_>>().await, [Ok(2)]);
+}
+
+#[rstest]
+#[case::stream_fails(true)]
+#[case::stream_is_dropped(false)]
+#[tokio::test]
+async fn coalesced_streams_fail_with_the_first_requester(#[case] fails: bool) {
+    let inflight = coalesce::Inflight::<BlockNumber, _>::default();
+    let (items, stream) = fmpsc::unbounded::<Result<u64, &'static str>>();
+    let stream = std::sync::Mutex::new(Some(stream));
+    let request = || {
+        coalesce::coalesce_stream(
+            &inflight,
+            BlockNumber::GENESIS,
+            || async {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                Some((peer(0).0, stream.lock().unwrap().take().unwrap()))
+            },
+            |_| "shared stream failed",
+        )
+    };
+
+    let (first, second) = tokio::join!(request(), request());
+    let (_, mut first) = first.unwrap();
+    let (_, second) = second.unwrap();
+
+    items.unbounded_send(Ok(1)).unwrap();
+    assert_eq!(first.next().await, Some(Ok(1)));
+    if fails {
+        items.unbounded_send(Err("connection lost")).unwrap();
+        assert_eq!(first.next().await, Some(Err("connection lost")));
+    } else {
+        drop(first);
+    }
+
+    assert_eq!(
+        second.collect::<Vec<_>>().await,
+        [Ok(1), Err("shared stream failed")]
+    );
+}
+
+#[test]
+fn decaying_expires_after_timeout() {
+    let mut peers = Decaying::<HashSet<PeerId>>::new(Duration::from_secs(3600));
+    // Nothing is cached initially, regardless of the timeout.
+    assert!(peers.get().is_none());
+
+    peers.update(HashSet::from([peer(0).0]));
+    assert_eq!(peers.get(), Some(&HashSet::from([peer(0).0])));
+
+    peers.last_update = Some(Instant::now() - Duration::from_secs(2));
+    peers.timeout = Duration::from_secs(1);
+    assert!(peers.get().is_none());
+}
+
+#[tokio::test]
+async fn cancelled_stream_stops_requesting() {
+    let requests = Arc::new(std::sync::Mutex::new(0));
+    let get_peers = || async { vec![peer(0).0] };
+    let send_request = {
+        let requests = requests.clone();
+        move |_: PeerId, _: TransactionsRequest| {
+            *requests.lock().unwrap() += 1;
+            // Accept the request but never respond.
+            let (sender, responses) = fmpsc::channel::<std::io::Result<TransactionsResponse>>(1);
+            std::mem::forget(sender);
+            async move { Ok(responses) }
+        }
+    };
+    let config = StreamConfig::default();
+    let cancellation = config.cancellation.clone();
+
+    let stream = super::transaction_stream::make(
+        BlockNumber::GENESIS,
+        BlockNumber::GENESIS,
+        false,
+        NonZeroU64::MIN,
+        stream::iter([Ok(1)]),
+        None,
+        config,
+        get_peers,
+        send_request,
+    );
+    cancellation.cancel();
+
+    assert_eq!(stream.collect::<Vec<_>>().await.len(), 0);
+    assert_eq!(*requests.lock().unwrap(), 0);
+}
+
+#[tokio::test]
+async fn stream_waits_for_a_permit() {
+    let requests = Arc::new(std::sync::Mutex::new(0));
+    let get_peers = || async { vec![peer(0).0] };
+    let send_request = {
+        let requests = requests.clone();
+        move |_: PeerId, _: TransactionsRequest| {
+            *requests.lock().unwrap() += 1;
+            // Accept the request but never respond.
+            let (sender, responses) = fmpsc::channel::<std::io::Result<TransactionsResponse>>(1);
+            std::mem::forget(sender);
+            async move { Ok(responses) }
+        }
+    };
+    let permits = Arc::new(tokio::sync::Semaphore::new(1));
+    let config = StreamConfig {
+        permits: Some(permits.clone()),
+        ..Default::default()
+    };
+    // Another stream is running.
+    let permit = permits.clone().acquire_owned().await.unwrap();
+
+    let _stream = super::transaction_stream::make(
+        BlockNumber::GENESIS,
+        BlockNumber::GENESIS,
+        false,
+        NonZeroU64::MIN,
+        stream::iter([Ok(1)]),
+        None,
+        config,
+        get_peers,
+        send_request,
+    );
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(*requests.lock().unwrap(), 0);
+
+    drop(permit);
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while *requests.lock().unwrap() == 0 {
+            tokio::task::yield_now().await;
+        }
+    })
+    .await
+    .unwrap();
+}
+
+#[rstest]
+#[case::consistently_slow(vec![1000, 1000, 1000, 1000], vec![0])]
+#[case::occasionally_slow(vec![1000, 0, 1000, 0], vec![0, 1, 2, 3])]
+#[case::fast(vec![0, 0, 0, 0], vec![0, 1, 2, 3])]
+#[tokio::test(start_paused = true)]
+async fn slow_responses_end_the_stream(#[case] delays_ms: Vec<u64>, #[case] expected: Vec<usize>) {
+    let responses = stream::iter(delays_ms.into_iter().enumerate())
+        .then(|(i, delay)| async move {
+            tokio::time::sleep(Duration::from_millis(delay)).await;
+            i
+        })
+        .boxed();
+
+    let responses = end_when_slow(
+        peer(0).0,
+        responses,
+        Duration::from_millis(500),
+        NonZeroUsize::new(2).unwrap(),
+    );
+
+    assert_eq!(responses.collect::<Vec<_>>().await, expected);
+}
+
+#[tokio::test]
+async fn stream_gives_up_after_failed_rounds() {
+    let make = |peers: Vec<PeerId>| {
+        super::header_stream::make(
+            BlockNumber::GENESIS,
+            BlockNumber::GENESIS,
+            false,
+            NonZeroU64::MIN,
+            false,
+            StreamConfig {
+                max_failed_rounds: Some(NonZeroUsize::new(2).unwrap()),
+                ..Default::default()
+            },
+            move || {
+                let peers = peers.clone();
+                async move { peers }
+            },
+            // Peers which never have the block.
+            |_: PeerId, _: BlockHeadersRequest| async {
+                anyhow::Ok(stream::iter([std::io::Result::Ok(HdrFin)]))
+            },
+        )
+    };
+
+    let mut items = make(vec![peer(0).0, peer(1).0]).collect::<Vec<_>>().await;
+    assert_eq!(items.len(), 1);
+    let error = items.pop().unwrap().unwrap_err().data;
+    assert!(
+        matches!(error, StreamError::AllPeersFailed { block } if block == BlockNumber::GENESIS),
+        "{error}"
+    );
+
+    let mut items = make(vec![]).collect::<Vec<_>>().await;
+    assert_eq!(items.len(), 1);
+    let error = items.pop().unwrap().unwrap_err().data;
+    assert!(matches!(error, StreamError::NoPeers), "{error}");
+}
+
+#[tokio::test(start_paused = true)]
+async fn only_the_highest_of_rapid_heads_is_propagated() {
+    let published = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let (tx, rx) = mpsc::unbounded_channel();
+    let task = tokio::spawn(debounce_heads(Duration::from_secs(1), rx, {
+        let published = published.clone();
+        move |head: u64| {
+            published.lock().unwrap().push(head);
+            async { anyhow::Ok(()) }
+        }
+    }));
+
+    for number in [1, 3, 2] {
+        tx.send((number, number)).unwrap();
+    }
+    tokio::time::sleep(Duration::from_secs(2)).await;
+    tx.send((4, 4)).unwrap();
+    drop(tx);
+    task.await.unwrap();
+
+    assert_eq!(*published.lock().unwrap(), vec![3, 4]);
+}
+
+#[rstest]
+#[case::exact_count(vec![class_resp(70), class_resp(71), ClassFin], 2, vec![Ok(class(70, 0)), Ok(class(71, 0))])]
+#[case::without_fin(vec![class_resp(70)], 1, vec![Ok(class(70, 0))])]
+#[case::too_many(vec![class_resp(70), class_resp(71), ClassFin], 1, vec![Ok(class(70, 0)), Err(peer(0).0)])]
+#[case::too_few(vec![class_resp(70), ClassFin], 2, vec![Ok(class(70, 0)), Err(peer(0).0)])]
+#[tokio::test]
+async fn class_definitions_are_streamed_as_they_arrive(
+    #[case] responses: Vec<ClassesResponse>,
+    #[case] declared_classes_count: u64,
+    #[case] expected: Vec<Result<ClassDefinition, PeerId>>,
+) {
+    let responses = stream::iter(responses.into_iter().map(Ok));
+
+    let actual = class_definitions_from_responses(
+        peer(0).0,
+        BlockNumber::GENESIS,
+        declared_classes_count,
+        responses,
+    )
+    .map(|x| {
+        x.map_err(|error| match error {
+            ClassDefinitionsError::IncorrectClassDefinitionCount(peer) => peer,
+            other => panic!("Unexpected error: {other}"),
+        })
+    })
+    .collect::<Vec<_>>()
+    .await;
+
+    pretty_assertions_sorted::assert_eq!(actual, expected);
+}
+
+#[tokio::test]
+async fn block_is_attributed_to_the_peer_which_completed_it() {
+    let (peers, responses) = unzip_fixtures(vec![
+        // The first peer only sends one of the two transactions of the block.
+        Ok((peer(0), vec![txn_resp(80, 0), TxnFin])),
+        Ok((peer(1), vec![txn_resp(80, 0), txn_resp(81, 1), TxnFin])),
+    ]);
+    let get_peers = move || {
+        let peers = peers.clone();
+        async move { peers }
+    };
+    let send_request = move |_: PeerId, _: TransactionsRequest| {
+        let responses = responses.clone();
+        async move { send_request(responses).await }
+    };
+
+    let actual = super::transaction_stream::make(
+        BlockNumber::GENESIS,
+        BlockNumber::GENESIS,
+        false,
+        NonZeroU64::MIN,
+        stream::iter([anyhow::Ok(2)]),
+        None,
+        Default::default(),
+        get_peers,
+        send_request,
+    )
+    .map_ok(|x| {
+        (
+            TestPeer(x.peer),
+            x.data.0.into_iter().map(TestTxn::new).collect::<Vec<_>>(),
+        )
+    })
+    .try_collect::<Vec<_>>()
+    .await
+    .unwrap();
+
+    pretty_assertions_sorted::assert_eq!(actual, vec![(peer(1), vec![txn(80, 0), txn(81, 1)])]);
+}
+
+#[derive(Debug, Default)]
+struct MemoryPeerStore(std::sync::Mutex<HashMap<PeerId, PeerRecord>>);
+
+impl PeerStore for MemoryPeerStore {
+    fn load(&self) -> anyhow::Result<HashMap<PeerId, PeerRecord>> {
+        Ok(self.0.lock().unwrap().clone())
+    }
+
+    fn save(&self, records: &HashMap<PeerId, PeerRecord>) -> anyhow::Result<()> {
+        *self.0.lock().unwrap() = records.clone();
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn peer_scores_are_persisted() {
+    let store = Arc::new(MemoryPeerStore::default());
+    store.0.lock().unwrap().insert(
+        peer(0).0,
+        PeerRecord {
+            successes: 5.0,
+            failures: 0.0,
+        },
+    );
+
+    let (sender, _receiver) = mpsc::channel(1);
+    let client = Client::new(
+        peer_aware::Client::new(sender, PeerId::random()),
+        "blocks".to_owned(),
+    )
+    .with_peer_store(store.clone(), Duration::from_millis(10));
+
+    // Scores from before the restart are picked up.
+    assert!((client.peers.read().await.score(&peer(0).0) - 5.0).abs() < 0.01);
+
+    client
+        .peers
+        .write()
+        .await
+        .scores
+        .entry(peer(1).0)
+        .or_insert_with(PeerScore::new)
+        .record_failure();
+
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while !store.0.lock().unwrap().contains_key(&peer(1).0) {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("Scores are saved");
+
+    let records = store.load().unwrap();
+    assert!((records[&peer(0).0].successes - 5.0).abs() < 0.01);
+    assert!((records[&peer(1).0].failures - 1.0).abs() < 0.01);
+}
+
+#[tokio::test]
+async fn peer_scores_are_saved_when_the_client_is_dropped() {
+    let store = Arc::new(MemoryPeerStore::default());
+    store.0.lock().unwrap().insert(
+        peer(0).0,
+        PeerRecord {
+            successes: 5.0,
+            failures: 0.0,
+        },
+    );
+
+    let (sender, _receiver) = mpsc::channel(1);
+    let client = Client::new(
+        peer_aware::Client::new(sender, PeerId::random()),
+        "blocks".to_owned(),
+    )
+    // Too long for a periodic save to happen during the test.
+    .with_peer_store(store.clone(), Duration::from_secs(3600))
+    .with_peer_cache_timeout(Duration::from_secs(60));
+
+    // Setting the peer cache timeout afterwards keeps the scores.
+    assert!((client.peers.read().await.score(&peer(0).0) - 5.0).abs() < 0.01);
+
+    client
+        .peers
+        .write()
+        .await
+        .scores
+        .entry(peer(1).0)
+        .or_insert_with(PeerScore::new)
+        .record_failure();
+    let other = client.clone();
+    drop(client);
+    assert!(!store.0.lock().unwrap().contains_key(&peer(1).0));
+
+    drop(other);
+    let records = store.load().unwrap();
+    assert!((records[&peer(0).0].successes - 5.0).abs() < 0.01);
+    assert!((records[&peer(1).0].failures - 1.0).abs() < 0.01);
+}
+
+#[test]
+fn file_peer_store() {
+    let dir = tempfile::tempdir().unwrap();
+    let store = FilePeerStore::new(dir.path().join("peers.json"));
+
+    // Nothing was saved yet.
+    assert_eq!(store.load().unwrap(), HashMap::new());
+
+    let records = HashMap::from([(
+        peer(0).0,
+        PeerRecord {
+            successes: 2.5,
+            failures: 1.0,
+        },
+    )]);
+    store.save(&records).unwrap();
+    assert_eq!(store.load().unwrap(), records);
+}
+
+#[tokio::test]
+async fn blacklisted_peers_are_not_used() {
+    let (sender, _receiver) = mpsc::channel(1);
+    let client = Client::new(
+        peer_aware::Client::new(sender, PeerId::random()),
+        "blocks".to_owned(),
+    );
+    client
+        .peers
+        .write()
+        .await
+        .known
+        .update(HashSet::from([peer(0).0, peer(1).0]));
+
+    client.blacklist_peer(peer(0).0).await;
+    assert_eq!(client.get_random_peers().await, vec![peer(1).0]);
+
+    client.unblacklist_peer(peer(0).0).await;
+    let mut peers = client.get_random_peers().await;
+    peers.sort();
+    let mut expected = vec![peer(0).0, peer(1).0];
+    expected.sort();
+    assert_eq!(peers, expected);
+}
+
+#[tokio::test]
+async fn concurrent_callers_share_a_peer_refresh() {
+    let queries = Arc::new(std::sync::Mutex::new(0));
+    let client = mock_client({
+        let queries = queries.clone();
+        move |command| {
+            if let crate::Command::GetClosestPeers { sender, .. } = command {
+                *queries.lock().unwrap() += 1;
+                tokio::spawn(async move {
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                    let _ = sender.send(Ok(vec![peer(0).0, peer(1).0])).await;
+                });
+            }
+        }
+    });
+
+    let results = futures::future::join_all((0..5).map(|_| client.get_random_peers())).await;
+
+    assert_eq!(*queries.lock().unwrap(), 1);
+    for mut peers in results {
+        peers.sort();
+        let mut expected = vec![peer(0).0, peer(1).0];
+        expected.sort();
+        assert_eq!(peers, expected);
+    }
+}
+
+#[tokio::test]
+async fn recently_connected_peers_are_used_while_the_dht_fails() {
+    // No peers are connected at the moment.
+    let client = mock_client(|command| match command {
+        crate::Command::GetClosestPeers { sender, .. } => {
+            let _ = sender.try_send(Err(anyhow::anyhow!("DHT failed")));
+        }
+        crate::Command::ConnectedPeers { sender } => {
+            let _ = sender.send(HashSet::new());
+        }
+        _ => {}
+    })
+    .with_connected_peers_fallback();
+    client.peers.write().await.recently_connected = HashSet::from([peer(0).0]);
+
+    let peers = client.get_random_peers().await;
+
+    assert_eq!(peers, vec![peer(0).0]);
+}
+
+#[tokio::test]
+async fn connected_peers_are_not_queried_while_the_dht_works() {
+    let queries = Arc::new(std::sync::Mutex::new(0));
+    let client = mock_client({
+        let queries = queries.clone();
+        move |command| match command {
+            crate::Command::GetClosestPeers { sender, .. } => {
+                let _ = sender.try_send(Ok(vec![peer(1).0]));
+            }
+            crate::Command::ConnectedPeers { sender } => {
+                *queries.lock().unwrap() += 1;
+                let _ = sender.send(HashSet::from([peer(0).0]));
+            }
+            _ => {}
+        }
+    })
+    .with_connected_peers_fallback();
+
+    let peers = client.get_random_peers().await;
+
+    assert_eq!(peers, vec![peer(1).0]);
+    assert_eq!(*queries.lock().unwrap(), 0);
+    assert!(client.peers.read().await.recently_connected.is_empty());
+}
+
+#[tokio::test]
+async fn preferred_peers_come_first() {
+    // Peer 3 is not connected.
+    let client = mock_client(|command| {
+        if let crate::Command::ConnectedPeers { sender } = command {
+            let _ = sender.send(HashSet::from([peer(0).0, peer(1).0, peer(2).0]));
+        }
+    });
+    client.peers.write().await.known.update(HashSet::from([
+        peer(0).0,
+        peer(1).0,
+        peer(4).0,
+        peer(5).0,
+    ]));
+
+    client
+        .set_preferred_peers(vec![peer(1).0, peer(0).0, peer(3).0, peer(2).0])
+        .await;
+    client.blacklist_peer(peer(2).0).await;
+
+    let peers = client.get_random_peers().await;
+    assert_eq!(peers.len(), 4);
+    assert_eq!(peers[..2], [peer(1).0, peer(0).0]);
+
+    // A misbehaving preferred peer loses its spot at the front.
+    client
+        .peers
+        .write()
+        .await
+        .scores
+        .entry(peer(1).0)
+        .or_insert_with(PeerScore::new)
+        .record_failure();
+
+    let peers = client.get_random_peers().await;
+    assert_eq!(peers.len(), 4);
+    assert_eq!(peers[0], peer(0).0);
+    assert!(peers[1..].contains(&peer(1).0));
+}
+
+#[tokio::test]
+async fn seeded_peer_order_is_reproducible() {
+    async fn peer_orders(seed: u64) -> Vec<Vec<PeerId>> {
+        let client = mock_client(|command| {
+            if let crate::Command::ConnectedPeers { sender } = command {
+                let _ = sender.send(HashSet::new());
+            }
+        })
+        .with_rng_seed(seed);
+        client
+            .peers
+            .write()
+            .await
+            .known
+            .update((0..10).map(|i| peer(i).0).collect());
+
+        let mut orders = Vec::new();
+        for _ in 0..3 {
+            orders.push(client.get_random_peers().await);
+        }
+        orders
+    }
+
+    let orders = peer_orders(1).await;
+    assert_eq!(orders, peer_orders(1).await);
+    // Consecutive calls still shuffle the peers.
+    assert!(orders.iter().any(|order| order != &orders[0]));
+}
+
+#[tokio::test]
+async fn full_blocks_are_assembled_from_the_sync_streams() {
+    let (transactions, transaction_counts) = fmpsc::unbounded();
+    let (state_diffs, state_diff_lengths) = fmpsc::unbounded();
+    let (classes, class_counts) = fmpsc::unbounded();
+    let (events, event_counts) = fmpsc::unbounded();
+    fn item<T>(data: T) -> StreamItem<T> {
+        Ok(PeerData::new(peer(1).0, data))
+    }
+
+    let expected = (0..2)
+        .map(|i| {
+            let TestTxn { t, r } = txn(i, 0);
+            FullBlock {
+                header: hdr(i),
+                transactions: vec![(t, r)],
+                state_diff: state_diff(i),
+                classes: vec![class(i, i as u64)],
+                events: vec![],
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let actual = super::full_block_stream::make(
+        Default::default(),
+        stream::iter(
+            expected
+                .iter()
+                .map(|block| Ok(PeerData::new(peer(0).0, block.header.clone()))),
+        ),
+        super::full_block_stream::CountSenders {
+            transactions,
+            state_diffs,
+            classes,
+            events,
+        },
+        stream::iter(
+            expected
+                .iter()
+                .map(|block| item((block.transactions.clone(), block.header.header.number))),
+        ),
+        stream::iter(
+            expected
+                .iter()
+                .map(|block| item((block.state_diff.clone(), block.header.header.number))),
+        ),
+        stream::iter(expected.iter().map(|block| item(block.classes[0].clone()))),
+        stream::iter(
+            expected
+                .iter()
+                .map(|block| item((block.header.header.number, vec![]))),
+        ),
+    )
+    .map_ok(|x| (TestPeer(x.peer), x.data))
+    .try_collect::<Vec<_>>()
+    .await
+    .unwrap();
+
+    pretty_assertions_sorted::assert_eq!(
+        actual,
+        expected
+            .iter()
+            .map(|block| (peer(0), block.clone()))
+            .collect::<Vec<_>>()
+    );
+
+    // The counts are taken from the headers, the declared classes from the state
+    // diffs.
+    let counts = |counts: fmpsc::UnboundedReceiver<anyhow::Result<usize>>| {
+        counts.map(Result::unwrap).collect::<Vec<_>>()
+    };
+    assert_eq!(
+        counts(transaction_counts).await,
+        expected
+            .iter()
+            .map(|block| block.header.header.transaction_count)
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(
+        counts(state_diff_lengths).await,
+        expected
+            .iter()
+            .map(|block| block.header.header.state_diff_length as usize)
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(counts(class_counts).await, vec![1, 1]);
+    assert_eq!(
+        counts(event_counts).await,
+        expected
+            .iter()
+            .map(|block| block.header.header.event_count)
+            .collect::<Vec<_>>()
+    );
+}
+
+#[tokio::test]
+async fn header_requests_are_split_into_windows() {
+    let (peers, responses) = unzip_fixtures(vec![
+        Ok((peer(0), vec![hdr_resp(0), hdr_resp(1), HdrFin])),
+        Ok((peer(1), vec![hdr_resp(2), hdr_resp(3), HdrFin])),
+    ]);
+    let requests = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let get_peers = move || {
+        let peers = peers.clone();
+        async move { peers }
+    };
+    let send_request = {
+        let requests = requests.clone();
+        move |_: PeerId, request: BlockHeadersRequest| {
+            requests.lock().unwrap().push(request.iteration);
+            let responses = responses.clone();
+            async move { send_request(responses).await }
+        }
+    };
+    let config = StreamConfig {
+        max_request_limit: NonZeroU64::new(2).unwrap(),
+        ..Default::default()
+    };
+
+    let actual = super::header_stream::make(
+        BlockNumber::GENESIS,
+        BlockNumber::new_or_panic(3),
+        false,
+        NonZeroU64::MIN,
+        false,
+        config,
+        get_peers,
+        send_request,
+    )
+    .map_ok(|x| (TestPeer(x.peer), x.data))
+    .try_collect::<Vec<_>>()
+    .await
+    .unwrap();
+
+    let expected = vec![
+        (peer(0), hdr(0)),
+        (peer(0), hdr(1)),
+        (peer(1), hdr(2)),
+        (peer(1), hdr(3)),
+    ];
+    pretty_assertions_sorted::assert_eq!(actual, expected);
+
+    let window = |start: u64| Iteration {
+        start: start.into(),
+        direction: Direction::Forward,
+        limit: 2,
+        step: 1.into(),
+    };
+    assert_eq!(*requests.lock().unwrap(), vec![window(0), window(2)]);
+}
+
+#[tokio::test]
+async fn completing_a_window_does_not_penalize_the_peer() {
+    let (peers, responses) = unzip_fixtures(vec![
+        Ok((peer(0), vec![txn_resp(90, 0), TxnFin])),
+        Ok((peer(1), vec![txn_resp(91, 0), TxnFin])),
+    ]);
+    let requests = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let get_peers = move || {
+        let peers = peers.clone();
+        async move { peers }
+    };
+    let send_request = {
+        let requests = requests.clone();
+        move |_: PeerId, request: TransactionsRequest| {
+            requests.lock().unwrap().push(request.iteration);
+            let responses = responses.clone();
+            async move { send_request(responses).await }
+        }
+    };
+    let config = StreamConfig {
+        max_request_limit: NonZeroU64::MIN,
+        ..Default::default()
+    };
+    let scores = config.peers.clone();
+
+    let actual = super::transaction_stream::make(
+        BlockNumber::GENESIS,
+        BlockNumber::new_or_panic(1),
+        false,
+        NonZeroU64::MIN,
+        stream::iter([Ok(1), Ok(1)]),
+        None,
+        config,
+        get_peers,
+        send_request,
+    )
+    .map_ok(|x| {
+        (
+            TestPeer(x.peer),
+            x.data.0.into_iter().map(TestTxn::new).collect::<Vec<_>>(),
+        )
+    })
+    .try_collect::<Vec<_>>()
+    .await
+    .unwrap();
+
+    let expected = vec![(peer(0), vec![txn(90, 0)]), (peer(1), vec![txn(91, 0)])];
+    pretty_assertions_sorted::assert_eq!(actual, expected);
+    let window = |start: u64| Iteration {
+        start: start.into(),
+        direction: Direction::Forward,
+        limit: 1,
+        step: 1.into(),
+    };
+    assert_eq!(*requests.lock().unwrap(), vec![window(0), window(1)]);
+
+    let state = scores.read().await;
+    assert!((state.score(&peer(0).0) - 1.0).abs() < 0.01);
+    assert!((state.score(&peer(1).0) - 1.0).abs() < 0.01);
+}
+
+#[tokio::test]
+async fn probe_peers_skips_failing_peers() {
+    let client = mock_client(|command| {
+        if let crate::Command::SendHeadersSyncRequest {
+            peer_id, sender, ..
+        } = command
+        {
+            if peer_id == peer(0).0 {
+                let (mut tx, rx) = fmpsc::channel(1);
+                tx.try_send(Ok(hdr_resp(0))).unwrap();
+                let _ = sender.send(Ok(rx.into()));
+            } else {
+                let _ = sender.send(Err(anyhow::anyhow!("Peer unreachable")));
+            }
+        }
+    });
+    client
+        .peers
+        .write()
+        .await
+        .known
+        .update(HashSet::from([peer(0).0, peer(1).0]));
+
+    let latencies = client.probe_peers(5).await;
+    assert_eq!(
+        latencies
+            .into_iter()
+            .map(|(peer, _)| peer)
+            .collect::<Vec<_>>(),
+        vec![peer(0).0]
+    );
+}
+
+#[tokio::test]
+async fn header_for_block_skips_failing_peers() {
+    let client = mock_client(|command| {
+        if let crate::Command::SendHeadersSyncRequest {
+            peer_id, sender, ..
+        } = command
+        {
+            let (mut tx, rx) = fmpsc::channel(2);
+            // The first peer sends the header of another block.
+            let tag = if peer_id == peer(0).0 { 4 } else { 3 };
+            tx.try_send(Ok(hdr_resp(tag))).unwrap();
+            tx.try_send(Ok(HdrFin)).unwrap();
+            let _ = sender.send(Ok(rx.into()));
+        }
+    });
+    client
+        .peers
+        .write()
+        .await
+        .known
+        .update(HashSet::from([peer(0).0, peer(1).0]));
+
+    let (found_peer, header) = client
+        .header_for_block(BlockNumber::new_or_panic(3))
+        .await
+        .unwrap();
+    assert_eq!(TestPeer(found_peer), peer(1));
+    pretty_assertions_sorted::assert_eq!(header, hdr(3));
+}
+
+#[tokio::test]
+async fn hinted_peer_is_asked_first() {
+    let requested = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let client = mock_client({
+        let requested = requested.clone();
+        move |command| {
+            if let crate::Command::SendHeadersSyncRequest {
+                peer_id, sender, ..
+            } = command
+            {
+                requested.lock().unwrap().push(TestPeer(peer_id));
+                if peer_id == peer(2).0 {
+                    let _ = sender.send(Err(anyhow::anyhow!("Peer unreachable")));
+                } else {
+                    let (mut tx, rx) = fmpsc::channel(2);
+                    tx.try_send(Ok(hdr_resp(3))).unwrap();
+                    tx.try_send(Ok(HdrFin)).unwrap();
+                    let _ = sender.send(Ok(rx.into()));
+                }
+            }
+        }
+    });
+    client
+        .peers
+        .write()
+        .await
+        .known
+        .update(HashSet::from([peer(0).0]));
+
+    let (found, _) = client
+        .clone()
+        .with_peer_hint(peer(1).0)
+        .header_for_block(BlockNumber::new_or_panic(3))
+        .await
+        .unwrap();
+    assert_eq!(TestPeer(found), peer(1));
+    assert_eq!(*requested.lock().unwrap(), vec![peer(1)]);
+
+    // Random peers are only asked once the hinted peer fails.
+    requested.lock().unwrap().clear();
+    let (found, _) = client
+        .with_peer_hint(peer(2).0)
+        .header_for_block(BlockNumber::new_or_panic(3))
+        .await
+        .unwrap();
+    assert_eq!(TestPeer(found), peer(0));
+    assert_eq!(*requested.lock().unwrap(), vec![peer(2), peer(0)]);
+}
+
+#[tokio::test]
+async fn peer_sending_invalid_class_is_demoted() {
+    let client = mock_client(|command| {
+        if let crate::Command::SendClassesSyncRequest {
+            peer_id, sender, ..
+        } = command
+        {
+            let class = match cairo0_class_resp() {
+                ClassesResponse::Class(p2p_proto::class::Class::Cairo0 { mut class, domain }) => {
+                    if peer_id == peer(0).0 {
+                        class.program = "not base64".to_owned();
+                    }
+                    ClassesResponse::Class(p2p_proto::class::Class::Cairo0 { class, domain })
+                }
+                _ => unreachable!(),
+            };
+            let (mut tx, rx) = fmpsc::channel(2);
+            tx.try_send(Ok(class)).unwrap();
+            tx.try_send(Ok(ClassFin)).unwrap();
+            let _ = sender.send(Ok(rx.into()));
+        }
+    });
+    client
+        .peers
+        .write()
+        .await
+        .known
+        .update(HashSet::from([peer(0).0, peer(1).0]));
+
+    let (found, _) = client
+        .clone()
+        .with_peer_hint(peer(0).0)
+        .class_definitions_for_block(BlockNumber::GENESIS, 1)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(TestPeer(found), peer(1));
+
+    assert!(client.peer_score(peer(0).0).await < 0.0);
+    let state = client.peers.read().await;
+    assert!(state.selection_weight(&peer(0).0) < state.selection_weight(&peer(1).0));
+}
+
+#[tokio::test]
+async fn pending_transactions_poll_the_successor_of_the_tip() {
+    use pathfinder_common::BlockHeader;
+
+    use crate::client::conv::ToDto;
+
+    let header = SignedBlockHeader {
+        header: BlockHeader {
+            number: BlockNumber::new_or_panic(1),
+            transaction_count: 1,
+            ..hdr(70).header
+        },
+        ..hdr(70)
+    }
+    .to_dto();
+    let mut header_requests = 0;
+    let client = mock_client(move |command| match command {
+        crate::Command::SendHeadersSyncRequest { sender, .. } => {
+            // The successor of the tip is only served from the second poll on.
+            header_requests += 1;
+            let (mut tx, rx) = fmpsc::channel(2);
+            if header_requests > 1 {
+                tx.try_send(Ok(BlockHeadersResponse::Header(Box::new(header.clone()))))
+                    .unwrap();
+            }
+            tx.try_send(Ok(HdrFin)).unwrap();
+            let _ = sender.send(Ok(rx.into()));
+        }
+        crate::Command::SendTransactionsSyncRequest { sender, .. } => {
+            let (mut tx, rx) = fmpsc::channel(2);
+            tx.try_send(Ok(txn_resp(70, 0))).unwrap();
+            tx.try_send(Ok(TxnFin)).unwrap();
+            let _ = sender.send(Ok(rx.into()));
+        }
+        _ => {}
+    });
+    client
+        .peers
+        .write()
+        .await
+        .known
+        .update(HashSet::from([peer(0).0]));
+
+    let (found, block, transactions) =
+        Box::pin(client.pending_transactions(BlockNumber::GENESIS, Duration::from_millis(1)))
+            .next()
+            .await
+            .unwrap();
+
+    assert_eq!(TestPeer(found), peer(0));
+    assert_eq!(block, BlockNumber::new_or_panic(1));
+    assert_eq!(
+        transactions
+            .into_iter()
+            .map(TestTxn::new)
+            .collect::<Vec<_>>(),
+        vec![txn(70, 0)]
+    );
+}
+
+#[tokio::test]
+async fn transactions_for_block_without_fin_are_capped() {
+    // Keeps the response streams open, so they never end on their own.
+    let mut open = Vec::new();
+    let client = mock_client(move |command| {
+        if let crate::Command::SendTransactionsSyncRequest { sender, .. } = command {
+            let (mut tx, rx) = fmpsc::channel(3);
+            for i in 0..3 {
+                tx.try_send(Ok(txn_resp(80 + i, i as u64))).unwrap();
+            }
+            open.push(tx);
+            let _ = sender.send(Ok(rx.into()));
+        }
+    })
+    .with_block_item_limits(BlockItemLimits {
+        max_transactions: 2,
+        ..Default::default()
+    });
+
+    let transactions = client
+        .transactions_for_block_from_peer(peer(0).0, BlockNumber::GENESIS)
+        .await
+        .unwrap()
+        .collect::<Vec<_>>()
+        .await;
+
+    assert_eq!(transactions.len(), 3);
+    assert_eq!(
+        transactions[..2]
+            .iter()
+            .map(|x| TestTxn::new(x.as_ref().unwrap().clone()))
+            .collect::<Vec<_>>(),
+        vec![txn(80, 0), txn(81, 1)]
+    );
+    assert!(transactions[2].is_err());
+}
+
+#[tokio::test]
+async fn new_heads_are_validated_against_the_announcing_peer() {
+    use p2p_proto::common::{BlockId, BlockNumberOrHash, Hash};
+    use p2p_proto::header::NewBlock;
+    use pathfinder_common::BlockHeader;
+
+    use crate::client::conv::ToDto;
+
+    // The hash of each block is its number.
+    let id = |number: u64, hash: u64| BlockId {
+        number,
+        hash: Hash(Felt::from_u64(hash)),
+    };
+    let announcements = [
+        (peer(0), id(3, 0)), // zero hash
+        (peer(0), id(2, 2)), // not above the tip
+        (peer(1), id(3, 4)), // hash mismatch
+        (peer(1), id(3, 3)),
+        (peer(0), id(3, 3)), // already yielded
+        (peer(0), id(5, 5)),
+    ];
+    let client = mock_client(move |command| match command {
+        crate::Command::SubscribeTopic {
+            messages, sender, ..
+        } => {
+            let messages = messages.unwrap();
+            for (peer, id) in announcements.clone() {
+                let message = GossipMessage {
+                    topic: "blocks".to_owned(),
+                    data: NewBlock::Id(id).to_protobuf().encode_to_vec(),
+                };
+                messages.try_send((peer.0, message)).unwrap();
+            }
+            let _ = sender.send(Ok(()));
+        }
+        crate::Command::SendHeadersSyncRequest {
+            request, sender, ..
+        } => {
+            let BlockNumberOrHash::Number(number) = request.iteration.start else {
+                unreachable!()
+            };
+            let header = SignedBlockHeader {
+                header: BlockHeader {
+                    number: BlockNumber::new_or_panic(number),
+                    hash: BlockHash(Felt::from_u64(number)),
+                    ..hdr(80).header
+                },
+                ..hdr(80)
+            };
+            let (mut tx, rx) = fmpsc::channel(2);
+            tx.try_send(Ok(BlockHeadersResponse::Header(Box::new(header.to_dto()))))
+                .unwrap();
+            tx.try_send(Ok(HdrFin)).unwrap();
+            let _ = sender.send(Ok(rx.into()));
+        }
+        _ => {}
+    });
+
+    let heads = client
+        .new_head_stream(Some(BlockNumber::new_or_panic(2)))
+        .await
+        .unwrap()
+        .map(|(peer, id)| (TestPeer(peer), id))
+        .collect::<Vec<_>>()
+        .await;
+
+    assert_eq!(heads, vec![(peer(1), id(3, 3)), (peer(0), id(5, 5))]);
+}
+
+#[tokio::test]
+async fn class_definition_is_picked_by_hash() {
+    let client = mock_client(|command| {
+        if let crate::Command::SendClassesSyncRequest { sender, .. } = command {
+            let (mut tx, rx) = fmpsc::channel(3);
+            tx.try_send(Ok(cairo0_class_resp())).unwrap();
+            tx.try_send(Ok(sierra_class_resp("0.1.0"))).unwrap();
+            tx.try_send(Ok(ClassFin)).unwrap();
+            let _ = sender.send(Ok(rx.into()));
+        }
+    });
+    client
+        .peers
+        .write()
+        .await
+        .known
+        .update(HashSet::from([peer(0).0]));
+    let hasher = ClassHasher::new(|class| {
+        Ok(match class {
+            ClassDefinition::Cairo { .. } => ClassHash(Felt::from_u64(1)),
+            ClassDefinition::Sierra { .. } => ClassHash(Felt::from_u64(2)),
+        })
+    });
+
+    let (found, class) = client
+        .clone()
+        .class_definition_by_hash(
+            BlockNumber::GENESIS,
+            2,
+            ClassHash(Felt::from_u64(2)),
+            &hasher,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(TestPeer(found), peer(0));
+    assert!(matches!(class, ClassDefinition::Sierra { .. }));
+
+    let missing = client
+        .class_definition_by_hash(
+            BlockNumber::GENESIS,
+            2,
+            ClassHash(Felt::from_u64(3)),
+            &hasher,
+        )
+        .await
+        .unwrap();
+    assert!(missing.is_none());
+}
+
+#[rstest]
+#[case::exact_count(vec![contract_diff(80), declared_class(80), SDFin], len(80), vec![true, true])]
+#[case::too_many(vec![contract_diff(80), declared_class(80), surplus_class(), SDFin], len(80), vec![true, true, false])]
+#[case::too_few(vec![contract_diff(80), declared_class(80), SDFin], len(80) + 1, vec![true, true, false])]
+#[case::without_fin(vec![contract_diff(80), declared_class(80)], len(80), vec![true, true, false])]
+#[tokio::test]
+async fn state_diff_is_streamed_in_chunks(
+    #[case] responses: Vec<StateDiffsResponse>,
+    #[case] state_diff_length: usize,
+    #[case] expected_ok: Vec<bool>,
+) {
+    let responses = stream::iter(responses.into_iter().map(Ok));
+
+    let chunks = state_diff_from_responses(peer(0).0, state_diff_length as u64, responses)
+        .collect::<Vec<_>>()
+        .await;
+    assert_eq!(
+        chunks.iter().map(Result::is_ok).collect::<Vec<_>>(),
+        expected_ok
+    );
+
+    let mut merged = StateUpdateData::default();
+    chunks
+        .into_iter()
+        .flatten()
+        .for_each(|chunk| chunk.merge_into(&mut merged));
+    pretty_assertions_sorted::assert_eq!(merged, state_diff(80));
+}
+
+#[tokio::test]
+async fn repeated_contract_diff_ends_the_state_diff() {
+    let responses = [split_contract_diff(81), vec![declared_class(81), SDFin]].concat();
+    let responses = stream::iter(responses.into_iter().map(Ok));
+
+    let chunks = state_diff_from_responses(peer(0).0, len(81) as u64, responses)
+        .collect::<Vec<_>>()
+        .await;
+
+    assert_eq!(chunks.len(), 2);
+    assert!(chunks[0].is_ok());
+    assert!(matches!(
+        chunks[1],
+        Err(StateDiffsError::IncorrectStateDiffCount(p)) if p == peer(0).0
+    ));
+}
+
+#[rstest]
+#[case::nonce(system_contract_nonce())]
+#[case::class(system_contract_class())]
+#[tokio::test]
+async fn system_contract_diff_with_nonce_or_class_is_rejected(#[case] diff: StateDiffsResponse) {
+    let responses = stream::iter([diff, SDFin].map(Ok));
+
+    let chunks = state_diff_from_responses(peer(0).0, 0, responses)
+        .collect::<Vec<_>>()
+        .await;
+
+    assert_eq!(chunks.len(), 1);
+    assert!(matches!(
+        chunks[0],
+        Err(StateDiffsError::IncorrectStateDiffCount(p)) if p == peer(0).0
+    ));
+}
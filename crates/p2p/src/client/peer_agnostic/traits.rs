@@ -1,39 +1,82 @@
-use futures::{Future, Stream};
+use std::num::NonZeroU64;
+use std::time::Duration;
+
+use futures::stream::BoxStream;
+use futures::{Future, Stream, StreamExt, TryStreamExt};
 use libp2p::PeerId;
 use pathfinder_common::event::Event;
-use pathfinder_common::state_update::StateUpdateData;
+use pathfinder_common::state_update::{ContractClassUpdate, StateUpdateData};
 use pathfinder_common::transaction::TransactionVariant;
-use pathfinder_common::{BlockNumber, SignedBlockHeader, TransactionHash};
+use pathfinder_common::{BlockNumber, ContractAddress, SignedBlockHeader, TransactionHash};
 
 use crate::client::types::{
     ClassDefinition,
     ClassDefinitionsError,
+    ClassFilter,
+    ConversionError,
     EventsForBlockByTransaction,
     EventsResponseStreamFailure,
     Receipt,
+    StateDiffChunk,
     StateDiffsError,
+    StreamError,
     TransactionData,
 };
 use crate::PeerData;
 
-pub type StreamItem<T> = Result<PeerData<T>, PeerData<anyhow::Error>>;
+/// Item of the sync streams. An error is always the last item of a stream, it
+/// tells why the stream ended before covering the requested range.
+///
+/// The peer of an item is the one which supplied all of its data. Data from a
+/// peer which failed part way through a block is discarded, so a block which
+/// is requested again from another peer is attributed to that peer only.
+pub type StreamItem<T> = Result<PeerData<T>, PeerData<StreamError>>;
+
+/// Item of the sync streams created with
+/// [`Client::with_conversion_errors`](crate::client::peer_agnostic::Client::with_conversion_errors),
+/// which also yield the data that failed to parse, attributed to the peer
+/// which sent it.
+pub type AttributedStreamItem<T> =
+    Result<PeerData<Result<T, ConversionError>>, PeerData<StreamError>>;
 
 pub trait HeaderStream {
+    /// See [`TransactionStream::transaction_stream`] regarding `reverse` and
+    /// `step`.
     fn header_stream(
         self,
         start: BlockNumber,
         stop: BlockNumber,
         reverse: bool,
-    ) -> impl Stream<Item = PeerData<SignedBlockHeader>> + Send;
+        step: Option<NonZeroU64>,
+    ) -> impl Stream<Item = StreamItem<SignedBlockHeader>> + Send;
 }
 
 pub trait TransactionStream {
+    /// The number of transactions of each block is taken from
+    /// `transaction_count_stream` as is. Use
+    /// [`CountSource`](crate::client::types::CountSource) to cross-check it
+    /// against the block headers first.
+    ///
+    /// With `reverse` set the blocks are streamed from `stop` down to `start`,
+    /// so the counts stream has to yield the count of `stop` first.
+    ///
+    /// With `step` set only every `step`-th block is streamed, starting from
+    /// the first block of the walk, and the counts stream has to yield the
+    /// counts of those blocks only. `None` streams every block.
+    ///
+    /// With `verify_commitments` set the transactions of each block are
+    /// checked against its transaction commitment before being yielded, and
+    /// blocks which fail the check are requested from another peer. Otherwise
+    /// the caller is responsible for verifying the commitment.
     fn transaction_stream(
         self,
         start: BlockNumber,
         stop: BlockNumber,
+        reverse: bool,
+        step: Option<NonZeroU64>,
+        verify_commitments: bool,
         transaction_count_stream: impl Stream<Item = anyhow::Result<usize>> + Send + 'static,
-    ) -> impl Stream<Item = StreamItem<(TransactionData, BlockNumber)>>;
+    ) -> impl Stream<Item = StreamItem<(TransactionData, BlockNumber)>> + Send;
 }
 
 pub trait StateDiffStream {
@@ -42,21 +85,33 @@ pub trait StateDiffStream {
     /// Contract class updates are by default set to
     /// `ContractClassUpdate::Deploy` but __the caller is responsible for
     /// determining if the class was really deployed or replaced__.
+    ///
+    /// See [`TransactionStream::transaction_stream`] regarding `reverse`,
+    /// `step` and `verify_commitments`. The state diff commitment does not
+    /// depend on whether a class was deployed or replaced.
     fn state_diff_stream(
         self,
         start: BlockNumber,
         stop: BlockNumber,
+        reverse: bool,
+        step: Option<NonZeroU64>,
+        verify_commitments: bool,
         state_diff_length_stream: impl Stream<Item = anyhow::Result<usize>> + Send + 'static,
-    ) -> impl Stream<Item = StreamItem<(StateUpdateData, BlockNumber)>>;
+    ) -> impl Stream<Item = StreamItem<(StateUpdateData, BlockNumber)>> + Send;
 }
 
 pub trait ClassStream {
+    /// See [`TransactionStream::transaction_stream`] regarding `reverse` and
+    /// `step`.
     fn class_stream(
         self,
         start: BlockNumber,
         stop: BlockNumber,
+        reverse: bool,
+        step: Option<NonZeroU64>,
         declared_class_count_stream: impl Stream<Item = anyhow::Result<usize>> + Send + 'static,
-    ) -> impl Stream<Item = StreamItem<ClassDefinition>>;
+        filter: ClassFilter,
+    ) -> impl Stream<Item = StreamItem<ClassDefinition>> + Send;
 }
 
 pub trait EventStream {
@@ -67,15 +122,204 @@ pub trait EventStream {
     /// commitment is part of block hash. However the number of events per
     /// transaction for __pre 0.13.2__ Starknet blocks is __TRUSTED__
     /// because neither signature nor block hash contain this information.
+    ///
+    /// `event_count_stream` determines how many events are read for each
+    /// block. If the client is configured with an
+    /// [`EventCountLookup`](crate::client::types::EventCountLookup) the total
+    /// number of events received for a block is also checked against the
+    /// `event_count` from its header, which holds for all Starknet versions.
+    ///
+    /// With `verify_commitments` set the events of each block, grouping
+    /// included, are checked with the
+    /// [`EventCommitmentVerifier`](crate::client::types::EventCommitmentVerifier)
+    /// the client is configured with, which makes the grouping verified rather
+    /// than trusted for blocks whose event commitment covers it.
+    ///
+    /// See [`TransactionStream::transaction_stream`] regarding `reverse`,
+    /// `step` and `verify_commitments`.
     fn event_stream(
         self,
         start: BlockNumber,
         stop: BlockNumber,
+        reverse: bool,
+        step: Option<NonZeroU64>,
+        verify_commitments: bool,
         event_count_stream: impl Stream<Item = anyhow::Result<usize>> + Send + 'static,
-    ) -> impl Stream<Item = StreamItem<EventsForBlockByTransaction>>;
+    ) -> impl Stream<Item = StreamItem<EventsForBlockByTransaction>> + Send;
+}
+
+/// Object-safe facade over the sync stream traits, which allows holding the
+/// sync source as `Box<dyn SyncClient>`, for example to swap the p2p client for
+/// a mock. It is implemented for every cloneable type which implements all of
+/// the stream traits.
+///
+/// Prefer the generic traits unless dynamic dispatch is needed, they don't
+/// require boxing.
+pub trait SyncClient: Send + Sync {
+    fn header_stream(
+        &self,
+        start: BlockNumber,
+        stop: BlockNumber,
+        reverse: bool,
+        step: Option<NonZeroU64>,
+    ) -> BoxStream<'static, StreamItem<SignedBlockHeader>>;
+
+    fn transaction_stream(
+        &self,
+        start: BlockNumber,
+        stop: BlockNumber,
+        reverse: bool,
+        step: Option<NonZeroU64>,
+        verify_commitments: bool,
+        transaction_count_stream: BoxStream<'static, anyhow::Result<usize>>,
+    ) -> BoxStream<'static, StreamItem<(TransactionData, BlockNumber)>>;
+
+    fn state_diff_stream(
+        &self,
+        start: BlockNumber,
+        stop: BlockNumber,
+        reverse: bool,
+        step: Option<NonZeroU64>,
+        verify_commitments: bool,
+        state_diff_length_stream: BoxStream<'static, anyhow::Result<usize>>,
+    ) -> BoxStream<'static, StreamItem<(StateUpdateData, BlockNumber)>>;
+
+    fn class_stream(
+        &self,
+        start: BlockNumber,
+        stop: BlockNumber,
+        reverse: bool,
+        step: Option<NonZeroU64>,
+        declared_class_count_stream: BoxStream<'static, anyhow::Result<usize>>,
+        filter: ClassFilter,
+    ) -> BoxStream<'static, StreamItem<ClassDefinition>>;
+
+    fn event_stream(
+        &self,
+        start: BlockNumber,
+        stop: BlockNumber,
+        reverse: bool,
+        step: Option<NonZeroU64>,
+        verify_commitments: bool,
+        event_count_stream: BoxStream<'static, anyhow::Result<usize>>,
+    ) -> BoxStream<'static, StreamItem<EventsForBlockByTransaction>>;
+}
+
+impl<T> SyncClient for T
+where
+    T: HeaderStream
+        + TransactionStream
+        + StateDiffStream
+        + ClassStream
+        + EventStream
+        + Clone
+        + Send
+        + Sync
+        + 'static,
+{
+    fn header_stream(
+        &self,
+        start: BlockNumber,
+        stop: BlockNumber,
+        reverse: bool,
+        step: Option<NonZeroU64>,
+    ) -> BoxStream<'static, StreamItem<SignedBlockHeader>> {
+        HeaderStream::header_stream(self.clone(), start, stop, reverse, step).boxed()
+    }
+
+    fn transaction_stream(
+        &self,
+        start: BlockNumber,
+        stop: BlockNumber,
+        reverse: bool,
+        step: Option<NonZeroU64>,
+        verify_commitments: bool,
+        transaction_count_stream: BoxStream<'static, anyhow::Result<usize>>,
+    ) -> BoxStream<'static, StreamItem<(TransactionData, BlockNumber)>> {
+        TransactionStream::transaction_stream(
+            self.clone(),
+            start,
+            stop,
+            reverse,
+            step,
+            verify_commitments,
+            transaction_count_stream,
+        )
+        .boxed()
+    }
+
+    fn state_diff_stream(
+        &self,
+        start: BlockNumber,
+        stop: BlockNumber,
+        reverse: bool,
+        step: Option<NonZeroU64>,
+        verify_commitments: bool,
+        state_diff_length_stream: BoxStream<'static, anyhow::Result<usize>>,
+    ) -> BoxStream<'static, StreamItem<(StateUpdateData, BlockNumber)>> {
+        StateDiffStream::state_diff_stream(
+            self.clone(),
+            start,
+            stop,
+            reverse,
+            step,
+            verify_commitments,
+            state_diff_length_stream,
+        )
+        .boxed()
+    }
+
+    fn class_stream(
+        &self,
+        start: BlockNumber,
+        stop: BlockNumber,
+        reverse: bool,
+        step: Option<NonZeroU64>,
+        declared_class_count_stream: BoxStream<'static, anyhow::Result<usize>>,
+        filter: ClassFilter,
+    ) -> BoxStream<'static, StreamItem<ClassDefinition>> {
+        ClassStream::class_stream(
+            self.clone(),
+            start,
+            stop,
+            reverse,
+            step,
+            declared_class_count_stream,
+            filter,
+        )
+        .boxed()
+    }
+
+    fn event_stream(
+        &self,
+        start: BlockNumber,
+        stop: BlockNumber,
+        reverse: bool,
+        step: Option<NonZeroU64>,
+        verify_commitments: bool,
+        event_count_stream: BoxStream<'static, anyhow::Result<usize>>,
+    ) -> BoxStream<'static, StreamItem<EventsForBlockByTransaction>> {
+        EventStream::event_stream(
+            self.clone(),
+            start,
+            stop,
+            reverse,
+            step,
+            verify_commitments,
+            event_count_stream,
+        )
+        .boxed()
+    }
 }
 
 pub trait BlockClient {
+    /// Returns `None` if no peer provided a valid header for `block`. The
+    /// header's signature is not checked.
+    fn header_for_block(
+        self,
+        block: BlockNumber,
+    ) -> impl Future<Output = Option<(PeerId, SignedBlockHeader)>> + Send;
+
     fn transactions_for_block(
         self,
         block: BlockNumber,
@@ -86,18 +330,112 @@ pub trait BlockClient {
         )>,
     > + Send;
 
+    /// The protocol has no way to request the pending block, so this polls the
+    /// peers every `poll_interval` for the successor of `tip`, and yields its
+    /// transactions as soon as a peer serves both its header and a matching
+    /// number of transactions. It then moves on to the block after it, for as
+    /// long as the stream is polled.
+    ///
+    /// The transactions are those of blocks which are already closed, which is
+    /// as close to the pending block as peer data gets.
+    fn pending_transactions(
+        self,
+        tip: BlockNumber,
+        poll_interval: Duration,
+    ) -> impl Stream<Item = (PeerId, BlockNumber, Vec<(TransactionVariant, Receipt)>)> + Send
+    where
+        Self: Clone + Send + Sized + 'static,
+    {
+        futures::stream::unfold((self, tip + 1), move |(client, block)| async move {
+            loop {
+                if let Some((_, header)) = client.clone().header_for_block(block).await {
+                    if let Some((peer, transactions)) =
+                        client.clone().transactions_for_block(block).await
+                    {
+                        match transactions.try_collect::<Vec<_>>().await {
+                            Ok(transactions)
+                                if transactions.len() == header.header.transaction_count =>
+                            {
+                                return Some(((peer, block, transactions), (client, block + 1)));
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+                tokio::time::sleep(poll_interval).await;
+            }
+        })
+    }
+
+    /// Contract class updates are set to `ContractClassUpdate::Deploy`, see
+    /// [`BlockClient::state_diff_for_block_with_parent`] to tell them apart
+    /// from replaced classes.
     fn state_diff_for_block(
         self,
         block: BlockNumber,
         state_diff_length: u64,
     ) -> impl Future<Output = Result<Option<(PeerId, StateUpdateData)>, StateDiffsError>> + Send;
 
+    /// Same as [`BlockClient::state_diff_for_block`], except that the state
+    /// diff is yielded in chunks as they arrive instead of being buffered,
+    /// which bounds memory use for blocks with large state diffs. Invalid
+    /// data, including more or fewer than `state_diff_length` updates across
+    /// the whole stream, ends the stream with an error.
+    fn state_diff_stream_for_block(
+        self,
+        block: BlockNumber,
+        state_diff_length: u64,
+    ) -> impl Future<
+        Output = Option<(
+            PeerId,
+            impl Stream<Item = Result<StateDiffChunk, StateDiffsError>> + Send,
+        )>,
+    > + Send;
+
+    /// Same as [`BlockClient::state_diff_for_block`], except that the class
+    /// updates of contracts which `existed_in_parent` reports as already
+    /// deployed before `block` are set to `ContractClassUpdate::Replace`.
+    fn state_diff_for_block_with_parent(
+        self,
+        block: BlockNumber,
+        state_diff_length: u64,
+        existed_in_parent: impl Fn(ContractAddress) -> bool + Send,
+    ) -> impl Future<Output = Result<Option<(PeerId, StateUpdateData)>, StateDiffsError>> + Send
+    where
+        Self: Sized,
+    {
+        let state_diff = self.state_diff_for_block(block, state_diff_length);
+        async move {
+            let found = state_diff.await?;
+            Ok(found.map(|(peer, mut state_diff)| {
+                resolve_class_updates(&mut state_diff, existed_in_parent);
+                (peer, state_diff)
+            }))
+        }
+    }
+
     fn class_definitions_for_block(
         self,
         block: BlockNumber,
         declared_classes_count: u64,
     ) -> impl Future<Output = Result<Option<(PeerId, Vec<ClassDefinition>)>, ClassDefinitionsError>> + Send;
 
+    /// Same as [`BlockClient::class_definitions_for_block`], except that the
+    /// class definitions are yielded as they arrive instead of being buffered,
+    /// which bounds memory use for blocks declaring large classes. Invalid
+    /// data, including more or fewer than `declared_classes_count` classes,
+    /// ends the stream with an error.
+    fn class_definitions_stream_for_block(
+        self,
+        block: BlockNumber,
+        declared_classes_count: u64,
+    ) -> impl Future<
+        Output = Option<(
+            PeerId,
+            impl Stream<Item = Result<ClassDefinition, ClassDefinitionsError>> + Send,
+        )>,
+    > + Send;
+
     fn events_for_block(
         self,
         block: BlockNumber,
@@ -108,3 +446,18 @@ pub trait BlockClient {
         )>,
     > + Send;
 }
+
+/// Turns the class updates of contracts which already existed into
+/// `ContractClassUpdate::Replace`.
+pub(super) fn resolve_class_updates(
+    state_diff: &mut StateUpdateData,
+    existed: impl Fn(ContractAddress) -> bool,
+) {
+    for (address, update) in state_diff.contract_updates.iter_mut() {
+        if let Some(ContractClassUpdate::Deploy(class_hash)) = update.class {
+            if existed(*address) {
+                update.class = Some(ContractClassUpdate::Replace(class_hash));
+            }
+        }
+    }
+}
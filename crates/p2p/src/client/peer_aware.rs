@@ -4,7 +4,6 @@
 use std::collections::HashSet;
 
 use anyhow::Context;
-use futures::channel::mpsc::Receiver as ResponseReceiver;
 use libp2p::gossipsub::IdentTopic;
 use libp2p::{Multiaddr, PeerId};
 use p2p_proto::class::{ClassesRequest, ClassesResponse};
@@ -12,11 +11,12 @@ use p2p_proto::event::{EventsRequest, EventsResponse};
 use p2p_proto::header::{BlockHeadersRequest, BlockHeadersResponse, NewBlock};
 use p2p_proto::state::{StateDiffsRequest, StateDiffsResponse};
 use p2p_proto::transaction::{TransactionsRequest, TransactionsResponse};
+use p2p_stream::ResponseReceiver;
 use tokio::sync::{mpsc, oneshot};
 
 #[cfg(test)]
 use crate::test_utils;
-use crate::Command;
+use crate::{Command, GossipMessage};
 
 #[derive(Clone, Debug)]
 pub struct Client {
@@ -30,7 +30,7 @@ macro_rules! impl_send {
             &self,
             peer_id: PeerId,
             request: $req_type,
-        ) -> anyhow::Result<ResponseReceiver<std::io::Result<$res_type>>> {
+        ) -> anyhow::Result<ResponseReceiver<$res_type>> {
             let (sender, receiver) = oneshot::channel();
             self.sender
                 .send(Command::$req_command {
@@ -158,7 +158,46 @@ impl Client {
         let (sender, receiver) = oneshot::channel();
         let topic = IdentTopic::new(topic);
         self.sender
-            .send(Command::SubscribeTopic { topic, sender })
+            .send(Command::SubscribeTopic {
+                topic,
+                messages: None,
+                sender,
+            })
+            .await
+            .expect("Command receiver not to be dropped");
+        receiver.await.expect("Sender not to be dropped")
+    }
+
+    /// Same as [`Client::subscribe_topic`], except that the messages on the
+    /// topic are also passed on as they are through the returned receiver.
+    /// Each call returns a separate receiver, all of which get every message.
+    /// Messages are dropped while a receiver is full.
+    pub async fn subscribe_topic_messages(
+        &self,
+        topic: &str,
+    ) -> anyhow::Result<mpsc::Receiver<(PeerId, GossipMessage)>> {
+        const CAPACITY: usize = 1024;
+
+        let (sender, receiver) = oneshot::channel();
+        let (messages, messages_rx) = mpsc::channel(CAPACITY);
+        let topic = IdentTopic::new(topic);
+        self.sender
+            .send(Command::SubscribeTopic {
+                topic,
+                messages: Some(messages),
+                sender,
+            })
+            .await
+            .expect("Command receiver not to be dropped");
+        receiver.await.expect("Sender not to be dropped")?;
+        Ok(messages_rx)
+    }
+
+    pub async fn unsubscribe_topic(&self, topic: &str) -> anyhow::Result<()> {
+        let (sender, receiver) = oneshot::channel();
+        let topic = IdentTopic::new(topic);
+        self.sender
+            .send(Command::UnsubscribeTopic { topic, sender })
             .await
             .expect("Command receiver not to be dropped");
         receiver.await.expect("Sender not to be dropped")
@@ -225,6 +264,16 @@ impl Client {
         receiver.await.expect("Sender not to be dropped")
     }
 
+    /// Peers we currently have a connection to.
+    pub async fn connected_peers(&self) -> HashSet<PeerId> {
+        let (sender, receiver) = oneshot::channel();
+        self.sender
+            .send(Command::ConnectedPeers { sender })
+            .await
+            .expect("Command receiver not to be dropped");
+        receiver.await.expect("Sender not to be dropped")
+    }
+
     #[cfg(test)]
     pub(crate) fn for_test(&self) -> test_utils::peer_aware::Client {
         test_utils::peer_aware::Client::new(self.sender.clone())
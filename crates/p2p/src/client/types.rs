@@ -1,9 +1,16 @@
+use std::num::NonZeroU64;
+use std::sync::Arc;
+
 use anyhow::Context;
 use fake::Dummy;
+use futures::stream::BoxStream;
+use futures::{Stream, StreamExt};
 use libp2p::PeerId;
+use pathfinder_common::class_definition::SierraEntryPoints;
 use pathfinder_common::event::Event;
 use pathfinder_common::receipt::{ExecutionResources, ExecutionStatus, L2ToL1Message};
-use pathfinder_common::transaction::TransactionVariant;
+use pathfinder_common::state_update::{ContractUpdate, StateUpdateData, SystemContractUpdate};
+use pathfinder_common::transaction::{DataAvailabilityMode, TransactionVariant};
 use pathfinder_common::{
     BlockCommitmentSignature,
     BlockCommitmentSignatureElem,
@@ -11,12 +18,16 @@ use pathfinder_common::{
     BlockHeader,
     BlockNumber,
     BlockTimestamp,
+    CasmHash,
     ClassCommitment,
+    ClassHash,
+    ContractAddress,
     EventCommitment,
     Fee,
     GasPrice,
     ReceiptCommitment,
     SequencerAddress,
+    SierraHash,
     SignedBlockHeader,
     StateCommitment,
     StateDiffCommitment,
@@ -28,17 +39,24 @@ use pathfinder_common::{
 use tagged::Tagged;
 use tagged_debug_derive::TaggedDebug;
 
-use crate::client::conv::TryFromDto;
+use crate::client::conv::{TryFromDto, UnsupportedProtocolVersion};
+use crate::peer_data::PeerData;
 
 #[derive(Clone, PartialEq, Dummy, TaggedDebug)]
 pub enum ClassDefinition {
     Cairo {
         block_number: BlockNumber,
         definition: Vec<u8>,
+        /// The data availability domain of the class, as sent by the peer.
+        domain: DataAvailabilityMode,
     },
     Sierra {
         block_number: BlockNumber,
         sierra_definition: Vec<u8>,
+        /// Available if the class was parsed from its p2p representation.
+        interface: Option<SierraInterface>,
+        /// The data availability domain of the class, as sent by the peer.
+        domain: DataAvailabilityMode,
     },
 }
 
@@ -52,6 +70,189 @@ impl ClassDefinition {
             } => sierra_definition.clone(),
         }
     }
+
+    /// The entry points of a Sierra class, if its interface is available.
+    pub fn sierra_entry_points(&self) -> Option<&SierraEntryPoints> {
+        self.sierra_interface().map(|x| &x.entry_points_by_type)
+    }
+
+    /// The ABI of a Sierra class, if its interface is available.
+    pub fn sierra_abi(&self) -> Option<&str> {
+        self.sierra_interface().map(|x| x.abi.as_str())
+    }
+
+    pub fn block_number(&self) -> BlockNumber {
+        match self {
+            Self::Cairo { block_number, .. } | Self::Sierra { block_number, .. } => *block_number,
+        }
+    }
+
+    fn sierra_interface(&self) -> Option<&SierraInterface> {
+        match self {
+            Self::Cairo { .. } => None,
+            Self::Sierra { interface, .. } => interface.as_ref(),
+        }
+    }
+}
+
+/// The parts of a Sierra class needed to interact with it, kept alongside the
+/// serialized definition so that consumers don't have to parse it again.
+#[derive(Clone, Debug, PartialEq, Dummy)]
+pub struct SierraInterface {
+    pub entry_points_by_type: SierraEntryPoints,
+    pub abi: String,
+}
+
+/// Outcome of cross-checking the same data received from several independent
+/// peers.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Agreement<T> {
+    /// All peers sent the same data.
+    Agreed(T),
+    /// At least one peer sent different data. Contains the data of every peer.
+    Disagreed(Vec<PeerData<T>>),
+}
+
+/// Decides where the per block transaction counts fed to the transaction
+/// stream come from, when both a separate counts stream and the counts from
+/// the (authenticated) block headers are available.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CountSource {
+    #[default]
+    TrustCountsStream,
+    TrustHeader,
+    /// Cross-check both sources and fail on the first block for which they
+    /// disagree.
+    RequireAgreement,
+}
+
+impl CountSource {
+    /// Combines the two count sources into a single stream according to this
+    /// policy. The stream ends when the selected source(s) end.
+    pub fn select(
+        self,
+        counts_stream: impl Stream<Item = anyhow::Result<usize>> + Send + 'static,
+        header_counts: impl Stream<Item = anyhow::Result<usize>> + Send + 'static,
+    ) -> BoxStream<'static, anyhow::Result<usize>> {
+        match self {
+            CountSource::TrustCountsStream => counts_stream.boxed(),
+            CountSource::TrustHeader => header_counts.boxed(),
+            CountSource::RequireAgreement => counts_stream
+                .zip(header_counts)
+                .map(|(count, header_count)| {
+                    let (count, header_count) = (count?, header_count?);
+                    anyhow::ensure!(
+                        count == header_count,
+                        "Transaction count mismatch: counts stream {count}, header {header_count}"
+                    );
+                    Ok(count)
+                })
+                .boxed(),
+        }
+    }
+}
+
+/// The part of the walk of a sync stream which is left after the stream was
+/// interrupted, so that it can be restarted without yielding the blocks which
+/// were already delivered again.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Resumed {
+    pub start: BlockNumber,
+    pub stop: BlockNumber,
+    /// The number of blocks of the walk which were already delivered, and
+    /// whose counts are skipped by [`Resumed::fast_forward`].
+    pub delivered: usize,
+}
+
+impl Resumed {
+    /// Resumes the walk from `start` to `stop` with the given `reverse` and
+    /// `step`, see [`TransactionStream::transaction_stream`], after
+    /// `resume_from`, the last block which was delivered before the
+    /// interruption. `None` picks up from the beginning.
+    ///
+    /// Returns `None` if there is nothing left to walk.
+    ///
+    /// [`TransactionStream::transaction_stream`]: crate::client::peer_agnostic::traits::TransactionStream::transaction_stream
+    pub fn new(
+        start: BlockNumber,
+        stop: BlockNumber,
+        reverse: bool,
+        step: Option<NonZeroU64>,
+        resume_from: Option<BlockNumber>,
+    ) -> Option<Self> {
+        let step = step.unwrap_or(NonZeroU64::MIN).get();
+        let (first, last) = match reverse {
+            true => (stop.get(), start.get()),
+            false => (start.get(), stop.get()),
+        };
+        // Distance walked from the first block up to and including `resume_from`.
+        let walked = match (resume_from, reverse) {
+            (Some(resumed), false) if resumed.get() >= first => Some(resumed.get() - first),
+            (Some(resumed), true) if resumed.get() <= first => Some(first - resumed.get()),
+            _ => None,
+        };
+        let delivered = walked.map_or(0, |walked| walked / step + 1);
+        let offset = delivered.checked_mul(step)?;
+
+        let next = match reverse {
+            true => first.checked_sub(offset).filter(|next| *next >= last)?,
+            false => first.checked_add(offset).filter(|next| *next <= last)?,
+        };
+        let next = BlockNumber::new(next)?;
+        let delivered = usize::try_from(delivered).ok()?;
+
+        Some(match reverse {
+            true => Self {
+                start,
+                stop: next,
+                delivered,
+            },
+            false => Self {
+                start: next,
+                stop,
+                delivered,
+            },
+        })
+    }
+
+    /// Skips the counts of the blocks which were already delivered, so that
+    /// the counts stream of the original walk can be reused.
+    pub fn fast_forward<S: Stream>(&self, counts: S) -> impl Stream<Item = S::Item> {
+        counts.skip(self.delivered)
+    }
+}
+
+/// Selects which classes are yielded by the class stream. Classes which are
+/// filtered out still count towards the number of declared classes in a block.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ClassFilter {
+    /// Sierra classes with a lower `contract_class_version` are skipped.
+    pub min_sierra_version: Option<semver::Version>,
+    pub include_cairo0: bool,
+}
+
+impl Default for ClassFilter {
+    fn default() -> Self {
+        Self {
+            min_sierra_version: None,
+            include_cairo0: true,
+        }
+    }
+}
+
+impl ClassFilter {
+    /// Sierra classes whose version cannot be parsed are rejected if a minimum
+    /// version is set.
+    pub fn accepts(&self, class: &p2p_proto::class::Class) -> bool {
+        match class {
+            p2p_proto::class::Class::Cairo0 { .. } => self.include_cairo0,
+            p2p_proto::class::Class::Cairo1 { class, .. } => match &self.min_sierra_version {
+                Some(min) => semver::Version::parse(&class.contract_class_version)
+                    .is_ok_and(|version| version >= *min),
+                None => true,
+            },
+        }
+    }
 }
 
 #[derive(Clone, Debug, Default, PartialEq, Eq, Dummy)]
@@ -77,8 +278,177 @@ impl From<pathfinder_common::receipt::Receipt> for Receipt {
 
 pub type TransactionData = Vec<(TransactionVariant, Receipt)>;
 
+/// Checks the transactions received for a block against the block's
+/// transaction commitment, see
+/// [`TransactionStream`](crate::client::peer_agnostic::traits::TransactionStream).
+///
+/// The check returns `Ok(false)` if the commitment does not match. Errors are
+/// meant for failures unrelated to the received data, such as the expected
+/// commitment being unavailable.
+#[derive(Clone)]
+pub struct TransactionCommitmentVerifier(
+    Arc<dyn Fn(BlockNumber, &TransactionData) -> anyhow::Result<bool> + Send + Sync>,
+);
+
+impl TransactionCommitmentVerifier {
+    pub fn new(
+        verify: impl Fn(BlockNumber, &TransactionData) -> anyhow::Result<bool> + Send + Sync + 'static,
+    ) -> Self {
+        Self(Arc::new(verify))
+    }
+
+    pub fn verify(
+        &self,
+        block: BlockNumber,
+        transactions: &TransactionData,
+    ) -> anyhow::Result<bool> {
+        (self.0)(block, transactions)
+    }
+}
+
+impl std::fmt::Debug for TransactionCommitmentVerifier {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TransactionCommitmentVerifier")
+            .finish_non_exhaustive()
+    }
+}
+
+/// Provides the expected state diff commitment of a block, which
+/// [`StateDiffStream`](crate::client::peer_agnostic::traits::StateDiffStream)
+/// checks the received state diffs against.
+#[derive(Clone)]
+pub struct StateDiffCommitmentLookup(
+    Arc<dyn Fn(BlockNumber) -> anyhow::Result<StateDiffCommitment> + Send + Sync>,
+);
+
+impl StateDiffCommitmentLookup {
+    pub fn new(
+        lookup: impl Fn(BlockNumber) -> anyhow::Result<StateDiffCommitment> + Send + Sync + 'static,
+    ) -> Self {
+        Self(Arc::new(lookup))
+    }
+
+    pub fn get(&self, block: BlockNumber) -> anyhow::Result<StateDiffCommitment> {
+        (self.0)(block)
+    }
+}
+
+impl std::fmt::Debug for StateDiffCommitmentLookup {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StateDiffCommitmentLookup")
+            .finish_non_exhaustive()
+    }
+}
+
+/// Provides the `event_count` from the authenticated header of a block, which
+/// [`EventStream`](crate::client::peer_agnostic::traits::EventStream) checks
+/// the total number of events received for the block against.
+#[derive(Clone)]
+pub struct EventCountLookup(Arc<dyn Fn(BlockNumber) -> anyhow::Result<usize> + Send + Sync>);
+
+impl EventCountLookup {
+    pub fn new(
+        lookup: impl Fn(BlockNumber) -> anyhow::Result<usize> + Send + Sync + 'static,
+    ) -> Self {
+        Self(Arc::new(lookup))
+    }
+
+    pub fn get(&self, block: BlockNumber) -> anyhow::Result<usize> {
+        (self.0)(block)
+    }
+}
+
+impl std::fmt::Debug for EventCountLookup {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EventCountLookup").finish_non_exhaustive()
+    }
+}
+
 pub type EventsForBlockByTransaction = (BlockNumber, Vec<(TransactionHash, Vec<Event>)>);
 
+/// Checks the events received for a block, grouped by transaction, against the
+/// block's event commitment, see
+/// [`EventStream`](crate::client::peer_agnostic::traits::EventStream).
+///
+/// Event commitments from Starknet 0.13.2 onwards cover the transaction hash
+/// of each event, so a successful check also verifies the grouping. For older
+/// blocks it is up to the verifier to decide what to check.
+///
+/// The check returns `Ok(false)` if the commitment does not match. Errors are
+/// meant for failures unrelated to the received data, such as the expected
+/// commitment being unavailable.
+#[derive(Clone)]
+pub struct EventCommitmentVerifier(
+    Arc<
+        dyn Fn(BlockNumber, &[(TransactionHash, Vec<Event>)]) -> anyhow::Result<bool> + Send + Sync,
+    >,
+);
+
+impl EventCommitmentVerifier {
+    pub fn new(
+        verify: impl Fn(BlockNumber, &[(TransactionHash, Vec<Event>)]) -> anyhow::Result<bool>
+            + Send
+            + Sync
+            + 'static,
+    ) -> Self {
+        Self(Arc::new(verify))
+    }
+
+    pub fn verify(
+        &self,
+        block: BlockNumber,
+        events: &[(TransactionHash, Vec<Event>)],
+    ) -> anyhow::Result<bool> {
+        (self.0)(block, events)
+    }
+}
+
+impl std::fmt::Debug for EventCommitmentVerifier {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EventCommitmentVerifier")
+            .finish_non_exhaustive()
+    }
+}
+
+/// Computes the class hash of a [`ClassDefinition`], see
+/// [`Client::class_definition_by_hash`](crate::client::peer_agnostic::Client::class_definition_by_hash).
+///
+/// Errors are meant for definitions which cannot be hashed, such as ones with
+/// an invalid layout.
+#[derive(Clone)]
+pub struct ClassHasher(Arc<dyn Fn(&ClassDefinition) -> anyhow::Result<ClassHash> + Send + Sync>);
+
+impl ClassHasher {
+    pub fn new(
+        hash: impl Fn(&ClassDefinition) -> anyhow::Result<ClassHash> + Send + Sync + 'static,
+    ) -> Self {
+        Self(Arc::new(hash))
+    }
+
+    pub fn hash(&self, class: &ClassDefinition) -> anyhow::Result<ClassHash> {
+        (self.0)(class)
+    }
+}
+
+impl std::fmt::Debug for ClassHasher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ClassHasher").finish_non_exhaustive()
+    }
+}
+
+/// All the data of a single block, see
+/// [`Client::full_block_stream`](crate::client::peer_agnostic::Client::full_block_stream).
+#[derive(Clone, Debug, PartialEq)]
+pub struct FullBlock {
+    pub header: SignedBlockHeader,
+    pub transactions: TransactionData,
+    /// Contract class updates are set to `ContractClassUpdate::Deploy`, see
+    /// [`StateDiffStream`](crate::client::peer_agnostic::traits::StateDiffStream).
+    pub state_diff: StateUpdateData,
+    pub classes: Vec<ClassDefinition>,
+    pub events: Vec<(TransactionHash, Vec<Event>)>,
+}
+
 impl TryFromDto<p2p_proto::header::SignedBlockHeader> for SignedBlockHeader {
     fn try_from_dto(dto: p2p_proto::header::SignedBlockHeader) -> anyhow::Result<Self> {
         anyhow::ensure!(dto.signatures.len() == 1, "expected exactly one signature");
@@ -120,27 +490,83 @@ impl TryFromDto<p2p_proto::header::SignedBlockHeader> for SignedBlockHeader {
     }
 }
 
+/// Part of the state diff of a block, as received from a peer. All updates of
+/// a contract are in a single chunk, along with the data availability domain
+/// of its storage.
+#[derive(Clone, Debug, PartialEq)]
+pub enum StateDiffChunk {
+    ContractUpdate(ContractAddress, ContractUpdate, DataAvailabilityMode),
+    SystemContractUpdate(ContractAddress, SystemContractUpdate, DataAvailabilityMode),
+    DeclaredCairoClass(ClassHash),
+    DeclaredSierraClass(SierraHash, CasmHash),
+}
+
+impl StateDiffChunk {
+    /// Adds this chunk to `state_diff`, on top of the chunks merged before it.
+    pub fn merge_into(self, state_diff: &mut StateUpdateData) {
+        match self {
+            StateDiffChunk::ContractUpdate(address, update, domain) => {
+                state_diff.set_storage_domain(address, domain);
+                let merged = state_diff.contract_updates.entry(address).or_default();
+                merged.storage.extend(update.storage);
+                if update.nonce.is_some() {
+                    merged.nonce = update.nonce;
+                }
+                if update.class.is_some() {
+                    merged.class = update.class;
+                }
+            }
+            StateDiffChunk::SystemContractUpdate(address, update, domain) => {
+                state_diff.set_storage_domain(address, domain);
+                state_diff
+                    .system_contract_updates
+                    .entry(address)
+                    .or_default()
+                    .storage
+                    .extend(update.storage);
+            }
+            StateDiffChunk::DeclaredCairoClass(class_hash) => {
+                state_diff.declared_cairo_classes.insert(class_hash);
+            }
+            StateDiffChunk::DeclaredSierraClass(sierra_hash, casm_hash) => {
+                state_diff
+                    .declared_sierra_classes
+                    .insert(sierra_hash, casm_hash);
+            }
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum StateDiffsError {
+    RequestFailed(PeerId, anyhow::Error),
     IncorrectStateDiffCount(PeerId),
     ResponseStreamFailure(PeerId, std::io::Error),
+    PrematureStreamTermination(PeerId),
 }
 
 impl std::fmt::Display for StateDiffsError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
+            StateDiffsError::RequestFailed(peer, err) => {
+                write!(f, "State diffs request to peer {} failed: {}", peer, err)
+            }
             StateDiffsError::IncorrectStateDiffCount(peer) => {
                 write!(f, "Incorrect state diff count from peer {}", peer)
             }
             StateDiffsError::ResponseStreamFailure(peer, err) => {
                 write!(f, "Failed to read state diffs from peer {}: {}", peer, err)
             }
+            StateDiffsError::PrematureStreamTermination(peer) => {
+                write!(f, "State diffs stream from peer {} ended without Fin", peer)
+            }
         }
     }
 }
 
 #[derive(Debug)]
 pub enum ClassDefinitionsError {
+    RequestFailed(PeerId, anyhow::Error),
     IncorrectClassDefinitionCount(PeerId),
     CairoDefinitionError(PeerId),
     SierraDefinitionError(PeerId),
@@ -150,6 +576,13 @@ pub enum ClassDefinitionsError {
 impl std::fmt::Display for ClassDefinitionsError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
+            ClassDefinitionsError::RequestFailed(peer, err) => {
+                write!(
+                    f,
+                    "Class definitions request to peer {} failed: {}",
+                    peer, err
+                )
+            }
             ClassDefinitionsError::IncorrectClassDefinitionCount(peer) => {
                 write!(f, "Incorrect class definition count from peer {}", peer)
             }
@@ -178,3 +611,78 @@ impl std::fmt::Display for EventsResponseStreamFailure {
         write!(f, "Failed to read events from peer {}: {}", self.0, self.1)
     }
 }
+
+/// The reason why a sync stream ended before covering the requested range.
+/// It is yielded as the last item of such a stream, a stream which ends
+/// without it covered the whole range.
+#[derive(Debug)]
+pub enum StreamError {
+    /// No peers were found for the configured number of rounds, see
+    /// [`Client::with_max_failed_rounds`](crate::client::peer_agnostic::Client::with_max_failed_rounds).
+    NoPeers,
+    /// None of the peers provided `block` for the configured number of
+    /// rounds, see
+    /// [`Client::with_max_failed_rounds`](crate::client::peer_agnostic::Client::with_max_failed_rounds).
+    AllPeersFailed { block: BlockNumber },
+    /// The stream of counts provided by the caller ended before the end of
+    /// the range.
+    PrematureTermination,
+    /// At least two distinct peers sent the same data introduced by a newer
+    /// version of the protocol, which is not held against them. Until then
+    /// the stream moves on to the next peer.
+    UnsupportedProtocolVersion(UnsupportedProtocolVersion),
+    /// Failures unrelated to the peers, such as the stream of counts or a
+    /// commitment check failing.
+    Other(anyhow::Error),
+}
+
+impl std::fmt::Display for StreamError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StreamError::NoPeers => write!(f, "No peers available"),
+            StreamError::AllPeersFailed { block } => {
+                write!(f, "All peers failed to provide block {}", block)
+            }
+            StreamError::PrematureTermination => write!(f, "Count stream terminated prematurely"),
+            StreamError::UnsupportedProtocolVersion(unsupported) => write!(f, "{}", unsupported),
+            StreamError::Other(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for StreamError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            StreamError::Other(err) => err.source(),
+            _ => None,
+        }
+    }
+}
+
+/// Data which a peer sent for `block` but which failed to parse. The sync
+/// streams skip such data and move on to the next peer, see
+/// [`Client::with_conversion_errors`](crate::client::peer_agnostic::Client::with_conversion_errors)
+/// to receive it along with the peer which sent it.
+#[derive(Debug)]
+pub struct ConversionError {
+    pub block: BlockNumber,
+    pub error: anyhow::Error,
+}
+
+impl std::fmt::Display for ConversionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Malformed data for block {}: {:#}",
+            self.block, self.error
+        )
+    }
+}
+
+impl std::error::Error for ConversionError {}
+
+impl From<anyhow::Error> for StreamError {
+    fn from(err: anyhow::Error) -> Self {
+        StreamError::Other(err)
+    }
+}
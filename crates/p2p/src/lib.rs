@@ -2,7 +2,7 @@
 use std::collections::{HashMap, HashSet};
 use std::time::Duration;
 
-use futures::channel::mpsc::{Receiver as ResponseReceiver, Sender as ResponseSender};
+use futures::channel::mpsc::Sender as ResponseSender;
 use ipnet::IpNet;
 use libp2p::gossipsub::IdentTopic;
 use libp2p::identity::Keypair;
@@ -14,6 +14,7 @@ use p2p_proto::event::{EventsRequest, EventsResponse};
 use p2p_proto::header::{BlockHeadersRequest, BlockHeadersResponse, NewBlock};
 use p2p_proto::state::{StateDiffsRequest, StateDiffsResponse};
 use p2p_proto::transaction::{TransactionsRequest, TransactionsResponse};
+use p2p_stream::ResponseReceiver;
 use pathfinder_common::{BlockHash, BlockNumber, ChainId};
 use peers::Peer;
 use tokio::sync::{mpsc, oneshot};
@@ -112,38 +113,41 @@ enum Command {
         sender: mpsc::Sender<anyhow::Result<Vec<PeerId>>>,
     },
     SubscribeTopic {
+        topic: IdentTopic,
+        /// Receives the messages on the topic as they are, if set. Messages
+        /// which decode as propagated blocks are still emitted as
+        /// [`Event::BlockPropagation`].
+        messages: Option<mpsc::Sender<(PeerId, GossipMessage)>>,
+        sender: EmptyResultSender,
+    },
+    UnsubscribeTopic {
         topic: IdentTopic,
         sender: EmptyResultSender,
     },
     SendHeadersSyncRequest {
         peer_id: PeerId,
         request: BlockHeadersRequest,
-        sender: oneshot::Sender<
-            anyhow::Result<ResponseReceiver<std::io::Result<BlockHeadersResponse>>>,
-        >,
+        sender: oneshot::Sender<anyhow::Result<ResponseReceiver<BlockHeadersResponse>>>,
     },
     SendClassesSyncRequest {
         peer_id: PeerId,
         request: ClassesRequest,
-        sender: oneshot::Sender<anyhow::Result<ResponseReceiver<std::io::Result<ClassesResponse>>>>,
+        sender: oneshot::Sender<anyhow::Result<ResponseReceiver<ClassesResponse>>>,
     },
     SendStateDiffsSyncRequest {
         peer_id: PeerId,
         request: StateDiffsRequest,
-        sender:
-            oneshot::Sender<anyhow::Result<ResponseReceiver<std::io::Result<StateDiffsResponse>>>>,
+        sender: oneshot::Sender<anyhow::Result<ResponseReceiver<StateDiffsResponse>>>,
     },
     SendTransactionsSyncRequest {
         peer_id: PeerId,
         request: TransactionsRequest,
-        sender: oneshot::Sender<
-            anyhow::Result<ResponseReceiver<std::io::Result<TransactionsResponse>>>,
-        >,
+        sender: oneshot::Sender<anyhow::Result<ResponseReceiver<TransactionsResponse>>>,
     },
     SendEventsSyncRequest {
         peer_id: PeerId,
         request: EventsRequest,
-        sender: oneshot::Sender<anyhow::Result<ResponseReceiver<std::io::Result<EventsResponse>>>>,
+        sender: oneshot::Sender<anyhow::Result<ResponseReceiver<EventsResponse>>>,
     },
     PublishPropagationMessage {
         topic: IdentTopic,
@@ -154,6 +158,9 @@ enum Command {
         peer_id: PeerId,
         sender: oneshot::Sender<()>,
     },
+    ConnectedPeers {
+        sender: oneshot::Sender<HashSet<PeerId>>,
+    },
     /// For testing purposes only
     _Test(TestCommand),
 }
@@ -216,3 +223,12 @@ pub enum TestEvent {
 }
 
 pub type EventReceiver = mpsc::Receiver<Event>;
+
+/// A message received on a gossip topic subscribed to with
+/// [`client::peer_agnostic::Client::subscribe_topic`]. The data is passed on
+/// as is, decoding it is up to the subscriber.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GossipMessage {
+    pub topic: String,
+    pub data: Vec<u8>,
+}
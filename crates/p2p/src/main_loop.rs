@@ -2,7 +2,6 @@ use std::collections::{HashMap, HashSet};
 use std::fmt::Debug;
 use std::num::NonZeroUsize;
 
-use futures::channel::mpsc::Receiver as ResponseReceiver;
 use futures::StreamExt;
 use libp2p::gossipsub::{self, IdentTopic};
 use libp2p::kad::{self, BootstrapError, BootstrapOk, QueryId, QueryResult};
@@ -16,13 +15,13 @@ use p2p_proto::header::BlockHeadersResponse;
 use p2p_proto::state::StateDiffsResponse;
 use p2p_proto::transaction::TransactionsResponse;
 use p2p_proto::{ToProtobuf, TryFromProtobuf};
-use p2p_stream::{self, OutboundRequestId};
+use p2p_stream::{self, OutboundRequestId, ResponseReceiver};
 use tokio::sync::{mpsc, oneshot};
 use tokio::time::Duration;
 
 #[cfg(test)]
 use crate::test_utils;
-use crate::{behaviour, Command, EmptyResultSender, Event, TestCommand, TestEvent};
+use crate::{behaviour, Command, EmptyResultSender, Event, GossipMessage, TestCommand, TestEvent};
 
 pub struct MainLoop {
     swarm: libp2p::swarm::Swarm<behaviour::Behaviour>,
@@ -39,29 +38,31 @@ pub struct MainLoop {
     // request_sync_status: HashSetDelay<PeerId>,
     pending_queries: PendingQueries,
     _pending_test_queries: TestQueries,
+    /// Subscribers which receive the messages on a topic as they are.
+    topic_subscribers: HashMap<gossipsub::TopicHash, Vec<mpsc::Sender<(PeerId, GossipMessage)>>>,
 }
 
 #[derive(Debug, Default)]
 struct PendingRequests {
     pub headers: HashMap<
         OutboundRequestId,
-        oneshot::Sender<anyhow::Result<ResponseReceiver<std::io::Result<BlockHeadersResponse>>>>,
+        oneshot::Sender<anyhow::Result<ResponseReceiver<BlockHeadersResponse>>>,
     >,
     pub classes: HashMap<
         OutboundRequestId,
-        oneshot::Sender<anyhow::Result<ResponseReceiver<std::io::Result<ClassesResponse>>>>,
+        oneshot::Sender<anyhow::Result<ResponseReceiver<ClassesResponse>>>,
     >,
     pub state_diffs: HashMap<
         OutboundRequestId,
-        oneshot::Sender<anyhow::Result<ResponseReceiver<std::io::Result<StateDiffsResponse>>>>,
+        oneshot::Sender<anyhow::Result<ResponseReceiver<StateDiffsResponse>>>,
     >,
     pub transactions: HashMap<
         OutboundRequestId,
-        oneshot::Sender<anyhow::Result<ResponseReceiver<std::io::Result<TransactionsResponse>>>>,
+        oneshot::Sender<anyhow::Result<ResponseReceiver<TransactionsResponse>>>,
     >,
     pub events: HashMap<
         OutboundRequestId,
-        oneshot::Sender<anyhow::Result<ResponseReceiver<std::io::Result<EventsResponse>>>>,
+        oneshot::Sender<anyhow::Result<ResponseReceiver<EventsResponse>>>,
     >,
 }
 
@@ -85,6 +86,7 @@ impl MainLoop {
             pending_sync_requests: Default::default(),
             pending_queries: Default::default(),
             _pending_test_queries: Default::default(),
+            topic_subscribers: Default::default(),
         }
     }
 
@@ -296,6 +298,32 @@ impl MainLoop {
             })) => {
                 use prost::Message;
 
+                // Messages on topics with subscribers are not necessarily propagated blocks.
+                let mut subscribed = false;
+                if let Some(subscribers) = self.topic_subscribers.get_mut(&message.topic) {
+                    let gossip = GossipMessage {
+                        topic: message.topic.to_string(),
+                        data: message.data.clone(),
+                    };
+                    subscribers.retain(|subscriber| {
+                        match subscriber.try_send((peer_id, gossip.clone())) {
+                            Ok(()) => true,
+                            Err(mpsc::error::TrySendError::Full(_)) => {
+                                tracing::debug!(topic=%message.topic, from=%peer_id, "Topic subscriber lagging, dropping message");
+                                true
+                            }
+                            Err(mpsc::error::TrySendError::Closed(_)) => {
+                                tracing::debug!(topic=%message.topic, "Topic subscriber gone");
+                                false
+                            }
+                        }
+                    });
+                    if subscribers.is_empty() {
+                        self.topic_subscribers.remove(&message.topic);
+                    }
+                    subscribed = true;
+                }
+
                 match p2p_proto::proto::header::NewBlock::decode(message.data.as_ref()) {
                     Ok(new_block) => {
                         match p2p_proto::header::NewBlock::try_from_protobuf(new_block, "message") {
@@ -315,14 +343,16 @@ impl MainLoop {
                                     .await
                                     .expect("Event receiver not to be dropped");
                             }
-                            Err(error) => {
+                            Err(error) if !subscribed => {
                                 tracing::error!(from=%peer_id, %error, "Gossipsub Message")
                             }
+                            Err(_) => {}
                         }
                     }
-                    Err(error) => {
+                    Err(error) if !subscribed => {
                         tracing::error!(from=%peer_id, %error, "Gossipsub Message");
                     }
+                    Err(_) => {}
                 };
             }
             // ===========================
@@ -796,10 +826,30 @@ impl MainLoop {
                     .get_closest_peers
                     .insert(query_id, sender);
             }
-            Command::SubscribeTopic { topic, sender } => {
+            Command::SubscribeTopic {
+                topic,
+                messages,
+                sender,
+            } => {
                 let _ = match self.swarm.behaviour_mut().subscribe_topic(&topic) {
                     Ok(_) => {
                         tracing::debug!(%topic, "Subscribing to topic");
+                        if let Some(messages) = messages {
+                            self.topic_subscribers
+                                .entry(topic.hash())
+                                .or_default()
+                                .push(messages);
+                        }
+                        sender.send(Ok(()))
+                    }
+                    Err(e) => sender.send(Err(e)),
+                };
+            }
+            Command::UnsubscribeTopic { topic, sender } => {
+                self.topic_subscribers.remove(&topic.hash());
+                let _ = match self.swarm.behaviour_mut().unsubscribe_topic(&topic) {
+                    Ok(_) => {
+                        tracing::debug!(%topic, "Unsubscribing from topic");
                         sender.send(Ok(()))
                     }
                     Err(e) => sender.send(Err(e)),
@@ -897,6 +947,16 @@ impl MainLoop {
                 self.swarm.behaviour_mut().not_useful(peer_id);
                 let _ = sender.send(());
             }
+            Command::ConnectedPeers { sender } => {
+                let connected = self
+                    .swarm
+                    .behaviour()
+                    .peers()
+                    .filter(|(_, peer)| peer.is_connected())
+                    .map(|(peer_id, _)| peer_id)
+                    .collect();
+                let _ = sender.send(connected);
+            }
             Command::_Test(command) => self.handle_test_command(command).await,
         };
     }
@@ -15,7 +15,7 @@ use rstest::rstest;
 
 use crate::sync::codec;
 use crate::test_utils::peer::TestPeer;
-use crate::{Config, Event, EventReceiver, RateLimit, TestEvent};
+use crate::{Config, Event, EventReceiver, GossipMessage, RateLimit, TestEvent};
 
 /// [`MainLoop`](p2p::MainLoop)'s event channel size is 1, so we need to consume
 /// all events as soon as they're sent otherwise the main loop will stall.
@@ -838,6 +838,116 @@ async fn subscription_and_propagation(#[case] peers: (TestPeer, TestPeer)) {
     assert_eq!(msg, expected);
 }
 
+#[rstest]
+#[case::server_to_client(server_to_client().await)]
+#[case::client_to_server(client_to_server().await)]
+#[test_log::test(tokio::test)]
+async fn subscription_with_messages(#[case] peers: (TestPeer, TestPeer)) {
+    use p2p_proto::ToProtobuf;
+    use prost::Message;
+
+    let _ = env_logger::builder().is_test(true).try_init();
+    let (peer1, peer2) = peers;
+
+    let mut peer2_subscribed_to_peer1 = filter_events(peer1.event_receiver, |event| match event {
+        Event::Test(TestEvent::Subscribed { .. }) => Some(()),
+        _ => None,
+    });
+
+    const TOPIC: &str = "TOPIC";
+
+    let mut messages = peer2.client.subscribe_topic_messages(TOPIC).await.unwrap();
+    peer2_subscribed_to_peer1.recv().await;
+
+    let new_block = Faker.fake::<NewBlock>();
+
+    peer1
+        .client
+        .publish(TOPIC, new_block.clone())
+        .await
+        .unwrap();
+
+    let (from, message) = messages.recv().await.unwrap();
+
+    assert_eq!(from, peer1.peer_id);
+    assert_eq!(
+        message,
+        GossipMessage {
+            topic: TOPIC.to_owned(),
+            data: new_block.to_protobuf().encode_to_vec(),
+        }
+    );
+
+    peer2.client.unsubscribe_topic(TOPIC).await.unwrap();
+    assert!(messages.recv().await.is_none());
+}
+
+#[rstest]
+#[case::server_to_client(server_to_client().await)]
+#[case::client_to_server(client_to_server().await)]
+#[test_log::test(tokio::test)]
+async fn subscription_with_messages_by_several_subscribers(#[case] peers: (TestPeer, TestPeer)) {
+    let _ = env_logger::builder().is_test(true).try_init();
+    let (peer1, peer2) = peers;
+
+    let mut peer2_subscribed_to_peer1 = filter_events(peer1.event_receiver, |event| match event {
+        Event::Test(TestEvent::Subscribed { .. }) => Some(()),
+        _ => None,
+    });
+
+    const TOPIC: &str = "TOPIC";
+
+    let mut first = peer2.client.subscribe_topic_messages(TOPIC).await.unwrap();
+    let mut second = peer2.client.subscribe_topic_messages(TOPIC).await.unwrap();
+    peer2_subscribed_to_peer1.recv().await;
+
+    peer1
+        .client
+        .publish(TOPIC, Faker.fake::<NewBlock>())
+        .await
+        .unwrap();
+
+    // The second subscription does not replace the first one.
+    let (_, first_message) = first.recv().await.unwrap();
+    let (_, second_message) = second.recv().await.unwrap();
+    assert_eq!(first_message, second_message);
+
+    peer2.client.unsubscribe_topic(TOPIC).await.unwrap();
+    assert!(first.recv().await.is_none());
+    assert!(second.recv().await.is_none());
+}
+
+#[rstest]
+#[case::server_to_client(server_to_client().await)]
+#[case::client_to_server(client_to_server().await)]
+#[test_log::test(tokio::test)]
+async fn subscription_with_messages_still_propagates_blocks(#[case] peers: (TestPeer, TestPeer)) {
+    let _ = env_logger::builder().is_test(true).try_init();
+    let (peer1, peer2) = peers;
+
+    let mut peer2_subscribed_to_peer1 = filter_events(peer1.event_receiver, |event| match event {
+        Event::Test(TestEvent::Subscribed { .. }) => Some(()),
+        _ => None,
+    });
+
+    let mut propagated_to_peer2 = filter_events(peer2.event_receiver, |event| match event {
+        Event::BlockPropagation { new_block, .. } => Some(new_block),
+        _ => None,
+    });
+
+    const TOPIC: &str = "TOPIC";
+
+    let mut messages = peer2.client.subscribe_topic_messages(TOPIC).await.unwrap();
+    peer2_subscribed_to_peer1.recv().await;
+
+    let expected = Faker.fake::<NewBlock>();
+
+    peer1.client.publish(TOPIC, expected.clone()).await.unwrap();
+
+    assert!(messages.recv().await.is_some());
+    assert_eq!(propagated_to_peer2.recv().await.unwrap(), expected);
+}
+
 mod successful_sync {
     use super::*;
 
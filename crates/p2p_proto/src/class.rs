@@ -5,8 +5,8 @@ use pathfinder_crypto::Felt;
 use tagged::Tagged;
 use tagged_debug_derive::TaggedDebug;
 
-use crate::common::Iteration;
-use crate::{proto, proto_field, ToProtobuf, TryFromProtobuf};
+use crate::common::{Iteration, VolitionDomain};
+use crate::{proto, proto_field, proto_variant, ToProtobuf, TryFromProtobuf};
 
 #[derive(Debug, Clone, PartialEq, Eq, ToProtobuf, TryFromProtobuf, Dummy, PartialOrd, Ord)]
 #[protobuf(name = "crate::proto::class::EntryPoint")]
@@ -75,8 +75,14 @@ impl<T> Dummy<T> for Cairo1Class {
 
 #[derive(Clone, PartialEq, Eq, Dummy, TaggedDebug)]
 pub enum Class {
-    Cairo0 { class: Cairo0Class, domain: u32 },
-    Cairo1 { class: Cairo1Class, domain: u32 },
+    Cairo0 {
+        class: Cairo0Class,
+        domain: VolitionDomain,
+    },
+    Cairo1 {
+        class: Cairo1Class,
+        domain: VolitionDomain,
+    },
 }
 
 impl ToProtobuf<proto::class::Class> for Class {
@@ -86,11 +92,11 @@ impl ToProtobuf<proto::class::Class> for Class {
         match self {
             Self::Cairo0 { class, domain } => Class {
                 class: Some(Cairo0(class.to_protobuf())),
-                domain,
+                domain: domain.to_protobuf() as u32,
             },
             Self::Cairo1 { class, domain } => Class {
                 class: Some(Cairo1(class.to_protobuf())),
-                domain,
+                domain: domain.to_protobuf() as u32,
             },
         }
     }
@@ -102,14 +108,22 @@ impl TryFromProtobuf<proto::class::Class> for Class {
         field_name: &'static str,
     ) -> Result<Self, std::io::Error> {
         use proto::class::class::Class::{Cairo0, Cairo1};
-        Ok(match proto_field(input.class, field_name)? {
+        // The domain is a plain integer in the class message, unlike in state diffs.
+        let domain = i32::try_from(input.domain).map_err(|e| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("Invalid {field_name} domain: {e}"),
+            )
+        })?;
+        let domain = VolitionDomain::try_from_protobuf(domain, field_name)?;
+        Ok(match proto_variant(input.class, field_name)? {
             Cairo0(c) => Self::Cairo0 {
                 class: Cairo0Class::try_from_protobuf(c, field_name)?,
-                domain: input.domain,
+                domain,
             },
             Cairo1(c) => Self::Cairo1 {
                 class: Cairo1Class::try_from_protobuf(c, field_name)?,
-                domain: input.domain,
+                domain,
             },
         })
     }
@@ -164,6 +164,19 @@ fn proto_field<T>(input: Option<T>, field_name: &'static str) -> Result<T, std::
     })
 }
 
+/// Same as [proto_field] for a oneof selecting the kind of a transaction,
+/// receipt or class. Variants added by newer versions of the protocol are not
+/// decoded into the oneof, so a missing oneof is reported as
+/// [std::io::ErrorKind::Unsupported] instead of as invalid data.
+fn proto_variant<T>(input: Option<T>, field_name: &'static str) -> Result<T, std::io::Error> {
+    input.ok_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            format!("Unknown variant of field {field_name}"),
+        )
+    })
+}
+
 use p2p_proto_derive::*;
 pub mod class;
 pub mod common;
@@ -3,7 +3,7 @@ use pathfinder_crypto::Felt;
 use primitive_types::H160;
 
 use crate::common::Hash256;
-use crate::{proto, proto_field, ToProtobuf, TryFromProtobuf};
+use crate::{proto, proto_variant, ToProtobuf, TryFromProtobuf};
 
 #[derive(Debug, Clone, PartialEq, Eq, ToProtobuf, TryFromProtobuf, Dummy)]
 #[protobuf(name = "crate::proto::receipt::MessageToL1")]
@@ -184,7 +184,7 @@ impl TryFromProtobuf<proto::receipt::Receipt> for Receipt {
             L1Handler,
         };
 
-        Ok(match proto_field(input.r#type, field_name)? {
+        Ok(match proto_variant(input.r#type, field_name)? {
             Invoke(r) => Self::Invoke(TryFromProtobuf::try_from_protobuf(r, field_name)?),
             L1Handler(r) => Self::L1Handler(TryFromProtobuf::try_from_protobuf(r, field_name)?),
             Declare(r) => Self::Declare(TryFromProtobuf::try_from_protobuf(r, field_name)?),
@@ -3,7 +3,7 @@ use pathfinder_crypto::Felt;
 
 use crate::common::{Address, Hash, Iteration, VolitionDomain};
 use crate::receipt::Receipt;
-use crate::{proto, proto_field, ToProtobuf, TryFromProtobuf};
+use crate::{proto, proto_field, proto_variant, ToProtobuf, TryFromProtobuf};
 
 #[derive(Debug, Clone, PartialEq, Eq, ToProtobuf, TryFromProtobuf, Dummy)]
 #[protobuf(name = "crate::proto::transaction::ResourceLimits")]
@@ -246,7 +246,7 @@ impl TryFromProtobuf<proto::transaction::Transaction> for Transaction {
             InvokeV3,
             L1Handler,
         };
-        match proto_field(input.txn, field_name)? {
+        match proto_variant(input.txn, field_name)? {
             DeclareV0(t) => TryFromProtobuf::try_from_protobuf(t, field_name).map(Self::DeclareV0),
             DeclareV1(t) => TryFromProtobuf::try_from_protobuf(t, field_name).map(Self::DeclareV1),
             DeclareV2(t) => TryFromProtobuf::try_from_protobuf(t, field_name).map(Self::DeclareV2),
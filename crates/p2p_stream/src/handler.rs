@@ -25,6 +25,7 @@
 pub(crate) mod protocol;
 
 use std::collections::VecDeque;
+use std::pin::Pin;
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use std::task::{Context, Poll};
@@ -47,7 +48,27 @@ use libp2p::swarm::SubstreamProtocol;
 
 use crate::codec::Codec;
 use crate::handler::protocol::Protocol;
-use crate::{InboundRequestId, OutboundRequestId, EMPTY_QUEUE_SHRINK_THRESHOLD};
+use crate::{InboundRequestId, OutboundRequestId, ResponseReceiver, EMPTY_QUEUE_SHRINK_THRESHOLD};
+
+/// Counts the bytes read from the wrapped substream.
+struct CountingRead<S> {
+    inner: S,
+    count: Arc<AtomicU64>,
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for CountingRead<S> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let poll = Pin::new(&mut self.inner).poll_read(cx, buf);
+        if let Poll::Ready(Ok(read)) = &poll {
+            self.count.fetch_add(*read as u64, Ordering::Relaxed);
+        }
+        poll
+    }
+}
 
 /// A connection handler for a request/streaming-response
 /// [`Behaviour`](super::Behaviour) protocol.
@@ -81,15 +102,9 @@ where
     )>,
     /// A channel for signalling that an outbound request has been sent. Cloned
     /// for each outbound request.
-    outbound_sender: mpsc::Sender<(
-        OutboundRequestId,
-        mpsc::Receiver<std::io::Result<TCodec::Response>>,
-    )>,
+    outbound_sender: mpsc::Sender<(OutboundRequestId, ResponseReceiver<TCodec::Response>)>,
     /// The [`mpsc::Receiver`] for the above sender.
-    outbound_receiver: mpsc::Receiver<(
-        OutboundRequestId,
-        mpsc::Receiver<std::io::Result<TCodec::Response>>,
-    )>,
+    outbound_receiver: mpsc::Receiver<(OutboundRequestId, ResponseReceiver<TCodec::Response>)>,
 
     inbound_request_id: Arc<AtomicU64>,
 
@@ -215,8 +230,14 @@ where
 
             stream.close().await?;
 
+            let bytes_received = Arc::new(AtomicU64::new(0));
+            let mut stream = CountingRead {
+                inner: stream,
+                count: bytes_received.clone(),
+            };
+
             sender
-                .send((request_id, rs_recv))
+                .send((request_id, ResponseReceiver::new(rs_recv, bytes_received)))
                 .await
                 .expect("`ConnectionHandler` owns both ends of the channel");
             drop(sender);
@@ -325,7 +346,7 @@ where
         /// The ID of the outbound request.
         request_id: OutboundRequestId,
         /// The channel through which we can receive the responses.
-        receiver: mpsc::Receiver<std::io::Result<TCodec::Response>>,
+        receiver: ResponseReceiver<TCodec::Response>,
     },
     /// An outbound response stream to an inbound request was closed.
     OutboundResponseStreamClosed(InboundRequestId),
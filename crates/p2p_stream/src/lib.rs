@@ -54,7 +54,8 @@ mod codec;
 mod handler;
 
 use std::collections::{HashMap, HashSet, VecDeque};
-use std::sync::atomic::AtomicU64;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use std::task::{Context, Poll};
 use std::time::Duration;
@@ -62,6 +63,7 @@ use std::{fmt, io};
 
 pub use codec::Codec;
 use futures::channel::mpsc;
+use futures::{Stream, StreamExt};
 use handler::Handler;
 use libp2p::core::transport::PortUse;
 use libp2p::core::{ConnectedPoint, Endpoint, Multiaddr};
@@ -104,7 +106,7 @@ pub enum Event<TRequest, TResponse, TChannelResponse = TResponse> {
         /// The ID of the outbound request.
         request_id: OutboundRequestId,
         /// The channel through which we can receive the responses.
-        channel: mpsc::Receiver<std::io::Result<TResponse>>,
+        channel: ResponseReceiver<TResponse>,
     },
     /// An outbound request failed.
     OutboundFailure {
@@ -138,6 +140,57 @@ pub enum Event<TRequest, TResponse, TChannelResponse = TResponse> {
     },
 }
 
+/// The responses to an outbound request, see
+/// [`Event::OutboundRequestSentAwaitingResponses`].
+///
+/// Also counts the bytes received on the underlying substream, as they are read
+/// by the [`Codec`].
+#[derive(Debug)]
+pub struct ResponseReceiver<TResponse> {
+    responses: mpsc::Receiver<io::Result<TResponse>>,
+    bytes_received: Arc<AtomicU64>,
+}
+
+impl<TResponse> ResponseReceiver<TResponse> {
+    /// `bytes_received` is shared with whoever reads the responses.
+    pub fn new(
+        responses: mpsc::Receiver<io::Result<TResponse>>,
+        bytes_received: Arc<AtomicU64>,
+    ) -> Self {
+        Self {
+            responses,
+            bytes_received,
+        }
+    }
+
+    /// The number of bytes read from the substream so far.
+    ///
+    /// The codec reads ahead of the receiver, so this can include the bytes of
+    /// a response which has been decoded but not yet received.
+    pub fn bytes_received(&self) -> u64 {
+        self.bytes_received.load(Ordering::Relaxed)
+    }
+}
+
+/// Responses which were not read from a substream, so no bytes are counted.
+impl<TResponse> From<mpsc::Receiver<io::Result<TResponse>>> for ResponseReceiver<TResponse> {
+    fn from(responses: mpsc::Receiver<io::Result<TResponse>>) -> Self {
+        Self::new(responses, Default::default())
+    }
+}
+
+impl<TResponse> Stream for ResponseReceiver<TResponse> {
+    type Item = io::Result<TResponse>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.responses.poll_next_unpin(cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.responses.size_hint()
+    }
+}
+
 /// Possible failures occurring in the context of sending
 /// an outbound request and receiving the response.
 #[derive(Debug)]
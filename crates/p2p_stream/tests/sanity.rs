@@ -141,6 +141,11 @@ async fn sanity(
 
         assert_eq!(peer, responder.peer_id);
         assert_eq!(req_id_done, req_id);
+        // Each response is encoded as a single u32
+        assert_eq!(
+            resp_rx.bytes_received(),
+            (num_responses * std::mem::size_of::<u32>()) as u64
+        );
     };
 
     tokio::join!(responder_task, requester_task);
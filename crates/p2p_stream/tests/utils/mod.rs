@@ -251,7 +251,7 @@ pub async fn wait_outbound_request_sent_awaiting_responses(
 ) -> Result<(
     PeerId,
     OutboundRequestId,
-    mpsc::Receiver<std::io::Result<Action>>,
+    p2p_stream::ResponseReceiver<Action>,
 )> {
     loop {
         match swarm.select_next_some().await.try_into_behaviour_event() {
@@ -211,6 +211,23 @@ This should only be enabled for debugging purposes as it adds substantial proces
     )]
     rpc_batch_concurrency_limit: NonZeroUsize,
 
+    #[arg(
+        long = "rpc.batch-size-limit",
+        long_help = "Sets the maximum number of requests in a single batch request.",
+        env = "PATHFINDER_RPC_BATCH_SIZE_LIMIT",
+        default_value = "1000"
+    )]
+    rpc_batch_size_limit: NonZeroUsize,
+
+    #[arg(
+        long = "rpc.method-metrics",
+        long_help = "Enable recording the execution time and error codes of each RPC method",
+        env = "PATHFINDER_RPC_METHOD_METRICS",
+        default_value = "true",
+        action=ArgAction::Set
+    )]
+    rpc_method_metrics: bool,
+
     #[arg(
         long = "sync.enable",
         long_help = "Enable syncing the chain",
@@ -544,6 +561,26 @@ Example:
         env = "PATHFINDER_P2P_EXPERIMENTAL_EVICTION_TIMEOUT"
     )]
     eviction_timeout: u32,
+
+    #[arg(
+        long = "p2p.experimental.peer-store-file",
+        long_help = "Path to a file in which peer scores are persisted, so that sync prefers \
+                     historically reliable peers after a restart. If not provided, peer scores \
+                     are kept in memory only.",
+        value_name = "PATH",
+        env = "PATHFINDER_P2P_EXPERIMENTAL_PEER_STORE_FILE"
+    )]
+    peer_store_file: Option<std::path::PathBuf>,
+
+    #[arg(
+        long = "p2p.experimental.max-failed-rounds",
+        long_help = "Number of times in a row a sync stream may go through all of its peers \
+                     without receiving a single block before it gives up and the sync is \
+                     restarted. If not provided, the streams keep retrying.",
+        value_name = "ROUNDS",
+        env = "PATHFINDER_P2P_EXPERIMENTAL_MAX_FAILED_ROUNDS"
+    )]
+    max_failed_rounds: Option<NonZeroUsize>,
 }
 
 #[cfg(feature = "p2p")]
@@ -706,6 +743,8 @@ pub struct Config {
     pub debug: DebugConfig,
     pub verify_tree_hashes: bool,
     pub rpc_batch_concurrency_limit: NonZeroUsize,
+    pub rpc_batch_size_limit: NonZeroUsize,
+    pub rpc_method_metrics: bool,
     pub is_sync_enabled: bool,
     pub is_rpc_enabled: bool,
     pub gateway_api_key: Option<String>,
@@ -754,6 +793,8 @@ pub struct P2PConfig {
     pub max_concurrent_streams: usize,
     pub direct_connection_timeout: Duration,
     pub eviction_timeout: Duration,
+    pub peer_store_file: Option<std::path::PathBuf>,
+    pub max_failed_rounds: Option<NonZeroUsize>,
 }
 
 #[cfg(not(feature = "p2p"))]
@@ -904,6 +945,8 @@ impl P2PConfig {
             max_concurrent_streams: args.max_concurrent_streams,
             direct_connection_timeout: Duration::from_secs(args.direct_connection_timeout.into()),
             eviction_timeout: Duration::from_secs(args.eviction_timeout.into()),
+            peer_store_file: args.peer_store_file,
+            max_failed_rounds: args.max_failed_rounds,
         }
     }
 }
@@ -997,6 +1040,8 @@ impl Config {
             debug: DebugConfig::parse(cli.debug),
             verify_tree_hashes: cli.verify_tree_node_data,
             rpc_batch_concurrency_limit: cli.rpc_batch_concurrency_limit,
+            rpc_batch_size_limit: cli.rpc_batch_size_limit,
+            rpc_method_metrics: cli.rpc_method_metrics,
             is_sync_enabled: cli.is_sync_enabled,
             is_rpc_enabled: cli.is_rpc_enabled,
             gateway_api_key: cli.gateway_api_key,
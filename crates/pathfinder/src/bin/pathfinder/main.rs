@@ -216,6 +216,7 @@ Hint: This is usually caused by exceeding the file descriptor limit of your syst
 
     let rpc_config = pathfinder_rpc::context::RpcConfig {
         batch_concurrency_limit: config.rpc_batch_concurrency_limit,
+        batch_size_limit: config.rpc_batch_size_limit,
         get_events_max_blocks_to_scan: config.get_events_max_blocks_to_scan,
         get_events_max_uncached_bloom_filters_to_load: config
             .get_events_max_uncached_bloom_filters_to_load,
@@ -250,7 +251,8 @@ Hint: This is usually caused by exceeding the file descriptor limit of your syst
         config::RpcVersion::V07 => pathfinder_rpc::RpcVersion::V07,
     };
 
-    let rpc_server = pathfinder_rpc::RpcServer::new(config.rpc_address, context, default_version);
+    let rpc_server = pathfinder_rpc::RpcServer::new(config.rpc_address, context, default_version)
+        .with_method_metrics(config.rpc_method_metrics);
     let rpc_server = match config.rpc_cors_domains {
         Some(ref allowed_origins) => rpc_server.with_cors(allowed_origins.clone()),
         None => rpc_server,
@@ -479,6 +481,8 @@ async fn start_p2p(
         listen_on: config.listen_on,
         bootstrap_addresses: config.bootstrap_addresses,
         predefined_peers: config.predefined_peers,
+        peer_store_file: config.peer_store_file,
+        max_failed_rounds: config.max_failed_rounds,
     };
 
     let (p2p_client, _head_receiver, p2p_handle) =
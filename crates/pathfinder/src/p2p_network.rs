@@ -1,5 +1,10 @@
+use std::num::NonZeroUsize;
+use std::sync::Arc;
+use std::time::Duration;
+
 use anyhow::Context;
 use p2p::client::peer_agnostic;
+use p2p::client::peer_agnostic::peer_store::FilePeerStore;
 use p2p::libp2p::identity::Keypair;
 use p2p::libp2p::multiaddr::Multiaddr;
 use p2p::{HeadRx, HeadTx};
@@ -12,6 +17,9 @@ mod sync_handlers;
 
 use sync_handlers::{get_classes, get_events, get_headers, get_state_diffs, get_transactions};
 
+/// How often peer scores are persisted, if a peer store file is configured.
+const PEER_STORE_SAVE_INTERVAL: Duration = Duration::from_secs(60);
+
 // Silence clippy
 pub type P2PNetworkHandle = (peer_agnostic::Client, HeadRx, tokio::task::JoinHandle<()>);
 
@@ -24,6 +32,8 @@ pub struct P2PContext {
     pub listen_on: Multiaddr,
     pub bootstrap_addresses: Vec<Multiaddr>,
     pub predefined_peers: Vec<Multiaddr>,
+    pub peer_store_file: Option<std::path::PathBuf>,
+    pub max_failed_rounds: Option<NonZeroUsize>,
 }
 
 #[tracing::instrument(name = "p2p", skip_all)]
@@ -37,6 +47,8 @@ pub async fn start(context: P2PContext) -> anyhow::Result<P2PNetworkHandle> {
         listen_on,
         bootstrap_addresses,
         predefined_peers,
+        peer_store_file,
+        max_failed_rounds,
     } = context;
 
     let peer_id = keypair.public().to_peer_id();
@@ -91,6 +103,9 @@ pub async fn start(context: P2PContext) -> anyhow::Result<P2PNetworkHandle> {
         tracing::info!(topic=%block_propagation_topic, "Subscribed to");
     }
 
+    let transaction_verifier =
+        crate::sync::transaction_commitment_verifier(storage.clone(), chain_id);
+    let state_diff_commitments = crate::sync::state_diff_commitment_lookup(storage.clone());
     let (mut tx, rx) = tokio::sync::watch::channel(None);
 
     let join_handle = {
@@ -115,11 +130,18 @@ pub async fn start(context: P2PContext) -> anyhow::Result<P2PNetworkHandle> {
         )
     };
 
-    Ok((
-        peer_agnostic::Client::new(p2p_client, block_propagation_topic),
-        rx,
-        join_handle,
-    ))
+    let mut client = peer_agnostic::Client::new(p2p_client, block_propagation_topic)
+        .with_transaction_commitment_verifier(transaction_verifier)
+        .with_state_diff_commitment_lookup(state_diff_commitments);
+    if let Some(path) = peer_store_file {
+        client =
+            client.with_peer_store(Arc::new(FilePeerStore::new(path)), PEER_STORE_SAVE_INTERVAL);
+    }
+    if let Some(rounds) = max_failed_rounds {
+        client = client.with_max_failed_rounds(rounds);
+    }
+
+    Ok((client, rx, join_handle))
 }
 
 async fn handle_p2p_event(
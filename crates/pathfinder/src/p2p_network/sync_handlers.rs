@@ -193,7 +193,7 @@ fn get_classes_for_block(
                     serde_json::from_slice::<class_definition::Cairo<'_>>(&definition)?;
                 Class::Cairo0 {
                     class: cairo_class.to_dto(),
-                    domain: 0, // TODO
+                    domain: VolitionDomain::L1, // TODO
                 }
             }
             ClassDefinition::Sierra {
@@ -204,7 +204,7 @@ fn get_classes_for_block(
 
                 Class::Cairo1 {
                     class: sierra_class.to_dto(),
-                    domain: 0, // TODO
+                    domain: VolitionDomain::L1, // TODO
                 }
             }
         };
@@ -365,7 +365,7 @@ mod prop {
                     actual_cairo.push(CairoDefinition::try_from_dto(class).unwrap().0);
                 },
                 ClassesResponse::Class(Class::Cairo1 { class, domain: _ }) => {
-                    let SierraDefinition(sierra) = SierraDefinition::try_from_dto(class).unwrap();
+                    let SierraDefinition(sierra, _) = SierraDefinition::try_from_dto(class).unwrap();
                     actual_sierra.push(sierra);
                 },
                 _ => panic!("unexpected response"),
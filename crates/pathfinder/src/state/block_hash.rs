@@ -3,7 +3,7 @@ use std::sync::LazyLock;
 
 use anyhow::{Context, Result};
 use pathfinder_common::event::Event;
-use pathfinder_common::hash::{FeltHash, PedersenHash, PoseidonHash};
+use pathfinder_common::hash::{FeltHash, PoseidonHash};
 use pathfinder_common::receipt::{ExecutionStatus, Receipt};
 use pathfinder_common::transaction::{Transaction, TransactionVariant};
 use pathfinder_common::{
@@ -32,6 +32,8 @@ use pathfinder_merkle_tree::TransactionOrEventTree;
 use sha3::Digest;
 use starknet_gateway_types::reply::Block;
 
+pub mod commitment;
+
 const V_0_11_1: StarknetVersion = StarknetVersion::new(0, 11, 1, 0);
 
 #[derive(Debug, PartialEq, Eq)]
@@ -454,26 +456,7 @@ pub fn calculate_transaction_commitment(
     transactions: &[Transaction],
     version: StarknetVersion,
 ) -> Result<TransactionCommitment> {
-    use rayon::prelude::*;
-
-    let final_hashes = transactions
-        .par_iter()
-        .map(|tx| {
-            if version < V_0_11_1 {
-                calculate_transaction_hash_with_signature_pre_0_11_1(tx)
-            } else if version < StarknetVersion::V_0_13_2 {
-                calculate_transaction_hash_with_signature_pre_0_13_2(tx)
-            } else {
-                calculate_transaction_hash_with_signature(tx)
-            }
-        })
-        .collect();
-
-    if version < StarknetVersion::V_0_13_2 {
-        calculate_commitment_root::<PedersenHash>(final_hashes).map(TransactionCommitment)
-    } else {
-        calculate_commitment_root::<PoseidonHash>(final_hashes).map(TransactionCommitment)
-    }
+    commitment::for_version(version).transaction_commitment(transactions)
 }
 
 pub fn calculate_receipt_commitment(receipts: &[Receipt]) -> Result<ReceiptCommitment> {
@@ -657,25 +640,7 @@ pub fn calculate_event_commitment(
     transaction_events: &[(TransactionHash, &[Event])],
     version: StarknetVersion,
 ) -> Result<EventCommitment> {
-    use rayon::prelude::*;
-
-    let event_hashes = transaction_events
-        .par_iter()
-        .flat_map(|(tx_hash, events)| events.par_iter().map(|e| (*tx_hash, e)))
-        .map(|(tx_hash, e)| {
-            if version < StarknetVersion::V_0_13_2 {
-                calculate_event_hash_pre_0_13_2(e)
-            } else {
-                calculate_event_hash(e, tx_hash)
-            }
-        })
-        .collect();
-
-    if version < StarknetVersion::V_0_13_2 {
-        calculate_commitment_root::<PedersenHash>(event_hashes).map(EventCommitment)
-    } else {
-        calculate_commitment_root::<PoseidonHash>(event_hashes).map(EventCommitment)
-    }
+    commitment::for_version(version).event_commitment(transaction_events)
 }
 
 /// Calculate the hash of a pre-v0.13.2 Starknet event.
@@ -0,0 +1,262 @@
+//! Version dependent block commitment algorithms.
+//!
+//! The way transaction and event commitments are computed has changed a few
+//! times over Starknet's history. Each algorithm is captured by a
+//! [CommitmentScheme] implementation and [for_version] selects the one that
+//! applies to a given [StarknetVersion]. Supporting a new algorithm therefore
+//! means adding an implementation and extending [for_version], instead of
+//! adding yet another version check to each verification site.
+
+use anyhow::Result;
+use pathfinder_common::event::Event;
+use pathfinder_common::hash::{PedersenHash, PoseidonHash};
+use pathfinder_common::state_update::StateUpdateData;
+use pathfinder_common::transaction::Transaction;
+use pathfinder_common::{
+    EventCommitment,
+    StarknetVersion,
+    StateDiffCommitment,
+    TransactionCommitment,
+    TransactionHash,
+};
+
+use super::{
+    calculate_commitment_root,
+    calculate_event_hash,
+    calculate_event_hash_pre_0_13_2,
+    calculate_transaction_hash_with_signature,
+    calculate_transaction_hash_with_signature_pre_0_11_1,
+    calculate_transaction_hash_with_signature_pre_0_13_2,
+    V_0_11_1,
+};
+
+pub trait CommitmentScheme: Send + Sync {
+    /// Root of the transaction tree, built from the transaction hashes
+    /// combined with their signatures.
+    fn transaction_commitment(&self, transactions: &[Transaction])
+        -> Result<TransactionCommitment>;
+
+    /// Root of the event tree. Events are expected in the order in which they
+    /// were emitted within the block.
+    fn event_commitment(
+        &self,
+        transaction_events: &[(TransactionHash, &[Event])],
+    ) -> Result<EventCommitment>;
+
+    /// The state diff commitment has not changed since its introduction in
+    /// Starknet 0.13.2.
+    fn state_diff_commitment(&self, state_diff: &StateUpdateData) -> StateDiffCommitment {
+        state_diff.compute_state_diff_commitment()
+    }
+}
+
+/// Returns the commitment scheme used by blocks of the given version.
+pub fn for_version(version: StarknetVersion) -> &'static dyn CommitmentScheme {
+    if version < V_0_11_1 {
+        &PreV0_11_1
+    } else if version < StarknetVersion::V_0_13_2 {
+        &V0_13_1
+    } else {
+        &V0_13_2
+    }
+}
+
+/// Blocks before Starknet 0.11.1, where only invoke transactions contribute
+/// their signature to the commitment.
+#[derive(Debug, Clone, Copy)]
+pub struct PreV0_11_1;
+
+/// Blocks from Starknet 0.11.1 up to and including 0.13.1.
+#[derive(Debug, Clone, Copy)]
+pub struct V0_13_1;
+
+/// Blocks from Starknet 0.13.2 onwards, as well as all commitments exchanged
+/// via p2p.
+#[derive(Debug, Clone, Copy)]
+pub struct V0_13_2;
+
+impl CommitmentScheme for PreV0_11_1 {
+    fn transaction_commitment(
+        &self,
+        transactions: &[Transaction],
+    ) -> Result<TransactionCommitment> {
+        use rayon::prelude::*;
+
+        let final_hashes = transactions
+            .par_iter()
+            .map(calculate_transaction_hash_with_signature_pre_0_11_1)
+            .collect();
+
+        calculate_commitment_root::<PedersenHash>(final_hashes).map(TransactionCommitment)
+    }
+
+    fn event_commitment(
+        &self,
+        transaction_events: &[(TransactionHash, &[Event])],
+    ) -> Result<EventCommitment> {
+        V0_13_1.event_commitment(transaction_events)
+    }
+}
+
+impl CommitmentScheme for V0_13_1 {
+    fn transaction_commitment(
+        &self,
+        transactions: &[Transaction],
+    ) -> Result<TransactionCommitment> {
+        use rayon::prelude::*;
+
+        let final_hashes = transactions
+            .par_iter()
+            .map(calculate_transaction_hash_with_signature_pre_0_13_2)
+            .collect();
+
+        calculate_commitment_root::<PedersenHash>(final_hashes).map(TransactionCommitment)
+    }
+
+    fn event_commitment(
+        &self,
+        transaction_events: &[(TransactionHash, &[Event])],
+    ) -> Result<EventCommitment> {
+        use rayon::prelude::*;
+
+        let event_hashes = transaction_events
+            .par_iter()
+            .flat_map(|(_, events)| events.par_iter())
+            .map(calculate_event_hash_pre_0_13_2)
+            .collect();
+
+        calculate_commitment_root::<PedersenHash>(event_hashes).map(EventCommitment)
+    }
+}
+
+impl CommitmentScheme for V0_13_2 {
+    fn transaction_commitment(
+        &self,
+        transactions: &[Transaction],
+    ) -> Result<TransactionCommitment> {
+        use rayon::prelude::*;
+
+        let final_hashes = transactions
+            .par_iter()
+            .map(calculate_transaction_hash_with_signature)
+            .collect();
+
+        calculate_commitment_root::<PoseidonHash>(final_hashes).map(TransactionCommitment)
+    }
+
+    fn event_commitment(
+        &self,
+        transaction_events: &[(TransactionHash, &[Event])],
+    ) -> Result<EventCommitment> {
+        use rayon::prelude::*;
+
+        let event_hashes = transaction_events
+            .par_iter()
+            .flat_map(|(tx_hash, events)| events.par_iter().map(|e| (*tx_hash, e)))
+            .map(|(tx_hash, e)| calculate_event_hash(e, tx_hash))
+            .collect();
+
+        calculate_commitment_root::<PoseidonHash>(event_hashes).map(EventCommitment)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pathfinder_common::macro_prelude::*;
+    use pathfinder_common::transaction::{InvokeTransactionV3, TransactionVariant};
+    use pathfinder_common::{felt, ContractAddress, EventData, EventKey, TransactionSignatureElem};
+    use pathfinder_crypto::Felt;
+    use starknet_gateway_types::reply::Block;
+
+    use super::*;
+
+    /// Computes both commitments of a gateway block using the scheme selected
+    /// for its version.
+    fn block_commitments(json: &str) -> (Block, TransactionCommitment, EventCommitment) {
+        let block: Block = serde_json::from_str(json).unwrap();
+        let scheme = for_version(block.starknet_version);
+
+        let transaction_commitment = scheme.transaction_commitment(&block.transactions).unwrap();
+        let events = block
+            .transactions
+            .iter()
+            .zip(&block.transaction_receipts)
+            .map(|(tx, (_, events))| (tx.hash, events.as_slice()))
+            .collect::<Vec<_>>();
+        let event_commitment = scheme.event_commitment(&events).unwrap();
+
+        (block, transaction_commitment, event_commitment)
+    }
+
+    #[test]
+    fn pre_0_11_1() {
+        let (block, transaction_commitment, event_commitment) =
+            block_commitments(starknet_gateway_test_fixtures::v0_9_0::block::MAINNET_2800);
+
+        assert_eq!(transaction_commitment, block.transaction_commitment);
+        assert_eq!(event_commitment, block.event_commitment);
+    }
+
+    #[test]
+    fn v0_13_1() {
+        let (block, transaction_commitment, event_commitment) =
+            block_commitments(starknet_gateway_test_fixtures::v0_11_1::block::MAINNET_65000);
+
+        assert_eq!(transaction_commitment, block.transaction_commitment);
+        assert_eq!(event_commitment, block.event_commitment);
+    }
+
+    /// Source:
+    /// https://github.com/starkware-libs/starknet-api/blob/5565e5282f5fead364a41e49c173940fd83dee00/src/block_hash/transaction_commitment_test.rs#L32.
+    #[test]
+    fn v0_13_2_transaction_commitment() {
+        let transaction = Transaction {
+            hash: TransactionHash(Felt::ONE),
+            variant: TransactionVariant::InvokeV3(InvokeTransactionV3 {
+                signature: vec![
+                    TransactionSignatureElem(Felt::from_u64(2)),
+                    TransactionSignatureElem(Felt::from_u64(3)),
+                ],
+                ..Default::default()
+            }),
+        };
+        let expected = TransactionCommitment(felt!(
+            "0x0282b635972328bd1cfa86496fe920d20bd9440cd78ee8dc90ae2b383d664dcf"
+        ));
+        assert_eq!(
+            V0_13_2
+                .transaction_commitment(&[transaction.clone(), transaction])
+                .unwrap(),
+            expected
+        );
+    }
+
+    /// Source:
+    /// https://github.com/starkware-libs/starknet-api/blob/5565e5282f5fead364a41e49c173940fd83dee00/src/block_hash/event_commitment_test.rs#L10.
+    #[test]
+    fn v0_13_2_event_commitment() {
+        let events = (0..3)
+            .map(|seed| Event {
+                from_address: ContractAddress(Felt::from_u64(seed + 8)),
+                keys: [seed, seed + 1]
+                    .into_iter()
+                    .map(|key| EventKey(Felt::from(key)))
+                    .collect(),
+                data: [seed + 2, seed + 3, seed + 4]
+                    .into_iter()
+                    .map(Felt::from)
+                    .map(EventData)
+                    .collect(),
+            })
+            .collect::<Vec<_>>();
+        let expected = EventCommitment(felt!(
+            "0x069bb140ddbbeb01d81c7201ecfb933031306e45dab9c77ff9f9ba3cd4c2b9c3"
+        ));
+        assert_eq!(
+            V0_13_2
+                .event_commitment(&[(transaction_hash!("0x1234"), &events)])
+                .unwrap(),
+            expected
+        );
+    }
+}
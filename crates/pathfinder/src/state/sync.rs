@@ -692,7 +692,15 @@ async fn consumer(
             }
             L1ToL2Message(msg) => {
                 tracing::trace!("Got a new L1 to L2 message log: {:?}", msg);
-                // todo!()
+                tokio::task::block_in_place(|| {
+                    let tx = db_conn
+                        .transaction_with_behavior(TransactionBehavior::Immediate)
+                        .context("Creating database transaction")?;
+                    tx.insert_l1_to_l2_message_log(&msg)
+                        .context("Inserting L1 to L2 message log")?;
+                    tx.commit().context("Committing database transaction")
+                })
+                .with_context(|| format!("Insert L1 to L2 message log for {:?}", msg.l1_tx_hash))?;
             }
         }
     }
@@ -1036,7 +1044,7 @@ async fn l2_reorg(
             .transaction_with_behavior(TransactionBehavior::Immediate)
             .context("Create database transaction")?;
 
-        let mut head = transaction
+        let head = transaction
             .block_id(pathfinder_storage::BlockId::Latest)
             .context("Querying latest block number")?
             .context("Latest block number is none during reorg")?
@@ -1075,17 +1083,18 @@ async fn l2_reorg(
         // This is acceptable performance because reorgs are rare and need not be
         // 100% optimal. However a large reorg could cause a massive memory spike
         // which is not acceptable.
-        while head >= reorg_tail {
+        let mut block = head;
+        while block >= reorg_tail {
             transaction
-                .purge_block(head)
-                .with_context(|| format!("Purging block {head} from database"))?;
+                .purge_block(block)
+                .with_context(|| format!("Purging block {block} from database"))?;
 
             // No further blocks to purge if we just purged genesis.
-            if head == BlockNumber::GENESIS {
+            if block == BlockNumber::GENESIS {
                 break;
             }
 
-            head -= 1;
+            block -= 1;
         }
 
         // Track combined L1 and L2 state.
@@ -1241,18 +1250,21 @@ pub fn update_starknet_state(
     }
     .with_verify_hashes(verify_hashes);
 
+    let mut leaves = Vec::with_capacity(state_update.declared_sierra_classes.len());
     for (sierra, casm) in state_update.declared_sierra_classes {
-        let leaf_hash = pathfinder_common::calculate_class_commitment_leaf_hash(*casm);
+        let leaf_hash = pathfinder_merkle_tree::class::class_commitment_leaf_hash(*casm);
 
         transaction
             .insert_class_commitment_leaf(block, &leaf_hash, casm)
             .context("Adding class commitment leaf")?;
 
-        class_commitment_tree
-            .set(*sierra, leaf_hash)
-            .context("Update class commitment tree")?;
+        leaves.push((*sierra, leaf_hash));
     }
 
+    class_commitment_tree
+        .set_batch(leaves)
+        .context("Update class commitment tree")?;
+
     // Apply all class commitment tree changes.
     let (class_commitment, trie_update) = class_commitment_tree
         .commit()
@@ -1291,7 +1303,7 @@ mod tests {
         TransactionCommitment,
     };
     use pathfinder_crypto::Felt;
-    use pathfinder_rpc::SyncState;
+    use pathfinder_rpc::{Notifications, SyncState};
     use pathfinder_storage::StorageBuilder;
     use starknet_gateway_types::reply::{self, Block, GasPrices};
 
@@ -1457,7 +1469,11 @@ mod tests {
         let (event_tx, event_rx) = tokio::sync::mpsc::channel(100);
 
         // Send block updates, followed by a reorg to genesis.
-        for (a, b, c, d, e) in generate_block_data() {
+        let blocks = generate_block_data();
+        let first_hash = blocks[2].0 .0.block_hash;
+        let last_hash = blocks.last().unwrap().0 .0.block_hash;
+        let last_number = blocks.last().unwrap().0 .0.block_number;
+        for (a, b, c, d, e) in blocks {
             event_tx
                 .send(SyncEvent::Block(a, b, c, d, e))
                 .await
@@ -1470,6 +1486,9 @@ mod tests {
         // Close the event channel which allows the consumer task to exit.
         drop(event_tx);
 
+        let notifications = Notifications::default();
+        let mut reorgs = notifications.reorgs.subscribe();
+
         let (tx, _rx) = tokio::sync::watch::channel(Default::default());
         let context = ConsumerContext {
             storage,
@@ -1477,12 +1496,18 @@ mod tests {
             pending_data: tx,
             verify_tree_hashes: false,
             websocket_txs: None,
-            notifications: Default::default(),
+            notifications,
         };
 
         let (tx, _rx) = tokio::sync::watch::channel(Default::default());
         consumer(event_rx, context, tx).await.unwrap();
 
+        let reorg = reorgs.try_recv().unwrap();
+        assert_eq!(reorg.first_block_number, BlockNumber::new_or_panic(2));
+        assert_eq!(reorg.first_block_hash, first_hash);
+        assert_eq!(reorg.last_block_number, last_number);
+        assert_eq!(reorg.last_block_hash, last_hash);
+
         let tx = connection.transaction().unwrap();
         let genesis_exists = tx.block_exists(BlockNumber::GENESIS.into()).unwrap();
         assert!(genesis_exists);
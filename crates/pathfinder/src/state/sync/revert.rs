@@ -132,7 +132,7 @@ fn revert_class_updates(
             Some(casm_hash) => {
                 // Class hash has changed. Note that the class commitment leaf must have already
                 // been added to storage.
-                pathfinder_common::calculate_class_commitment_leaf_hash(casm_hash)
+                pathfinder_merkle_tree::class::class_commitment_leaf_hash(casm_hash)
             }
         };
 
@@ -24,6 +24,7 @@ use stream::ProcessStage;
 use tokio::sync::watch::{self, Receiver};
 use tokio_stream::wrappers::WatchStream;
 
+mod block;
 mod checkpoint;
 mod class_definitions;
 mod error;
@@ -35,6 +36,9 @@ mod stream;
 mod track;
 mod transactions;
 
+pub(crate) use state_updates::commitment_lookup as state_diff_commitment_lookup;
+pub(crate) use transactions::commitment_verifier as transaction_commitment_verifier;
+
 const CHECKPOINT_MARGIN: u64 = 10;
 
 pub struct Sync {
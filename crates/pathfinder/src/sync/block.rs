@@ -0,0 +1,390 @@
+use std::sync::Arc;
+
+use p2p::client::types::EventsForBlockByTransaction;
+use pathfinder_common::receipt::Receipt;
+use pathfinder_common::state_update::StateUpdateData;
+use pathfinder_common::transaction::Transaction;
+use pathfinder_common::{
+    BlockHash,
+    Chain,
+    ChainId,
+    EventCommitment,
+    ReceiptCommitment,
+    SignedBlockHeader,
+    StarknetVersion,
+    StateDiffCommitment,
+    TransactionCommitment,
+};
+
+use crate::state::block_hash::{
+    calculate_receipt_commitment,
+    commitment,
+    verify_block_hash,
+    BlockHeaderData,
+};
+use crate::sync::error::SyncError2;
+
+#[derive(Debug, thiserror::Error)]
+pub(super) enum BlockVerificationError {
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+    #[error("Transaction count mismatch: expected {expected}, got {actual}")]
+    TransactionCountMismatch { expected: usize, actual: usize },
+    #[error("Transaction commitment mismatch: expected {expected}, computed {computed}")]
+    TransactionCommitmentMismatch {
+        expected: TransactionCommitment,
+        computed: TransactionCommitment,
+    },
+    #[error("Receipt commitment mismatch: expected {expected}, computed {computed}")]
+    ReceiptCommitmentMismatch {
+        expected: ReceiptCommitment,
+        computed: ReceiptCommitment,
+    },
+    #[error("State diff length mismatch: expected {expected}, got {actual}")]
+    StateDiffLengthMismatch { expected: u64, actual: u64 },
+    #[error("State diff commitment mismatch: expected {expected}, computed {computed}")]
+    StateDiffCommitmentMismatch {
+        expected: StateDiffCommitment,
+        computed: StateDiffCommitment,
+    },
+    #[error("Event count mismatch: expected {expected}, got {actual}")]
+    EventCountMismatch { expected: usize, actual: usize },
+    #[error("Event commitment mismatch: expected {expected}, computed {computed}")]
+    EventCommitmentMismatch {
+        expected: EventCommitment,
+        computed: EventCommitment,
+    },
+    #[error("Block hash mismatch for {0}")]
+    BlockHashMismatch(BlockHash),
+}
+
+impl From<BlockVerificationError> for SyncError2 {
+    fn from(e: BlockVerificationError) -> Self {
+        match e {
+            BlockVerificationError::Other(e) => SyncError2::Other(Arc::new(e)),
+            BlockVerificationError::TransactionCountMismatch { expected, actual } => {
+                if actual < expected {
+                    SyncError2::TooFewTransactions
+                } else {
+                    SyncError2::TooManyTransactions
+                }
+            }
+            BlockVerificationError::TransactionCommitmentMismatch { .. }
+            | BlockVerificationError::ReceiptCommitmentMismatch { .. } => {
+                SyncError2::TransactionCommitmentMismatch
+            }
+            BlockVerificationError::StateDiffLengthMismatch { .. } => {
+                SyncError2::IncorrectStateDiffCount
+            }
+            BlockVerificationError::StateDiffCommitmentMismatch { .. } => {
+                SyncError2::StateDiffCommitmentMismatch
+            }
+            BlockVerificationError::EventCountMismatch { expected, actual } => {
+                if actual < expected {
+                    SyncError2::TooFewEvents
+                } else {
+                    SyncError2::TooManyEvents
+                }
+            }
+            BlockVerificationError::EventCommitmentMismatch { .. } => {
+                SyncError2::EventCommitmentMismatch
+            }
+            BlockVerificationError::BlockHashMismatch(_) => SyncError2::BadBlockHash,
+        }
+    }
+}
+
+/// Verifies all the components of a block received from p2p against its
+/// header.
+///
+/// Commitments received via p2p are always computed using the 0.13.2
+/// algorithms, so the block hash can only be recomputed from them for blocks
+/// from Starknet 0.13.2 onwards. For older blocks the caller is expected to
+/// have verified the header signature instead.
+///
+/// The header's signature is __not__ checked here.
+pub(super) fn verify_block(
+    header: &SignedBlockHeader,
+    transactions: &[(Transaction, Receipt)],
+    state_diff: &StateUpdateData,
+    events: &EventsForBlockByTransaction,
+    chain: Chain,
+    chain_id: ChainId,
+) -> Result<(), BlockVerificationError> {
+    let header = &header.header;
+    let scheme = commitment::for_version(header.starknet_version.max(StarknetVersion::V_0_13_2));
+
+    if transactions.len() != header.transaction_count {
+        return Err(BlockVerificationError::TransactionCountMismatch {
+            expected: header.transaction_count,
+            actual: transactions.len(),
+        });
+    }
+
+    let txns = transactions
+        .iter()
+        .map(|(t, _)| t.clone())
+        .collect::<Vec<_>>();
+    let computed = scheme.transaction_commitment(&txns)?;
+    if computed != header.transaction_commitment {
+        return Err(BlockVerificationError::TransactionCommitmentMismatch {
+            expected: header.transaction_commitment,
+            computed,
+        });
+    }
+
+    // Receipt commitments are not part of older headers.
+    if header.starknet_version >= StarknetVersion::V_0_13_2 {
+        let receipts = transactions
+            .iter()
+            .map(|(_, r)| r.clone())
+            .collect::<Vec<_>>();
+        let computed = calculate_receipt_commitment(&receipts)?;
+        if computed != header.receipt_commitment {
+            return Err(BlockVerificationError::ReceiptCommitmentMismatch {
+                expected: header.receipt_commitment,
+                computed,
+            });
+        }
+    }
+
+    let actual = u64::try_from(state_diff.state_diff_length()).expect("ptr size is 64bits");
+    if actual != header.state_diff_length {
+        return Err(BlockVerificationError::StateDiffLengthMismatch {
+            expected: header.state_diff_length,
+            actual,
+        });
+    }
+
+    let computed = scheme.state_diff_commitment(state_diff);
+    if computed != header.state_diff_commitment {
+        return Err(BlockVerificationError::StateDiffCommitmentMismatch {
+            expected: header.state_diff_commitment,
+            computed,
+        });
+    }
+
+    let (_, events) = events;
+    let actual = events.iter().map(|(_, e)| e.len()).sum::<usize>();
+    if actual != header.event_count {
+        return Err(BlockVerificationError::EventCountMismatch {
+            expected: header.event_count,
+            actual,
+        });
+    }
+
+    let computed = scheme.event_commitment(
+        &events
+            .iter()
+            .map(|(tx_hash, events)| (*tx_hash, events.as_slice()))
+            .collect::<Vec<_>>(),
+    )?;
+    if computed != header.event_commitment {
+        return Err(BlockVerificationError::EventCommitmentMismatch {
+            expected: header.event_commitment,
+            computed,
+        });
+    }
+
+    if header.starknet_version >= StarknetVersion::V_0_13_2
+        && !verify_block_hash(BlockHeaderData::from_header(header), chain, chain_id)?.is_match()
+    {
+        return Err(BlockVerificationError::BlockHashMismatch(header.hash));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_matches::assert_matches;
+    use pathfinder_common::BlockHeader;
+    use pathfinder_storage::fake::init::Config;
+    use pathfinder_storage::fake::{self, Block};
+
+    use super::*;
+    use crate::state::block_hash::{
+        calculate_event_commitment,
+        calculate_transaction_commitment,
+        compute_final_hash,
+    };
+
+    struct Input {
+        header: SignedBlockHeader,
+        transactions: Vec<(Transaction, Receipt)>,
+        state_diff: StateUpdateData,
+        events: EventsForBlockByTransaction,
+    }
+
+    impl Input {
+        fn verify(&self) -> Result<(), BlockVerificationError> {
+            verify_block(
+                &self.header,
+                &self.transactions,
+                &self.state_diff,
+                &self.events,
+                Chain::SepoliaTestnet,
+                ChainId::SEPOLIA_TESTNET,
+            )
+        }
+    }
+
+    /// A 0.13.2 block with all commitments and the block hash set correctly.
+    fn valid_block() -> Input {
+        let Block {
+            header,
+            transaction_data,
+            state_update,
+            ..
+        } = fake::init::with_n_blocks_and_config(
+            1,
+            Config {
+                calculate_block_hash: Box::new(|header: &BlockHeader| {
+                    compute_final_hash(&BlockHeaderData::from_header(header))
+                }),
+                calculate_transaction_commitment: Box::new(calculate_transaction_commitment),
+                calculate_receipt_commitment: Box::new(calculate_receipt_commitment),
+                calculate_event_commitment: Box::new(calculate_event_commitment),
+            },
+        )
+        .pop()
+        .unwrap();
+
+        let events = (
+            header.header.number,
+            transaction_data
+                .iter()
+                .map(|(t, _, e)| (t.hash, e.clone()))
+                .collect(),
+        );
+        let transactions = transaction_data
+            .into_iter()
+            .map(|(t, r, _)| (t, r))
+            .collect();
+
+        Input {
+            header,
+            transactions,
+            state_diff: state_update.into(),
+            events,
+        }
+    }
+
+    #[test]
+    fn valid_block_passes() {
+        valid_block().verify().unwrap();
+    }
+
+    #[test]
+    fn transaction_count_mismatch() {
+        let mut block = valid_block();
+        block.transactions.pop();
+
+        let error = block.verify().unwrap_err();
+        assert_matches!(
+            error,
+            BlockVerificationError::TransactionCountMismatch { expected, actual } if actual + 1 == expected
+        );
+        assert_matches!(SyncError2::from(error), SyncError2::TooFewTransactions);
+    }
+
+    #[test]
+    fn transaction_commitment_mismatch() {
+        let mut block = valid_block();
+        block.header.header.transaction_commitment = TransactionCommitment::ZERO;
+
+        let error = block.verify().unwrap_err();
+        assert_matches!(
+            error,
+            BlockVerificationError::TransactionCommitmentMismatch { .. }
+        );
+        assert_matches!(
+            SyncError2::from(error),
+            SyncError2::TransactionCommitmentMismatch
+        );
+    }
+
+    #[test]
+    fn receipt_commitment_mismatch() {
+        let mut block = valid_block();
+        block.header.header.receipt_commitment = ReceiptCommitment::ZERO;
+
+        let error = block.verify().unwrap_err();
+        assert_matches!(
+            error,
+            BlockVerificationError::ReceiptCommitmentMismatch { .. }
+        );
+        assert_matches!(
+            SyncError2::from(error),
+            SyncError2::TransactionCommitmentMismatch
+        );
+    }
+
+    #[test]
+    fn state_diff_length_mismatch() {
+        let mut block = valid_block();
+        block.header.header.state_diff_length += 1;
+
+        let error = block.verify().unwrap_err();
+        assert_matches!(
+            error,
+            BlockVerificationError::StateDiffLengthMismatch { .. }
+        );
+        assert_matches!(SyncError2::from(error), SyncError2::IncorrectStateDiffCount);
+    }
+
+    #[test]
+    fn state_diff_commitment_mismatch() {
+        let mut block = valid_block();
+        block.header.header.state_diff_commitment = StateDiffCommitment::ZERO;
+
+        let error = block.verify().unwrap_err();
+        assert_matches!(
+            error,
+            BlockVerificationError::StateDiffCommitmentMismatch { .. }
+        );
+        assert_matches!(
+            SyncError2::from(error),
+            SyncError2::StateDiffCommitmentMismatch
+        );
+    }
+
+    #[test]
+    fn event_count_mismatch() {
+        let mut block = valid_block();
+        block.header.header.event_count += 1;
+
+        let error = block.verify().unwrap_err();
+        assert_matches!(
+            error,
+            BlockVerificationError::EventCountMismatch { expected, actual } if actual + 1 == expected
+        );
+        assert_matches!(SyncError2::from(error), SyncError2::TooFewEvents);
+    }
+
+    #[test]
+    fn event_commitment_mismatch() {
+        let mut block = valid_block();
+        block.header.header.event_commitment = EventCommitment::ZERO;
+
+        let error = block.verify().unwrap_err();
+        assert_matches!(
+            error,
+            BlockVerificationError::EventCommitmentMismatch { .. }
+        );
+        assert_matches!(SyncError2::from(error), SyncError2::EventCommitmentMismatch);
+    }
+
+    #[test]
+    fn block_hash_mismatch() {
+        let mut block = valid_block();
+        block.header.header.hash = BlockHash::ZERO;
+
+        let error = block.verify().unwrap_err();
+        assert_matches!(
+            error,
+            BlockVerificationError::BlockHashMismatch(hash) if hash == BlockHash::ZERO
+        );
+        assert_matches!(SyncError2::from(error), SyncError2::BadBlockHash);
+    }
+}
@@ -15,7 +15,12 @@ use p2p::client::peer_agnostic::traits::{
     TransactionStream,
 };
 use p2p::client::peer_agnostic::Client as P2PClient;
-use p2p::client::types::{ClassDefinition, EventsForBlockByTransaction, TransactionData};
+use p2p::client::types::{
+    ClassDefinition,
+    ClassFilter,
+    EventsForBlockByTransaction,
+    TransactionData,
+};
 use p2p::PeerData;
 use p2p_proto::common::{BlockNumberOrHash, Direction, Iteration};
 use p2p_proto::transaction::{TransactionWithReceipt, TransactionsRequest, TransactionsResponse};
@@ -45,7 +50,7 @@ use tracing::Instrument;
 use super::error::SyncError2;
 use crate::state::block_hash::calculate_transaction_commitment;
 use crate::sync::error::SyncError;
-use crate::sync::stream::{InfallibleSource, Source, SyncReceiver, SyncResult};
+use crate::sync::stream::{Source, SyncReceiver, SyncResult};
 use crate::sync::{class_definitions, events, headers, state_updates, transactions};
 
 #[cfg(test)]
@@ -166,7 +171,9 @@ impl Sync {
             tracing::info!(?gap, "Syncing headers");
 
             handle_header_stream(
-                self.p2p.clone().header_stream(gap.tail, gap.head, true),
+                self.p2p
+                    .clone()
+                    .header_stream(gap.tail, gap.head, true, None),
                 gap.head(),
                 self.chain,
                 self.chain_id,
@@ -196,6 +203,9 @@ impl Sync {
         let transaction_stream = self.p2p.clone().transaction_stream(
             start,
             stop,
+            false,
+            None,
+            false,
             transactions::counts_stream(
                 self.storage.clone(),
                 start,
@@ -226,6 +236,9 @@ impl Sync {
         let stream = self.p2p.clone().state_diff_stream(
             start,
             stop,
+            false,
+            None,
+            false,
             state_updates::state_diff_length_stream(
                 self.storage.clone(),
                 start,
@@ -251,12 +264,15 @@ impl Sync {
         let class_stream = self.p2p.clone().class_stream(
             start,
             stop,
+            false,
+            None,
             class_definitions::declared_class_counts_stream(
                 self.storage.clone(),
                 start,
                 stop,
                 NonZeroUsize::new(100).expect("100>0"),
             ),
+            ClassFilter::default(),
         );
 
         handle_class_stream(
@@ -283,6 +299,9 @@ impl Sync {
         let event_stream = self.p2p.clone().event_stream(
             start,
             stop,
+            false,
+            None,
+            false,
             events::counts_stream(
                 self.storage.clone(),
                 start,
@@ -298,7 +317,7 @@ impl Sync {
 }
 
 async fn handle_header_stream(
-    stream: impl Stream<Item = PeerData<SignedBlockHeader>> + Send + 'static,
+    stream: impl Stream<Item = StreamItem<SignedBlockHeader>> + Send + 'static,
     head: (BlockNumber, BlockHash),
     chain: Chain,
     chain_id: ChainId,
@@ -306,7 +325,7 @@ async fn handle_header_stream(
     block_hash_db: Option<pathfinder_block_hashes::BlockHashDb>,
     storage: Storage,
 ) -> Result<(), SyncError> {
-    InfallibleSource::from_stream(stream)
+    Source::from_stream(stream.map_err(|e| e.map(Into::into)))
         .spawn()
         .pipe(headers::BackwardContinuity::new(head.0, head.1), 10)
         .pipe(
@@ -695,6 +714,8 @@ async fn persist_anchor(storage: Storage, anchor: EthereumStateUpdate) -> anyhow
 
 #[cfg(test)]
 mod tests {
+    use p2p::client::types::StreamError;
+
     use super::*;
 
     mod handle_header_stream {
@@ -831,7 +852,7 @@ mod tests {
             } = setup().await;
 
             handle_header_stream(
-                stream::iter(streamed_headers),
+                stream::iter(streamed_headers).map(Ok),
                 head,
                 Chain::SepoliaTestnet,
                 ChainId::SEPOLIA_TESTNET,
@@ -878,7 +899,7 @@ mod tests {
 
             assert_matches!(
                 handle_header_stream(
-                    stream::iter(streamed_headers),
+                    stream::iter(streamed_headers).map(Ok),
                     head,
                     Chain::SepoliaTestnet,
                     ChainId::SEPOLIA_TESTNET,
@@ -905,7 +926,7 @@ mod tests {
 
             assert_matches!(
                 handle_header_stream(
-                    stream::iter(streamed_headers),
+                    stream::iter(streamed_headers).map(Ok),
                     head,
                     // Causes mismatches for all block hashes because setup assumes Sepolia
                     Chain::Mainnet,
@@ -931,7 +952,7 @@ mod tests {
 
         //     assert_matches!(
         //         handle_header_stream(
-        //             stream::iter(streamed_headers),
+        //             stream::iter(streamed_headers).map(Ok),
         //             head,
         //             Chain::SepoliaTestnet,
         //             ChainId::SEPOLIA_TESTNET,
@@ -964,7 +985,7 @@ mod tests {
 
             assert_matches!(
                 handle_header_stream(
-                    stream::iter(streamed_headers),
+                    stream::iter(streamed_headers).map(Ok),
                     head,
                     Chain::SepoliaTestnet,
                     ChainId::SEPOLIA_TESTNET,
@@ -1129,7 +1150,7 @@ mod tests {
             assert_matches!(
                 handle_transaction_stream(
                     stream::once(std::future::ready(Err(PeerData::for_tests(
-                        anyhow::anyhow!("")
+                        StreamError::Other(anyhow::anyhow!(""))
                     )))),
                     StorageBuilder::in_memory().unwrap(),
                     ChainId::SEPOLIA_TESTNET,
@@ -1298,7 +1319,7 @@ mod tests {
             assert_matches!(
                 handle_state_diff_stream(
                     stream::once(std::future::ready(Err(PeerData::for_tests(
-                        anyhow::anyhow!("")
+                        StreamError::Other(anyhow::anyhow!(""))
                     )))),
                     StorageBuilder::in_memory().unwrap(),
                     BlockNumber::GENESIS,
@@ -1337,7 +1358,7 @@ mod tests {
         use futures::{stream, SinkExt};
         use p2p::libp2p::PeerId;
         use pathfinder_common::event::Event;
-        use pathfinder_common::transaction::TransactionVariant;
+        use pathfinder_common::transaction::{DataAvailabilityMode, TransactionVariant};
         use pathfinder_common::{
             class_hash,
             felt,
@@ -1381,7 +1402,7 @@ mod tests {
         }
 
         struct Setup {
-            pub streamed_classes: Vec<Result<PeerData<ClassDefinition>, PeerData<anyhow::Error>>>,
+            pub streamed_classes: Vec<Result<PeerData<ClassDefinition>, PeerData<StreamError>>>,
             pub expected_defs: HashMap<ClassHash, Vec<u8>>,
             pub storage: Storage,
         }
@@ -1427,14 +1448,19 @@ mod tests {
                     Ok(PeerData::for_tests(ClassDefinition::Cairo {
                         block_number: BlockNumber::GENESIS + 1,
                         definition: CAIRO.to_vec(),
+                        domain: DataAvailabilityMode::L1,
                     })),
                     Ok(PeerData::for_tests(ClassDefinition::Sierra {
                         block_number: BlockNumber::GENESIS + 1,
                         sierra_definition: SIERRA0.to_vec(),
+                        interface: None,
+                        domain: DataAvailabilityMode::L1,
                     })),
                     Ok(PeerData::for_tests(ClassDefinition::Sierra {
                         block_number: BlockNumber::GENESIS + 1,
                         sierra_definition: SIERRA2.to_vec(),
+                        interface: None,
+                        domain: DataAvailabilityMode::L1,
                     })),
                 ];
 
@@ -1509,11 +1535,14 @@ mod tests {
         #[rstest::rstest]
         #[case::cairo(ClassDefinition::Cairo {
             block_number: BlockNumber::GENESIS + 1,
-            definition: Default::default()
+            definition: Default::default(),
+            domain: DataAvailabilityMode::L1,
         })]
         #[case::sierra(ClassDefinition::Sierra {
             block_number: BlockNumber::GENESIS + 1,
             sierra_definition: Default::default(),
+            interface: None,
+            domain: DataAvailabilityMode::L1,
         })]
         #[tokio::test]
         async fn bad_layout(#[case] class: ClassDefinition) {
@@ -1569,7 +1598,7 @@ mod tests {
             assert_matches!(
                 handle_class_stream(
                     stream::once(std::future::ready(Err(PeerData::for_tests(
-                        anyhow::anyhow!("")
+                        StreamError::Other(anyhow::anyhow!(""))
                     )))),
                     StorageBuilder::in_memory().unwrap(),
                     FakeFgw,
@@ -1599,7 +1628,7 @@ mod tests {
 
         struct Setup {
             pub streamed_events:
-                Vec<Result<PeerData<EventsForBlockByTransaction>, PeerData<anyhow::Error>>>,
+                Vec<Result<PeerData<EventsForBlockByTransaction>, PeerData<StreamError>>>,
             pub expected_events: Vec<Vec<(TransactionHash, Vec<Event>)>>,
             pub storage: Storage,
         }
@@ -1720,7 +1749,7 @@ mod tests {
             assert_matches::assert_matches!(
                 handle_event_stream(
                     stream::once(std::future::ready(Err(PeerData::for_tests(
-                        anyhow::anyhow!("")
+                        StreamError::Other(anyhow::anyhow!(""))
                     )))),
                     StorageBuilder::in_memory().unwrap()
                 )
@@ -121,6 +121,7 @@ impl ProcessStage for VerifyLayout {
             P2PClassDefinition::Cairo {
                 block_number,
                 definition,
+                ..
             } => {
                 let layout = GwClassDefinition::Cairo(
                     serde_json::from_slice::<Cairo<'_>>(&definition).map_err(|e| {
@@ -137,6 +138,7 @@ impl ProcessStage for VerifyLayout {
             P2PClassDefinition::Sierra {
                 block_number,
                 sierra_definition,
+                ..
             } => {
                 let layout = GwClassDefinition::Sierra(
                     serde_json::from_slice::<Sierra<'_>>(&sierra_definition).map_err(|e| {
@@ -1,5 +1,6 @@
 use std::sync::Arc;
 
+use p2p::client::types::StreamError;
 use p2p::libp2p::PeerId;
 use p2p::PeerData;
 use pathfinder_common::{BlockNumber, ClassHash, SignedBlockHeader};
@@ -149,3 +150,15 @@ impl From<anyhow::Error> for SyncError2 {
         Self::Other(Arc::new(value))
     }
 }
+
+impl From<StreamError> for SyncError {
+    fn from(value: StreamError) -> Self {
+        Self::Other(value.into())
+    }
+}
+
+impl From<StreamError> for SyncError2 {
+    fn from(value: StreamError) -> Self {
+        Self::Other(Arc::new(value.into()))
+    }
+}
@@ -21,7 +21,7 @@ use tokio_stream::wrappers::ReceiverStream;
 
 use super::error::SyncError;
 use super::storage_adapters;
-use crate::state::block_hash::calculate_event_commitment;
+use crate::state::block_hash::commitment;
 use crate::sync::error::SyncError2;
 use crate::sync::stream::ProcessStage;
 
@@ -87,14 +87,15 @@ pub(super) async fn verify_commitment(
             .block_header(block_number.into())
             .context("Querying block header")?
             .context("Block header not found")?;
-        let computed = calculate_event_commitment(
-            &events
-                .iter()
-                .map(|(tx_hash, events)| (*tx_hash, events.as_slice()))
-                .collect::<Vec<_>>(),
-            header.starknet_version.max(StarknetVersion::V_0_13_2),
-        )
-        .context("Calculating commitment")?;
+        let computed =
+            commitment::for_version(header.starknet_version.max(StarknetVersion::V_0_13_2))
+                .event_commitment(
+                    &events
+                        .iter()
+                        .map(|(tx_hash, events)| (*tx_hash, events.as_slice()))
+                        .collect::<Vec<_>>(),
+                )
+                .context("Calculating commitment")?;
         if computed != header.event_commitment {
             return Err(SyncError::EventCommitmentMismatch(peer));
         }
@@ -169,8 +170,8 @@ impl ProcessStage for VerifyCommitment {
             tracing::debug!(expected=%ordered_events.len(), actual=%events.len(), "Number of events received does not match expected number of events");
             return Err(SyncError2::EventsTransactionsMismatch);
         }
-        let actual =
-            calculate_event_commitment(&ordered_events, version.max(StarknetVersion::V_0_13_2))?;
+        let actual = commitment::for_version(version.max(StarknetVersion::V_0_13_2))
+            .event_commitment(&ordered_events)?;
         if actual != event_commitment {
             tracing::debug!(expected=%event_commitment, actual=%actual, "Event commitment mismatch");
             return Err(SyncError2::EventCommitmentMismatch);
@@ -2,6 +2,7 @@ use std::collections::VecDeque;
 use std::num::NonZeroUsize;
 
 use anyhow::Context;
+use p2p::client::types::StateDiffCommitmentLookup;
 use p2p::PeerData;
 use pathfinder_common::state_update::{self, ContractClassUpdate, ContractUpdate, StateUpdateData};
 use pathfinder_common::{
@@ -23,6 +24,7 @@ use tokio::task::spawn_blocking;
 use tokio_stream::wrappers::ReceiverStream;
 
 use super::storage_adapters;
+use crate::state::block_hash::commitment::{self, CommitmentScheme};
 use crate::state::{update_starknet_state, StarknetStateUpdate};
 use crate::sync::error::{SyncError, SyncError2};
 use crate::sync::stream::ProcessStage;
@@ -72,6 +74,20 @@ pub(super) fn state_diff_length_stream(
     storage_adapters::counts_stream(storage, start, stop, batch_size, get_state_diff_lengths)
 }
 
+/// Provides the state diff commitments of the headers already stored in
+/// `storage` to the p2p state diff stream.
+pub(crate) fn commitment_lookup(storage: Storage) -> StateDiffCommitmentLookup {
+    StateDiffCommitmentLookup::new(move |block_number| {
+        let mut db = storage
+            .connection()
+            .context("Creating database connection")?;
+        let db = db.transaction().context("Creating database transaction")?;
+        db.state_diff_commitment(block_number)
+            .context("Fetching state diff commitment")?
+            .context("State diff commitment not found")
+    })
+}
+
 pub struct FetchCommitmentFromDb<T> {
     db: pathfinder_storage::Connection,
     _marker: std::marker::PhantomData<T>,
@@ -113,7 +129,9 @@ impl ProcessStage for VerifyCommitment {
 
     fn map(&mut self, input: Self::Input) -> Result<Self::Output, SyncError2> {
         let (state_diff, block_number, expected_commitment) = input;
-        let actual_commitment = state_diff.compute_state_diff_commitment();
+        // State diff commitments only exist from 0.13.2 onwards, and p2p always
+        // uses that algorithm regardless of the block's version.
+        let actual_commitment = commitment::V0_13_2.state_diff_commitment(&state_diff);
 
         if actual_commitment != expected_commitment {
             tracing::debug!(%block_number, %expected_commitment, %actual_commitment, "State diff commitment mismatch");
@@ -1,10 +1,11 @@
 use std::collections::{HashMap, HashSet};
+use std::num::NonZeroUsize;
 use std::pin;
 
 use anyhow::{anyhow, Context};
 use futures::stream::BoxStream;
 use futures::{pin_mut, Stream, StreamExt, TryStreamExt};
-use p2p::client::peer_agnostic::traits::{BlockClient, HeaderStream};
+use p2p::client::peer_agnostic::traits::{BlockClient, HeaderStream, StreamItem};
 use p2p::client::peer_agnostic::Client as P2PClient;
 use p2p::client::types::{
     ClassDefinition as P2PClassDefinition,
@@ -50,7 +51,7 @@ use crate::state::{update_starknet_state, StarknetStateUpdate};
 use crate::sync::class_definitions::{self, ClassWithLayout};
 use crate::sync::error::SyncError2;
 use crate::sync::stream::{ProcessStage, SyncReceiver, SyncResult};
-use crate::sync::{events, headers};
+use crate::sync::{block, events, headers};
 
 pub struct Sync<L, P> {
     pub latest: L,
@@ -168,8 +169,17 @@ impl<L, P> Sync<L, P> {
             state_diff,
             transactions,
             classes,
+            // Every block is committed on its own by `StoreBlock`.
+            checkpoint_interval: None,
         }
         .spawn()
+        .pipe(
+            VerifyBlock {
+                chain: self.chain,
+                chain_id: self.chain_id,
+            },
+            10,
+        )
         .pipe(
             StoreBlock::new(
                 storage_connection,
@@ -210,9 +220,19 @@ impl<L, P> HeaderSource<L, P> {
 
                 // TODO: Probably need a loop here if we don't get enough headers?
                 let mut headers =
-                    Box::pin(p2p.clone().header_stream(start, latest_onchain.0, false));
+                    Box::pin(
+                        p2p.clone()
+                            .header_stream(start, latest_onchain.0, false, None),
+                    );
 
                 while let Some(header) = headers.next().await {
+                    let header = match header {
+                        Ok(header) => header,
+                        Err(error) => {
+                            _ = tx.send(Err(error.map(Into::into))).await;
+                            return;
+                        }
+                    };
                     start = header.data.header.number + 1;
 
                     if tx.send(Ok(header)).await.is_err() {
@@ -546,7 +566,13 @@ impl<P> StateDiffSource<P> {
                         .await;
                     match state_diff {
                         Ok(Some(state_diff)) => break state_diff,
-                        Ok(None) => {}
+                        // The client moves on to the next peer on request failures, so these are
+                        // equivalent to none of the peers having the data.
+                        Ok(None)
+                        | Err(
+                            StateDiffsError::RequestFailed(..)
+                            | StateDiffsError::PrematureStreamTermination(_),
+                        ) => {}
                         Err(StateDiffsError::IncorrectStateDiffCount(peer)) => {
                             let err = PeerData::new(peer, SyncError2::IncorrectStateDiffCount);
                             let _ = tx.send(Err(err)).await;
@@ -611,7 +637,9 @@ impl<P> ClassSource<P> {
                         .await;
                     match class_definitions {
                         Ok(Some(class_definitions)) => break class_definitions,
-                        Ok(None) => {}
+                        // The client moves on to the next peer on request failures, so this is
+                        // equivalent to none of the peers having the data.
+                        Ok(None) | Err(ClassDefinitionsError::RequestFailed(..)) => {}
                         Err(err) => {
                             let err = match err {
                                 ClassDefinitionsError::IncorrectClassDefinitionCount(peer) => {
@@ -655,23 +683,56 @@ struct BlockStream {
     pub state_diff: SyncReceiver<StateUpdateData>,
     pub transactions: SyncReceiver<Vec<(Transaction, Receipt)>>,
     pub classes: SyncReceiver<Vec<CompiledClass>>,
+    /// Emit a [BlockStreamItem::Checkpoint] after every `n` blocks.
+    pub checkpoint_interval: Option<NonZeroUsize>,
+}
+
+enum BlockStreamItem {
+    Block(BlockData),
+    /// All blocks up to and including this one have been yielded. Since stages
+    /// process their input in order, a consumer receiving this has already
+    /// processed these blocks and can safely commit and record its progress.
+    Checkpoint(BlockNumber),
 }
 
 impl BlockStream {
-    fn spawn(mut self) -> SyncReceiver<BlockData> {
+    fn spawn(mut self) -> SyncReceiver<BlockStreamItem> {
         let (tx, rx) = tokio::sync::mpsc::channel(1);
 
         tokio::spawn(async move {
+            let mut blocks_since_checkpoint = 0;
+
             loop {
                 let Some(result) = self.next().await else {
                     return;
                 };
 
+                let checkpoint = match (&result, self.checkpoint_interval) {
+                    (Ok(block), Some(interval)) => {
+                        blocks_since_checkpoint += 1;
+                        (blocks_since_checkpoint == interval.get()).then(|| {
+                            blocks_since_checkpoint = 0;
+                            PeerData::new(
+                                block.peer,
+                                BlockStreamItem::Checkpoint(block.data.header.header.number),
+                            )
+                        })
+                    }
+                    _ => None,
+                };
+
+                let result = result.map(|x| x.map(BlockStreamItem::Block));
                 let is_err = result.is_err();
 
                 if tx.send(result).await.is_err() || is_err {
                     return;
                 }
+
+                if let Some(checkpoint) = checkpoint {
+                    if tx.send(Ok(checkpoint)).await.is_err() {
+                        return;
+                    }
+                }
             }
         });
 
@@ -729,6 +790,47 @@ struct BlockData {
     pub classes: Vec<CompiledClass>,
 }
 
+/// Verifies each assembled block as a whole against its header before it is
+/// stored.
+struct VerifyBlock {
+    chain: Chain,
+    chain_id: ChainId,
+}
+
+impl ProcessStage for VerifyBlock {
+    const NAME: &'static str = "Blocks::Verify";
+    type Input = BlockStreamItem;
+    type Output = BlockStreamItem;
+
+    fn map(&mut self, input: Self::Input) -> Result<Self::Output, SyncError2> {
+        let BlockStreamItem::Block(block) = &input else {
+            return Ok(input);
+        };
+
+        // Events are keyed by transaction hash, so restore the order in which
+        // the transactions appear in the block.
+        let events = (
+            block.header.header.number,
+            block
+                .transactions
+                .iter()
+                .filter_map(|(t, _)| block.events.get(&t.hash).map(|e| (t.hash, e.clone())))
+                .collect(),
+        );
+
+        block::verify_block(
+            &block.header,
+            &block.transactions,
+            &block.state_diff,
+            &events,
+            self.chain,
+            self.chain_id,
+        )?;
+
+        Ok(input)
+    }
+}
+
 struct StoreBlock {
     connection: pathfinder_storage::Connection,
     // We need this so that we can create extra read-only transactions for parallel contract state
@@ -754,10 +856,19 @@ impl StoreBlock {
 
 impl ProcessStage for StoreBlock {
     const NAME: &'static str = "Blocks::Persist";
-    type Input = BlockData;
+    type Input = BlockStreamItem;
     type Output = ();
 
     fn map(&mut self, input: Self::Input) -> Result<Self::Output, SyncError2> {
+        let input = match input {
+            BlockStreamItem::Block(block) => block,
+            BlockStreamItem::Checkpoint(block_number) => {
+                // Blocks are committed individually so every block is already durable.
+                tracing::debug!(%block_number, "Checkpoint reached");
+                return Ok(());
+            }
+        };
+
         let BlockData {
             header: SignedBlockHeader { header, signature },
             mut events,
@@ -887,17 +998,21 @@ impl ProcessStage for StoreBlock {
 
 #[cfg(test)]
 mod tests {
+    use std::num::NonZeroU64;
+
     use futures::{stream, Stream, StreamExt};
     use p2p::client::types::{
         ClassDefinition,
         ClassDefinitionsError,
         EventsResponseStreamFailure,
         Receipt as P2PReceipt,
+        StateDiffChunk,
         StateDiffsError,
     };
     use p2p::libp2p::PeerId;
     use p2p::PeerData;
     use p2p_proto::common::Hash;
+    use pathfinder_common::transaction::DataAvailabilityMode;
     use pathfinder_common::{BlockHeader, ReceiptCommitment, SignedBlockHeader};
     use pathfinder_storage::fake::init::Config;
     use pathfinder_storage::fake::{self, Block};
@@ -1012,6 +1127,52 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn block_stream_emits_checkpoints() {
+        const N: u64 = 5;
+        fn n_times<T: Default + Send + 'static>() -> SyncReceiver<T> {
+            SyncReceiver::iter((0..N).map(|_| Ok(PeerData::for_tests(T::default()))))
+        }
+
+        let headers = (0..N)
+            .map(|n| {
+                let mut header = SignedBlockHeader::default();
+                header.header.number = BlockNumber::new_or_panic(n);
+                Ok(PeerData::for_tests(header))
+            })
+            .collect::<Vec<_>>();
+
+        let items = BlockStream {
+            header: SyncReceiver::iter(headers),
+            events: n_times(),
+            state_diff: n_times(),
+            transactions: n_times(),
+            classes: n_times(),
+            checkpoint_interval: Some(NonZeroUsize::new(2).unwrap()),
+        }
+        .spawn()
+        .into_stream()
+        .map(|x| match x.unwrap().data {
+            BlockStreamItem::Block(block) => format!("block {}", block.header.header.number),
+            BlockStreamItem::Checkpoint(number) => format!("checkpoint {number}"),
+        })
+        .collect::<Vec<_>>()
+        .await;
+
+        assert_eq!(
+            items,
+            vec![
+                "block 0",
+                "block 1",
+                "checkpoint 1",
+                "block 2",
+                "block 3",
+                "checkpoint 3",
+                "block 4",
+            ]
+        );
+    }
+
     #[derive(Clone)]
     struct FakeP2PClient {
         pub blocks: Vec<Block>,
@@ -1023,20 +1184,29 @@ mod tests {
             start: BlockNumber,
             stop: BlockNumber,
             reverse: bool,
-        ) -> impl Stream<Item = PeerData<SignedBlockHeader>> + Send {
+            step: Option<NonZeroU64>,
+        ) -> impl Stream<Item = StreamItem<SignedBlockHeader>> + Send {
             assert!(!reverse);
+            assert_eq!(step, None);
             assert_eq!(start, self.blocks.first().unwrap().header.header.number);
             assert_eq!(stop, self.blocks.last().unwrap().header.header.number);
 
             stream::iter(
                 self.blocks
                     .into_iter()
-                    .map(|block| PeerData::for_tests(block.header)),
+                    .map(|block| Ok(PeerData::for_tests(block.header))),
             )
         }
     }
 
     impl BlockClient for FakeP2PClient {
+        async fn header_for_block(self, block: BlockNumber) -> Option<(PeerId, SignedBlockHeader)> {
+            self.blocks
+                .iter()
+                .find(|b| b.header.header.number == block)
+                .map(|b| (PeerId::random(), b.header.clone()))
+        }
+
         async fn transactions_for_block(
             self,
             block: BlockNumber,
@@ -1076,6 +1246,42 @@ mod tests {
             Ok(Some((PeerId::random(), sd)))
         }
 
+        async fn state_diff_stream_for_block(
+            self,
+            block: BlockNumber,
+            state_diff_length: u64,
+        ) -> Option<(
+            PeerId,
+            impl Stream<Item = Result<StateDiffChunk, StateDiffsError>> + Send,
+        )> {
+            let (peer, sd) = self
+                .state_diff_for_block(block, state_diff_length)
+                .await
+                .unwrap()?;
+
+            let chunks = sd
+                .contract_updates
+                .iter()
+                .map(|(a, u)| StateDiffChunk::ContractUpdate(*a, u.clone(), sd.storage_domain(a)))
+                .chain(sd.system_contract_updates.iter().map(|(a, u)| {
+                    StateDiffChunk::SystemContractUpdate(*a, u.clone(), sd.storage_domain(a))
+                }))
+                .chain(
+                    sd.declared_cairo_classes
+                        .iter()
+                        .copied()
+                        .map(StateDiffChunk::DeclaredCairoClass),
+                )
+                .chain(
+                    sd.declared_sierra_classes
+                        .iter()
+                        .map(|(s, c)| StateDiffChunk::DeclaredSierraClass(*s, *c)),
+                )
+                .collect::<Vec<_>>();
+
+            Some((peer, stream::iter(chunks.into_iter().map(Ok))))
+        }
+
         async fn class_definitions_for_block(
             self,
             block: BlockNumber,
@@ -1092,6 +1298,7 @@ mod tests {
                 .map(|(_, x)| ClassDefinition::Cairo {
                     block_number: block,
                     definition: x.clone(),
+                    domain: DataAvailabilityMode::L1,
                 })
                 .chain(
                     b.sierra_defs
@@ -1099,6 +1306,8 @@ mod tests {
                         .map(|(_, x, _)| ClassDefinition::Sierra {
                             block_number: block,
                             sierra_definition: x.clone(),
+                            interface: None,
+                            domain: DataAvailabilityMode::L1,
                         }),
                 )
                 .collect::<Vec<ClassDefinition>>();
@@ -1106,6 +1315,22 @@ mod tests {
             Ok(Some((PeerId::random(), defs)))
         }
 
+        async fn class_definitions_stream_for_block(
+            self,
+            block: BlockNumber,
+            declared_classes_count: u64,
+        ) -> Option<(
+            PeerId,
+            impl Stream<Item = Result<ClassDefinition, ClassDefinitionsError>> + Send,
+        )> {
+            let (peer, defs) = self
+                .class_definitions_for_block(block, declared_classes_count)
+                .await
+                .unwrap()?;
+
+            Some((peer, stream::iter(defs.into_iter().map(Ok))))
+        }
+
         async fn events_for_block(
             self,
             block: BlockNumber,
@@ -2,7 +2,7 @@ use std::collections::{HashMap, VecDeque};
 use std::num::NonZeroUsize;
 
 use anyhow::{anyhow, Context};
-use p2p::client::types::TransactionData;
+use p2p::client::types::{TransactionCommitmentVerifier, TransactionData};
 use p2p::PeerData;
 use pathfinder_common::receipt::Receipt;
 use pathfinder_common::transaction::{Transaction, TransactionVariant};
@@ -21,7 +21,7 @@ use tokio_stream::wrappers::ReceiverStream;
 use super::error::{SyncError, SyncError2};
 use super::storage_adapters;
 use super::stream::ProcessStage;
-use crate::state::block_hash::calculate_transaction_commitment;
+use crate::state::block_hash::commitment;
 
 /// For a single block
 #[derive(Clone, Debug)]
@@ -72,6 +72,41 @@ pub(super) fn counts_stream(
     storage_adapters::counts_stream(storage, start, stop, batch_size, get_counts)
 }
 
+/// Verifies the transactions received by the p2p transaction stream against the
+/// commitment of the corresponding header in `storage`, so that the stream can
+/// retry mismatching blocks with another peer.
+pub(crate) fn commitment_verifier(
+    storage: Storage,
+    chain_id: ChainId,
+) -> TransactionCommitmentVerifier {
+    TransactionCommitmentVerifier::new(move |block_number, transactions| {
+        let mut db = storage
+            .connection()
+            .context("Creating database connection")?;
+        let db = db.transaction().context("Creating database transaction")?;
+        let version = db
+            .block_version(block_number)
+            .context("Fetching starknet version")?
+            .context("Starknet version not found")?;
+        let expected_commitment = db
+            .transaction_commitment(block_number)
+            .context("Fetching transaction commitment")?
+            .context("Transaction commitment not found")?;
+
+        let transactions = transactions
+            .iter()
+            .map(|(tv, _)| Transaction {
+                hash: tv.calculate_hash(chain_id, false),
+                variant: tv.clone(),
+            })
+            .collect::<Vec<_>>();
+        let actual = commitment::for_version(version.max(StarknetVersion::V_0_13_2))
+            .transaction_commitment(&transactions)?;
+
+        Ok(actual == expected_commitment)
+    })
+}
+
 pub struct CalculateHashes(pub ChainId);
 
 impl ProcessStage for CalculateHashes {
@@ -166,8 +201,8 @@ impl ProcessStage for VerifyCommitment {
             block_number,
         } = transactions;
         let txs: Vec<_> = transactions.iter().map(|(t, _)| t.clone()).collect();
-        let actual =
-            calculate_transaction_commitment(&txs, version.max(StarknetVersion::V_0_13_2))?;
+        let actual = commitment::for_version(version.max(StarknetVersion::V_0_13_2))
+            .transaction_commitment(&txs)?;
         if actual != expected_commitment {
             tracing::debug!(%block_number, %expected_commitment, actual_commitment=%actual, "Transaction commitment mismatch");
             return Err(SyncError2::TransactionCommitmentMismatch);
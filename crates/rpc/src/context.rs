@@ -16,6 +16,7 @@ use tokio::sync::watch as tokio_watch;
 #[derive(Clone)]
 pub struct RpcConfig {
     pub batch_concurrency_limit: NonZeroUsize,
+    pub batch_size_limit: NonZeroUsize,
     pub get_events_max_blocks_to_scan: NonZeroUsize,
     pub get_events_max_uncached_bloom_filters_to_load: NonZeroUsize,
     pub custom_versioned_constants: Option<VersionedConstants>,
@@ -104,6 +105,7 @@ impl RpcContext {
 
         let config = RpcConfig {
             batch_concurrency_limit: NonZeroUsize::new(8).unwrap(),
+            batch_size_limit: NonZeroUsize::new(1000).unwrap(),
             get_events_max_blocks_to_scan: NonZeroUsize::new(1000).unwrap(),
             get_events_max_uncached_bloom_filters_to_load: NonZeroUsize::new(1000).unwrap(),
             custom_versioned_constants: None,
@@ -1,4 +1,5 @@
 use super::U256Hex;
+use crate::RpcVersion;
 
 #[derive(Debug, PartialEq, Eq)]
 pub struct FeeEstimate<'a>(pub &'a pathfinder_executor::types::FeeEstimate);
@@ -9,10 +10,28 @@ impl crate::dto::serialize::SerializeForVersion for FeeEstimate<'_> {
         serializer: crate::dto::serialize::Serializer,
     ) -> Result<crate::dto::serialize::Ok, crate::dto::serialize::Error> {
         let mut serializer = serializer.serialize_struct()?;
-        serializer.serialize_field("gas_consumed", &U256Hex(self.0.gas_consumed))?;
-        serializer.serialize_field("gas_price", &U256Hex(self.0.gas_price))?;
-        serializer.serialize_field("data_gas_consumed", &U256Hex(self.0.data_gas_consumed))?;
-        serializer.serialize_field("data_gas_price", &U256Hex(self.0.data_gas_price))?;
+        match serializer.version {
+            RpcVersion::V06 | RpcVersion::V07 => {
+                serializer.serialize_field("gas_consumed", &U256Hex(self.0.gas_consumed))?;
+                serializer.serialize_field("gas_price", &U256Hex(self.0.gas_price))?;
+                serializer
+                    .serialize_field("data_gas_consumed", &U256Hex(self.0.data_gas_consumed))?;
+                serializer.serialize_field("data_gas_price", &U256Hex(self.0.data_gas_price))?;
+            }
+            _ => {
+                serializer.serialize_field("l1_gas_consumed", &U256Hex(self.0.gas_consumed))?;
+                serializer.serialize_field("l1_gas_price", &U256Hex(self.0.gas_price))?;
+                serializer
+                    .serialize_field("l1_data_gas_consumed", &U256Hex(self.0.data_gas_consumed))?;
+                serializer.serialize_field("l1_data_gas_price", &U256Hex(self.0.data_gas_price))?;
+                // The executor does not meter L2 gas yet, all computation is charged as L1
+                // gas.
+                serializer
+                    .serialize_field("l2_gas_consumed", &U256Hex(primitive_types::U256::zero()))?;
+                serializer
+                    .serialize_field("l2_gas_price", &U256Hex(primitive_types::U256::zero()))?;
+            }
+        }
         serializer.serialize_field("overall_fee", &U256Hex(self.0.overall_fee))?;
         serializer.serialize_field("unit", &PriceUnit(&self.0.unit))?;
         serializer.end()
@@ -232,6 +232,16 @@ impl DeserializeForVersion for U128Hex {
     }
 }
 
+impl DeserializeForVersion for H256Hex {
+    fn deserialize(value: Value) -> Result<Self, serde_json::Error> {
+        let hex_str: String = value.deserialize_serde()?;
+        let bytes = hex_str::bytes_from_hex_str_stripped::<32>(&hex_str).map_err(|e| {
+            serde_json::Error::custom(format!("failed to parse hex string as H256: {}", e))
+        })?;
+        Ok(Self(primitive_types::H256(bytes)))
+    }
+}
+
 impl SerializeForVersion for H256Hex {
     fn serialize(&self, serializer: Serializer) -> Result<serialize::Ok, serialize::Error> {
         serializer.serialize_str(&hex_str::bytes_to_hex_str_stripped(self.0.as_bytes()))
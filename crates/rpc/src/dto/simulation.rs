@@ -3,6 +3,7 @@ use pathfinder_common::{ContractAddress, ContractNonce};
 use serde::ser::Error;
 
 use super::serialize::SerializeStruct;
+use crate::RpcVersion;
 
 #[derive(Debug)]
 pub struct TransactionTrace<'a> {
@@ -441,11 +442,21 @@ impl crate::dto::serialize::SerializeForVersion for ExecutionResources<'_> {
         serializer: super::serialize::Serializer,
     ) -> Result<super::serialize::Ok, super::serialize::Error> {
         let mut serializer = serializer.serialize_struct()?;
-        serializer.flatten(&ComputationResources(&self.0.computation_resources))?;
-        serializer.serialize_field(
-            "data_availability",
-            &DataAvailabilityResources(&self.0.data_availability),
-        )?;
+        match serializer.version {
+            RpcVersion::V06 | RpcVersion::V07 => {
+                serializer.flatten(&ComputationResources(&self.0.computation_resources))?;
+                serializer.serialize_field(
+                    "data_availability",
+                    &DataAvailabilityResources(&self.0.data_availability),
+                )?;
+            }
+            _ => {
+                serializer.serialize_field("l1_gas", &self.0.data_availability.l1_gas)?;
+                serializer.serialize_field("l1_data_gas", &self.0.data_availability.l1_data_gas)?;
+                // The executor does not meter L2 gas yet.
+                serializer.serialize_field("l2_gas", &0u128)?;
+            }
+        }
         serializer.end()
     }
 }
@@ -75,6 +75,10 @@ pub enum ApplicationError {
     UnsupportedContractClassVersion,
     #[error("An unexpected error occurred")]
     UnexpectedError { data: String },
+    #[error("The node doesn't support storage proofs for blocks that are too far in the past")]
+    StorageProofNotSupported,
+    #[error("Cannot go back more than 1024 blocks")]
+    TooManyBlocksBack,
     #[error("Too many storage keys requested")]
     ProofLimitExceeded { limit: u32, requested: u32 },
     #[error("Internal error")]
@@ -125,6 +129,7 @@ impl ApplicationError {
             ApplicationError::TooManyKeysInFilter { .. } => 34,
             ApplicationError::ContractError { .. } => 40,
             ApplicationError::TransactionExecutionError { .. } => 41,
+            ApplicationError::StorageProofNotSupported => 42,
             ApplicationError::InvalidContractClass => 50,
             ApplicationError::ClassAlreadyDeclared => 51,
             ApplicationError::InvalidTransactionNonce => 52,
@@ -139,6 +144,7 @@ impl ApplicationError {
             ApplicationError::UnsupportedTxVersion => 61,
             ApplicationError::UnsupportedContractClassVersion => 62,
             ApplicationError::UnexpectedError { .. } => 63,
+            ApplicationError::TooManyBlocksBack => 68,
             // doc/rpc/pathfinder_rpc_api.json
             ApplicationError::ProofLimitExceeded { .. } => 10000,
             ApplicationError::ProofMissing => 10001,
@@ -212,6 +218,8 @@ impl ApplicationError {
                 "requested": requested,
             })),
             ApplicationError::UnexpectedError { data } => Some(json!(data)),
+            ApplicationError::TooManyBlocksBack => None,
+            ApplicationError::StorageProofNotSupported => None,
             ApplicationError::ProofLimitExceeded { limit, requested } => Some(json!({
                 "limit": limit,
                 "requested": requested,
@@ -11,10 +11,11 @@ use pathfinder_common::{BlockHash, BlockNumber};
 pub use request::RpcRequest;
 pub use response::RpcResponse;
 #[cfg(test)]
-pub use router::{handle_json_rpc_socket, CATCH_UP_BATCH_SIZE};
+pub use router::{handle_json_rpc_socket, CATCH_UP_BATCH_SIZE, MAX_BLOCKS_BACK};
 pub use router::{
     rpc_handler,
     CatchUp,
+    RateLimit,
     RpcRouter,
     RpcRouterBuilder,
     RpcSubscriptionFlow,
@@ -10,6 +10,7 @@ pub enum RpcError {
     MethodNotFound,
     InvalidParams(String),
     InternalError(anyhow::Error),
+    RateLimitExceeded,
     ApplicationError(crate::error::ApplicationError),
     WebsocketSubscriptionClosed {
         subscription_id: u32,
@@ -35,6 +36,8 @@ impl RpcError {
             RpcError::MethodNotFound { .. } => -32601,
             RpcError::InvalidParams(..) => -32602,
             RpcError::InternalError(_) => -32603,
+            // Implementation defined server error.
+            RpcError::RateLimitExceeded => -32005,
             RpcError::ApplicationError(err) => err.code(),
             RpcError::WebsocketSubscriptionClosed { .. } => -32099,
         }
@@ -47,6 +50,7 @@ impl RpcError {
             RpcError::MethodNotFound { .. } => "Method not found".into(),
             RpcError::InvalidParams(..) => "Invalid params".into(),
             RpcError::InternalError(_) => "Internal error".into(),
+            RpcError::RateLimitExceeded => "Rate limit exceeded".into(),
             RpcError::ApplicationError(e) => e.to_string().into(),
             RpcError::WebsocketSubscriptionClosed { .. } => "Websocket subscription closed".into(),
         }
@@ -63,6 +67,7 @@ impl RpcError {
             })),
             RpcError::ApplicationError(e) => e.data(),
             RpcError::InternalError(_) => None,
+            RpcError::RateLimitExceeded => None,
             RpcError::MethodNotFound => None,
             RpcError::ParseError(e) | RpcError::InvalidRequest(e) | RpcError::InvalidParams(e) => {
                 Some(json!({
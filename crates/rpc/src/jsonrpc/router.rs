@@ -7,10 +7,12 @@ use axum::response::IntoResponse;
 use futures::{Future, FutureExt, StreamExt};
 use http::HeaderValue;
 use method::RpcMethodEndpoint;
-#[cfg(test)]
-pub use subscription::CATCH_UP_BATCH_SIZE;
+pub use rate_limit::RateLimit;
+use rate_limit::RateLimiter;
 pub use subscription::{handle_json_rpc_socket, CatchUp, RpcSubscriptionFlow, SubscriptionMessage};
 use subscription::{split_ws, RpcSubscriptionEndpoint};
+#[cfg(test)]
+pub use subscription::{CATCH_UP_BATCH_SIZE, MAX_BLOCKS_BACK};
 
 use crate::context::RpcContext;
 use crate::jsonrpc::error::RpcError;
@@ -19,6 +21,7 @@ use crate::jsonrpc::response::RpcResponse;
 use crate::RpcVersion;
 
 mod method;
+mod rate_limit;
 mod subscription;
 
 pub use method::handle_json_rpc_body;
@@ -28,12 +31,16 @@ pub struct RpcRouter {
     pub context: RpcContext,
     method_endpoints: &'static HashMap<&'static str, Box<dyn RpcMethodEndpoint>>,
     subscription_endpoints: &'static HashMap<&'static str, Box<dyn RpcSubscriptionEndpoint>>,
+    rate_limits: &'static HashMap<&'static str, RateLimiter>,
+    method_metrics: bool,
     version: RpcVersion,
 }
 
 pub struct RpcRouterBuilder {
     method_endpoints: HashMap<&'static str, Box<dyn RpcMethodEndpoint>>,
     subscription_endpoints: HashMap<&'static str, Box<dyn RpcSubscriptionEndpoint>>,
+    rate_limits: HashMap<&'static str, RateLimiter>,
+    method_metrics: bool,
     version: RpcVersion,
 }
 
@@ -71,6 +78,32 @@ impl RpcRouterBuilder {
         self
     }
 
+    /// Registers an RPC method which may only be called at the given rate.
+    /// Calls over the limit fail without running the method.
+    ///
+    /// Panics if the method was already registered, or if it is a
+    /// subscription.
+    pub fn register_limited<I, O, S, M: IntoRpcEndpoint<I, O, S>>(
+        self,
+        method_name: &'static str,
+        method: M,
+        limit: RateLimit,
+    ) -> Self {
+        let mut this = self.register(method_name, method);
+        if !this.method_endpoints.contains_key(method_name) {
+            panic!("'{method_name}' is a subscription, only methods can be rate limited");
+        }
+        this.rate_limits
+            .insert(method_name, RateLimiter::new(limit));
+        this
+    }
+
+    /// Enables the per method execution time and error code metrics.
+    pub fn with_method_metrics(mut self, enabled: bool) -> Self {
+        self.method_metrics = enabled;
+        self
+    }
+
     pub fn build(self, context: RpcContext) -> RpcRouter {
         // Intentionally leak the hashmaps to give them a static lifetime.
         // Since the router is expected to be long lived, this shouldn't be an issue.
@@ -78,10 +111,14 @@ impl RpcRouterBuilder {
         let methods = Box::leak(methods);
         let subscriptions = Box::new(self.subscription_endpoints);
         let subscriptions = Box::leak(subscriptions);
+        let rate_limits = Box::new(self.rate_limits);
+        let rate_limits = Box::leak(rate_limits);
         RpcRouter {
             context,
             method_endpoints: methods,
             subscription_endpoints: subscriptions,
+            rate_limits,
+            method_metrics: self.method_metrics,
             version: self.version,
         }
     }
@@ -90,6 +127,8 @@ impl RpcRouterBuilder {
         RpcRouterBuilder {
             method_endpoints: Default::default(),
             subscription_endpoints: Default::default(),
+            rate_limits: Default::default(),
+            method_metrics: false,
             version,
         }
     }
@@ -126,21 +165,38 @@ impl RpcRouter {
 
         metrics::increment_counter!("rpc_method_calls_total", "method" => method_name, "version" => self.version.to_str());
 
-        let method = method.invoke(self.context.clone(), request.params, self.version);
-        let result = std::panic::AssertUnwindSafe(method).catch_unwind().await;
+        let rate_limited = self
+            .rate_limits
+            .get(method_name)
+            .is_some_and(|limiter| !limiter.try_acquire());
 
-        let output = match result {
-            Ok(output) => output,
-            Err(e) => {
-                tracing::warn!(method=%request.method, backtrace=?e, "RPC method panic'd");
-                Err(RpcError::InternalError(anyhow::anyhow!(
-                    "RPC method panic'd"
-                )))
+        let output = if !rate_limited {
+            let started = std::time::Instant::now();
+            let method = method.invoke(self.context.clone(), request.params, self.version);
+            let result = std::panic::AssertUnwindSafe(method).catch_unwind().await;
+
+            if self.method_metrics {
+                metrics::histogram!("rpc_method_calls_duration_seconds", started.elapsed(), "method" => method_name, "version" => self.version.to_str());
             }
+
+            match result {
+                Ok(output) => output,
+                Err(e) => {
+                    tracing::warn!(method=%request.method, backtrace=?e, "RPC method panic'd");
+                    Err(RpcError::InternalError(anyhow::anyhow!(
+                        "RPC method panic'd"
+                    )))
+                }
+            }
+        } else {
+            Err(RpcError::RateLimitExceeded)
         };
 
-        if output.is_err() {
+        if let Err(e) = &output {
             metrics::increment_counter!("rpc_method_calls_failed_total", "method" => method_name, "version" => self.version.to_str());
+            if self.method_metrics {
+                metrics::increment_counter!("rpc_method_calls_errors_total", "method" => method_name, "version" => self.version.to_str(), "code" => e.code().to_string());
+            }
         }
 
         Some(RpcResponse {
@@ -717,6 +773,74 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn batch_size_limit() {
+        fn always_success() -> &'static str {
+            "Success"
+        }
+
+        let router = || {
+            let mut context = RpcContext::for_tests();
+            context.config.batch_size_limit = 2.try_into().unwrap();
+            RpcRouter::builder(Default::default())
+                .register("success", always_success)
+                .build(context)
+        };
+
+        let request = json!([
+            {"jsonrpc": "2.0", "method": "success", "id": 1},
+            {"jsonrpc": "2.0", "method": "success", "id": 2},
+        ]);
+        let expected = json!([
+            {"jsonrpc": "2.0", "result": "Success", "id": 1},
+            {"jsonrpc": "2.0", "result": "Success", "id": 2},
+        ]);
+        assert_eq!(serve_and_query(router(), request.clone()).await, expected);
+        assert_eq!(serve_and_query_ws(router(), request).await, expected);
+
+        let request = json!([
+            {"jsonrpc": "2.0", "method": "success", "id": 1},
+            {"jsonrpc": "2.0", "method": "success", "id": 2},
+            {"jsonrpc": "2.0", "method": "success", "id": 3},
+        ]);
+        let expected = json!({"jsonrpc": "2.0", "id": null,
+        "error": {"code": -32600, "message": "Invalid request", "data": {
+            "reason": "A batch request must contain at most 2 requests"
+        }}});
+        assert_eq!(serve_and_query(router(), request.clone()).await, expected);
+        assert_eq!(serve_and_query_ws(router(), request).await, expected);
+    }
+
+    #[tokio::test]
+    async fn rate_limit() {
+        fn always_success() -> &'static str {
+            "Success"
+        }
+
+        // Run the batch sequentially so that the first call gets the only permit.
+        let mut context = RpcContext::for_tests();
+        context.config.batch_concurrency_limit = 1.try_into().unwrap();
+        let router = RpcRouter::builder(Default::default())
+            .register_limited("limited", always_success, RateLimit::per_sec(1))
+            .register("unlimited", always_success)
+            .build(context);
+
+        let request = json!([
+            {"jsonrpc": "2.0", "method": "limited", "id": 1},
+            {"jsonrpc": "2.0", "method": "limited", "id": 2},
+            {"jsonrpc": "2.0", "method": "unlimited", "id": 3},
+            {"jsonrpc": "2.0", "method": "unlimited", "id": 4},
+        ]);
+        let response = serve_and_query(router, request).await;
+        let expected = json!([
+            {"jsonrpc": "2.0", "result": "Success", "id": 1},
+            {"jsonrpc": "2.0", "error": {"code": -32005, "message": "Rate limit exceeded"}, "id": 2},
+            {"jsonrpc": "2.0", "result": "Success", "id": 3},
+            {"jsonrpc": "2.0", "result": "Success", "id": 4},
+        ]);
+        assert_eq!(response, expected);
+    }
+
     #[tokio::test]
     async fn rejects_non_json_content_header() {
         async fn always_success(_ctx: RpcContext) -> RpcResult {
@@ -75,6 +75,13 @@ pub async fn handle_json_rpc_body(
             ));
         }
 
+        let limit = state.context.config.batch_size_limit;
+        if requests.len() > limit.get() {
+            return Err(RpcRequestError::InvalidRequest(format!(
+                "A batch request must contain at most {limit} requests"
+            )));
+        }
+
         let responses = run_concurrently(
             state.context.config.batch_concurrency_limit,
             requests.into_iter().enumerate(),
@@ -0,0 +1,97 @@
+use std::num::NonZeroU32;
+use std::sync::Mutex;
+use std::time::Instant;
+
+/// The number of calls per second allowed for an RPC method.
+///
+/// The limit is shared by all callers of the method, regardless of the
+/// connection the calls arrive on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+    per_second: NonZeroU32,
+}
+
+impl RateLimit {
+    /// Panics if `per_second` is zero.
+    pub fn per_sec(per_second: u32) -> Self {
+        Self {
+            per_second: NonZeroU32::new(per_second).expect("Rate limit must be non-zero"),
+        }
+    }
+}
+
+/// A token bucket which holds up to one second worth of calls, and is
+/// refilled at the rate of the [RateLimit].
+pub(super) struct RateLimiter {
+    limit: RateLimit,
+    bucket: Mutex<Bucket>,
+}
+
+struct Bucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(limit: RateLimit) -> Self {
+        Self {
+            limit,
+            bucket: Mutex::new(Bucket {
+                tokens: limit.per_second.get().into(),
+                last_refill: Instant::now(),
+            }),
+        }
+    }
+
+    /// Returns `false` if the call exceeds the rate limit.
+    pub fn try_acquire(&self) -> bool {
+        self.try_acquire_at(Instant::now())
+    }
+
+    fn try_acquire_at(&self, now: Instant) -> bool {
+        let capacity = f64::from(self.limit.per_second.get());
+        let mut bucket = self
+            .bucket
+            .lock()
+            .expect("Rate limiter lock is not poisoned");
+
+        let elapsed = now.saturating_duration_since(bucket.last_refill);
+        bucket.tokens = (bucket.tokens + elapsed.as_secs_f64() * capacity).min(capacity);
+        bucket.last_refill = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn refills_over_time() {
+        let limiter = RateLimiter::new(RateLimit::per_sec(2));
+        let start = limiter.bucket.lock().unwrap().last_refill;
+
+        assert!(limiter.try_acquire_at(start));
+        assert!(limiter.try_acquire_at(start));
+        assert!(!limiter.try_acquire_at(start));
+
+        // Half a second refills a single call.
+        let later = start + Duration::from_millis(500);
+        assert!(limiter.try_acquire_at(later));
+        assert!(!limiter.try_acquire_at(later));
+
+        // Unused calls don't accumulate past the limit.
+        let much_later = later + Duration::from_secs(10);
+        assert!(limiter.try_acquire_at(much_later));
+        assert!(limiter.try_acquire_at(much_later));
+        assert!(!limiter.try_acquire_at(much_later));
+    }
+}
@@ -18,6 +18,9 @@ use crate::{RpcVersion, SubscriptionId};
 
 pub const CATCH_UP_BATCH_SIZE: u64 = 64;
 
+/// How far behind the latest block a subscription is allowed to start.
+pub const MAX_BLOCKS_BACK: u64 = 1024;
+
 /// See [`RpcSubscriptionFlow`].
 #[axum::async_trait]
 pub(super) trait RpcSubscriptionEndpoint: Send + Sync {
@@ -46,7 +49,8 @@ pub(super) struct InvokeParams {
 /// The `catch_up` method is used to stream historical data, while the
 /// `subscribe` method is used to subscribe to active updates. The
 /// `starting_block` method extracts the first block to start streaming from.
-/// This will probably always just be the `block` field of the request.
+/// This will probably always just be the `block` field of the request. It may
+/// be at most [`MAX_BLOCKS_BACK`] blocks behind the latest block.
 ///
 /// If a subscription endpoint does not need to stream historical data, it
 /// should always return an empty vec from `catch_up`.
@@ -181,9 +185,19 @@ where
                 let current_block = tokio::task::spawn_blocking(move || -> Result<_, RpcError> {
                     let mut conn = storage.connection().map_err(RpcError::InternalError)?;
                     let db = conn.transaction().map_err(RpcError::InternalError)?;
-                    db.block_number(first_block)
+                    let first_block = db
+                        .block_number(first_block)
+                        .map_err(RpcError::InternalError)?
+                        .ok_or(ApplicationError::BlockNotFound)?;
+                    let latest = db
+                        .block_id(pathfinder_storage::BlockId::Latest)
                         .map_err(RpcError::InternalError)?
-                        .ok_or_else(|| ApplicationError::BlockNotFound.into())
+                        .map(|(number, _)| number)
+                        .unwrap_or(first_block);
+                    if latest.get().saturating_sub(first_block.get()) > MAX_BLOCKS_BACK {
+                        return Err(ApplicationError::TooManyBlocksBack.into());
+                    }
+                    Ok(first_block)
                 })
                 .await
                 .map_err(|e| RpcError::InternalError(e.into()))??;
@@ -401,11 +415,11 @@ pub fn handle_json_rpc_socket(
                 }
                 Some(Ok(Message::Close(_))) | None => {
                     // Websocket closed.
-                    return;
+                    break;
                 }
                 Some(Err(e)) => {
                     tracing::trace!(error = ?e, "Error receiving websocket message");
-                    return;
+                    break;
                 }
             };
 
@@ -491,6 +505,21 @@ pub fn handle_json_rpc_socket(
                     }
                 }
 
+                let limit = state.context.config.batch_size_limit;
+                if requests.len() > limit.get() {
+                    if ws_tx
+                        .send(Err(RpcResponse::invalid_request(format!(
+                            "A batch request must contain at most {limit} requests"
+                        ))))
+                        .await
+                        .is_err()
+                    {
+                        // Connection is closing.
+                        break;
+                    }
+                    continue;
+                }
+
                 let responses = run_concurrently(
                     state.context.config.batch_concurrency_limit,
                     requests.into_iter().enumerate(),
@@ -533,6 +562,12 @@ pub fn handle_json_rpc_socket(
                 }
             }
         }
+
+        // The websocket is gone, so there is no one left to stream to.
+        for subscription in subscriptions.iter() {
+            subscription.value().abort();
+        }
+        subscriptions.clear();
     });
 }
 
@@ -597,10 +632,13 @@ async fn handle_request(
         .map_err(|e| RpcResponse::invalid_params(req_id.clone(), e.to_string()))?;
 
     // Start the subscription.
+    let version = state.version;
+    let method_metrics = state.method_metrics;
     let state = state.clone();
     let subscription_id = SubscriptionId::next();
     let ws_tx = ws_tx.clone();
-    match endpoint
+    let started = std::time::Instant::now();
+    let result = endpoint
         .invoke(InvokeParams {
             router: state,
             input: params,
@@ -609,8 +647,18 @@ async fn handle_request(
             ws_tx: ws_tx.clone(),
             lock,
         })
-        .await
-    {
+        .await;
+
+    // Subscriptions live until the client unsubscribes, so only the time taken to
+    // establish them is recorded.
+    if method_metrics {
+        metrics::histogram!("rpc_method_calls_duration_seconds", started.elapsed(), "method" => method_name, "version" => version.to_str());
+        if let Err(e) = &result {
+            metrics::increment_counter!("rpc_method_calls_errors_total", "method" => method_name, "version" => version.to_str(), "code" => e.code().to_string());
+        }
+    }
+
+    match result {
         Ok(handle) => {
             if subscriptions.insert(subscription_id, handle).is_some() {
                 panic!("subscription id overflow");
@@ -982,6 +1030,7 @@ mod tests {
             notifications,
             config: RpcConfig {
                 batch_concurrency_limit: 1.try_into().unwrap(),
+                batch_size_limit: 1000.try_into().unwrap(),
                 get_events_max_blocks_to_scan: 1.try_into().unwrap(),
                 get_events_max_uncached_bloom_filters_to_load: 1.try_into().unwrap(),
                 custom_versioned_constants: None,
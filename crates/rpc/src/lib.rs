@@ -61,6 +61,20 @@ impl RpcVersion {
             RpcVersion::PathfinderV01 => "v0.1",
         }
     }
+
+    /// The Starknet JSON-RPC specification versions served by this node.
+    pub const STARKNET_VERSIONS: [RpcVersion; 3] =
+        [RpcVersion::V06, RpcVersion::V07, RpcVersion::V08];
+
+    /// The version of the specification implemented by this API version.
+    pub fn spec_version_str(self) -> &'static str {
+        match self {
+            RpcVersion::V06 => "0.6.0",
+            RpcVersion::V07 => "0.7.1",
+            RpcVersion::V08 => "0.8.0-rc0",
+            RpcVersion::PathfinderV01 => "0.1",
+        }
+    }
 }
 
 // TODO: make this configurable
@@ -73,6 +87,7 @@ pub struct RpcServer {
     context: RpcContext,
     max_connections: usize,
     cors: Option<CorsLayer>,
+    method_metrics: bool,
     default_version: RpcVersion,
 }
 
@@ -83,6 +98,7 @@ impl RpcServer {
             context,
             max_connections: DEFAULT_MAX_CONNECTIONS,
             cors: None,
+            method_metrics: false,
             default_version,
         }
     }
@@ -99,6 +115,14 @@ impl RpcServer {
         }
     }
 
+    /// Records execution time and error code metrics for each RPC method.
+    pub fn with_method_metrics(self, enabled: bool) -> Self {
+        Self {
+            method_metrics: enabled,
+            ..self
+        }
+    }
+
     /// Starts the HTTP-RPC server.
     pub async fn spawn(
         self,
@@ -165,10 +189,18 @@ impl RpcServer {
             }
         }
 
-        let v06_routes = v06::register_routes().build(self.context.clone());
-        let v07_routes = v07::register_routes().build(self.context.clone());
-        let v08_routes = v08::register_routes().build(self.context.clone());
-        let pathfinder_routes = pathfinder::register_routes().build(self.context.clone());
+        let v06_routes = v06::register_routes()
+            .with_method_metrics(self.method_metrics)
+            .build(self.context.clone());
+        let v07_routes = v07::register_routes()
+            .with_method_metrics(self.method_metrics)
+            .build(self.context.clone());
+        let v08_routes = v08::register_routes()
+            .with_method_metrics(self.method_metrics)
+            .build(self.context.clone());
+        let pathfinder_routes = pathfinder::register_routes()
+            .with_method_metrics(self.method_metrics)
+            .build(self.context.clone());
 
         let default_router = match self.default_version {
             RpcVersion::V06 => v06_routes.clone(),
@@ -867,41 +899,35 @@ mod tests {
     #[case::root_trace("/", "v06/starknet_trace_api_openrpc.json", &[])]
     #[case::root_write("/", "v06/starknet_write_api.json",         &[])]
     // get_transaction_status is now part of the official spec, so we are phasing it out.
-    #[case::root_pathfinder("/", "pathfinder_rpc_api.json", &["pathfinder_version", "pathfinder_getTransactionStatus"])]
+    #[case::root_pathfinder("/", "pathfinder_rpc_api.json", &["pathfinder_version", "pathfinder_getTransactionStatus", "pathfinder_supportedSpecVersions"])]
 
     #[case::v0_8_api  ("/rpc/v0_8", "v08/starknet_api_openrpc.json", &[
         "starknet_getBlockWithReceipts",
-        "starknet_getMessagesStatus",
         "starknet_getTransactionReceipt",
         "starknet_call",
         "starknet_estimateFee",
         "starknet_estimateMessageFee",
-        "starknet_getStorageProof",
-    ])]
-    #[case::v0_8_trace("/rpc/v0_8", "v08/starknet_trace_api_openrpc.json", &[
-        "starknet_traceTransaction",
-        "starknet_simulateTransactions",
-        "starknet_traceBlockTransactions"
     ])]
+    #[case::v0_8_trace("/rpc/v0_8", "v08/starknet_trace_api_openrpc.json", &[])]
     #[case::v0_8_write("/rpc/v0_8", "v08/starknet_write_api.json", &[
         "starknet_addInvokeTransaction",
         "starknet_addDeclareTransaction",
         "starknet_addDeployAccountTransaction"
     ])]
     // get_transaction_status is now part of the official spec, so we are phasing it out.
-    #[case::v0_8_pathfinder("/rpc/v0_8", "pathfinder_rpc_api.json", &["pathfinder_version", "pathfinder_getTransactionStatus"])]
+    #[case::v0_8_pathfinder("/rpc/v0_8", "pathfinder_rpc_api.json", &["pathfinder_version", "pathfinder_getTransactionStatus", "pathfinder_supportedSpecVersions"])]
 
     #[case::v0_7_api  ("/rpc/v0_7", "v07/starknet_api_openrpc.json", &[])]
     #[case::v0_7_trace("/rpc/v0_7", "v07/starknet_trace_api_openrpc.json", &[])]
     #[case::v0_7_write("/rpc/v0_7", "v07/starknet_write_api.json", &[])]
     // get_transaction_status is now part of the official spec, so we are phasing it out.
-    #[case::v0_7_pathfinder("/rpc/v0_7", "pathfinder_rpc_api.json", &["pathfinder_version", "pathfinder_getTransactionStatus"])]
+    #[case::v0_7_pathfinder("/rpc/v0_7", "pathfinder_rpc_api.json", &["pathfinder_version", "pathfinder_getTransactionStatus", "pathfinder_supportedSpecVersions"])]
 
     #[case::v0_6_api  ("/rpc/v0_6", "v06/starknet_api_openrpc.json", &[])]
     #[case::v0_6_trace("/rpc/v0_6", "v06/starknet_trace_api_openrpc.json", &[])]
     #[case::v0_6_write("/rpc/v0_6", "v06/starknet_write_api.json", &[])]
     // get_transaction_status is now part of the official spec, so we are phasing it out.
-    #[case::v0_6_pathfinder("/rpc/v0_6", "pathfinder_rpc_api.json", &["pathfinder_version", "pathfinder_getTransactionStatus"])]
+    #[case::v0_6_pathfinder("/rpc/v0_6", "pathfinder_rpc_api.json", &["pathfinder_version", "pathfinder_getTransactionStatus", "pathfinder_supportedSpecVersions"])]
 
     #[case::pathfinder("/rpc/pathfinder/v0.1", "pathfinder_rpc_api.json", &[])]
     #[case::pathfinder("/rpc/pathfinder/v0_1", "pathfinder_rpc_api.json", &[])]
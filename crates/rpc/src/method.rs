@@ -15,9 +15,11 @@ pub mod get_class;
 pub mod get_class_at;
 pub mod get_class_hash_at;
 pub mod get_events;
+pub mod get_messages_status;
 pub mod get_nonce;
 pub mod get_state_update;
 pub mod get_storage_at;
+pub mod get_storage_proof;
 pub mod get_transaction_by_block_id_and_index;
 pub mod get_transaction_by_hash;
 pub mod get_transaction_receipt;
@@ -48,9 +50,11 @@ pub use get_class::get_class;
 pub use get_class_at::get_class_at;
 pub use get_class_hash_at::get_class_hash_at;
 pub use get_events::get_events;
+pub use get_messages_status::get_messages_status;
 pub use get_nonce::get_nonce;
 pub use get_state_update::get_state_update;
 pub use get_storage_at::get_storage_at;
+pub use get_storage_proof::get_storage_proof;
 pub use get_transaction_by_block_id_and_index::get_transaction_by_block_id_and_index;
 pub use get_transaction_by_hash::get_transaction_by_hash;
 pub use get_transaction_receipt::get_transaction_receipt;
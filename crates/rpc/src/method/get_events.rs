@@ -10,6 +10,7 @@ use pathfinder_common::{
     EventKey,
     TransactionHash,
 };
+use pathfinder_crypto::Felt;
 use pathfinder_storage::{EventFilterError, EVENT_KEY_FILTER_LIMIT};
 use starknet_gateway_types::reply::PendingBlock;
 use tokio::task::JoinHandle;
@@ -166,6 +167,10 @@ pub async fn get_events(
             .transaction()
             .context("Creating database transaction")?;
 
+        if let Some(token) = &continuation_token {
+            token.verify_block_hash(&transaction)?;
+        }
+
         // Handle the trivial (1), (2) and (4a) cases.
         match (&request.from_block, &request.to_block) {
             (Some(Pending), id) if !matches!(id, Some(Pending) | None) => {
@@ -228,15 +233,23 @@ pub async fn get_events(
                 EventFilterError::PageSizeTooSmall => GetEventsError::Custom(e.into()),
             })?;
 
-        let mut events = GetEventsResult {
-            events: page.events.into_iter().map(|e| e.into()).collect(),
-            continuation_token: page.continuation_token.map(|token| {
-                ContinuationToken {
+        let continuation_token_for_page = page
+            .continuation_token
+            .map(|token| -> anyhow::Result<String> {
+                let block_hash = transaction.block_hash(token.block_number.into())?;
+                Ok(ContinuationToken {
                     block_number: token.block_number,
                     offset: token.offset,
+                    block_hash,
                 }
-                .to_string()
-            }),
+                .to_string())
+            })
+            .transpose()
+            .context("Fetching continuation token block hash")?;
+
+        let mut events = GetEventsResult {
+            events: page.events.into_iter().map(|e| e.into()).collect(),
+            continuation_token: continuation_token_for_page,
         };
 
         // Append pending data if required.
@@ -277,6 +290,7 @@ pub async fn get_events(
                     let continuation_token = ContinuationToken {
                         block_number: pending.number,
                         offset: current_offset + amount,
+                        block_hash: None,
                     };
                     Some(continuation_token.to_string())
                 };
@@ -287,6 +301,7 @@ pub async fn get_events(
                     ContinuationToken {
                         block_number: pending.number,
                         offset: 0,
+                        block_hash: None,
                     }
                     .to_string(),
                 );
@@ -337,6 +352,7 @@ fn get_pending_events(
             ContinuationToken {
                 block_number: pending.number,
                 offset: current_offset + request.chunk_size,
+                block_hash: None,
             }
             .to_string(),
         )
@@ -470,20 +486,35 @@ fn append_pending_events(
     is_last_page
 }
 
+/// Serialized as `<block number>-<offset>-<block hash>`, or without the hash
+/// if the block is not in the database (yet).
 #[derive(Clone, Copy, Debug, PartialEq)]
 struct ContinuationToken {
     block_number: BlockNumber,
     offset: usize,
+    /// Used to detect that the block was reorged away between pages.
+    block_hash: Option<BlockHash>,
 }
 
 impl FromStr for ContinuationToken {
     type Err = ParseContinuationTokenError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if let Some((block_number, offset)) = s.split_once('-') {
+        if let Some((block_number, rest)) = s.split_once('-') {
             let block_number = block_number
                 .parse::<u64>()
                 .map_err(|_| ParseContinuationTokenError)?;
+            let (offset, block_hash) = match rest.split_once('-') {
+                Some((offset, block_hash)) => {
+                    let block_hash = block_hash
+                        .strip_prefix("0x")
+                        .ok_or(ParseContinuationTokenError)?;
+                    let block_hash =
+                        Felt::from_hex_str(block_hash).map_err(|_| ParseContinuationTokenError)?;
+                    (offset, Some(BlockHash(block_hash)))
+                }
+                None => (rest, None),
+            };
             let offset = offset.parse().map_err(|_| ParseContinuationTokenError)?;
 
             let block_number = BlockNumber::new(block_number).ok_or(ParseContinuationTokenError)?;
@@ -491,6 +522,7 @@ impl FromStr for ContinuationToken {
             Ok(ContinuationToken {
                 block_number,
                 offset,
+                block_hash,
             })
         } else {
             Err(ParseContinuationTokenError)
@@ -500,11 +532,35 @@ impl FromStr for ContinuationToken {
 
 impl std::fmt::Display for ContinuationToken {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}-{}", self.block_number.get(), self.offset)
+        write!(f, "{}-{}", self.block_number.get(), self.offset)?;
+        if let Some(block_hash) = self.block_hash {
+            write!(f, "-{}", block_hash.0.to_hex_str())?;
+        }
+        Ok(())
     }
 }
 
 impl ContinuationToken {
+    /// Fails if the block the token points to is no longer the one it was
+    /// issued for.
+    fn verify_block_hash(
+        &self,
+        tx: &pathfinder_storage::Transaction<'_>,
+    ) -> Result<(), GetEventsError> {
+        let Some(block_hash) = self.block_hash else {
+            return Ok(());
+        };
+
+        let current = tx
+            .block_hash(self.block_number.into())
+            .context("Fetching continuation token block hash")?;
+        if current != Some(block_hash) {
+            return Err(GetEventsError::InvalidContinuationToken);
+        }
+
+        Ok(())
+    }
+
     fn offset_in_block(&self, block_number: BlockNumber) -> Result<usize, GetEventsError> {
         use std::cmp::Ordering;
         match Ord::cmp(&self.block_number, &block_number) {
@@ -678,13 +734,42 @@ mod tests {
             Err(ParseContinuationTokenError)
         );
 
+        assert_matches!(
+            "1234-5678-0xnothex".parse::<ContinuationToken>(),
+            Err(ParseContinuationTokenError)
+        );
+
         assert_eq!(
             "1234-4567".parse::<ContinuationToken>().unwrap(),
             ContinuationToken {
                 block_number: BlockNumber::new_or_panic(1234),
-                offset: 4567
+                offset: 4567,
+                block_hash: None,
             }
         );
+
+        let token = ContinuationToken {
+            block_number: BlockNumber::new_or_panic(1234),
+            offset: 4567,
+            block_hash: Some(block_hash!("0xabc")),
+        };
+        assert_eq!(token.to_string(), "1234-4567-0xabc");
+        assert_eq!("1234-4567-0xabc".parse::<ContinuationToken>(), Ok(token));
+    }
+
+    /// The token the database query returns when continuing from `block`.
+    fn token(context: &RpcContext, block: u64, offset: usize) -> String {
+        let mut db = context.storage.connection().unwrap();
+        let tx = db.transaction().unwrap();
+        let block_number = BlockNumber::new_or_panic(block);
+        let block_hash = tx.block_hash(block_number.into()).unwrap();
+
+        ContinuationToken {
+            block_number,
+            offset,
+            block_hash,
+        }
+        .to_string()
     }
 
     fn setup() -> (RpcContext, Vec<EmittedEvent>) {
@@ -873,7 +958,7 @@ mod tests {
             result,
             GetEventsResult {
                 events: expected_events[..1].to_vec(),
-                continuation_token: Some("0-1".to_string()),
+                continuation_token: Some(token(&context, 0, 1)),
             }
         );
 
@@ -890,7 +975,7 @@ mod tests {
             result,
             GetEventsResult {
                 events: expected_events[1..3].to_vec(),
-                continuation_token: Some("3-0".to_string()),
+                continuation_token: Some(token(&context, 3, 0)),
             }
         );
 
@@ -926,6 +1011,30 @@ mod tests {
         assert_eq!(result.continuation_token, None);
     }
 
+    #[tokio::test]
+    async fn continuation_token_of_reorged_block() {
+        let (context, _) = setup();
+
+        let input = GetEventsInput {
+            filter: EventFilter {
+                chunk_size: 1,
+                continuation_token: Some("0-1-0x1234".to_string()),
+                ..Default::default()
+            },
+        };
+        let error = get_events(context.clone(), input).await.unwrap_err();
+        assert_eq!(error, GetEventsError::InvalidContinuationToken);
+
+        let input = GetEventsInput {
+            filter: EventFilter {
+                chunk_size: 1,
+                continuation_token: Some(token(&context, 0, 1)),
+                ..Default::default()
+            },
+        };
+        get_events(context, input).await.unwrap();
+    }
+
     mod pending {
         use pretty_assertions_sorted::assert_eq;
 
@@ -0,0 +1,265 @@
+use anyhow::Context;
+use pathfinder_common::TransactionHash;
+use primitive_types::H256;
+
+use crate::context::RpcContext;
+use crate::dto::serialize::{self, SerializeForVersion, Serializer};
+use crate::dto::{self, TxnStatus};
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Input {
+    transaction_hash: H256,
+}
+
+impl crate::dto::DeserializeForVersion for Input {
+    fn deserialize(value: crate::dto::Value) -> Result<Self, serde_json::Error> {
+        value.deserialize_map(|value| {
+            Ok(Self {
+                transaction_hash: value
+                    .deserialize::<dto::H256Hex>("transaction_hash")
+                    .map(|hash| hash.0)?,
+            })
+        })
+    }
+}
+
+pub struct Output(Vec<MessageStatus>);
+
+#[derive(Clone, Copy)]
+struct MessageStatus {
+    transaction_hash: TransactionHash,
+    finality_status: TxnStatus,
+}
+
+crate::error::generate_rpc_error_subset!(Error: TxnHashNotFound);
+
+/// Returns the status of the L1 handler transactions triggered by the messages
+/// sent in the given L1 transaction.
+///
+/// Only messages logged on L1 while this node was following it are known, so
+/// L1 transactions sent before upgrading to a version tracking messages are
+/// reported as not found.
+pub async fn get_messages_status(context: RpcContext, input: Input) -> Result<Output, Error> {
+    let span = tracing::Span::current();
+
+    let statuses = tokio::task::spawn_blocking(move || {
+        let _g = span.enter();
+
+        let mut db = context
+            .storage
+            .connection()
+            .context("Opening database connection")?;
+        let db_tx = db.transaction().context("Creating database transaction")?;
+
+        let transaction_hashes = db_tx
+            .l1_handler_tx_hashes_by_l1_tx_hash(input.transaction_hash)
+            .context("Fetching L1 handler transactions")?;
+
+        transaction_hashes
+            .into_iter()
+            .map(|transaction_hash| {
+                let block_hash = db_tx
+                    .transaction_block_hash(transaction_hash)
+                    .context("Fetching transaction's block hash")?
+                    .context("L1 handler transaction's block is missing")?;
+                let l1_accepted = db_tx
+                    .block_is_l1_accepted(block_hash.into())
+                    .context("Querying block's status")?;
+
+                let finality_status = if l1_accepted {
+                    TxnStatus::AcceptedOnL1
+                } else {
+                    TxnStatus::AcceptedOnL2
+                };
+
+                Ok(MessageStatus {
+                    transaction_hash,
+                    finality_status,
+                })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()
+    })
+    .await
+    .context("Joining database task")??;
+
+    if statuses.is_empty() {
+        return Err(Error::TxnHashNotFound);
+    }
+
+    Ok(Output(statuses))
+}
+
+impl SerializeForVersion for Output {
+    fn serialize(&self, serializer: Serializer) -> Result<serialize::Ok, serialize::Error> {
+        serializer.serialize_iter(self.0.len(), &mut self.0.iter().copied())
+    }
+}
+
+impl SerializeForVersion for MessageStatus {
+    fn serialize(&self, serializer: Serializer) -> Result<serialize::Ok, serialize::Error> {
+        let mut serializer = serializer.serialize_struct()?;
+        serializer.serialize_field("transaction_hash", &dto::TxnHash(&self.transaction_hash))?;
+        serializer.serialize_field("finality_status", &self.finality_status)?;
+        serializer.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pathfinder_common::macro_prelude::*;
+    use pathfinder_common::receipt::Receipt;
+    use pathfinder_common::transaction::{
+        L1HandlerTransaction,
+        Transaction as StarknetTransaction,
+        TransactionVariant,
+    };
+    use pathfinder_common::{BlockHeader, BlockNumber, L1ToL2MessageLog};
+    use pathfinder_storage::StorageBuilder;
+    use serde_json::json;
+
+    use super::*;
+    use crate::dto::DeserializeForVersion;
+    use crate::RpcVersion;
+
+    const L1_TX_HASH: H256 = H256::repeat_byte(0x11);
+
+    /// Stores an L1 handler transaction in each of two blocks, both triggered
+    /// by [L1_TX_HASH]. Only the first block is accepted on L1.
+    fn setup() -> RpcContext {
+        let storage = StorageBuilder::in_memory().unwrap();
+        let mut connection = storage.connection().unwrap();
+        let db_tx = connection.transaction().unwrap();
+
+        let header0 = BlockHeader::builder()
+            .number(BlockNumber::GENESIS)
+            .finalize_with_hash(block_hash_bytes!(b"genesis"));
+        let header1 = header0
+            .child_builder()
+            .finalize_with_hash(block_hash_bytes!(b"block 1"));
+
+        for (header, hash, nonce) in [
+            (
+                &header0,
+                transaction_hash_bytes!(b"txn 0"),
+                transaction_nonce!("0x0"),
+            ),
+            (
+                &header1,
+                transaction_hash_bytes!(b"txn 1"),
+                transaction_nonce!("0x1"),
+            ),
+        ] {
+            let l1_handler = L1HandlerTransaction {
+                contract_address: contract_address!("0x1234"),
+                entry_point_selector: entry_point!("0x5678"),
+                nonce,
+                calldata: vec![call_param!("0xabcd")],
+            };
+            let message_hash = l1_handler.calculate_message_hash();
+            let transaction = StarknetTransaction {
+                hash,
+                variant: TransactionVariant::L1Handler(l1_handler),
+            };
+
+            db_tx.insert_block_header(header).unwrap();
+            db_tx
+                .insert_transaction_data(header.number, &[(transaction, Receipt::default())], None)
+                .unwrap();
+            db_tx
+                .insert_l1_to_l2_message_log(&L1ToL2MessageLog {
+                    message_hash,
+                    l1_tx_hash: L1_TX_HASH,
+                })
+                .unwrap();
+        }
+        db_tx.update_l1_l2_pointer(Some(header0.number)).unwrap();
+        db_tx.commit().unwrap();
+
+        RpcContext::for_tests().with_storage(storage)
+    }
+
+    #[test]
+    fn parse_input() {
+        let input = json!({"transaction_hash": "0x1234"});
+        let input = Input::deserialize(crate::dto::Value::new(input, RpcVersion::V08)).unwrap();
+        assert_eq!(
+            input,
+            Input {
+                transaction_hash: H256::from_low_u64_be(0x1234),
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn statuses() {
+        let context = setup();
+
+        let input = Input {
+            transaction_hash: L1_TX_HASH,
+        };
+        let output = get_messages_status(context, input)
+            .await
+            .unwrap()
+            .serialize(Serializer::new(RpcVersion::V08))
+            .unwrap();
+
+        assert_eq!(
+            output,
+            json!([
+                {
+                    "transaction_hash": transaction_hash_bytes!(b"txn 0"),
+                    "finality_status": "ACCEPTED_ON_L1",
+                },
+                {
+                    "transaction_hash": transaction_hash_bytes!(b"txn 1"),
+                    "finality_status": "ACCEPTED_ON_L2",
+                },
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn unknown_l1_transaction() {
+        let context = setup();
+
+        let input = Input {
+            transaction_hash: H256::repeat_byte(0x22),
+        };
+        let error = get_messages_status(context, input).await.unwrap_err();
+        assert!(matches!(error, Error::TxnHashNotFound));
+    }
+
+    #[tokio::test]
+    async fn message_logged_before_upgrade() {
+        let storage = StorageBuilder::in_memory().unwrap();
+        let mut connection = storage.connection().unwrap();
+        let db_tx = connection.transaction().unwrap();
+
+        // The L1 handler transaction is stored but its message log was emitted
+        // before the node started recording them.
+        let header = BlockHeader::builder()
+            .number(BlockNumber::GENESIS)
+            .finalize_with_hash(block_hash_bytes!(b"genesis"));
+        let transaction = StarknetTransaction {
+            hash: transaction_hash_bytes!(b"txn 0"),
+            variant: TransactionVariant::L1Handler(L1HandlerTransaction {
+                contract_address: contract_address!("0x1234"),
+                entry_point_selector: entry_point!("0x5678"),
+                nonce: transaction_nonce!("0x0"),
+                calldata: vec![call_param!("0xabcd")],
+            }),
+        };
+        db_tx.insert_block_header(&header).unwrap();
+        db_tx
+            .insert_transaction_data(header.number, &[(transaction, Receipt::default())], None)
+            .unwrap();
+        db_tx.commit().unwrap();
+        let context = RpcContext::for_tests().with_storage(storage);
+
+        let input = Input {
+            transaction_hash: L1_TX_HASH,
+        };
+        let error = get_messages_status(context, input).await.unwrap_err();
+        assert!(matches!(error, Error::TxnHashNotFound));
+    }
+}
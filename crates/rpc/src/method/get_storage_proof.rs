@@ -0,0 +1,433 @@
+use anyhow::{anyhow, Context};
+use pathfinder_common::prelude::*;
+use pathfinder_common::trie::TrieNode;
+use pathfinder_common::BlockId;
+use pathfinder_crypto::Felt;
+use pathfinder_merkle_tree::{
+    storage_proof,
+    ContractLeafData,
+    NodeHashToNodeMapping,
+    ProofError,
+    StorageProof,
+};
+
+use crate::context::RpcContext;
+
+/// The maximum number of classes, contracts and storage keys which can be
+/// proven in a single request.
+const MAX_KEYS: usize = 100;
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Input {
+    pub block_id: BlockId,
+    pub class_hashes: Vec<ClassHash>,
+    pub contract_addresses: Vec<ContractAddress>,
+    pub contracts_storage_keys: Vec<ContractStorageKeys>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct ContractStorageKeys {
+    pub contract_address: ContractAddress,
+    pub storage_keys: Vec<StorageAddress>,
+}
+
+impl crate::dto::DeserializeForVersion for Input {
+    fn deserialize(value: crate::dto::Value) -> Result<Self, serde_json::Error> {
+        value.deserialize_map(|value| {
+            Ok(Self {
+                block_id: value.deserialize("block_id")?,
+                class_hashes: value
+                    .deserialize_optional_array("class_hashes", |value| {
+                        value.deserialize().map(ClassHash)
+                    })?
+                    .unwrap_or_default(),
+                contract_addresses: value
+                    .deserialize_optional_array("contract_addresses", |value| {
+                        value.deserialize().map(ContractAddress)
+                    })?
+                    .unwrap_or_default(),
+                contracts_storage_keys: value
+                    .deserialize_optional_array("contracts_storage_keys", |value| {
+                        value.deserialize()
+                    })?
+                    .unwrap_or_default(),
+            })
+        })
+    }
+}
+
+impl crate::dto::DeserializeForVersion for ContractStorageKeys {
+    fn deserialize(value: crate::dto::Value) -> Result<Self, serde_json::Error> {
+        value.deserialize_map(|value| {
+            Ok(Self {
+                contract_address: value.deserialize("contract_address").map(ContractAddress)?,
+                storage_keys: value.deserialize_array("storage_keys", |value| {
+                    value.deserialize().map(StorageAddress)
+                })?,
+            })
+        })
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct GlobalRoots {
+    pub contracts_tree_root: StorageCommitment,
+    pub classes_tree_root: ClassCommitment,
+    pub block_hash: BlockHash,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Output {
+    pub classes_proof: NodeHashToNodeMapping,
+    pub contracts_proof: NodeHashToNodeMapping,
+    /// In the same order as the requested contract addresses.
+    pub contract_leaves_data: Vec<ContractLeafData>,
+    /// In the same order as the requested contracts' storage keys.
+    pub contracts_storage_proofs: Vec<NodeHashToNodeMapping>,
+    pub global_roots: GlobalRoots,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Internal(anyhow::Error),
+    BlockNotFound,
+    StorageProofNotSupported,
+    ProofLimitExceeded { limit: u32, requested: u32 },
+}
+
+impl From<anyhow::Error> for Error {
+    fn from(e: anyhow::Error) -> Self {
+        Self::Internal(e)
+    }
+}
+
+impl From<ProofError> for Error {
+    fn from(e: ProofError) -> Self {
+        match e {
+            ProofError::Pruned { .. } => Self::StorageProofNotSupported,
+            ProofError::Internal(e) => Self::Internal(e),
+        }
+    }
+}
+
+impl From<Error> for crate::error::ApplicationError {
+    fn from(x: Error) -> Self {
+        match x {
+            Error::Internal(internal) => Self::Internal(internal),
+            Error::BlockNotFound => Self::BlockNotFound,
+            Error::StorageProofNotSupported => Self::StorageProofNotSupported,
+            Error::ProofLimitExceeded { limit, requested } => {
+                Self::ProofLimitExceeded { limit, requested }
+            }
+        }
+    }
+}
+
+/// Returns merkle proofs for the given classes, contracts and contract storage
+/// keys, along with the roots they can be verified against.
+///
+/// Proofs of keys which are not in their tree are proofs of non-membership.
+pub async fn get_storage_proof(context: RpcContext, input: Input) -> Result<Output, Error> {
+    let requested = input.class_hashes.len()
+        + input.contract_addresses.len()
+        + input
+            .contracts_storage_keys
+            .iter()
+            .map(|contract| contract.storage_keys.len())
+            .sum::<usize>();
+    if requested > MAX_KEYS {
+        return Err(Error::ProofLimitExceeded {
+            limit: MAX_KEYS as u32,
+            requested: requested as u32,
+        });
+    }
+
+    let block_id = match input.block_id {
+        BlockId::Pending => {
+            return Err(Error::Internal(anyhow!(
+                "'pending' is not currently supported by this method!"
+            )))
+        }
+        other => other.try_into().expect("Only pending cast should fail"),
+    };
+
+    let span = tracing::Span::current();
+
+    let jh = tokio::task::spawn_blocking(move || {
+        let _g = span.enter();
+        let mut db = context
+            .storage
+            .connection()
+            .context("Opening database connection")?;
+
+        let tx = db.transaction().context("Creating database transaction")?;
+
+        let header = tx
+            .block_header(block_id)
+            .context("Fetching block header")?
+            .ok_or(Error::BlockNotFound)?;
+
+        let storage_keys = input
+            .contracts_storage_keys
+            .iter()
+            .flat_map(|contract| {
+                contract
+                    .storage_keys
+                    .iter()
+                    .map(|&key| (contract.contract_address, key))
+            })
+            .collect::<Vec<_>>();
+        let StorageProof {
+            classes_proof,
+            contracts_proof,
+            contract_leaves_data,
+            contracts_storage_proofs,
+            contracts_tree_root,
+            classes_tree_root,
+        } = storage_proof(
+            &tx,
+            header.number,
+            &input.contract_addresses,
+            &storage_keys,
+            &input.class_hashes,
+        )?;
+
+        let contracts_storage_proofs = input
+            .contracts_storage_keys
+            .iter()
+            .map(|contract| {
+                contracts_storage_proofs
+                    .iter()
+                    .find(|(address, _)| *address == contract.contract_address)
+                    .map(|(_, proof)| proof.clone())
+                    .unwrap_or_default()
+            })
+            .collect();
+
+        Ok(Output {
+            classes_proof,
+            contracts_proof,
+            contract_leaves_data,
+            contracts_storage_proofs,
+            global_roots: GlobalRoots {
+                contracts_tree_root,
+                classes_tree_root,
+                block_hash: header.hash,
+            },
+        })
+    });
+
+    jh.await.context("Database read panic or shutting down")?
+}
+
+struct Nodes<'a>(&'a NodeHashToNodeMapping);
+
+impl crate::dto::serialize::SerializeForVersion for Nodes<'_> {
+    fn serialize(
+        &self,
+        serializer: crate::dto::serialize::Serializer,
+    ) -> Result<crate::dto::serialize::Ok, crate::dto::serialize::Error> {
+        struct Node<'a>(&'a TrieNode);
+
+        impl crate::dto::serialize::SerializeForVersion for Node<'_> {
+            fn serialize(
+                &self,
+                serializer: crate::dto::serialize::Serializer,
+            ) -> Result<crate::dto::serialize::Ok, crate::dto::serialize::Error> {
+                let mut serializer = serializer.serialize_struct()?;
+                match self.0 {
+                    TrieNode::Binary { left, right } => {
+                        serializer.serialize_field("left", &crate::dto::Felt(left))?;
+                        serializer.serialize_field("right", &crate::dto::Felt(right))?;
+                    }
+                    TrieNode::Edge { child, path } => {
+                        let value = Felt::from_bits(path).expect("Edge path fits in a felt");
+                        serializer.serialize_field("path", &crate::dto::Felt(&value))?;
+                        serializer.serialize_field("length", &path.len())?;
+                        serializer.serialize_field("child", &crate::dto::Felt(child))?;
+                    }
+                }
+                serializer.end()
+            }
+        }
+
+        struct Entry<'a>(&'a Felt, &'a TrieNode);
+
+        impl crate::dto::serialize::SerializeForVersion for Entry<'_> {
+            fn serialize(
+                &self,
+                serializer: crate::dto::serialize::Serializer,
+            ) -> Result<crate::dto::serialize::Ok, crate::dto::serialize::Error> {
+                let mut serializer = serializer.serialize_struct()?;
+                serializer.serialize_field("node_hash", &crate::dto::Felt(self.0))?;
+                serializer.serialize_field("node", &Node(self.1))?;
+                serializer.end()
+            }
+        }
+
+        serializer.serialize_iter(
+            self.0 .0.len(),
+            &mut self.0 .0.iter().map(|(hash, node)| Entry(hash, node)),
+        )
+    }
+}
+
+impl crate::dto::serialize::SerializeForVersion for ContractLeafData {
+    fn serialize(
+        &self,
+        serializer: crate::dto::serialize::Serializer,
+    ) -> Result<crate::dto::serialize::Ok, crate::dto::serialize::Error> {
+        let mut serializer = serializer.serialize_struct()?;
+        serializer.serialize_field("nonce", &crate::dto::Felt(&self.nonce.0))?;
+        serializer.serialize_field("class_hash", &crate::dto::Felt(&self.class_hash.0))?;
+        serializer.serialize_field("storage_root", &crate::dto::Felt(&self.storage_root.0))?;
+        serializer.end()
+    }
+}
+
+impl crate::dto::serialize::SerializeForVersion for GlobalRoots {
+    fn serialize(
+        &self,
+        serializer: crate::dto::serialize::Serializer,
+    ) -> Result<crate::dto::serialize::Ok, crate::dto::serialize::Error> {
+        let mut serializer = serializer.serialize_struct()?;
+        serializer.serialize_field(
+            "contracts_tree_root",
+            &crate::dto::Felt(&self.contracts_tree_root.0),
+        )?;
+        serializer.serialize_field(
+            "classes_tree_root",
+            &crate::dto::Felt(&self.classes_tree_root.0),
+        )?;
+        serializer.serialize_field("block_hash", &crate::dto::BlockHash(&self.block_hash))?;
+        serializer.end()
+    }
+}
+
+impl crate::dto::serialize::SerializeForVersion for Output {
+    fn serialize(
+        &self,
+        serializer: crate::dto::serialize::Serializer,
+    ) -> Result<crate::dto::serialize::Ok, crate::dto::serialize::Error> {
+        struct ContractsProof<'a>(&'a Output);
+
+        impl crate::dto::serialize::SerializeForVersion for ContractsProof<'_> {
+            fn serialize(
+                &self,
+                serializer: crate::dto::serialize::Serializer,
+            ) -> Result<crate::dto::serialize::Ok, crate::dto::serialize::Error> {
+                let mut serializer = serializer.serialize_struct()?;
+                serializer.serialize_field("nodes", &Nodes(&self.0.contracts_proof))?;
+                serializer.serialize_iter(
+                    "contract_leaves_data",
+                    self.0.contract_leaves_data.len(),
+                    &mut self.0.contract_leaves_data.iter().copied(),
+                )?;
+                serializer.end()
+            }
+        }
+
+        let mut serializer = serializer.serialize_struct()?;
+        serializer.serialize_field("classes_proof", &Nodes(&self.classes_proof))?;
+        serializer.serialize_field("contracts_proof", &ContractsProof(self))?;
+        serializer.serialize_iter(
+            "contracts_storage_proofs",
+            self.contracts_storage_proofs.len(),
+            &mut self.contracts_storage_proofs.iter().map(Nodes),
+        )?;
+        serializer.serialize_field("global_roots", &self.global_roots)?;
+        serializer.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_matches::assert_matches;
+    use pathfinder_common::hash::PedersenHash;
+    use pathfinder_common::macro_prelude::*;
+    use serde_json::json;
+
+    use super::*;
+    use crate::dto::DeserializeForVersion;
+    use crate::RpcVersion;
+
+    #[test]
+    fn parsing() {
+        let input = json!({
+            "block_id": "latest",
+            "contract_addresses": ["0x1"],
+            "contracts_storage_keys": [
+                {"contract_address": "0x2", "storage_keys": ["0x3", "0x4"]}
+            ],
+        });
+        let expected = Input {
+            block_id: BlockId::Latest,
+            class_hashes: vec![],
+            contract_addresses: vec![contract_address!("0x1")],
+            contracts_storage_keys: vec![ContractStorageKeys {
+                contract_address: contract_address!("0x2"),
+                storage_keys: vec![storage_address!("0x3"), storage_address!("0x4")],
+            }],
+        };
+
+        let input = Input::deserialize(crate::dto::Value::new(input, RpcVersion::V08)).unwrap();
+
+        assert_eq!(input, expected);
+    }
+
+    #[tokio::test]
+    async fn contracts_and_storage() {
+        let context = RpcContext::for_tests();
+        let contract = contract_address_bytes!(b"contract 1");
+        let input = Input {
+            block_id: BlockId::Latest,
+            class_hashes: vec![],
+            contract_addresses: vec![contract, contract_address_bytes!(b"non-existent")],
+            contracts_storage_keys: vec![ContractStorageKeys {
+                contract_address: contract,
+                storage_keys: vec![storage_address_bytes!(b"storage addr 0")],
+            }],
+        };
+
+        let output = get_storage_proof(context, input).await.unwrap();
+
+        assert!(!output.contracts_proof.0.is_empty());
+        assert_eq!(
+            output.contract_leaves_data,
+            vec![
+                ContractLeafData {
+                    nonce: contract_nonce!("0x10"),
+                    class_hash: class_hash_bytes!(b"class 1 hash"),
+                    storage_root: output.contract_leaves_data[0].storage_root,
+                },
+                ContractLeafData {
+                    nonce: ContractNonce::ZERO,
+                    class_hash: ClassHash::ZERO,
+                    storage_root: ContractRoot::ZERO,
+                },
+            ]
+        );
+        assert_eq!(output.contracts_storage_proofs.len(), 1);
+        assert!(!output.contracts_storage_proofs[0].0.is_empty());
+        // Every node is keyed by its own hash.
+        for (hash, node) in &output.contracts_storage_proofs[0].0 {
+            assert_eq!(*hash, node.hash::<PedersenHash>());
+        }
+    }
+
+    #[tokio::test]
+    async fn limit_exceeded() {
+        let context = RpcContext::for_tests();
+        let input = Input {
+            block_id: BlockId::Latest,
+            class_hashes: vec![],
+            contract_addresses: (0..MAX_KEYS as u64 + 1)
+                .map(|idx| ContractAddress::new_or_panic(Felt::from_u64(idx)))
+                .collect(),
+            contracts_storage_keys: vec![],
+        };
+
+        let err = get_storage_proof(context, input).await.unwrap_err();
+        assert_matches!(err, Error::ProofLimitExceeded { .. });
+    }
+}
@@ -327,6 +327,88 @@ pub(crate) mod tests {
         pretty_assertions_sorted::assert_eq!(result, expected);
     }
 
+    #[tokio::test]
+    async fn test_simulate_transaction_with_skip_fee_charge_v08() {
+        let (context, _, _, _) = crate::test_setup::test_context().await;
+
+        let input_json = serde_json::json!({
+            "block_id": {"block_number": 1},
+            "transactions": [
+                {
+                    "contract_address_salt": "0x46c0d4abf0192a788aca261e58d7031576f7d8ea5229f452b0f23e691dd5971",
+                    "max_fee": "0x0",
+                    "signature": [],
+                    "class_hash": DUMMY_ACCOUNT_CLASS_HASH,
+                    "nonce": "0x0",
+                    "version": TransactionVersion::ONE_WITH_QUERY_VERSION,
+                    "constructor_calldata": [],
+                    "type": "DEPLOY_ACCOUNT"
+                }
+            ],
+            "simulation_flags": ["SKIP_FEE_CHARGE"]
+        });
+        let input = SimulateTransactionInput::deserialize(&input_json).unwrap();
+
+        let result = simulate_transactions(context, input).await.expect("result");
+        let result = result
+            .serialize(Serializer {
+                version: RpcVersion::V08,
+            })
+            .unwrap();
+
+        pretty_assertions_sorted::assert_eq!(
+            result[0]["fee_estimation"],
+            serde_json::json!({
+                "l1_gas_consumed": "0x13",
+                "l1_gas_price": "0x1",
+                "l1_data_gas_consumed": "0xa0",
+                "l1_data_gas_price": "0x2",
+                "l2_gas_consumed": "0x0",
+                "l2_gas_price": "0x0",
+                "overall_fee": "0x153",
+                "unit": "WEI",
+            })
+        );
+        pretty_assertions_sorted::assert_eq!(
+            result[0]["transaction_trace"]["execution_resources"],
+            serde_json::json!({
+                "l1_gas": 0,
+                "l1_data_gas": 160,
+                "l2_gas": 0,
+            })
+        );
+    }
+
+    /// Rewrites the v0.7 `expected` simulation output into the v0.8 one, which
+    /// splits fees and resources by gas kind.
+    pub(crate) fn simulations_v08(mut expected: serde_json::Value) -> serde_json::Value {
+        for simulation in expected.as_array_mut().unwrap() {
+            let fee = simulation["fee_estimation"].clone();
+            simulation["fee_estimation"] = serde_json::json!({
+                "l1_gas_consumed": fee["gas_consumed"],
+                "l1_gas_price": fee["gas_price"],
+                "l1_data_gas_consumed": fee["data_gas_consumed"],
+                "l1_data_gas_price": fee["data_gas_price"],
+                "l2_gas_consumed": "0x0",
+                "l2_gas_price": "0x0",
+                "overall_fee": fee["overall_fee"],
+                "unit": fee["unit"],
+            });
+            trace_v08(&mut simulation["transaction_trace"]);
+        }
+        expected
+    }
+
+    /// Rewrites the execution resources of a v0.7 `trace` into the v0.8 ones.
+    pub(crate) fn trace_v08(trace: &mut serde_json::Value) {
+        let data_availability = trace["execution_resources"]["data_availability"].clone();
+        trace["execution_resources"] = serde_json::json!({
+            "l1_gas": data_availability["l1_gas"],
+            "l1_data_gas": data_availability["l1_data_gas"],
+            "l2_gas": 0,
+        });
+    }
+
     #[tokio::test]
     async fn declare_cairo_v0_class() {
         pub const CAIRO0_DEFINITION: &[u8] =
@@ -1631,6 +1713,65 @@ pub(crate) mod tests {
         );
     }
 
+    #[test_log::test(tokio::test)]
+    async fn declare_deploy_and_invoke_sierra_class_v08() {
+        let (
+            storage,
+            last_block_header,
+            account_contract_address,
+            universal_deployer_address,
+            test_storage_value,
+        ) = setup_storage_with_starknet_version(StarknetVersion::new(0, 13, 1, 1)).await;
+        let context = RpcContext::for_tests().with_storage(storage);
+
+        let input = SimulateTransactionInput {
+            transactions: vec![
+                fixtures::input::declare(account_contract_address),
+                fixtures::input::universal_deployer(
+                    account_contract_address,
+                    universal_deployer_address,
+                ),
+                fixtures::input::invoke(account_contract_address),
+                fixtures::input::invoke_v3(account_contract_address),
+            ],
+            block_id: BlockId::Number(last_block_header.number),
+            simulation_flags: dto::SimulationFlags(vec![]),
+        };
+        let result = simulate_transactions(context, input).await.unwrap();
+
+        pretty_assertions_sorted::assert_eq!(
+            result
+                .serialize(Serializer {
+                    version: RpcVersion::V08
+                })
+                .unwrap(),
+            simulations_v08(
+                serde_json::to_value(vec![
+                    fixtures::expected_output_0_13_1_1::declare(
+                        account_contract_address,
+                        &last_block_header
+                    ),
+                    fixtures::expected_output_0_13_1_1::universal_deployer(
+                        account_contract_address,
+                        &last_block_header,
+                        universal_deployer_address,
+                    ),
+                    fixtures::expected_output_0_13_1_1::invoke(
+                        account_contract_address,
+                        &last_block_header,
+                        test_storage_value,
+                    ),
+                    fixtures::expected_output_0_13_1_1::invoke_v3(
+                        account_contract_address,
+                        &last_block_header,
+                        test_storage_value,
+                    ),
+                ])
+                .unwrap()
+            )
+        );
+    }
+
     #[test_log::test(tokio::test)]
     async fn declare_deploy_and_invoke_sierra_class_with_skip_fee_charge() {
         let (
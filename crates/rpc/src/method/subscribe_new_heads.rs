@@ -172,7 +172,7 @@ mod tests {
 
     #[tokio::test]
     async fn happy_path_with_historic_blocks() {
-        happy_path_test(2000).await;
+        happy_path_test(1000).await;
     }
 
     #[tokio::test]
@@ -457,6 +457,35 @@ mod tests {
         assert!(rx.is_empty());
     }
 
+    #[tokio::test]
+    async fn closing_websocket_ends_subscriptions() {
+        let (tx, _rx, _, router) = happy_path_test(0).await;
+        assert_eq!(
+            router.context.notifications.block_headers.receiver_count(),
+            1
+        );
+        drop(tx);
+        // Give time for background tasks to process.
+        for _ in 0..10 {
+            tokio::task::yield_now().await;
+        }
+        // The notification listener notices that its subscription ended once it has
+        // something to forward.
+        router
+            .context
+            .notifications
+            .block_headers
+            .send(sample_header(10).into())
+            .unwrap();
+        for _ in 0..10 {
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(
+            router.context.notifications.block_headers.receiver_count(),
+            0
+        );
+    }
+
     async fn setup(num_blocks: u64) -> RpcRouter {
         let storage = StorageBuilder::in_memory().unwrap();
         tokio::task::spawn_blocking({
@@ -490,6 +519,7 @@ mod tests {
             notifications,
             config: RpcConfig {
                 batch_concurrency_limit: 1.try_into().unwrap(),
+                batch_size_limit: 1000.try_into().unwrap(),
                 get_events_max_blocks_to_scan: 1.try_into().unwrap(),
                 get_events_max_uncached_bloom_filters_to_load: 1.try_into().unwrap(),
                 custom_versioned_constants: None,
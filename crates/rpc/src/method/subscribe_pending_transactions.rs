@@ -486,6 +486,7 @@ mod tests {
             notifications,
             config: RpcConfig {
                 batch_concurrency_limit: 1.try_into().unwrap(),
+                batch_size_limit: 1000.try_into().unwrap(),
                 get_events_max_blocks_to_scan: 1.try_into().unwrap(),
                 get_events_max_uncached_bloom_filters_to_load: 1.try_into().unwrap(),
                 custom_versioned_constants: None,
@@ -589,8 +589,9 @@ pub(crate) mod tests {
     use tokio::task::JoinSet;
 
     use super::v06::{Trace, TraceBlockTransactionsInput, TraceBlockTransactionsOutput};
-    use super::{trace_block_transactions, RpcContext};
+    use super::{trace_block_transactions, RpcContext, TraceBlockTransactionsError};
     use crate::dto::serialize::{SerializeForVersion, Serializer};
+    use crate::method::simulate_transactions::tests::trace_v08;
     use crate::v06::method::simulate_transactions::tests::setup_storage_with_starknet_version;
     use crate::RpcVersion;
 
@@ -758,6 +759,50 @@ pub(crate) mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_multiple_transactions_v08() -> anyhow::Result<()> {
+        let (context, next_block_header, traces) = setup_multi_tx_trace_test().await?;
+
+        let input = TraceBlockTransactionsInput {
+            block_id: next_block_header.hash.into(),
+        };
+        let output = trace_block_transactions(context, input).await.unwrap();
+        let mut expected = serde_json::to_value(TraceBlockTransactionsOutput(traces)).unwrap();
+        for trace in expected.as_array_mut().unwrap() {
+            trace_v08(&mut trace["trace_root"]);
+        }
+
+        pretty_assertions_sorted::assert_eq!(
+            output
+                .serialize(Serializer {
+                    version: RpcVersion::V08,
+                })
+                .unwrap(),
+            expected
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn block_not_found() -> anyhow::Result<()> {
+        let (context, next_block_header, _) = setup_multi_tx_trace_test().await?;
+
+        let input = TraceBlockTransactionsInput {
+            block_id: BlockId::Number(next_block_header.number + 1),
+        };
+        let error = trace_block_transactions(context, input)
+            .await
+            .err()
+            .unwrap();
+
+        assert_matches::assert_matches!(error, TraceBlockTransactionsError::BlockNotFound);
+        assert_matches::assert_matches!(
+            crate::error::ApplicationError::from(error),
+            crate::error::ApplicationError::BlockNotFound
+        );
+        Ok(())
+    }
+
     pub(crate) async fn setup_multi_tx_trace_pending_test(
     ) -> anyhow::Result<(RpcContext, Vec<Trace>)> {
         use super::super::simulate_transactions::tests::fixtures;
@@ -893,4 +938,28 @@ pub(crate) mod tests {
         );
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_multiple_pending_transactions_v08() -> anyhow::Result<()> {
+        let (context, traces) = setup_multi_tx_trace_pending_test().await?;
+
+        let input = TraceBlockTransactionsInput {
+            block_id: BlockId::Pending,
+        };
+        let output = trace_block_transactions(context, input).await.unwrap();
+        let mut expected = serde_json::to_value(TraceBlockTransactionsOutput(traces)).unwrap();
+        for trace in expected.as_array_mut().unwrap() {
+            trace_v08(&mut trace["trace_root"]);
+        }
+
+        pretty_assertions_sorted::assert_eq!(
+            output
+                .serialize(Serializer {
+                    version: RpcVersion::V08,
+                })
+                .unwrap(),
+            expected
+        );
+        Ok(())
+    }
 }
@@ -256,6 +256,8 @@ impl From<TraceTransactionError> for ApplicationError {
 
 #[cfg(test)]
 pub mod tests {
+    use pathfinder_common::macro_prelude::*;
+
     use super::super::trace_block_transactions::tests::{
         setup_multi_tx_trace_pending_test,
         setup_multi_tx_trace_test,
@@ -263,6 +265,7 @@ pub mod tests {
     use super::v06::{TraceTransactionInput, TraceTransactionOutput};
     use super::*;
     use crate::dto::serialize::{SerializeForVersion, Serializer};
+    use crate::method::simulate_transactions::tests::trace_v08;
     use crate::RpcVersion;
 
     #[tokio::test]
@@ -310,4 +313,160 @@ pub mod tests {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_multiple_transactions_v08() -> anyhow::Result<()> {
+        let (context, _, traces) = setup_multi_tx_trace_test().await?;
+
+        for trace in traces {
+            let input = TraceTransactionInput {
+                transaction_hash: trace.transaction_hash,
+            };
+            let output = trace_transaction(context.clone(), input).await.unwrap();
+            let mut expected =
+                serde_json::to_value(TraceTransactionOutput(trace.trace_root)).unwrap();
+            trace_v08(&mut expected);
+            pretty_assertions_sorted::assert_eq!(
+                output
+                    .serialize(Serializer {
+                        version: RpcVersion::V08
+                    })
+                    .unwrap(),
+                expected
+            );
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_multiple_pending_transactions_v08() -> anyhow::Result<()> {
+        let (context, traces) = setup_multi_tx_trace_pending_test().await?;
+
+        for trace in traces {
+            let input = TraceTransactionInput {
+                transaction_hash: trace.transaction_hash,
+            };
+            let output = trace_transaction(context.clone(), input).await.unwrap();
+            let mut expected =
+                serde_json::to_value(TraceTransactionOutput(trace.trace_root)).unwrap();
+            trace_v08(&mut expected);
+            pretty_assertions_sorted::assert_eq!(
+                output
+                    .serialize(Serializer {
+                        version: RpcVersion::V08
+                    })
+                    .unwrap(),
+                expected
+            );
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn transaction_not_found() -> anyhow::Result<()> {
+        let (context, _, _) = setup_multi_tx_trace_test().await?;
+
+        let input = TraceTransactionInput {
+            transaction_hash: transaction_hash_bytes!(b"not found"),
+        };
+        let error = trace_transaction(context, input).await.unwrap_err();
+
+        assert_matches::assert_matches!(error, TraceTransactionError::TxnHashNotFound);
+        assert_matches::assert_matches!(
+            ApplicationError::from(error),
+            ApplicationError::TxnHashNotFound
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn l1_handler_trace_v08() {
+        use pathfinder_crypto::Felt;
+        use pathfinder_executor::types::{
+            CallType,
+            ComputationResources,
+            DataAvailabilityResources,
+            EntryPointType,
+            ExecutionResources,
+            FunctionInvocation,
+            L1HandlerTransactionTrace,
+            TransactionTrace,
+        };
+
+        let output = Output {
+            trace: TransactionTrace::L1Handler(L1HandlerTransactionTrace {
+                function_invocation: Some(FunctionInvocation {
+                    calldata: vec![Felt::from_u64(1)],
+                    contract_address: contract_address!("0x2"),
+                    selector: Felt::from_u64(3),
+                    call_type: CallType::Call,
+                    caller_address: Felt::ZERO,
+                    internal_calls: vec![],
+                    class_hash: Some(Felt::from_u64(4)),
+                    entry_point_type: EntryPointType::L1Handler,
+                    events: vec![],
+                    messages: vec![],
+                    result: vec![],
+                    computation_resources: ComputationResources {
+                        steps: 10,
+                        ..Default::default()
+                    },
+                }),
+                state_diff: Default::default(),
+                execution_resources: ExecutionResources {
+                    computation_resources: ComputationResources {
+                        steps: 10,
+                        ..Default::default()
+                    },
+                    data_availability: DataAvailabilityResources {
+                        l1_gas: 5,
+                        l1_data_gas: 7,
+                    },
+                },
+            }),
+            include_state_diff: true,
+        };
+
+        let expected = serde_json::json!({
+            "type": "L1_HANDLER",
+            "function_invocation": {
+                "call_type": "CALL",
+                "caller_address": "0x0",
+                "calls": [],
+                "class_hash": "0x4",
+                "entry_point_type": "L1_HANDLER",
+                "events": [],
+                "contract_address": "0x2",
+                "entry_point_selector": "0x3",
+                "calldata": ["0x1"],
+                "messages": [],
+                "result": [],
+                "execution_resources": {"steps": 10},
+            },
+            "state_diff": {
+                "storage_diffs": [],
+                "deprecated_declared_classes": [],
+                "declared_classes": [],
+                "deployed_contracts": [],
+                "replaced_classes": [],
+                "nonces": [],
+            },
+            "execution_resources": {
+                "l1_gas": 5,
+                "l1_data_gas": 7,
+                "l2_gas": 0,
+            },
+        });
+
+        pretty_assertions_sorted::assert_eq!(
+            output
+                .serialize(Serializer {
+                    version: RpcVersion::V08
+                })
+                .unwrap(),
+            expected
+        );
+    }
 }
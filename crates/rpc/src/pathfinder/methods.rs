@@ -1,5 +1,7 @@
 mod get_proof;
 mod get_transaction_status;
+mod supported_spec_versions;
 
 pub(crate) use get_proof::{get_proof, get_proof_class};
 pub(crate) use get_transaction_status::get_transaction_status;
+pub(crate) use supported_spec_versions::supported_spec_versions;
@@ -3,7 +3,12 @@ use pathfinder_common::prelude::*;
 use pathfinder_common::trie::TrieNode;
 use pathfinder_common::BlockId;
 use pathfinder_crypto::Felt;
-use pathfinder_merkle_tree::{ClassCommitmentTree, ContractsStorageTree, StorageCommitmentTree};
+use pathfinder_merkle_tree::{
+    ClassCommitmentTree,
+    ContractsStorageTree,
+    ProofError,
+    StorageCommitmentTree,
+};
 use serde::{Deserialize, Serialize};
 use serde_with::skip_serializing_none;
 
@@ -62,6 +67,16 @@ impl From<anyhow::Error> for GetProofError {
     }
 }
 
+impl From<ProofError> for GetProofError {
+    fn from(e: ProofError) -> Self {
+        match e {
+            // Historic proofs are not available once the trie has been pruned.
+            ProofError::Pruned { .. } => Self::ProofMissing,
+            ProofError::Internal(e) => Self::Internal(e),
+        }
+    }
+}
+
 impl From<GetProofError> for crate::error::ApplicationError {
     fn from(x: GetProofError) -> Self {
         match x {
@@ -75,70 +90,11 @@ impl From<GetProofError> for crate::error::ApplicationError {
     }
 }
 
-/// Utility struct used for serializing.
+/// The nodes of a merkle proof, from the root down.
 #[derive(Debug, Serialize)]
-struct PathWrapper {
-    value: Felt,
-    len: usize,
-}
-
-/// Wrapper around [`Vec<TrieNode>`] as we don't control [TrieNode] in this
-/// crate.
-#[derive(Debug)]
+#[serde(transparent)]
 pub struct ProofNodes(Vec<TrieNode>);
 
-impl Serialize for ProofNodes {
-    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
-    where
-        S: serde::Serializer,
-    {
-        use serde::ser::{SerializeSeq, SerializeStructVariant};
-        let mut sequence = serializer.serialize_seq(Some(self.0.len()))?;
-
-        for node in &self.0 {
-            struct SerProofNode<'a>(&'a TrieNode);
-
-            impl Serialize for SerProofNode<'_> {
-                fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
-                where
-                    S: serde::Serializer,
-                {
-                    match self.0 {
-                        TrieNode::Binary { left, right } => {
-                            let mut state = serializer.serialize_struct_variant(
-                                "proof_node",
-                                0,
-                                "binary",
-                                2,
-                            )?;
-                            state.serialize_field("left", &left)?;
-                            state.serialize_field("right", &right)?;
-                            state.end()
-                        }
-                        TrieNode::Edge { child, path } => {
-                            let value = Felt::from_bits(path).unwrap();
-                            let path = PathWrapper {
-                                value,
-                                len: path.len(),
-                            };
-
-                            let mut state =
-                                serializer.serialize_struct_variant("proof_node", 1, "edge", 2)?;
-                            state.serialize_field("path", &path)?;
-                            state.serialize_field("child", &child)?;
-                            state.end()
-                        }
-                    }
-                }
-            }
-
-            sequence.serialize_element(&SerProofNode(node))?;
-        }
-
-        sequence.end()
-    }
-}
-
 /// Holds the data and proofs for a specific contract.
 #[derive(Debug, Serialize)]
 pub struct ContractData {
@@ -245,11 +201,15 @@ pub async fn get_proof(
         };
 
         // Generate a proof for this contract. If the contract does not exist, this will
-        // be a "non membership" proof.
-        let contract_proof =
+        // be a "non membership" proof. An empty tree has no nodes, so the empty proof
+        // is the proof of non membership.
+        let contract_proof = if header.storage_commitment == StorageCommitment::ZERO {
+            Vec::new()
+        } else {
             StorageCommitmentTree::get_proof(&tx, header.number, &input.contract_address)
                 .context("Creating contract proof")?
-                .ok_or(GetProofError::ProofMissing)?;
+                .ok_or(GetProofError::ProofMissing)?
+        };
         let contract_proof = ProofNodes(contract_proof);
 
         let contract_state_hash = tx
@@ -284,30 +244,33 @@ pub async fn get_proof(
             .contract_root_index(header.number, input.contract_address)
             .context("Querying contract root index")?;
 
-        let mut storage_proofs = Vec::new();
-        for k in &input.keys {
-            if let Some(root) = root {
-                let proof = ContractsStorageTree::get_proof(
+        let storage_proofs = match root {
+            Some(root) => {
+                let keys = input.keys.iter().map(|k| k.view_bits()).collect::<Vec<_>>();
+                ContractsStorageTree::get_proofs(
                     &tx,
                     input.contract_address,
                     header.number,
-                    k.view_bits(),
+                    &keys,
                     root,
                 )
-                .context("Get proof from contract state tree")?
-                .ok_or_else(|| {
-                    let e = anyhow!(
-                        "Storage proof missing for key {:?}, but should be present",
-                        k
-                    );
-                    tracing::warn!("{e}");
-                    e
-                })?;
-                storage_proofs.push(ProofNodes(proof));
-            } else {
-                storage_proofs.push(ProofNodes(vec![]));
+                .context("Get proofs from contract state tree")?
+                .into_iter()
+                .zip(&input.keys)
+                .map(|(proof, k)| {
+                    proof.map(ProofNodes).ok_or_else(|| {
+                        let e = anyhow!(
+                            "Storage proof missing for key {:?}, but should be present",
+                            k
+                        );
+                        tracing::warn!("{e}");
+                        e
+                    })
+                })
+                .collect::<Result<Vec<_>, _>>()?
             }
-        }
+            None => input.keys.iter().map(|_| ProofNodes(vec![])).collect(),
+        };
 
         let contract_data = ContractData {
             class_hash,
@@ -369,10 +332,13 @@ pub async fn get_proof_class(
         };
 
         // Generate a proof for this class. If the class does not exist, this will
-        // be a "non membership" proof.
-        let class_proof = ClassCommitmentTree::get_proof(&tx, header.number, input.class_hash)
-            .context("Creating class proof")?
-            .ok_or(GetProofError::ProofMissing)?;
+        // be a "non membership" proof. An empty tree has no nodes, so the empty proof
+        // is the proof of non membership.
+        let class_proof = match class_commitment {
+            Some(_) => ClassCommitmentTree::get_proof(&tx, header.number, input.class_hash)?
+                .ok_or(GetProofError::ProofMissing)?,
+            None => Vec::new(),
+        };
         let class_proof = ProofNodes(class_proof);
 
         Ok(GetClassProofOutput {
@@ -446,4 +412,30 @@ mod tests {
         let err = get_proof(context, input).await.unwrap_err();
         assert_matches::assert_matches!(err, GetProofError::ProofMissing);
     }
+
+    #[tokio::test]
+    async fn class_proof_of_empty_tree() {
+        let context = RpcContext::for_tests();
+        let mut conn = context.storage.connection().unwrap();
+        let tx = conn.transaction().unwrap();
+
+        let latest = tx
+            .block_header(pathfinder_storage::BlockId::Latest)
+            .unwrap()
+            .unwrap();
+        let header = latest
+            .child_builder()
+            .class_commitment(ClassCommitment::ZERO)
+            .finalize_with_hash(block_hash_bytes!(b"empty class tree"));
+        tx.insert_block_header(&header).unwrap();
+        tx.commit().unwrap();
+
+        let input = GetClassProofInput {
+            block_id: BlockId::Latest,
+            class_hash: class_hash_bytes!(b"class 0 hash"),
+        };
+        let output = get_proof_class(context, input).await.unwrap();
+        assert_eq!(output.class_commitment, None);
+        assert!(output.class_proof.0.is_empty());
+    }
 }
@@ -0,0 +1,22 @@
+use crate::RpcVersion;
+
+crate::error::generate_rpc_error_subset!(SupportedSpecVersionsError:);
+
+/// Lists the Starknet JSON-RPC specification versions served by this node.
+pub async fn supported_spec_versions() -> Result<Vec<&'static str>, SupportedSpecVersionsError> {
+    Ok(RpcVersion::STARKNET_VERSIONS
+        .iter()
+        .map(|version| version.spec_version_str())
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn lists_starknet_versions() {
+        let versions = supported_spec_versions().await.unwrap();
+        assert_eq!(versions, vec!["0.6.0", "0.7.1", "0.8.0-rc0"]);
+    }
+}
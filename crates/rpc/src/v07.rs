@@ -34,5 +34,5 @@ pub fn register_routes() -> RpcRouterBuilder {
         .register("starknet_traceTransaction",                    crate::method::trace_transaction)
         .register("starknet_getBlockWithReceipts",                crate::method::get_block_with_receipts)
         .register("pathfinder_getProof",                          crate::pathfinder::methods::get_proof)
-        .register("starknet_specVersion",                         || "0.7.1")
+        .register("starknet_specVersion",                         || crate::RpcVersion::V07.spec_version_str())
 }
@@ -16,17 +16,22 @@ pub fn register_routes() -> RpcRouterBuilder {
         .register("starknet_getClassAt",                          crate::method::get_class_at)
         .register("starknet_getClassHashAt",                      crate::method::get_class_hash_at)
         .register("starknet_getEvents",                           crate::method::get_events)
+        .register("starknet_getMessagesStatus",                   crate::method::get_messages_status)
         .register("starknet_getNonce",                            crate::method::get_nonce)
         .register("starknet_getStateUpdate",                      crate::method::get_state_update)
         .register("starknet_getStorageAt",                        crate::method::get_storage_at)
+        .register("starknet_getStorageProof",                     crate::method::get_storage_proof)
         .register("starknet_getTransactionByBlockIdAndIndex",     crate::method::get_transaction_by_block_id_and_index)
         .register("starknet_getTransactionByHash",                crate::method::get_transaction_by_hash)
         .register("starknet_getTransactionStatus",                crate::method::get_transaction_status)
+        .register("starknet_simulateTransactions",                crate::method::simulate_transactions)
         .register("starknet_subscribeNewHeads",                   SubscribeNewHeads)
         .register("starknet_subscribePendingTransactions",        SubscribePendingTransactions)
         .register("starknet_subscribeEvents",                     SubscribeEvents)
-        .register("starknet_specVersion",                         || "0.8.0-rc0")
+        .register("starknet_specVersion",                         || crate::RpcVersion::V08.spec_version_str())
         .register("starknet_syncing",                             crate::method::syncing)
+        .register("starknet_traceBlockTransactions",              crate::method::trace_block_transactions)
+        .register("starknet_traceTransaction",                    crate::method::trace_transaction)
 
         .register("pathfinder_getProof",                          crate::pathfinder::methods::get_proof)
 }
@@ -0,0 +1,122 @@
+//! This test was separated because the `metrics` crate uses a singleton
+//! recorder, so keeping a test that relies on metric values in a separate
+//! binary makes more sense than using an inter-test locking mechanism which can
+//! cause weird test failures without any obvious clue to what might have caused
+//! those failures in the first place.
+
+use std::num::NonZeroUsize;
+use std::sync::Arc;
+
+use futures::{SinkExt, StreamExt};
+use gateway_test_utils::GATEWAY_TIMEOUT;
+use metrics_exporter_prometheus::PrometheusBuilder;
+use pathfinder_common::test_utils::metrics::ScopedRecorderGuard;
+use pathfinder_common::ChainId;
+use pathfinder_rpc::context::{RpcConfig, RpcContext};
+use pathfinder_rpc::{Notifications, RpcServer, RpcVersion, SyncState};
+use pathfinder_storage::StorageBuilder;
+use serde_json::json;
+use tokio_tungstenite::tungstenite::Message;
+
+#[tokio::test]
+async fn method_and_subscription_calls_are_recorded() {
+    let recorder = PrometheusBuilder::new().build_recorder();
+    let handle = recorder.handle();
+    // Automatically deregister the recorder
+    let _guard = ScopedRecorderGuard::new(recorder);
+
+    let storage = StorageBuilder::in_memory().unwrap();
+    let (_, pending_data) = tokio::sync::watch::channel(Default::default());
+    let context = RpcContext::new(
+        storage.clone(),
+        storage,
+        Arc::new(SyncState::default()),
+        ChainId::SEPOLIA_TESTNET,
+        starknet_gateway_client::Client::sepolia_testnet(GATEWAY_TIMEOUT),
+        pending_data,
+        Notifications::default(),
+        RpcConfig {
+            batch_concurrency_limit: NonZeroUsize::new(8).unwrap(),
+            batch_size_limit: NonZeroUsize::new(1000).unwrap(),
+            get_events_max_blocks_to_scan: NonZeroUsize::new(1000).unwrap(),
+            get_events_max_uncached_bloom_filters_to_load: NonZeroUsize::new(1000).unwrap(),
+            custom_versioned_constants: None,
+        },
+    );
+    let (_server, addr) = RpcServer::new(([127, 0, 0, 1], 0).into(), context, RpcVersion::V08)
+        .with_method_metrics(true)
+        .spawn()
+        .await
+        .unwrap();
+
+    let client = reqwest::Client::new();
+    let url = format!("http://{addr}/rpc/v0_8");
+    for (method, params) in [
+        ("starknet_chainId", json!([])),
+        (
+            "starknet_getBlockWithTxHashes",
+            json!({"block_id": {"block_number": 1}}),
+        ),
+    ] {
+        client
+            .post(&url)
+            .json(&json!({"jsonrpc": "2.0", "id": 1, "method": method, "params": params}))
+            .send()
+            .await
+            .unwrap();
+    }
+
+    let (mut ws, _) = tokio_tungstenite::connect_async(format!("ws://{addr}/rpc/v0_8"))
+        .await
+        .unwrap();
+    for params in [json!({"block": {"block_number": 1}}), json!({})] {
+        let request = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "starknet_subscribeNewHeads",
+            "params": params,
+        });
+        ws.send(Message::Text(request.to_string())).await.unwrap();
+        ws.next().await.unwrap().unwrap();
+    }
+
+    let rendered = handle.render();
+    let duration_count = |method| {
+        sample(
+            &rendered,
+            "rpc_method_calls_duration_seconds_count",
+            &[("method", method), ("version", "v0.8")],
+        )
+    };
+    let errors = |method, code| {
+        sample(
+            &rendered,
+            "rpc_method_calls_errors_total",
+            &[("method", method), ("version", "v0.8"), ("code", code)],
+        )
+    };
+
+    assert_eq!(duration_count("starknet_chainId"), Some(1.0));
+    assert_eq!(duration_count("starknet_getBlockWithTxHashes"), Some(1.0));
+    assert_eq!(duration_count("starknet_subscribeNewHeads"), Some(2.0));
+    // BLOCK_NOT_FOUND
+    assert_eq!(errors("starknet_getBlockWithTxHashes", "24"), Some(1.0));
+    assert_eq!(errors("starknet_subscribeNewHeads", "24"), Some(1.0));
+    assert!(!rendered.lines().any(|line| {
+        line.starts_with("rpc_method_calls_errors_total")
+            && line.contains(r#"method="starknet_chainId""#)
+    }));
+}
+
+/// Returns the value of the first `metric` sample in the Prometheus `rendered`
+/// output which has all of the given `labels`.
+fn sample(rendered: &str, metric: &str, labels: &[(&str, &str)]) -> Option<f64> {
+    rendered.lines().find_map(|line| {
+        let (series, value) = line.rsplit_once(' ')?;
+        let series_labels = series.strip_prefix(metric)?.strip_prefix('{')?;
+        labels
+            .iter()
+            .all(|(key, expected)| series_labels.contains(&format!(r#"{key}="{expected}""#)))
+            .then(|| value.parse().unwrap())
+    })
+}
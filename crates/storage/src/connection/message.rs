@@ -0,0 +1,135 @@
+use anyhow::Context;
+use pathfinder_common::{L1ToL2MessageLog, TransactionHash};
+use primitive_types::H256;
+
+use crate::prelude::*;
+
+impl Transaction<'_> {
+    /// Records the L1 transaction which sent an L1 -> L2 message.
+    pub fn insert_l1_to_l2_message_log(&self, log: &L1ToL2MessageLog) -> anyhow::Result<()> {
+        self.inner()
+            .execute(
+                "INSERT OR IGNORE INTO l1_to_l2_message_logs (message_hash, l1_tx_hash) VALUES \
+                 (?, ?)",
+                params![&log.message_hash.as_bytes(), &log.l1_tx_hash.as_bytes()],
+            )
+            .context("Inserting L1 to L2 message log")?;
+
+        Ok(())
+    }
+
+    /// Returns the hashes of the L1 handler transactions triggered by the
+    /// messages sent in the given L1 transaction, in the order the messages
+    /// were sent.
+    ///
+    /// Messages which have not been consumed on L2 yet are not included.
+    pub fn l1_handler_tx_hashes_by_l1_tx_hash(
+        &self,
+        l1_tx_hash: H256,
+    ) -> anyhow::Result<Vec<TransactionHash>> {
+        let mut stmt = self
+            .inner()
+            .prepare_cached(
+                r"
+                SELECT l1_handler_txs.l2_tx_hash FROM l1_to_l2_message_logs
+                JOIN l1_handler_txs ON l1_handler_txs.message_hash = l1_to_l2_message_logs.message_hash
+                WHERE l1_to_l2_message_logs.l1_tx_hash = ?
+                ORDER BY l1_to_l2_message_logs.rowid
+                ",
+            )
+            .context("Preparing L1 handler transactions query")?;
+
+        let hashes = stmt
+            .query_map(params![&l1_tx_hash.as_bytes()], |row| {
+                row.get_transaction_hash(0)
+            })
+            .context("Querying L1 handler transactions")?
+            .collect::<Result<Vec<_>, _>>()
+            .context("Iterating over L1 handler transactions")?;
+
+        Ok(hashes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pathfinder_common::macro_prelude::*;
+    use pathfinder_common::receipt::Receipt;
+    use pathfinder_common::transaction::{
+        L1HandlerTransaction,
+        Transaction as StarknetTransaction,
+        TransactionVariant,
+    };
+    use pathfinder_common::{BlockHeader, BlockNumber, TransactionNonce};
+
+    use super::*;
+
+    fn l1_handler(hash: TransactionHash, nonce: TransactionNonce) -> StarknetTransaction {
+        StarknetTransaction {
+            hash,
+            variant: TransactionVariant::L1Handler(L1HandlerTransaction {
+                contract_address: contract_address!("0x1234"),
+                entry_point_selector: entry_point!("0x5678"),
+                nonce,
+                calldata: vec![call_param!("0xabcd"), call_param!("0x1")],
+            }),
+        }
+    }
+
+    fn message_hash(transaction: &StarknetTransaction) -> H256 {
+        match &transaction.variant {
+            TransactionVariant::L1Handler(l1_handler) => l1_handler.calculate_message_hash(),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn l1_handler_tx_hashes_by_l1_tx_hash() {
+        let storage = crate::StorageBuilder::in_memory().unwrap();
+        let mut connection = storage.connection().unwrap();
+        let tx = connection.transaction().unwrap();
+
+        let header = BlockHeader::builder().finalize_with_hash(block_hash_bytes!(b"block hash"));
+        tx.insert_block_header(&header).unwrap();
+
+        let first = l1_handler(transaction_hash_bytes!(b"first"), transaction_nonce!("0x1"));
+        let second = l1_handler(
+            transaction_hash_bytes!(b"second"),
+            transaction_nonce!("0x2"),
+        );
+        let other = l1_handler(transaction_hash_bytes!(b"other"), transaction_nonce!("0x3"));
+        let transactions = [&first, &second, &other]
+            .into_iter()
+            .map(|t| (t.clone(), Receipt::default()))
+            .collect::<Vec<_>>();
+        tx.insert_transaction_data(BlockNumber::GENESIS, &transactions, None)
+            .unwrap();
+
+        let l1_tx_hash = H256::from_low_u64_be(1);
+        let other_l1_tx_hash = H256::from_low_u64_be(2);
+        for (transaction, l1_tx_hash) in [
+            (&second, l1_tx_hash),
+            (&other, other_l1_tx_hash),
+            (&first, l1_tx_hash),
+        ] {
+            tx.insert_l1_to_l2_message_log(&L1ToL2MessageLog {
+                message_hash: message_hash(transaction),
+                l1_tx_hash,
+            })
+            .unwrap();
+        }
+
+        let result = tx.l1_handler_tx_hashes_by_l1_tx_hash(l1_tx_hash).unwrap();
+        assert_eq!(result, vec![second.hash, first.hash]);
+
+        let result = tx
+            .l1_handler_tx_hashes_by_l1_tx_hash(H256::from_low_u64_be(3))
+            .unwrap();
+        assert_eq!(result, vec![]);
+
+        // Reorged transactions are no longer returned.
+        tx.purge_block(BlockNumber::GENESIS).unwrap();
+        let result = tx.l1_handler_tx_hashes_by_l1_tx_hash(l1_tx_hash).unwrap();
+        assert_eq!(result, vec![]);
+    }
+}
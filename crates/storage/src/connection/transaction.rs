@@ -3,7 +3,7 @@
 use anyhow::Context;
 use pathfinder_common::event::Event;
 use pathfinder_common::receipt::Receipt;
-use pathfinder_common::transaction::Transaction as StarknetTransaction;
+use pathfinder_common::transaction::{Transaction as StarknetTransaction, TransactionVariant};
 use pathfinder_common::{BlockHash, BlockNumber, TransactionHash};
 
 use super::{EventsForBlock, TransactionDataForBlock, TransactionWithReceipt};
@@ -118,6 +118,14 @@ impl Transaction<'_> {
             )
             .context("Preparing insert transaction hash statement")?;
 
+        let mut insert_l1_handler_tx_stmt = self
+            .inner()
+            .prepare_cached(
+                "INSERT INTO l1_handler_txs (message_hash, l2_tx_hash, block_number) VALUES \
+                 (:message_hash, :l2_tx_hash, :block_number)",
+            )
+            .context("Preparing insert L1 handler transaction statement")?;
+
         for (idx, (transaction, ..)) in transactions.iter().enumerate() {
             let idx: i64 = idx.try_into()?;
             insert_transaction_hash_stmt.execute(named_params![
@@ -125,6 +133,16 @@ impl Transaction<'_> {
                 ":block_number": &block_number,
                 ":idx": &idx,
             ])?;
+
+            if let TransactionVariant::L1Handler(l1_handler) = &transaction.variant {
+                insert_l1_handler_tx_stmt
+                    .execute(named_params![
+                        ":message_hash": &l1_handler.calculate_message_hash().as_bytes(),
+                        ":l2_tx_hash": &transaction.hash,
+                        ":block_number": &block_number,
+                    ])
+                    .context("Inserting L1 handler transaction")?;
+            }
         }
         let transactions_with_receipts: Vec<_> = transactions
             .iter()
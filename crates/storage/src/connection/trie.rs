@@ -33,6 +33,21 @@ impl Transaction<'_> {
             .map_err(Into::into)
     }
 
+    /// Returns `true` if the trie history of `block_number` is older than the
+    /// blocks kept by trie pruning, so its trie nodes may have been removed.
+    pub fn trie_pruned_at(&self, block_number: BlockNumber) -> anyhow::Result<bool> {
+        let TriePruneMode::Prune { num_blocks_kept } = self.trie_prune_mode else {
+            return Ok(false);
+        };
+        let Some(latest) = self.block_number(BlockId::Latest)? else {
+            return Ok(false);
+        };
+
+        Ok(latest
+            .checked_sub(num_blocks_kept)
+            .is_some_and(|oldest_kept| block_number < oldest_kept))
+    }
+
     pub fn storage_root_index(&self, block_number: BlockNumber) -> anyhow::Result<Option<u64>> {
         self.inner()
             .query_row(
@@ -24,6 +24,7 @@ mod revision_0061;
 mod revision_0062;
 mod revision_0063;
 mod revision_0064;
+mod revision_0065;
 
 pub(crate) use base::base_schema;
 
@@ -56,6 +57,7 @@ pub fn migrations() -> &'static [MigrationFn] {
         revision_0062::migrate,
         revision_0063::migrate,
         revision_0064::migrate,
+        revision_0065::migrate,
     ]
 }
 
@@ -0,0 +1,34 @@
+use anyhow::Context;
+
+/// Adds the tables required to map an L1 transaction to the L1 handler
+/// transactions triggered by the messages it sent.
+///
+/// `l1_to_l2_message_logs` is populated from the L1 message logs of the
+/// Starknet core contract, while `l1_handler_txs` is populated when inserting
+/// the transactions of a block. The two are joined on the message hash.
+///
+/// Neither table is backfilled. Message logs are only recorded as the node
+/// follows L1, so messages sent before upgrading can't be matched to their L1
+/// handler transactions even if those were backfilled. Such messages are
+/// reported as not found by `starknet_getMessagesStatus`.
+pub(crate) fn migrate(tx: &rusqlite::Transaction<'_>) -> anyhow::Result<()> {
+    tracing::info!("Adding l1_to_l2_message_logs and l1_handler_txs tables");
+
+    tx.execute_batch(
+        r"CREATE TABLE l1_to_l2_message_logs (
+            message_hash BLOB PRIMARY KEY NOT NULL,
+            l1_tx_hash BLOB NOT NULL
+        );
+        CREATE INDEX l1_to_l2_message_logs_l1_tx_hash ON l1_to_l2_message_logs(l1_tx_hash);
+        CREATE TABLE l1_handler_txs (
+            message_hash BLOB NOT NULL,
+            l2_tx_hash BLOB NOT NULL,
+            block_number INTEGER NOT NULL REFERENCES block_headers(number) ON DELETE CASCADE
+        );
+        CREATE INDEX l1_handler_txs_message_hash ON l1_handler_txs(message_hash);
+        CREATE INDEX l1_handler_txs_block_number ON l1_handler_txs(block_number);",
+    )
+    .context("Adding l1_to_l2_message_logs and l1_handler_txs tables")?;
+
+    Ok(())
+}